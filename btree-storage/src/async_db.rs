@@ -0,0 +1,120 @@
+//! A `Db` wrapper for async code.
+//!
+//! `Db` calls block on page I/O and locks. `AsyncDb` runs each call on
+//! Tokio's blocking thread pool with `spawn_blocking`, so a task awaiting it
+//! does not hold up the runtime's worker threads. Clones share one `Db`.
+
+use crate::btree::PutResult;
+use crate::error::{Result, StorageError};
+use crate::Db;
+use std::sync::Arc;
+
+/// A database whose operations return futures
+#[derive(Clone)]
+pub struct AsyncDb {
+    db: Arc<Db>,
+}
+
+impl AsyncDb {
+    /// Wrap an open database
+    pub fn new(db: Db) -> Self {
+        Self { db: Arc::new(db) }
+    }
+
+    /// Wrap a database that is already shared
+    pub fn from_arc(db: Arc<Db>) -> Self {
+        Self { db }
+    }
+
+    /// Get the underlying database, for calls that are cheap or rare
+    /// enough to make directly
+    pub fn db(&self) -> &Arc<Db> {
+        &self.db
+    }
+
+    /// Get a value by key
+    pub async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let key = key.to_vec();
+        self.run(move |db| db.get(&key)).await
+    }
+
+    /// Insert or update a key-value pair
+    pub async fn put(&self, key: &[u8], value: &[u8]) -> Result<PutResult> {
+        let (key, value) = (key.to_vec(), value.to_vec());
+        self.run(move |db| db.put(&key, &value)).await
+    }
+
+    /// Delete a key, returning whether it existed
+    pub async fn delete(&self, key: &[u8]) -> Result<bool> {
+        let key = key.to_vec();
+        self.run(move |db| db.delete(&key)).await
+    }
+
+    /// Get all key-value pairs in `[start, end)`
+    pub async fn range(
+        &self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let (start, end) = (start.map(<[u8]>::to_vec), end.map(<[u8]>::to_vec));
+        self.run(move |db| db.range(start.as_deref(), end.as_deref()))
+            .await
+    }
+
+    /// Run `f` on the blocking pool, passing on a panic from it
+    async fn run<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Db) -> Result<T> + Send + 'static,
+    {
+        let db = Arc::clone(&self.db);
+        match tokio::task::spawn_blocking(move || f(&db)).await {
+            Ok(result) => result,
+            Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            Err(e) => Err(StorageError::invalid_operation(format!(
+                "blocking task did not run: {}",
+                e
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+    use tempfile::tempdir;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_async_db_matches_sync_api() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let db = AsyncDb::new(Db::open(Config::new(dir.path().join("test.db")))?);
+
+        let puts: Vec<_> = (0..200u32)
+            .map(|i| {
+                let db = db.clone();
+                tokio::spawn(async move {
+                    let key = format!("key{:04}", i);
+                    db.put(key.as_bytes(), format!("value{}", i).as_bytes()).await
+                })
+            })
+            .collect();
+        for put in puts {
+            assert_eq!(put.await.unwrap()?, PutResult::Inserted);
+        }
+
+        assert!(db.delete(b"key0007").await?);
+        assert!(!db.delete(b"key0007").await?);
+        assert_eq!(db.get(b"key0042").await?, Some(b"value42".to_vec()));
+        assert_eq!(db.get(b"key0007").await?, None);
+
+        let sync = db.db();
+        assert_eq!(db.range(None, None).await?, sync.iter()?);
+        assert_eq!(
+            db.range(Some(b"key0010"), Some(b"key0020")).await?,
+            sync.range(Some(b"key0010"), Some(b"key0020"))?
+        );
+        assert_eq!(sync.iter()?.len(), 199);
+        Ok(())
+    }
+}
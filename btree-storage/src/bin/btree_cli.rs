@@ -8,8 +8,10 @@
 //!   btree_cli <db_path> stats
 //!   btree_cli <db_path> bulk_insert <count>
 //!   btree_cli <db_path> debug <key>
+//!   btree_cli <db_path> tree [--dot]
+//!   btree_cli <db_path> page <id>
 
-use btree_storage::{Config, Db};
+use btree_storage::{Config, Db, PageId, PageInspection};
 use std::env;
 use std::process::exit;
 
@@ -25,6 +27,9 @@ fn main() {
         eprintln!("  scan [start] [end]  - Scan keys in range");
         eprintln!("  stats               - Show database statistics");
         eprintln!("  bulk_insert <count> - Insert count test records");
+        eprintln!("  debug <key>         - Trace the lookup path for a key");
+        eprintln!("  tree [--dot]        - Print the tree as JSON, or GraphViz DOT");
+        eprintln!("  page <id>           - Dump the layout of one page");
         exit(1);
     }
 
@@ -50,7 +55,7 @@ fn main() {
             let value = &args[4];
 
             match db.put(key.as_bytes(), value.as_bytes()) {
-                Ok(()) => println!("OK"),
+                Ok(_) => println!("OK"),
                 Err(e) => {
                     eprintln!("ERROR: {}", e);
                     exit(1);
@@ -123,7 +128,13 @@ fn main() {
             let stats = db.stats();
             println!("page_count: {}", stats.page_count);
             println!("buffer_pool_size: {}", stats.buffer_pool_size);
+            println!("dirty_pages: {}", stats.dirty_pages);
             println!("tree_height: {}", stats.tree_height);
+            println!("leaf_splits: {}", stats.structure.leaf_splits);
+            println!("interior_splits: {}", stats.structure.interior_splits);
+            println!("root_splits: {}", stats.structure.root_splits);
+            println!("merges: {}", stats.structure.merges);
+            println!("borrows: {}", stats.structure.borrows);
         }
 
         "bulk_insert" => {
@@ -139,21 +150,20 @@ fn main() {
                 }
             };
 
-            let start = std::time::Instant::now();
-            for i in 0..count {
-                let key = format!("key_{:08}", i);
-                let value = format!("value_{}", i);
-                if let Err(e) = db.put(key.as_bytes(), value.as_bytes()) {
-                    eprintln!("ERROR at {}: {}", i, e);
-                    exit(1);
-                }
-            }
-            let elapsed = start.elapsed();
+            let entries: Vec<(String, String)> = (0..count)
+                .map(|i| (format!("key_{:08}", i), format!("value_{}", i)))
+                .collect();
+            let pairs: Vec<(&[u8], &[u8])> = entries
+                .iter()
+                .map(|(k, v)| (k.as_bytes(), v.as_bytes()))
+                .collect();
 
-            if let Err(e) = db.flush() {
-                eprintln!("ERROR flushing: {}", e);
+            let start = std::time::Instant::now();
+            if let Err(e) = db.put_batch(&pairs) {
+                eprintln!("ERROR: {}", e);
                 exit(1);
             }
+            let elapsed = start.elapsed();
 
             let ops_per_sec = count as f64 / elapsed.as_secs_f64();
             println!("INSERTED: {}", count);
@@ -181,6 +191,80 @@ fn main() {
             }
         }
 
+        "tree" => {
+            let dot = args.get(3).map(String::as_str) == Some("--dot");
+
+            let output = if dot {
+                db.export_dot()
+            } else {
+                db.export_tree().map(|tree| {
+                    serde_json::to_string_pretty(&tree).unwrap_or_else(|e| e.to_string())
+                })
+            };
+            match output {
+                Ok(text) => println!("{}", text.trim_end()),
+                Err(e) => {
+                    eprintln!("ERROR: {}", e);
+                    exit(1);
+                }
+            }
+        }
+
+        "page" => {
+            if args.len() < 4 {
+                eprintln!("Usage: btree_cli <db_path> page <id>");
+                exit(1);
+            }
+            let page_id = match args[3].parse() {
+                Ok(id) => PageId::new(id),
+                Err(_) => {
+                    eprintln!("ERROR: Invalid page id");
+                    exit(1);
+                }
+            };
+
+            match db.inspect_page(page_id) {
+                Ok(PageInspection::FileHeader(header)) => {
+                    println!("type: FileHeader");
+                    println!("page_size: {}", header.page_size);
+                    println!("page_count: {}", header.page_count);
+                    println!("first_free_page: {}", header.first_free_page);
+                    println!("free_page_count: {}", header.free_page_count);
+                    println!("root_page: {}", header.root_page);
+                    println!("tree_height: {}", header.tree_height);
+                    println!("catalog_page: {}", header.catalog_page);
+                    println!("entry_count: {}", header.entry_count);
+                    println!("key_order: {:?}", header.key_order);
+                }
+                Ok(PageInspection::Slotted {
+                    page_type,
+                    cell_count,
+                    free_bytes,
+                    fragmented_bytes,
+                    right_child,
+                    cells,
+                }) => {
+                    println!("type: {:?}", page_type);
+                    println!("cell_count: {}", cell_count);
+                    println!("free_bytes: {}", free_bytes);
+                    println!("fragmented_bytes: {}", fragmented_bytes);
+                    println!("right_child: {}", right_child);
+                    for (i, cell) in cells.iter().enumerate() {
+                        let key = String::from_utf8_lossy(&cell.key);
+                        match (cell.value_len, cell.child) {
+                            (Some(len), _) => println!("{}: {} ({} bytes)", i, key, len),
+                            (_, Some(child)) => println!("{}: {} -> {}", i, key, child),
+                            _ => println!("{}: {}", i, key),
+                        }
+                    }
+                }
+                Err(e) => {
+                    eprintln!("ERROR: {}", e);
+                    exit(1);
+                }
+            }
+        }
+
         _ => {
             eprintln!("Unknown command: {}", command);
             exit(1);
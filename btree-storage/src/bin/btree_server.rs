@@ -6,18 +6,28 @@
 //! - Configuration management
 
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::Json,
+    extract::{Path, Query, State},
+    http::{header::RETRY_AFTER, HeaderValue, StatusCode},
+    middleware::map_response,
+    response::{Json, Response},
     routing::{delete, get, post},
     Router,
 };
-use btree_storage::{BTreeConfig, Config, Db, DbStats, TreeNode};
-use parking_lot::RwLock;
+use btree_storage::{
+    AsyncDb, BTreeConfig, Config, Db, DbStats, PutResult, StorageError, StructureStats, TreeNode,
+};
+use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
+use std::time::Duration;
 use tower_http::cors::{Any, CorsLayer};
 
+/// How long a handler waits for the database lock by default
+const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Environment variable overriding the lock timeout, in milliseconds
+const LOCK_TIMEOUT_ENV: &str = "BTREE_LOCK_TIMEOUT_MS";
+
 /// Application state shared across handlers
 struct AppState {
     db: Option<RwLock<Db>>,
@@ -76,6 +86,7 @@ struct StatsResponse {
     page_count: usize,
     buffer_pool_size: usize,
     tree_height: usize,
+    structure: StructureStats,
     btree_config: BTreeConfig,
 }
 
@@ -95,26 +106,66 @@ struct TreeResponse {
     stats: Option<StatsResponse>,
 }
 
+/// Error returned by handlers: a status code and a JSON message
+type ApiError = (StatusCode, Json<OperationResponse>);
+
 /// Mutable app state for database management
 struct MutableAppState {
-    db: RwLock<Option<Db>>,
+    db: RwLock<Option<AsyncDb>>,
     btree_config: RwLock<BTreeConfig>,
+    /// How long a handler waits for the database lock before giving up
+    lock_timeout: Duration,
 }
 
 impl MutableAppState {
-    fn new() -> Self {
+    fn new(lock_timeout: Duration) -> Self {
         Self {
             db: RwLock::new(None),
             btree_config: RwLock::new(BTreeConfig::default()),
+            lock_timeout,
         }
     }
+
+    /// Lock the database for reading, failing with 503 after the timeout
+    fn read_db(&self) -> Result<RwLockReadGuard<'_, Option<AsyncDb>>, ApiError> {
+        self.db.try_read_for(self.lock_timeout).ok_or_else(busy)
+    }
+
+    /// Lock the database for writing, failing with 503 after the timeout
+    fn write_db(&self) -> Result<RwLockWriteGuard<'_, Option<AsyncDb>>, ApiError> {
+        self.db.try_write_for(self.lock_timeout).ok_or_else(busy)
+    }
+}
+
+/// Response for a request that timed out waiting for the database lock
+fn busy() -> ApiError {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(OperationResponse {
+            success: false,
+            message: "Database is busy, try again later".to_string(),
+        }),
+    )
+}
+
+/// Read the lock timeout from the environment, falling back to the default
+fn lock_timeout_from_env() -> Duration {
+    std::env::var(LOCK_TIMEOUT_ENV)
+        .ok()
+        .and_then(|ms| ms.parse().ok())
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_LOCK_TIMEOUT)
 }
 
 type SharedState = Arc<MutableAppState>;
 
 #[tokio::main]
 async fn main() {
-    let state = Arc::new(MutableAppState::new());
+    let lock_timeout = lock_timeout_from_env();
+    let state = Arc::new(MutableAppState::new(lock_timeout));
+    // Whole seconds, rounded up, for the Retry-After header
+    let retry_after = lock_timeout.as_secs() + u64::from(lock_timeout.subsec_nanos() > 0);
+    let retry_after = retry_after.max(1);
 
     let cors = CorsLayer::new()
         .allow_origin(Any)
@@ -134,11 +185,21 @@ async fn main() {
         .route("/api/stats", get(get_stats))
         .route("/api/clear", post(clear_db))
         .route("/api/bulk", post(bulk_insert))
+        .layer(map_response(move |mut response: Response| async move {
+            // Tell clients turned away by lock timeouts when to come back
+            if response.status() == StatusCode::SERVICE_UNAVAILABLE {
+                response
+                    .headers_mut()
+                    .insert(RETRY_AFTER, HeaderValue::from(retry_after));
+            }
+            response
+        }))
         .layer(cors)
         .with_state(state);
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3001").await.unwrap();
     println!("🚀 B-tree server running on http://localhost:3001");
+    println!("Lock timeout: {:?} (set {} to change)", lock_timeout, LOCK_TIMEOUT_ENV);
     println!("API Endpoints:");
     println!("  POST   /api/db       - Create/open database");
     println!("  DELETE /api/db       - Close database");
@@ -147,7 +208,7 @@ async fn main() {
     println!("  GET    /api/kv/:key  - Get value by key");
     println!("  POST   /api/kv       - Put key-value pair");
     println!("  DELETE /api/kv/:key  - Delete key");
-    println!("  GET    /api/keys     - List all keys");
+    println!("  GET    /api/keys     - List all keys, or a page with ?limit=N&after=KEY");
     println!("  GET    /api/tree     - Get tree structure for visualization");
     println!("  GET    /api/stats    - Get database stats");
     println!("  POST   /api/clear    - Clear all data");
@@ -158,7 +219,7 @@ async fn main() {
 async fn create_db(
     State(state): State<SharedState>,
     Json(req): Json<CreateDbRequest>,
-) -> Result<Json<OperationResponse>, (StatusCode, Json<OperationResponse>)> {
+) -> Result<Json<OperationResponse>, ApiError> {
     let path = req.path.unwrap_or_else(|| "/tmp/btree_viz.db".to_string());
 
     // Update config if provided
@@ -181,8 +242,8 @@ async fn create_db(
 
     match Db::open(config) {
         Ok(db) => {
-            let mut db_lock = state.db.write();
-            *db_lock = Some(db);
+            let mut db_lock = state.write_db()?;
+            *db_lock = Some(AsyncDb::new(db));
             Ok(Json(OperationResponse {
                 success: true,
                 message: format!("Database opened at {}", path),
@@ -200,22 +261,22 @@ async fn create_db(
 
 async fn close_db(
     State(state): State<SharedState>,
-) -> Json<OperationResponse> {
-    let mut db_lock = state.db.write();
+) -> Result<Json<OperationResponse>, ApiError> {
+    let mut db_lock = state.write_db()?;
     if db_lock.is_some() {
         if let Some(ref db) = *db_lock {
-            let _ = db.flush();
+            let _ = db.db().flush();
         }
         *db_lock = None;
-        Json(OperationResponse {
+        Ok(Json(OperationResponse {
             success: true,
             message: "Database closed".to_string(),
-        })
+        }))
     } else {
-        Json(OperationResponse {
+        Ok(Json(OperationResponse {
             success: false,
             message: "No database open".to_string(),
-        })
+        }))
     }
 }
 
@@ -252,10 +313,11 @@ async fn set_config(
 async fn get_value(
     State(state): State<SharedState>,
     Path(key): Path<String>,
-) -> Result<Json<GetResponse>, (StatusCode, Json<OperationResponse>)> {
-    let db_lock = state.db.read();
-    match &*db_lock {
-        Some(db) => match db.get(key.as_bytes()) {
+) -> Result<Json<GetResponse>, ApiError> {
+    // Clone the handle out so the lock is not held across the await
+    let db = state.read_db()?.clone();
+    match db {
+        Some(db) => match db.get(key.as_bytes()).await {
             Ok(value) => {
                 let found = value.is_some();
                 Ok(Json(GetResponse {
@@ -285,13 +347,17 @@ async fn get_value(
 async fn put_value(
     State(state): State<SharedState>,
     Json(req): Json<PutRequest>,
-) -> Result<Json<OperationResponse>, (StatusCode, Json<OperationResponse>)> {
-    let db_lock = state.db.read();
-    match &*db_lock {
-        Some(db) => match db.put(req.key.as_bytes(), req.value.as_bytes()) {
-            Ok(()) => Ok(Json(OperationResponse {
+) -> Result<Json<OperationResponse>, ApiError> {
+    // Clone the handle out so the lock is not held across the await
+    let db = state.read_db()?.clone();
+    match db {
+        Some(db) => match db.put(req.key.as_bytes(), req.value.as_bytes()).await {
+            Ok(result) => Ok(Json(OperationResponse {
                 success: true,
-                message: format!("Inserted key '{}'", req.key),
+                message: match result {
+                    PutResult::Inserted => format!("Inserted key '{}'", req.key),
+                    PutResult::Updated => format!("Updated key '{}'", req.key),
+                },
             })),
             Err(e) => Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
@@ -314,10 +380,11 @@ async fn put_value(
 async fn delete_value(
     State(state): State<SharedState>,
     Path(key): Path<String>,
-) -> Result<Json<OperationResponse>, (StatusCode, Json<OperationResponse>)> {
-    let db_lock = state.db.read();
-    match &*db_lock {
-        Some(db) => match db.delete(key.as_bytes()) {
+) -> Result<Json<OperationResponse>, ApiError> {
+    // Clone the handle out so the lock is not held across the await
+    let db = state.read_db()?.clone();
+    match db {
+        Some(db) => match db.delete(key.as_bytes()).await {
             Ok(deleted) => Ok(Json(OperationResponse {
                 success: true,
                 message: if deleted {
@@ -344,27 +411,51 @@ async fn delete_value(
     }
 }
 
+#[derive(Debug, Deserialize)]
+struct ListKeysQuery {
+    /// Only list keys after this one
+    after: Option<String>,
+    /// Page size; without it every key is listed
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum ListKeysResponse {
+    All(Vec<String>),
+    Page { keys: Vec<String>, next: Option<String> },
+}
+
 async fn list_keys(
     State(state): State<SharedState>,
-) -> Result<Json<Vec<String>>, (StatusCode, Json<OperationResponse>)> {
-    let db_lock = state.db.read();
+    Query(query): Query<ListKeysQuery>,
+) -> Result<Json<ListKeysResponse>, ApiError> {
+    let to_string = |key: &[u8]| String::from_utf8_lossy(key).to_string();
+    let db_lock = state.read_db()?;
     match &*db_lock {
-        Some(db) => match db.iter() {
-            Ok(pairs) => {
-                let keys: Vec<String> = pairs
-                    .into_iter()
-                    .map(|(k, _)| String::from_utf8_lossy(&k).to_string())
-                    .collect();
-                Ok(Json(keys))
-            }
-            Err(e) => Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(OperationResponse {
-                    success: false,
-                    message: format!("List keys failed: {}", e),
+        Some(db) => {
+            let listed = match query.limit {
+                Some(limit) => db.db()
+                    .scan_page(query.after.as_deref().map(str::as_bytes), limit)
+                    .map(|(pairs, next)| ListKeysResponse::Page {
+                        keys: pairs.iter().map(|(k, _)| to_string(k)).collect(),
+                        next: next.as_deref().map(to_string),
+                    }),
+                None => db.db().iter().map(|pairs| {
+                    ListKeysResponse::All(pairs.iter().map(|(k, _)| to_string(k)).collect())
                 }),
-            )),
-        },
+            };
+            match listed {
+                Ok(response) => Ok(Json(response)),
+                Err(e) => Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(OperationResponse {
+                        success: false,
+                        message: format!("List keys failed: {}", e),
+                    }),
+                )),
+            }
+        }
         None => Err((
             StatusCode::BAD_REQUEST,
             Json(OperationResponse {
@@ -377,17 +468,18 @@ async fn list_keys(
 
 async fn get_tree(
     State(state): State<SharedState>,
-) -> Result<Json<TreeResponse>, (StatusCode, Json<OperationResponse>)> {
-    let db_lock = state.db.read();
+) -> Result<Json<TreeResponse>, ApiError> {
+    let db_lock = state.read_db()?;
     match &*db_lock {
         Some(db) => {
-            let tree = db.export_tree().ok().flatten();
-            let stats_data = db.stats();
-            let btree_config = db.btree_config();
+            let tree = db.db().export_tree().ok().flatten();
+            let stats_data = db.db().stats();
+            let btree_config = db.db().btree_config();
             let stats = Some(StatsResponse {
                 page_count: stats_data.page_count,
                 buffer_pool_size: stats_data.buffer_pool_size,
                 tree_height: stats_data.tree_height,
+                structure: stats_data.structure,
                 btree_config,
             });
             Ok(Json(TreeResponse { tree, stats }))
@@ -404,16 +496,17 @@ async fn get_tree(
 
 async fn get_stats(
     State(state): State<SharedState>,
-) -> Result<Json<StatsResponse>, (StatusCode, Json<OperationResponse>)> {
-    let db_lock = state.db.read();
+) -> Result<Json<StatsResponse>, ApiError> {
+    let db_lock = state.read_db()?;
     match &*db_lock {
         Some(db) => {
-            let stats = db.stats();
-            let btree_config = db.btree_config();
+            let stats = db.db().stats();
+            let btree_config = db.db().btree_config();
             Ok(Json(StatsResponse {
                 page_count: stats.page_count,
                 buffer_pool_size: stats.buffer_pool_size,
                 tree_height: stats.tree_height,
+                structure: stats.structure,
                 btree_config,
             }))
         }
@@ -429,22 +522,33 @@ async fn get_stats(
 
 async fn clear_db(
     State(state): State<SharedState>,
-) -> Result<Json<OperationResponse>, (StatusCode, Json<OperationResponse>)> {
-    // Close the database and reopen it fresh
-    let btree_config = state.btree_config.read().clone();
-    let mut db_lock = state.db.write();
-
-    if db_lock.is_some() {
-        *db_lock = None;
+) -> Result<Json<OperationResponse>, ApiError> {
+    let mut db_lock = state.write_db()?;
+    if let Some(db) = &*db_lock {
+        return match db.db().clear() {
+            Ok(()) => Ok(Json(OperationResponse {
+                success: true,
+                message: "Database cleared".to_string(),
+            })),
+            Err(e) => Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(OperationResponse {
+                    success: false,
+                    message: format!("Failed to clear database: {}", e),
+                }),
+            )),
+        };
     }
 
+    // Nothing open yet, so start a fresh database
+    let btree_config = state.btree_config.read().clone();
     let path = "/tmp/btree_viz.db";
     let _ = std::fs::remove_file(path);
 
     let config = Config::new(path).btree_config(btree_config);
     match Db::open(config) {
         Ok(db) => {
-            *db_lock = Some(db);
+            *db_lock = Some(AsyncDb::new(db));
             Ok(Json(OperationResponse {
                 success: true,
                 message: "Database cleared".to_string(),
@@ -469,27 +573,38 @@ struct BulkInsertRequest {
 async fn bulk_insert(
     State(state): State<SharedState>,
     Json(req): Json<BulkInsertRequest>,
-) -> Result<Json<OperationResponse>, (StatusCode, Json<OperationResponse>)> {
-    let db_lock = state.db.read();
+) -> Result<Json<OperationResponse>, ApiError> {
+    let db_lock = state.read_db()?;
     match &*db_lock {
         Some(db) => {
-            let mut count = 0;
-            for pair in req.pairs {
-                if let Err(e) = db.put(pair.key.as_bytes(), pair.value.as_bytes()) {
-                    return Err((
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        Json(OperationResponse {
-                            success: false,
-                            message: format!("Bulk insert failed at key '{}': {}", pair.key, e),
-                        }),
-                    ));
-                }
-                count += 1;
+            let pairs: Vec<(&[u8], &[u8])> = req
+                .pairs
+                .iter()
+                .map(|pair| (pair.key.as_bytes(), pair.value.as_bytes()))
+                .collect();
+            match db.db().put_batch(&pairs) {
+                Ok(()) => Ok(Json(OperationResponse {
+                    success: true,
+                    message: format!("Inserted {} key-value pairs", pairs.len()),
+                })),
+                Err(StorageError::BatchFailed { applied, source }) => Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(OperationResponse {
+                        success: false,
+                        message: format!(
+                            "Bulk insert failed at key '{}': {}",
+                            req.pairs[applied].key, source
+                        ),
+                    }),
+                )),
+                Err(e) => Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(OperationResponse {
+                        success: false,
+                        message: format!("Bulk insert failed: {}", e),
+                    }),
+                )),
             }
-            Ok(Json(OperationResponse {
-                success: true,
-                message: format!("Inserted {} key-value pairs", count),
-            }))
         }
         None => Err((
             StatusCode::BAD_REQUEST,
@@ -0,0 +1,104 @@
+//! Catalog of named trees.
+//!
+//! The catalog is a single leaf page, referenced from the file header,
+//! whose cells map a tree name to the tree's root page and height. The
+//! value of each cell is the root page ID followed by the height, both
+//! big-endian `u32`s. A root of 0 is an empty tree.
+
+use crate::buffer::BufferPool;
+use crate::error::{Result, StorageError};
+use crate::page::{Cell, SlottedPage};
+use crate::types::PageId;
+
+/// Size of a catalog entry's value: root page ID and height
+const ENTRY_SIZE: usize = 8;
+
+/// Allocate an empty catalog page
+pub(crate) fn create<B: BufferPool + ?Sized>(pool: &B) -> Result<PageId> {
+    let (page_id, guard) = pool.new_page()?;
+    *guard.write() = SlottedPage::new_leaf();
+    Ok(page_id)
+}
+
+/// Get the root page and height of the tree stored under `name`
+pub(crate) fn lookup<B: BufferPool + ?Sized>(
+    pool: &B,
+    catalog: PageId,
+    name: &[u8],
+) -> Result<Option<(PageId, u32)>> {
+    let guard = pool.fetch_page(catalog)?;
+    let page = guard.read();
+    check_catalog(&page, catalog)?;
+    let Some(index) = page.search(name)? else {
+        return Ok(None);
+    };
+    decode_entry(page.get_leaf_entry(index)?.1, catalog).map(Some)
+}
+
+/// Record the root page and height of the tree stored under `name`,
+/// adding the entry if it is new
+pub(crate) fn store<B: BufferPool + ?Sized>(
+    pool: &B,
+    catalog: PageId,
+    name: &[u8],
+    root: PageId,
+    height: u32,
+) -> Result<()> {
+    let mut value = [0u8; ENTRY_SIZE];
+    value[..4].copy_from_slice(&root.value().to_be_bytes());
+    value[4..].copy_from_slice(&height.to_be_bytes());
+
+    let guard = pool.fetch_page_mut(catalog)?;
+    let mut page = guard.write();
+    check_catalog(&page, catalog)?;
+    match page.search(name)? {
+        Some(index) => page.update_cell(index, &value),
+        None => page
+            .insert_cell(&Cell::new_leaf(name.to_vec(), value.to_vec()))
+            .map(|_| ()),
+    }
+}
+
+/// Get every tree in the catalog with its root page and height, in name
+/// order
+pub(crate) fn entries<B: BufferPool + ?Sized>(
+    pool: &B,
+    catalog: PageId,
+) -> Result<Vec<(Vec<u8>, PageId, u32)>> {
+    let guard = pool.fetch_page(catalog)?;
+    let page = guard.read();
+    check_catalog(&page, catalog)?;
+    (0..page.cell_count())
+        .map(|i| {
+            let (name, value) = page.get_leaf_entry(i)?;
+            let (root, height) = decode_entry(value, catalog)?;
+            Ok((name.to_vec(), root, height))
+        })
+        .collect()
+}
+
+/// Fail unless the catalog page is a leaf
+fn check_catalog(page: &SlottedPage, catalog: PageId) -> Result<()> {
+    if !page.is_leaf() {
+        return Err(StorageError::corruption(format!(
+            "catalog page {} is a {:?} page",
+            catalog,
+            page.page_type()
+        )));
+    }
+    Ok(())
+}
+
+/// Decode a catalog entry's root page and height
+fn decode_entry(value: &[u8], catalog: PageId) -> Result<(PageId, u32)> {
+    if value.len() != ENTRY_SIZE {
+        return Err(StorageError::corruption(format!(
+            "catalog page {} has a {}-byte entry",
+            catalog,
+            value.len()
+        )));
+    }
+    let root = PageId::from_be_bytes(value[..4].try_into().unwrap());
+    let height = u32::from_be_bytes(value[4..].try_into().unwrap());
+    Ok((root, height))
+}
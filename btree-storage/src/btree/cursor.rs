@@ -1,31 +1,57 @@
 //! B-tree cursor for iteration.
 //!
 //! The cursor provides a way to iterate over key-value pairs in the B-tree
-//! in sorted order. It maintains a stack of (page_id, cell_index) pairs
-//! representing the current position in the tree.
+//! in sorted order. It maintains a stack of (page_id, index) pairs
+//! representing the current position in the tree: a child index for each
+//! interior page on the path and a cell index for the leaf at the top.
+//!
+//! Moving forward off a leaf that links to the next one follows the link
+//! instead of climbing, leaving only the leaf on the stack. The path is
+//! rebuilt from the root if a step back needs it. Leaves written before
+//! links existed are left by climbing.
+//!
+//! No page latches are held between calls; each step fetches the pages it
+//! needs and releases them before returning.
 
+use super::overflow;
+use super::tree::now_millis;
 use crate::buffer::BufferPool;
 use crate::error::Result;
-use crate::types::PageId;
+use crate::page::SlottedPage;
+use crate::types::{KeyOrder, PageId};
 use std::sync::Arc;
 
 /// A cursor for iterating over B-tree entries
-pub struct Cursor<P: BufferPool> {
+pub struct Cursor<P: BufferPool + ?Sized> {
     /// The buffer pool for page access
     buffer_pool: Arc<P>,
-    /// Stack of (page_id, cell_index) representing path to current position
+    /// Root of the tree, for rebuilding the path
+    root_page: PageId,
+    /// Stack of (page_id, index) representing path to current position
+    ///
+    /// For interior pages the index selects a child in key order: 0 is
+    /// `right_child` (keys below the first separator) and `i + 1` is the
+    /// left child of cell `i`. For the leaf it is the cell index. After
+    /// following a leaf link only the leaf is left.
     stack: Vec<(PageId, usize)>,
     /// Whether the cursor is positioned at a valid entry
     valid: bool,
+    /// Exclusive upper bound; the cursor becomes invalid on reaching it
+    end_bound: Option<Vec<u8>>,
+    /// Order the tree sorts its keys in, for seeks and the end bound
+    order: KeyOrder,
 }
 
-impl<P: BufferPool> Cursor<P> {
+impl<P: BufferPool + ?Sized> Cursor<P> {
     /// Create a new cursor starting at the first entry
     pub fn new(buffer_pool: Arc<P>, root_page: PageId) -> Result<Self> {
         let mut cursor = Self {
             buffer_pool,
+            root_page,
             stack: Vec::new(),
             valid: false,
+            end_bound: None,
+            order: KeyOrder::Bytewise,
         };
 
         if root_page.value() != 0 {
@@ -37,10 +63,23 @@ impl<P: BufferPool> Cursor<P> {
 
     /// Create a cursor positioned at a specific key (or the first key >= target)
     pub fn seek(buffer_pool: Arc<P>, root_page: PageId, key: &[u8]) -> Result<Self> {
+        Self::seek_by(buffer_pool, root_page, key, KeyOrder::Bytewise)
+    }
+
+    /// Create a cursor positioned at a key in a tree sorted in `order`
+    pub fn seek_by(
+        buffer_pool: Arc<P>,
+        root_page: PageId,
+        key: &[u8],
+        order: KeyOrder,
+    ) -> Result<Self> {
         let mut cursor = Self {
             buffer_pool,
+            root_page,
             stack: Vec::new(),
             valid: false,
+            end_bound: None,
+            order,
         };
 
         if root_page.value() != 0 {
@@ -50,6 +89,66 @@ impl<P: BufferPool> Cursor<P> {
         Ok(cursor)
     }
 
+    /// Create a cursor over `[start, end)`, positioned at the first entry in
+    /// the range
+    ///
+    /// A missing `start` begins at the first entry, a missing `end` runs to
+    /// the last. Once the cursor reaches `end` it becomes invalid, so
+    /// `current` returns `None` and `next` returns false.
+    pub fn seek_range(
+        buffer_pool: Arc<P>,
+        root_page: PageId,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+    ) -> Result<Self> {
+        Self::seek_range_by(buffer_pool, root_page, start, end, KeyOrder::Bytewise)
+    }
+
+    /// Create a cursor over `[start, end)` in a tree sorted in `order`
+    pub fn seek_range_by(
+        buffer_pool: Arc<P>,
+        root_page: PageId,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        order: KeyOrder,
+    ) -> Result<Self> {
+        let mut cursor = Self {
+            buffer_pool,
+            root_page,
+            stack: Vec::new(),
+            valid: false,
+            end_bound: end.map(<[u8]>::to_vec),
+            order,
+        };
+
+        if root_page.value() != 0 {
+            match start {
+                Some(key) => cursor.seek_to_key(root_page, key)?,
+                None => cursor.seek_to_first(root_page)?,
+            }
+        }
+
+        Ok(cursor)
+    }
+
+    /// Create a cursor positioned at the last entry
+    pub fn last(buffer_pool: Arc<P>, root_page: PageId) -> Result<Self> {
+        let mut cursor = Self {
+            buffer_pool,
+            root_page,
+            stack: Vec::new(),
+            valid: false,
+            end_bound: None,
+            order: KeyOrder::Bytewise,
+        };
+
+        if root_page.value() != 0 {
+            cursor.seek_to_last(root_page)?;
+        }
+
+        Ok(cursor)
+    }
+
     /// Check if the cursor is positioned at a valid entry
     pub fn is_valid(&self) -> bool {
         self.valid
@@ -69,8 +168,9 @@ impl<P: BufferPool> Cursor<P> {
             return Ok(None);
         }
 
-        let cell = page.get_cell(*cell_idx)?;
-        Ok(Some((cell.key, cell.value)))
+        let (key, value, meta) = page.get_leaf_ref(*cell_idx)?;
+        let value = overflow::load(&*self.buffer_pool, value, &meta)?;
+        Ok(Some((key.to_vec(), value.into_owned())))
     }
 
     /// Move to the next entry
@@ -80,134 +180,421 @@ impl<P: BufferPool> Cursor<P> {
             return Ok(false);
         }
 
-        let (page_id, cell_idx) = self.stack.last_mut().unwrap();
-        let guard = self.buffer_pool.fetch_page(*page_id)?;
-        let page = guard.read();
+        self.stack.last_mut().unwrap().1 += 1;
+        self.settle()
+    }
 
-        // Move to next cell in current page
-        *cell_idx += 1;
+    /// Move to the previous entry
+    ///
+    /// Returns false, invalidating the cursor, when called at the first
+    /// entry.
+    pub fn prev(&mut self) -> Result<bool> {
+        if !self.valid || self.stack.is_empty() {
+            return Ok(false);
+        }
 
-        if *cell_idx < page.cell_count() {
-            // Still have cells in this page
-            return Ok(true);
+        if self.stack.len() == 1 && self.stack[0].0 != self.root_page {
+            self.restore_path()?;
         }
+        self.settle_back()
+    }
 
-        // Need to move to next leaf page
-        // For now, we pop and move up the tree
-        drop(page);
-        drop(guard);
-        self.stack.pop();
+    /// Seek to the first entry in the tree
+    fn seek_to_first(&mut self, root_page: PageId) -> Result<()> {
+        self.descend_to_leftmost(root_page)?;
+        self.settle()?;
+        Ok(())
+    }
+
+    /// Descend to the leftmost leaf starting from a page
+    ///
+    /// Pushes every page on the way, leaving the cursor at cell 0 of the
+    /// leaf, which may be empty.
+    fn descend_to_leftmost(&mut self, page_id: PageId) -> Result<()> {
+        let mut current = page_id;
 
-        while let Some((parent_page_id, parent_idx)) = self.stack.last_mut() {
-            let guard = self.buffer_pool.fetch_page(*parent_page_id)?;
+        loop {
+            let guard = self.buffer_pool.fetch_page(current)?;
             let page = guard.read();
 
-            if page.is_interior() {
-                // Try to move to next child
-                *parent_idx += 1;
+            self.stack.push((current, 0));
+            if page.is_leaf() {
+                return Ok(());
+            }
+            current = page.right_child();
+        }
+    }
 
-                if *parent_idx < page.cell_count() {
-                    // Go to left child of next separator
-                    let cell = page.get_cell(*parent_idx)?;
-                    drop(page);
-                    drop(guard);
-                    return self.descend_to_leftmost(cell.left_child);
-                } else {
-                    // Go to right child
-                    let right_child = page.right_child();
-                    drop(page);
-                    drop(guard);
-                    return self.descend_to_leftmost(right_child);
+    /// Move forward to the nearest entry at or after the current position
+    ///
+    /// Follows the link out of an exhausted leaf if it has one, and
+    /// otherwise climbs out of exhausted leaves and interior pages and
+    /// descends into the next subtree, skipping expired entries. Returns
+    /// false, invalidating the cursor, once the tree is exhausted.
+    fn settle(&mut self) -> Result<bool> {
+        let now = now_millis();
+        loop {
+            let Some(&(page_id, idx)) = self.stack.last() else {
+                self.valid = false;
+                return Ok(false);
+            };
+
+            let guard = self.buffer_pool.fetch_page(page_id)?;
+            let page = guard.read();
+
+            if page.is_leaf() {
+                if idx < page.cell_count() {
+                    let (key, _, meta) = page.get_leaf_ref(idx)?;
+                    if meta.is_expired(now) {
+                        self.stack.last_mut().unwrap().1 = idx + 1;
+                        continue;
+                    }
+                    self.valid = self
+                        .end_bound
+                        .as_deref()
+                        .is_none_or(|end| self.order.compare(key, end).is_lt());
+                    return Ok(self.valid);
+                }
+                match page.next_leaf() {
+                    Some(next) if next.value() == 0 => {
+                        self.valid = false;
+                        return Ok(false);
+                    }
+                    Some(next) => {
+                        self.stack.clear();
+                        self.stack.push((next, 0));
+                    }
+                    None => {
+                        self.stack.pop();
+                    }
                 }
+                continue;
             }
 
-            drop(page);
-            drop(guard);
-            self.stack.pop();
+            // An interior page's index is the child just visited
+            if idx < page.cell_count() {
+                let child = Self::child_at(&page, idx + 1)?;
+                drop(page);
+                drop(guard);
+                self.stack.last_mut().unwrap().1 = idx + 1;
+                self.descend_to_leftmost(child)?;
+            } else {
+                self.stack.pop();
+            }
         }
-
-        self.valid = false;
-        Ok(false)
     }
 
-    /// Seek to the first entry in the tree
-    fn seek_to_first(&mut self, root_page: PageId) -> Result<()> {
-        self.descend_to_leftmost(root_page)?;
+    /// Seek to the last entry in the tree
+    fn seek_to_last(&mut self, root_page: PageId) -> Result<()> {
+        self.descend_to_rightmost(root_page)?;
+        self.settle_back()?;
         Ok(())
     }
 
-    /// Descend to the leftmost leaf entry starting from a page
-    fn descend_to_leftmost(&mut self, page_id: PageId) -> Result<bool> {
+    /// Descend to the rightmost leaf starting from a page
+    ///
+    /// Pushes every page on the way, leaving the cursor one past the last
+    /// cell of the leaf so that `settle_back` steps onto the last entry.
+    fn descend_to_rightmost(&mut self, page_id: PageId) -> Result<()> {
         let mut current = page_id;
 
         loop {
             let guard = self.buffer_pool.fetch_page(current)?;
             let page = guard.read();
 
+            let count = page.cell_count();
+            self.stack.push((current, count));
             if page.is_leaf() {
-                if page.cell_count() > 0 {
-                    self.stack.push((current, 0));
-                    self.valid = true;
-                    return Ok(true);
-                } else {
-                    self.valid = false;
-                    return Ok(false);
-                }
+                return Ok(());
+            }
+            current = Self::child_at(&page, count)?;
+        }
+    }
+
+    /// Move back to the nearest entry before the current position
+    ///
+    /// Mirrors `settle`: climbs out of pages with nothing left before the
+    /// current index and descends into the previous subtree. Returns false,
+    /// invalidating the cursor, once the start of the tree is passed.
+    fn settle_back(&mut self) -> Result<bool> {
+        let now = now_millis();
+        loop {
+            let Some(&(page_id, idx)) = self.stack.last() else {
+                self.valid = false;
+                return Ok(false);
+            };
+            if idx == 0 {
+                self.stack.pop();
+                continue;
             }
 
-            // Interior page - go to leftmost child
-            if page.cell_count() > 0 {
-                let cell = page.get_cell(0)?;
-                self.stack.push((current, 0));
-                current = cell.left_child;
-            } else {
-                // Only right child
-                current = page.right_child();
+            let guard = self.buffer_pool.fetch_page(page_id)?;
+            let page = guard.read();
+            self.stack.last_mut().unwrap().1 = idx - 1;
+
+            if page.is_leaf() {
+                if page.get_leaf_ref(idx - 1)?.2.is_expired(now) {
+                    continue;
+                }
+                self.valid = true;
+                return Ok(true);
             }
+
+            let child = Self::child_at(&page, idx - 1)?;
+            drop(page);
+            drop(guard);
+            self.descend_to_rightmost(child)?;
+        }
+    }
+
+    /// Get the child of an interior page at a key-ordered child index
+    fn child_at(page: &SlottedPage, index: usize) -> Result<PageId> {
+        match index {
+            0 => Ok(page.right_child()),
+            i => Ok(page.get_cell(i - 1)?.left_child),
         }
     }
 
     /// Seek to a specific key (or first key >= target)
     fn seek_to_key(&mut self, root_page: PageId, key: &[u8]) -> Result<()> {
+        self.descend_to_key(root_page, key)?;
+        self.settle()?;
+        Ok(())
+    }
+
+    /// Rebuild the stack from the root down to the current entry
+    fn restore_path(&mut self) -> Result<()> {
+        let Some(&(page_id, idx)) = self.stack.last() else {
+            return Ok(());
+        };
+        let key = {
+            let guard = self.buffer_pool.fetch_page(page_id)?;
+            let page = guard.read();
+            page.get_leaf_entry(idx)?.0.to_vec()
+        };
+        self.stack.clear();
+        self.descend_to_key(self.root_page, &key)
+    }
+
+    /// Push the path to the first key >= target, which may be one past the
+    /// end of its leaf
+    fn descend_to_key(&mut self, root_page: PageId, key: &[u8]) -> Result<()> {
         let mut current = root_page;
 
         loop {
             let guard = self.buffer_pool.fetch_page(current)?;
             let page = guard.read();
 
-            if page.is_leaf() {
-                // Binary search for the key or first key >= target
-                let cell_count = page.cell_count();
-                for i in 0..cell_count {
-                    let cell = page.get_cell(i)?;
-                    if cell.key.as_slice() >= key {
-                        self.stack.push((current, i));
-                        self.valid = true;
-                        return Ok(());
-                    }
+            // Count the entries below `key`: on a leaf that is the position
+            // of the first key >= target, on an interior page the number of
+            // separators <= key is the index of the child to descend into
+            let (mut low, mut high) = (0, page.cell_count());
+            while low < high {
+                let mid = low + (high - low) / 2;
+                let below = if page.is_leaf() {
+                    self.order.compare(page.get_leaf_entry(mid)?.0, key).is_lt()
+                } else {
+                    self.order.compare(&page.get_cell(mid)?.key, key).is_le()
+                };
+                if below {
+                    low = mid + 1;
+                } else {
+                    high = mid;
                 }
-                // No key >= target in this leaf
-                self.valid = false;
+            }
+
+            self.stack.push((current, low));
+            if page.is_leaf() {
                 return Ok(());
             }
+            current = Self::child_at(&page, low)?;
+        }
+    }
+}
 
-            // Interior page - find correct child
-            let child = page.find_child(key)?;
-            let cell_count = page.cell_count();
+/// Yields the current entry, then moves past it
+///
+/// The inherent `next` still takes precedence in method-call syntax; this
+/// impl is what `for` loops and iterator adapters use. Iteration stops
+/// after the first error.
+impl<P: BufferPool + ?Sized> Iterator for Cursor<P> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = match self.current() {
+            Ok(entry) => entry?,
+            Err(e) => {
+                self.valid = false;
+                return Some(Err(e));
+            }
+        };
+        if let Err(e) = Cursor::next(self) {
+            self.valid = false;
+            return Some(Err(e));
+        }
+        Some(Ok(entry))
+    }
+}
 
-            // Find which separator we passed (for stack tracking)
-            for i in 0..cell_count {
-                let cell = page.get_cell(i)?;
-                if key < cell.key.as_slice() {
-                    self.stack.push((current, i));
-                    break;
-                }
-                if i == cell_count - 1 {
-                    self.stack.push((current, cell_count));
-                }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::BTree;
+    use crate::buffer::BufferPoolImpl;
+    use crate::storage::DiskManagerImpl;
+    use crate::types::BTreeConfig;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_cursor_walks_in_order() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let dm = Arc::new(DiskManagerImpl::open(&dir.path().join("test.db"), false)?);
+        let pool = Arc::new(BufferPoolImpl::new(dm, 100));
+        let mut btree = BTree::with_config(pool.clone(), BTreeConfig::new(3, 2))?;
+
+        for i in (0..300).rev() {
+            btree.put(format!("key{:03}", i * 2).as_bytes(), b"v")?;
+        }
+        let expected: Vec<Vec<u8>> = btree.scan(None, None)?.into_iter().map(|(k, _)| k).collect();
+
+        let mut cursor = Cursor::new(pool.clone(), btree.root_page())?;
+        let mut keys = Vec::new();
+        while let Some((key, _)) = cursor.current()? {
+            keys.push(key);
+            cursor.next()?;
+        }
+        assert_eq!(keys, expected);
+        assert!(!cursor.is_valid());
+
+        // Seeking lands on the key itself or the next one up
+        let cursor = Cursor::seek(pool.clone(), btree.root_page(), b"key101")?;
+        assert_eq!(cursor.current()?.unwrap().0, b"key102");
+        let cursor = Cursor::seek(pool.clone(), btree.root_page(), b"key400")?;
+        assert_eq!(cursor.current()?.unwrap().0, b"key400");
+        let cursor = Cursor::seek(pool, btree.root_page(), b"key999")?;
+        assert!(!cursor.is_valid());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cursor_stops_at_end_bound() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let dm = Arc::new(DiskManagerImpl::open(&dir.path().join("test.db"), false)?);
+        let pool = Arc::new(BufferPoolImpl::new(dm, 100));
+        let mut btree = BTree::new(pool.clone())?;
+
+        for key in [b"a", b"b", b"c", b"d", b"e"] {
+            btree.put(key, b"v")?;
+        }
+
+        let mut cursor = Cursor::seek_range(pool, btree.root_page(), Some(b"b"), Some(b"d"))?;
+        assert_eq!(cursor.current()?.unwrap().0, b"b");
+        assert!(cursor.next()?);
+        assert_eq!(cursor.current()?.unwrap().0, b"c");
+        assert!(!cursor.next()?);
+        assert!(!cursor.is_valid());
+        assert_eq!(cursor.current()?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cursor_walks_backward() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let dm = Arc::new(DiskManagerImpl::open(&dir.path().join("test.db"), false)?);
+        let pool = Arc::new(BufferPoolImpl::new(dm, 100));
+        let mut btree = BTree::with_config(pool.clone(), BTreeConfig::new(3, 2))?;
+
+        for i in 0..300 {
+            btree.put(format!("key{:03}", i).as_bytes(), b"v")?;
+        }
+        assert!(btree.height() > 3);
+        let mut expected: Vec<Vec<u8>> = btree.scan(None, None)?.into_iter().map(|(k, _)| k).collect();
+        expected.reverse();
+
+        // Stepping back crosses every leaf and interior boundary
+        let mut cursor = Cursor::last(pool.clone(), btree.root_page())?;
+        let mut keys = Vec::new();
+        while let Some((key, _)) = cursor.current()? {
+            keys.push(key);
+            cursor.prev()?;
+        }
+        assert_eq!(keys, expected);
+
+        // prev at the first entry invalidates the cursor
+        let mut cursor = Cursor::new(pool.clone(), btree.root_page())?;
+        assert!(!cursor.prev()?);
+        assert!(!cursor.is_valid());
+
+        // next and prev retrace each other
+        let mut cursor = Cursor::seek(pool, btree.root_page(), b"key150")?;
+        assert!(cursor.prev()?);
+        assert_eq!(cursor.current()?.unwrap().0, b"key149");
+        assert!(cursor.next()?);
+        assert!(cursor.next()?);
+        assert_eq!(cursor.current()?.unwrap().0, b"key151");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cursor_follows_leaf_links() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let dm = Arc::new(DiskManagerImpl::open(&dir.path().join("test.db"), false)?);
+        let pool = Arc::new(BufferPoolImpl::new(dm, 100));
+        let mut btree = BTree::new(pool.clone())?;
+
+        for i in (0..1000).rev() {
+            btree.put(format!("key{:04}", i).as_bytes(), &[b'v'; 200])?;
+        }
+        btree.delete_range(Some(b"key0200"), Some(b"key0700"))?;
+        let expected: Vec<Vec<u8>> = btree.scan(None, None)?.into_iter().map(|(k, _)| k).collect();
+        assert_eq!(expected.len(), 500);
+
+        // Crossing a leaf follows its link, leaving only the leaf stacked
+        let mut cursor = Cursor::new(pool.clone(), btree.root_page())?;
+        let mut keys = Vec::new();
+        while let Some((key, _)) = cursor.current()? {
+            keys.push(key);
+            cursor.next()?;
+            if cursor.is_valid() && keys.len() > 10 {
+                assert_eq!(cursor.stack.len(), 1);
             }
+        }
+        assert_eq!(keys, expected);
 
-            current = child;
+        // Stepping back rebuilds the path
+        let mut cursor = Cursor::seek(pool, btree.root_page(), b"key0150")?;
+        for _ in 0..50 {
+            assert!(cursor.next()?);
         }
+        assert_eq!(cursor.current()?.unwrap().0, b"key0700");
+        assert!(cursor.prev()?);
+        assert_eq!(cursor.current()?.unwrap().0, b"key0199");
+        Ok(())
+    }
+
+    #[test]
+    fn test_cursor_as_iterator() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let dm = Arc::new(DiskManagerImpl::open(&dir.path().join("test.db"), false)?);
+        let pool = Arc::new(BufferPoolImpl::new(dm, 100));
+        let mut btree = BTree::new(pool.clone())?;
+
+        for i in 0..100 {
+            btree.put(format!("key{:03}", i).as_bytes(), &[b'v'; 200])?;
+        }
+        let expected = btree.scan(None, None)?;
+
+        let cursor = Cursor::new(pool.clone(), btree.root_page())?;
+        let entries = cursor.collect::<Result<Vec<_>>>()?;
+        assert_eq!(entries, expected);
+
+        let cursor = Cursor::seek(pool, btree.root_page(), b"key090")?;
+        let keys: Vec<Vec<u8>> = cursor.map(|entry| entry.map(|(k, _)| k)).collect::<Result<_>>()?;
+        assert_eq!(keys.len(), 10);
+        assert_eq!(keys[0], b"key090");
+        Ok(())
     }
 }
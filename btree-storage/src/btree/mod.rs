@@ -6,8 +6,14 @@
 //! - Deletions (delete)
 //! - Range scans
 
+pub(crate) mod catalog;
 mod cursor;
+mod overflow;
+pub(crate) mod snapshot;
 mod tree;
 
 pub use cursor::Cursor;
-pub use tree::BTree;
+pub use overflow::LazyValue;
+pub use tree::{
+    BTree, CachedGet, MaintenanceReport, PutResult, StructureStats, Violation, VerifyReport,
+};
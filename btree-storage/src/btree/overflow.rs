@@ -0,0 +1,205 @@
+//! Overflow chains for large values.
+//!
+//! A value longer than `MAX_INLINE_VALUE_SIZE` keeps its first
+//! `MAX_INLINE_VALUE_SIZE` bytes in the leaf cell and spills the rest into
+//! a chain of overflow pages. The cell's metadata records the first page of
+//! the chain and the length of the whole value.
+
+use crate::buffer::BufferPool;
+use crate::error::{Result, StorageError};
+use crate::page::{CellMeta, OverflowRef, SlottedPage, OVERFLOW_PAGE_CAPACITY};
+use crate::types::{PageId, MAX_INLINE_VALUE_SIZE};
+use std::borrow::Cow;
+
+/// Write the part of `value` past the inline limit to a new overflow chain
+///
+/// Returns the bytes to store in the leaf cell and a reference to the
+/// chain, or `None` if the value fits in the cell.
+pub(crate) fn spill<'a, B: BufferPool + ?Sized>(
+    pool: &B,
+    value: &'a [u8],
+) -> Result<(&'a [u8], Option<OverflowRef>)> {
+    if value.len() <= MAX_INLINE_VALUE_SIZE {
+        return Ok((value, None));
+    }
+    let (inline, rest) = value.split_at(MAX_INLINE_VALUE_SIZE);
+
+    // Write back to front so every page knows its successor
+    let mut next = PageId::HEADER;
+    let mut written = Vec::new();
+    for chunk in rest.chunks(OVERFLOW_PAGE_CAPACITY).rev() {
+        let page = match SlottedPage::new_overflow(next, chunk) {
+            Ok(page) => page,
+            Err(e) => return Err(release(pool, &written, e)),
+        };
+        let (page_id, guard) = match pool.new_page() {
+            Ok(allocated) => allocated,
+            Err(e) => return Err(release(pool, &written, e)),
+        };
+        *guard.write() = page;
+        written.push(page_id);
+        next = page_id;
+    }
+
+    Ok((
+        inline,
+        Some(OverflowRef {
+            first_page: next,
+            total_len: value.len() as u32,
+        }),
+    ))
+}
+
+/// Free the pages of a partly written chain and hand back the error
+fn release<B: BufferPool + ?Sized>(pool: &B, pages: &[PageId], err: StorageError) -> StorageError {
+    for &page_id in pages {
+        let _ = pool.free_page(page_id);
+    }
+    err
+}
+
+/// Get the whole value of a leaf cell, reading its overflow chain if it
+/// has one
+pub(crate) fn load<'a, B: BufferPool + ?Sized>(
+    pool: &B,
+    inline: &'a [u8],
+    meta: &CellMeta,
+) -> Result<Cow<'a, [u8]>> {
+    let Some(overflow) = meta.overflow else {
+        return Ok(Cow::Borrowed(inline));
+    };
+
+    let mut value = Vec::with_capacity(overflow.total_len as usize);
+    value.extend_from_slice(inline);
+    walk(pool, inline.len(), overflow, |_, payload| {
+        value.extend_from_slice(payload)
+    })?;
+    Ok(Cow::Owned(value))
+}
+
+/// A value seen by a scan, whose overflow chain is only read when asked
+pub struct LazyValue<'a> {
+    pool: &'a dyn BufferPool,
+    inline: &'a [u8],
+    meta: CellMeta,
+}
+
+impl<'a> LazyValue<'a> {
+    pub(crate) fn new(pool: &'a dyn BufferPool, inline: &'a [u8], meta: CellMeta) -> Self {
+        Self { pool, inline, meta }
+    }
+
+    /// Get the length of the whole value without reading its chain
+    pub fn len(&self) -> usize {
+        match self.meta.overflow {
+            Some(overflow) => overflow.total_len as usize,
+            None => self.inline.len(),
+        }
+    }
+
+    /// Check whether the value is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Check whether reading the value needs its overflow chain
+    pub fn is_overflow(&self) -> bool {
+        self.meta.overflow.is_some()
+    }
+
+    /// Get the metadata of the value's cell
+    pub(crate) fn meta(&self) -> CellMeta {
+        self.meta
+    }
+
+    /// Get the whole value, reading its overflow chain if it has one
+    pub fn load(&self) -> Result<Cow<'a, [u8]>> {
+        load(self.pool, self.inline, &self.meta)
+    }
+}
+
+/// Get the whole value of a leaf cell using only resident pages
+///
+/// Returns `None` as soon as a page of the chain is not in the buffer pool.
+pub(crate) fn load_cached<B: BufferPool + ?Sized>(
+    pool: &B,
+    inline: &[u8],
+    meta: &CellMeta,
+) -> Result<Option<Vec<u8>>> {
+    let Some(overflow) = meta.overflow else {
+        return Ok(Some(inline.to_vec()));
+    };
+
+    let mut value = inline.to_vec();
+    let mut remaining = (overflow.total_len as usize).saturating_sub(inline.len());
+    let mut page_id = overflow.first_page;
+    while remaining > 0 {
+        let Some(guard) = pool.fetch_cached(page_id) else {
+            return Ok(None);
+        };
+        let page = guard.read();
+        let payload = checked_payload(&page, page_id, remaining)?;
+        value.extend_from_slice(payload);
+        remaining -= payload.len();
+        page_id = page.overflow_next()?;
+    }
+    Ok(Some(value))
+}
+
+/// Get the pages of an overflow chain in chain order
+pub(crate) fn chain_pages<B: BufferPool + ?Sized>(
+    pool: &B,
+    inline_len: usize,
+    overflow: OverflowRef,
+) -> Result<Vec<PageId>> {
+    let mut pages = Vec::new();
+    walk(pool, inline_len, overflow, |page_id, _| pages.push(page_id))?;
+    Ok(pages)
+}
+
+/// Visit each page of a chain with its payload
+///
+/// The chain must hold exactly the bytes of the value past the `inline_len`
+/// bytes kept in the cell.
+fn walk<B: BufferPool + ?Sized, F>(
+    pool: &B,
+    inline_len: usize,
+    overflow: OverflowRef,
+    mut visit: F,
+) -> Result<()>
+where
+    F: FnMut(PageId, &[u8]),
+{
+    let mut remaining = (overflow.total_len as usize).saturating_sub(inline_len);
+    let mut page_id = overflow.first_page;
+    while remaining > 0 {
+        let guard = pool.fetch_page(page_id)?;
+        let page = guard.read();
+        let payload = checked_payload(&page, page_id, remaining)?;
+        visit(page_id, payload);
+        remaining -= payload.len();
+        page_id = page.overflow_next()?;
+    }
+    if page_id != PageId::HEADER {
+        return Err(StorageError::corruption(format!(
+            "overflow chain continues to page {} past the end of its value",
+            page_id
+        )));
+    }
+    Ok(())
+}
+
+/// Get a chain page's payload, checking it moves the chain forward without
+/// running past the value
+fn checked_payload(page: &SlottedPage, page_id: PageId, remaining: usize) -> Result<&[u8]> {
+    let payload = page.overflow_payload()?;
+    if payload.is_empty() || payload.len() > remaining {
+        return Err(StorageError::corruption(format!(
+            "overflow page {} holds {} bytes with {} left in the value",
+            page_id,
+            payload.len(),
+            remaining
+        )));
+    }
+    Ok(payload)
+}
@@ -0,0 +1,229 @@
+//! Copy-on-write support for snapshots.
+//!
+//! A snapshot reads the tree from the root it was taken at without holding
+//! the tree lock. While any snapshot is live, a writer saves the current
+//! image of a page before first changing it, and pages the writer frees are
+//! held back from the free list, so everything reachable from a snapshot's
+//! root stays readable. Saved images are dropped with their snapshot; held
+//! back pages are freed once no snapshot is left.
+//!
+//! A writer saves an image while holding the page's write latch, and a
+//! snapshot reader checks for a saved image while holding the read latch,
+//! so a reader sees either the saved image or the page before any change.
+
+use super::overflow;
+use super::tree::now_millis;
+use crate::buffer::BufferPool;
+use crate::error::Result;
+use crate::page::{Cell, SlottedPage};
+use crate::types::{KeyOrder, PageId};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::{Arc, Weak};
+
+/// Images of pages as they were when a snapshot was taken
+#[derive(Default)]
+pub(crate) struct PageImages {
+    pages: Mutex<HashMap<PageId, SlottedPage>>,
+}
+
+/// The live snapshots of a tree
+#[derive(Default)]
+pub(crate) struct SnapshotRegistry {
+    /// Image stores of the snapshots taken so far; dead ones are pruned
+    live: Mutex<Vec<Weak<PageImages>>>,
+    /// Pages freed while a snapshot was live
+    deferred_frees: Mutex<Vec<PageId>>,
+}
+
+impl SnapshotRegistry {
+    /// Register a new snapshot and get its image store
+    pub(crate) fn register(&self) -> Arc<PageImages> {
+        let images = Arc::new(PageImages::default());
+        self.live.lock().push(Arc::downgrade(&images));
+        images
+    }
+
+    /// Check whether any snapshot is live
+    pub(crate) fn is_active(&self) -> bool {
+        let mut live = self.live.lock();
+        live.retain(|images| images.strong_count() > 0);
+        !live.is_empty()
+    }
+
+    /// Save the image of a page about to be changed for every live snapshot
+    /// that has not saved it yet
+    ///
+    /// Call with the page's write latch held.
+    pub(crate) fn preserve(&self, page_id: PageId, page: &SlottedPage) {
+        for images in self.live.lock().iter().filter_map(Weak::upgrade) {
+            images
+                .pages
+                .lock()
+                .entry(page_id)
+                .or_insert_with(|| page.clone());
+        }
+    }
+
+    /// Hold back a freed page until no snapshot is live
+    pub(crate) fn defer_free(&self, page_id: PageId) {
+        self.deferred_frees.lock().push(page_id);
+    }
+
+    /// Get the pages held back so far without releasing them
+    pub(crate) fn deferred_frees(&self) -> Vec<PageId> {
+        self.deferred_frees.lock().clone()
+    }
+
+    /// Take the pages held back so far, if no snapshot is live
+    pub(crate) fn take_deferred_frees(&self) -> Vec<PageId> {
+        if self.is_active() {
+            return Vec::new();
+        }
+        std::mem::take(&mut *self.deferred_frees.lock())
+    }
+}
+
+/// A read-only view of a tree as of a fixed root
+pub(crate) struct FrozenTree {
+    buffer_pool: Arc<dyn BufferPool>,
+    root_page: PageId,
+    images: Arc<PageImages>,
+    order: KeyOrder,
+}
+
+/// Where a lookup goes next from a page
+enum Step {
+    Descend(PageId),
+    Found(Option<Cell>),
+}
+
+impl FrozenTree {
+    /// Create a view of the tree rooted at `root_page`
+    pub(crate) fn new(
+        buffer_pool: Arc<dyn BufferPool>,
+        root_page: PageId,
+        images: Arc<PageImages>,
+        order: KeyOrder,
+    ) -> Self {
+        Self {
+            buffer_pool,
+            root_page,
+            images,
+            order,
+        }
+    }
+
+    /// Let go of the saved page images, ending the snapshot
+    pub(crate) fn close(&mut self) {
+        self.images = Arc::default();
+    }
+
+    /// Run `f` on a page as it was when the snapshot was taken
+    fn with_page<T>(&self, page_id: PageId, f: impl FnOnce(&SlottedPage) -> Result<T>) -> Result<T> {
+        let guard = self.buffer_pool.fetch_page(page_id)?;
+        let page = guard.read();
+        let images = self.images.pages.lock();
+        match images.get(&page_id) {
+            Some(image) => f(image),
+            None => f(&page),
+        }
+    }
+
+    /// Look up a key and return its value
+    pub(crate) fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        if self.root_page.value() == 0 {
+            return Ok(None);
+        }
+
+        let mut page_id = self.root_page;
+        let cell = loop {
+            let step = self.with_page(page_id, |page| {
+                if page.is_leaf() {
+                    return match page.search_by(key, self.order)? {
+                        Some(idx) => Ok(Step::Found(Some(page.get_cell(idx)?))),
+                        None => Ok(Step::Found(None)),
+                    };
+                }
+                Ok(Step::Descend(page.find_child_by(key, self.order)?))
+            })?;
+            match step {
+                Step::Descend(child_id) => page_id = child_id,
+                Step::Found(cell) => break cell,
+            }
+        };
+
+        match cell {
+            Some(cell) if !cell.meta.is_expired(now_millis()) => {
+                let value = overflow::load(&*self.buffer_pool, &cell.value, &cell.meta)?;
+                Ok(Some(value.into_owned()))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Get all key-value pairs in `[start, end)` in key order
+    pub(crate) fn scan(
+        &self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let mut results = Vec::new();
+        if self.root_page.value() != 0 {
+            self.scan_page(self.root_page, start, end, &mut results)?;
+        }
+        Ok(results)
+    }
+
+    /// Collect the entries in `[start, end)` under `page_id`
+    fn scan_page(
+        &self,
+        page_id: PageId,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        results: &mut Vec<(Vec<u8>, Vec<u8>)>,
+    ) -> Result<()> {
+        let order = self.order;
+        let in_range = |key: &[u8]| {
+            start.is_none_or(|s| order.compare(key, s).is_ge())
+                && end.is_none_or(|e| order.compare(key, e).is_lt())
+        };
+        let now = now_millis();
+
+        let (is_leaf, cells, leftmost) = self.with_page(page_id, |page| {
+            Ok((page.is_leaf(), page.get_all_cells()?, page.right_child()))
+        })?;
+
+        if is_leaf {
+            let live = |cell: &Cell| in_range(&cell.key) && !cell.meta.is_expired(now);
+            for cell in cells.into_iter().filter(live) {
+                let value = overflow::load(&*self.buffer_pool, &cell.value, &cell.meta)?;
+                results.push((cell.key, value.into_owned()));
+            }
+            return Ok(());
+        }
+
+        // Child i holds keys from separator i - 1 up to separator i
+        let mut lower: Option<&[u8]> = None;
+        let mut child_id = leftmost;
+        for i in 0..=cells.len() {
+            let upper = cells.get(i).map(|cell| cell.key.as_slice());
+            let below_end = match (end, lower) {
+                (Some(e), Some(lo)) => order.compare(lo, e).is_lt(),
+                _ => true,
+            };
+            let above_start = match (start, upper) {
+                (Some(s), Some(hi)) => order.compare(s, hi).is_lt(),
+                _ => true,
+            };
+            if below_end && above_start {
+                self.scan_page(child_id, start, end, results)?;
+            }
+            if let Some(cell) = cells.get(i) {
+                lower = Some(&cell.key);
+                child_id = cell.left_child;
+            }
+        }
+        Ok(())
+    }
+}
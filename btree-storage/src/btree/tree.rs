@@ -6,42 +6,266 @@
 //! - delete: Removals
 //! - scan: Range queries
 
-use crate::buffer::{BufferPool, BufferPoolImpl};
+use super::snapshot::{FrozenTree, SnapshotRegistry};
+use super::{catalog, overflow, Cursor, LazyValue};
+use crate::buffer::{BufferPool, PageGuardMut};
 use crate::error::{Result, StorageError};
-use crate::page::{Cell, SlottedPage};
-use crate::types::{BTreeConfig, PageId, MAX_KEY_SIZE, MAX_VALUE_SIZE};
+use crate::page::{BloomFilter, Cell, CellMeta, SlottedPage};
+use crate::types::{BTreeConfig, KeyOrder, PageId, MAX_KEY_SIZE, MAX_VALUE_SIZE, MIN_KEYS};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Result of a lookup that only reads pages already in the buffer pool
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CachedGet {
+    /// The key was found
+    Found(Vec<u8>),
+    /// The key does not exist
+    Absent,
+    /// A page on the lookup path is not resident
+    NotCached,
+}
+
+/// Whether a put added a new key or replaced an existing value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PutResult {
+    /// The key was not in the tree before
+    Inserted,
+    /// The key was already in the tree and its value was replaced
+    Updated,
+}
+
+/// Number of upcoming children a range scan reads ahead
+const SCAN_PREFETCH: usize = 4;
+
+/// What an insert below a page did
+struct InsertOutcome {
+    /// Separator and new page if the page split
+    split: Option<(Vec<u8>, PageId)>,
+    /// Whether the key was new
+    result: PutResult,
+    /// Number of expired entries removed from the leaf
+    expired: u64,
+}
+
+/// Get the current time in milliseconds since the Unix epoch
+pub(crate) fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Result of a compaction and integrity check pass
+#[derive(Debug, Clone, Default)]
+pub struct MaintenanceReport {
+    /// Number of tree pages visited
+    pub pages_visited: usize,
+    /// Number of pages that were defragmented
+    pub pages_compacted: usize,
+    /// Free bytes across visited pages before compaction
+    pub free_bytes_before: usize,
+    /// Free bytes across visited pages after compaction
+    pub free_bytes_after: usize,
+    /// Integrity problems found, one message per problem
+    pub problems: Vec<String>,
+}
+
+impl MaintenanceReport {
+    /// Check whether no integrity problems were found
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// Get the number of bytes reclaimed by compaction
+    pub fn reclaimed_bytes(&self) -> usize {
+        self.free_bytes_after.saturating_sub(self.free_bytes_before)
+    }
+}
+
+/// One broken invariant found by `BTree::verify`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Page the problem was found on
+    pub page_id: PageId,
+    /// What is wrong with the page
+    pub message: String,
+}
+
+/// Result of a read-only structural check of the tree
+#[derive(Debug, Clone, Default)]
+pub struct VerifyReport {
+    /// Number of tree pages visited
+    pub pages_visited: usize,
+    /// Every violation found, in the order the pages were visited
+    pub violations: Vec<Violation>,
+}
+
+impl VerifyReport {
+    /// Check whether no violations were found
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+/// Cumulative counts of structural changes to the tree
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StructureStats {
+    /// Number of leaf page splits
+    pub leaf_splits: u64,
+    /// Number of interior page splits
+    pub interior_splits: u64,
+    /// Number of times a new root was created above a split root
+    pub root_splits: u64,
+    /// Number of node merges
+    pub merges: u64,
+    /// Number of keys borrowed from a sibling
+    pub borrows: u64,
+}
+
+/// Atomic counters behind `StructureStats`
+#[derive(Debug, Default)]
+struct StructureCounters {
+    leaf_splits: AtomicU64,
+    interior_splits: AtomicU64,
+    root_splits: AtomicU64,
+    merges: AtomicU64,
+    borrows: AtomicU64,
+}
+
+impl StructureCounters {
+    /// Add one to a counter
+    fn bump(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get a snapshot of all counters
+    fn stats(&self) -> StructureStats {
+        StructureStats {
+            leaf_splits: self.leaf_splits.load(Ordering::Relaxed),
+            interior_splits: self.interior_splits.load(Ordering::Relaxed),
+            root_splits: self.root_splits.load(Ordering::Relaxed),
+            merges: self.merges.load(Ordering::Relaxed),
+            borrows: self.borrows.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Reset all counters to zero
+    fn reset(&self) {
+        for counter in [
+            &self.leaf_splits,
+            &self.interior_splits,
+            &self.root_splits,
+            &self.merges,
+            &self.borrows,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
 
 /// A disk-based B-tree
 pub struct BTree {
     /// Buffer pool for page access
-    buffer_pool: Arc<BufferPoolImpl>,
+    buffer_pool: Arc<dyn BufferPool>,
     /// Root page ID (0 means empty tree)
     root_page: PageId,
     /// Current height of the tree
     height: usize,
+    /// Number of entries, if known; `None` counts the leaves on demand
+    entry_count: Option<u64>,
     /// Configuration for node limits
     config: BTreeConfig,
+    /// Number of writes applied since the tree was opened
+    version: u64,
+    /// Whether root changes stay out of the file header (trees being built)
+    detached: bool,
+    /// Catalog page and name the root is recorded under, for named trees;
+    /// `None` records it in the file header
+    catalog_entry: Option<(PageId, Vec<u8>)>,
+    /// Bloom filters of leaves read so far, so lookups can rule out a leaf
+    /// without fetching it. Entries are dropped whenever the page is
+    /// fetched for writing.
+    leaf_filters: Mutex<HashMap<PageId, BloomFilter>>,
+    /// Split and merge counters
+    counters: StructureCounters,
+    /// Live snapshots, which writers must not disturb
+    snapshots: SnapshotRegistry,
 }
 
 impl BTree {
     /// Create a new B-tree or load existing one with default config
-    pub fn new(buffer_pool: Arc<BufferPoolImpl>) -> Result<Self> {
+    pub fn new(buffer_pool: Arc<dyn BufferPool>) -> Result<Self> {
         Self::with_config(buffer_pool, BTreeConfig::default())
     }
 
     /// Create a new B-tree with custom configuration
-    pub fn with_config(buffer_pool: Arc<BufferPoolImpl>, config: BTreeConfig) -> Result<Self> {
+    pub fn with_config(buffer_pool: Arc<dyn BufferPool>, config: BTreeConfig) -> Result<Self> {
         // Read root page and height from the persisted file header
         let root_page = buffer_pool.root_page();
         let height = buffer_pool.tree_height() as usize;
+        let entry_count = Self::stored_count(&*buffer_pool, root_page);
 
-        Ok(Self {
+        let mut tree = Self {
             buffer_pool,
             root_page,
             height,
+            entry_count,
             config,
-        })
+            version: 0,
+            detached: false,
+            catalog_entry: None,
+            leaf_filters: Mutex::new(HashMap::new()),
+            counters: StructureCounters::default(),
+            snapshots: SnapshotRegistry::default(),
+        };
+        if tree.entry_count.is_none() {
+            // Written before entries were counted
+            tree.entry_count = Some(tree.count_entries()?);
+        }
+        Ok(tree)
+    }
+
+    /// Get the entry count from the file header, or `None` if the file
+    /// predates it
+    fn stored_count(buffer_pool: &dyn BufferPool, root_page: PageId) -> Option<u64> {
+        match buffer_pool.entry_count() {
+            0 if root_page.value() != 0 => None,
+            count => Some(count),
+        }
+    }
+
+    /// Open the named tree recorded under `name` in a catalog page
+    ///
+    /// Root changes are recorded in the tree's catalog entry instead of the
+    /// file header. Returns `None` if the catalog has no such tree.
+    pub(crate) fn open_named(
+        buffer_pool: Arc<dyn BufferPool>,
+        config: BTreeConfig,
+        catalog: PageId,
+        name: &[u8],
+    ) -> Result<Option<Self>> {
+        let Some((root_page, height)) = catalog::lookup(&*buffer_pool, catalog, name)? else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            buffer_pool,
+            root_page,
+            height: height as usize,
+            entry_count: None,
+            config,
+            version: 0,
+            detached: false,
+            catalog_entry: Some((catalog, name.to_vec())),
+            leaf_filters: Mutex::new(HashMap::new()),
+            counters: StructureCounters::default(),
+            snapshots: SnapshotRegistry::default(),
+        }))
     }
 
     /// Get the configuration
@@ -49,6 +273,11 @@ impl BTree {
         &self.config
     }
 
+    /// Get the order the tree sorts its keys in
+    fn order(&self) -> KeyOrder {
+        self.config.key_order
+    }
+
     /// Get the height of the tree
     pub fn height(&self) -> usize {
         self.height
@@ -59,13 +288,334 @@ impl BTree {
         self.root_page
     }
 
+    /// Get the number of entries
+    ///
+    /// Kept up to date by every write for the main tree; named trees count
+    /// their leaves. Expired entries count until the next insert into their
+    /// leaf removes them, so this can exceed what a scan returns.
+    pub fn len(&self) -> Result<usize> {
+        match self.entry_count {
+            Some(count) => Ok(count as usize),
+            None => Ok(self.count_entries()? as usize),
+        }
+    }
+
+    /// Check whether the tree has no entries
+    ///
+    /// Unlike `len`, expired entries do not count, so this agrees with reads.
+    pub fn is_empty(&self) -> Result<bool> {
+        if self.entry_count == Some(0) {
+            return Ok(true);
+        }
+        Ok(self.first()?.is_none())
+    }
+
+    /// Count entries by summing the cell counts of all leaves
+    fn count_entries(&self) -> Result<u64> {
+        if self.root_page.value() == 0 {
+            return Ok(0);
+        }
+
+        let mut count = 0;
+        let mut stack = vec![self.root_page];
+        while let Some(page_id) = stack.pop() {
+            let guard = self.buffer_pool.fetch_page(page_id)?;
+            let page = guard.read();
+            if page.is_leaf() {
+                count += page.cell_count() as u64;
+                continue;
+            }
+            stack.push(page.right_child());
+            for i in 0..page.cell_count() {
+                stack.push(page.get_cell(i)?.left_child);
+            }
+        }
+        Ok(count)
+    }
+
+    /// Add `delta` to the entry count, if it is tracked
+    fn add_entries(&mut self, delta: i64) -> Result<()> {
+        let Some(count) = self.entry_count else {
+            return Ok(());
+        };
+        let count = count.saturating_add_signed(delta);
+        self.entry_count = Some(count);
+        if !self.detached && self.catalog_entry.is_none() {
+            self.buffer_pool.set_entry_count(count)?;
+        }
+        Ok(())
+    }
+
+    /// Get the cumulative split and merge counts
+    pub fn structure_stats(&self) -> StructureStats {
+        self.counters.stats()
+    }
+
+    /// Reset the split and merge counts, e.g. at the start of a benchmark
+    pub fn reset_structure_stats(&self) {
+        self.counters.reset();
+    }
+
+    /// Get the current version
+    ///
+    /// Starts at 0 when the tree is opened and goes up by one with every
+    /// write that changes its contents. Versions are not persisted.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
     /// Look up a key and return its value
     pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.get_with_meta(key)?.map(|(value, _)| value))
+    }
+
+    /// Look up a key and return its value along with its cell metadata
+    pub fn get_with_meta(&self, key: &[u8]) -> Result<Option<(Vec<u8>, CellMeta)>> {
+        if self.root_page.value() == 0 {
+            return Ok(None);
+        }
+
+        match self.search(self.root_page, key)? {
+            Some(cell) if !cell.meta.is_expired(now_millis()) => {
+                let value = overflow::load(&*self.buffer_pool, &cell.value, &cell.meta)?;
+                Ok(Some((value.into_owned(), cell.meta)))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Look up several keys, returning their values in the order given
+    ///
+    /// Keys are looked up in sorted order, each starting from the deepest
+    /// page on the previous lookup's path whose range still covers it, so
+    /// nearby keys share the walk down from the root.
+    pub fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>> {
+        let mut values = vec![None; keys.len()];
+        if self.root_page.value() == 0 {
+            return Ok(values);
+        }
+
+        let mut sorted: Vec<usize> = (0..keys.len()).collect();
+        sorted.sort_by(|&a, &b| self.config.compare_keys(keys[a], keys[b]));
+
+        // Pages on the last path, each with the exclusive upper bound of
+        // its key range; sorted keys only ever move past the upper bound
+        let mut path: Vec<(PageId, Option<Vec<u8>>)> = vec![(self.root_page, None)];
+        let now = now_millis();
+        for index in sorted {
+            let key = keys[index];
+            while path.len() > 1
+                && path
+                    .last()
+                    .and_then(|(_, upper)| upper.as_deref())
+                    .is_some_and(|upper| self.config.compare_keys(key, upper).is_ge())
+            {
+                path.pop();
+            }
+
+            if let Some(cell) = self.search_from_path(&mut path, key)? {
+                if !cell.meta.is_expired(now) {
+                    let value = overflow::load(&*self.buffer_pool, &cell.value, &cell.meta)?;
+                    values[index] = Some(value.into_owned());
+                }
+            }
+        }
+        Ok(values)
+    }
+
+    /// Search for a key starting from the last page on `path`
+    ///
+    /// Pushes each page descended into along with its upper bound.
+    fn search_from_path(
+        &self,
+        path: &mut Vec<(PageId, Option<Vec<u8>>)>,
+        key: &[u8],
+    ) -> Result<Option<Cell>> {
+        loop {
+            let (page_id, upper) = path.last().expect("path starts at the root");
+            let page_id = *page_id;
+            if let Some(filter) = self.leaf_filters.lock().get(&page_id) {
+                if !filter.may_contain(key) {
+                    return Ok(None);
+                }
+            }
+
+            let guard = self.buffer_pool.fetch_page(page_id)?;
+            let page = guard.read();
+            if page.is_leaf() {
+                if let Some(filter) = page.bloom_filter() {
+                    self.leaf_filters.lock().insert(page_id, filter);
+                    if !filter.may_contain(key) {
+                        return Ok(None);
+                    }
+                }
+                return match page.search_by(key, self.order())? {
+                    Some(idx) => Ok(Some(page.get_cell(idx)?)),
+                    None => Ok(None),
+                };
+            }
+
+            if let Some(value) = page.inline_value_by(key, self.order())? {
+                return Ok(Some(Cell::new_leaf(key.to_vec(), value)));
+            }
+
+            let index = page.child_index_by(key, self.order())?;
+            let child = match index {
+                0 => page.right_child(),
+                i => page.get_cell(i - 1)?.left_child,
+            };
+            let child_upper = if index < page.cell_count() {
+                Some(page.get_cell(index)?.key)
+            } else {
+                upper.clone()
+            };
+            path.push((child, child_upper));
+        }
+    }
+
+    /// Get the entry with the smallest key
+    ///
+    /// Descends the leftmost path, reading one page per level.
+    pub fn first(&self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        self.edge_entry(false)
+    }
+
+    /// Get the entry with the largest key
+    ///
+    /// Descends the rightmost path, reading one page per level.
+    pub fn last(&self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        self.edge_entry(true)
+    }
+
+    /// Get the first or last entry by descending the edge of the tree
+    fn edge_entry(&self, last: bool) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
         if self.root_page.value() == 0 {
             return Ok(None);
         }
 
-        self.search(self.root_page, key)
+        let mut page_id = self.root_page;
+        loop {
+            let guard = self.buffer_pool.fetch_page(page_id)?;
+            let page = guard.read();
+            if page.is_leaf() {
+                let count = page.cell_count();
+                if count > 0 {
+                    let index = if last { count - 1 } else { 0 };
+                    let (key, value, meta) = page.get_leaf_ref(index)?;
+                    if !meta.is_expired(now_millis()) {
+                        let value = overflow::load(&*self.buffer_pool, value, &meta)?;
+                        return Ok(Some((key.to_vec(), value.into_owned())));
+                    }
+                } else if page_id == self.root_page {
+                    return Ok(None);
+                }
+
+                // An empty leaf below the root or an expired entry; let a
+                // cursor step past it
+                drop(page);
+                drop(guard);
+                let pool = self.buffer_pool.clone();
+                let cursor = if last {
+                    Cursor::last(pool, self.root_page)?
+                } else {
+                    Cursor::new(pool, self.root_page)?
+                };
+                return cursor.current();
+            }
+
+            page_id = match page.cell_count() {
+                n if last && n > 0 => page.get_cell(n - 1)?.left_child,
+                _ => page.right_child(),
+            };
+        }
+    }
+
+    /// Get the overflow pages holding the spilled part of a key's value
+    ///
+    /// Returns the pages in chain order and the number of value bytes they
+    /// hold; a value stored entirely in its leaf cell has no pages. Returns
+    /// `None` if the key does not exist.
+    pub fn overflow_chain(&self, key: &[u8]) -> Result<Option<(Vec<PageId>, usize)>> {
+        if self.root_page.value() == 0 {
+            return Ok(None);
+        }
+
+        let Some(cell) = self.search(self.root_page, key)? else {
+            return Ok(None);
+        };
+        match cell.meta.overflow {
+            Some(chain) => {
+                let pages = overflow::chain_pages(&*self.buffer_pool, cell.value.len(), chain)?;
+                Ok(Some((pages, chain.total_len as usize - cell.value.len())))
+            }
+            None => Ok(Some((Vec::new(), 0))),
+        }
+    }
+
+    /// Get every overflow page referenced by a leaf cell
+    pub fn overflow_pages(&self) -> Result<Vec<PageId>> {
+        let mut pages = Vec::new();
+        if self.root_page.value() == 0 {
+            return Ok(pages);
+        }
+
+        let mut tree_pages = Vec::new();
+        self.collect_pages(self.root_page, &mut tree_pages)?;
+        for page_id in tree_pages {
+            let guard = self.buffer_pool.fetch_page(page_id)?;
+            let page = guard.read();
+            if !page.is_leaf() {
+                continue;
+            }
+            for i in 0..page.cell_count() {
+                let (_, value, meta) = page.get_leaf_ref(i)?;
+                if let Some(chain) = meta.overflow {
+                    pages.extend(overflow::chain_pages(&*self.buffer_pool, value.len(), chain)?);
+                }
+            }
+        }
+        Ok(pages)
+    }
+
+    /// Get a value by key without reading from disk
+    ///
+    /// Returns `CachedGet::NotCached` as soon as a page on the path is not
+    /// resident, leaving it unloaded.
+    pub fn get_cached(&self, key: &[u8]) -> Result<CachedGet> {
+        if self.root_page.value() == 0 {
+            return Ok(CachedGet::Absent);
+        }
+
+        let mut page_id = self.root_page;
+        loop {
+            let Some(guard) = self.buffer_pool.fetch_cached(page_id) else {
+                return Ok(CachedGet::NotCached);
+            };
+            let page = guard.read();
+
+            if page.is_leaf() {
+                if !page.may_contain(key) {
+                    return Ok(CachedGet::Absent);
+                }
+                let Some(idx) = page.search_by(key, self.order())? else {
+                    return Ok(CachedGet::Absent);
+                };
+                let (_, value, meta) = page.get_leaf_ref(idx)?;
+                if meta.is_expired(now_millis()) {
+                    return Ok(CachedGet::Absent);
+                }
+                return Ok(match overflow::load_cached(&*self.buffer_pool, value, &meta)? {
+                    Some(value) => CachedGet::Found(value),
+                    None => CachedGet::NotCached,
+                });
+            }
+
+            if let Some(value) = page.inline_value_by(key, self.order())? {
+                return Ok(CachedGet::Found(value));
+            }
+
+            page_id = page.find_child_by(key, self.order())?;
+        }
     }
 
     /// Debug search - traces the path through the tree
@@ -99,7 +649,7 @@ impl BTree {
                 }
             }
             
-            if let Some(idx) = page.search(key)? {
+            if let Some(idx) = page.search_by(key, self.order())? {
                 let cell = page.get_cell(idx)?;
                 trace.push(format!("  FOUND at index {}", idx));
                 return Ok(Some(cell.value));
@@ -118,7 +668,7 @@ impl BTree {
                 }
             }
             
-            let child_id = page.find_child(key)?;
+            let child_id = page.find_child_by(key, self.order())?;
             trace.push(format!("  -> Descending to child page {}", child_id.value()));
             drop(page);
             drop(guard);
@@ -127,337 +677,2080 @@ impl BTree {
     }
 
     /// Insert or update a key-value pair
-    pub fn put(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
-        // Validate key and value sizes
-        if key.len() > MAX_KEY_SIZE {
-            return Err(StorageError::KeyTooLarge {
-                size: key.len(),
-                max: MAX_KEY_SIZE,
-            });
-        }
-        if value.len() > MAX_VALUE_SIZE {
-            return Err(StorageError::ValueTooLarge {
-                size: value.len(),
-                max: MAX_VALUE_SIZE,
-            });
+    ///
+    /// Returns whether the key was new.
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> Result<PutResult> {
+        self.put_entry(key, value, CellMeta::default())
+    }
+
+    /// Insert a key-value pair only if the key is not already present
+    ///
+    /// Returns `false`, leaving the stored value alone, if the key exists.
+    /// Expired entries count as absent.
+    pub fn put_if_absent(&mut self, key: &[u8], value: &[u8]) -> Result<bool> {
+        self.validate_entry(key, value)?;
+        if self.root_page.value() != 0 {
+            if let Some(cell) = self.search(self.root_page, key)? {
+                if !cell.meta.is_expired(now_millis()) {
+                    return Ok(false);
+                }
+            }
         }
+        self.put_entry(key, value, CellMeta::default())?;
+        Ok(true)
+    }
+
+    /// Insert or update a key-value pair that expires after `ttl`
+    ///
+    /// Once expired the entry reads as absent; it is removed from its leaf
+    /// by the next insert into that leaf and counts toward `len` until then.
+    pub fn put_with_ttl(&mut self, key: &[u8], value: &[u8], ttl: Duration) -> Result<PutResult> {
+        let meta = CellMeta {
+            expires: Some(now_millis().saturating_add(ttl.as_millis() as u64)),
+            ..CellMeta::default()
+        };
+        self.put_entry(key, value, meta)
+    }
+
+    /// Insert or update a key-value pair, giving its cell the expiry and
+    /// modification time set in `meta`
+    pub(crate) fn put_entry(&mut self, key: &[u8], value: &[u8], meta: CellMeta) -> Result<PutResult> {
+        self.validate_entry(key, value)?;
+        self.version += 1;
 
         if self.root_page.value() == 0 {
             // Create root page
-            let (page_id, guard) = self.buffer_pool.new_page()?;
+            let (page_id, guard) = self.new_page()?;
             {
                 let mut page = guard.write();
-                let cell = Cell::new_leaf(key.to_vec(), value.to_vec());
-                page.insert_cell(&cell)?;
+                self.format_leaf(&mut page)?;
+                page.insert_cell_by(&self.new_leaf_cell(key, value, meta)?, self.order())?;
             }
+            drop(guard);
             self.root_page = page_id;
             self.height = 1;
-            // Persist the new root
-            self.buffer_pool.set_root_page(page_id, self.height as u32)?;
-            self.buffer_pool.flush_page(page_id)?;
-            return Ok(());
+            self.add_entries(1)?;
+            self.persist_root()?;
+            return Ok(PutResult::Inserted);
         }
 
         // Insert into existing tree
-        let result = self.insert_recursive(self.root_page, key, value)?;
+        let outcome = self.insert_recursive(self.root_page, key, value, meta, true)?;
+        let inserted = (outcome.result == PutResult::Inserted) as i64;
+        self.add_entries(inserted - outcome.expired as i64)?;
 
         // Handle root split
-        if let Some((separator, new_page_id)) = result {
+        if let Some((separator, new_page_id)) = outcome.split {
             self.split_root(separator, new_page_id)?;
         }
 
-        Ok(())
+        Ok(outcome.result)
     }
 
-    /// Delete a key from the tree
+    /// Append entries whose keys are strictly increasing
     ///
-    /// Returns true if the key was found and deleted.
-    pub fn delete(&mut self, key: &[u8]) -> Result<bool> {
-        if self.root_page.value() == 0 {
-            return Ok(false);
-        }
-
-        self.delete_recursive(self.root_page, key)
+    /// Entries that sort after every existing key are appended to the
+    /// rightmost leaf without re-descending from the root. A full leaf is
+    /// split rightward, leaving it full and starting a new leaf with the
+    /// appended entry. Entries that do not sort after the current maximum
+    /// go through the normal insert path. All entries are validated before
+    /// anything is written.
+    pub fn append_batch<K: AsRef<[u8]>, V: AsRef<[u8]>>(&mut self, entries: &[(K, V)]) -> Result<()> {
+        self.append_entries(entries, |(key, value)| {
+            (key.as_ref(), value.as_ref(), CellMeta::default())
+        })
     }
 
-    /// Scan a range of keys
-    ///
-    /// Returns all key-value pairs where start <= key < end.
-    /// If start is None, scan from the beginning.
-    /// If end is None, scan to the end.
-    pub fn scan(
-        &self,
-        start: Option<&[u8]>,
-        end: Option<&[u8]>,
-    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
-        if self.root_page.value() == 0 {
-            return Ok(Vec::new());
-        }
-
-        let mut results = Vec::new();
-        self.scan_recursive(self.root_page, start, end, &mut results)?;
-        Ok(results)
+    /// Append entries whose keys are strictly increasing, giving each cell
+    /// the expiry and modification time in its `CellMeta`
+    pub(crate) fn append_batch_with_meta(&mut self, entries: &[(Vec<u8>, Vec<u8>, CellMeta)]) -> Result<()> {
+        self.append_entries(entries, |(key, value, meta)| (key.as_slice(), value.as_slice(), *meta))
     }
 
-    /// Recursive search for a key
-    fn search(&self, page_id: PageId, key: &[u8]) -> Result<Option<Vec<u8>>> {
-        let guard = self.buffer_pool.fetch_page(page_id)?;
-        let page = guard.read();
+    /// Append entries, each split into key, value and metadata by `parts`
+    fn append_entries<E>(
+        &mut self,
+        entries: &[E],
+        parts: impl Fn(&E) -> (&[u8], &[u8], CellMeta),
+    ) -> Result<()> {
+        for entry in entries {
+            let (key, value, _) = parts(entry);
+            self.validate_entry(key, value)?;
+        }
+        if entries
+            .windows(2)
+            .any(|w| self.config.compare_keys(parts(&w[0]).0, parts(&w[1]).0).is_ge())
+        {
+            return Err(StorageError::invalid_operation(
+                "append_batch keys must be strictly increasing",
+            ));
+        }
 
-        if page.is_leaf() {
-            // Search in leaf
-            if let Some(idx) = page.search(key)? {
-                let cell = page.get_cell(idx)?;
-                return Ok(Some(cell.value));
+        // Insert normally until keys pass the current maximum
+        let mut rest = entries;
+        let mut path = Vec::new();
+        while let Some((entry, tail)) = rest.split_first() {
+            let (key, value, meta) = parts(entry);
+            if self.root_page.value() != 0 {
+                // An empty rightmost leaf says nothing about its lower bound,
+                // so only a non-empty one can start the fast path
+                path = self.rightmost_path()?;
+                let last = self.last_key_in(*path.last().unwrap())?;
+                if matches!(last, Some(last) if self.config.compare_keys(&last, key).is_lt()) {
+                    break;
+                }
             }
-            Ok(None)
-        } else {
-            // Find child to descend into
-            let child_id = page.find_child(key)?;
-            drop(page);
-            drop(guard);
-            self.search(child_id, key)
+            self.put_entry(key, value, meta)?;
+            rest = tail;
         }
+
+        for entry in rest {
+            let (key, value, meta) = parts(entry);
+            self.version += 1;
+            self.append_to_rightmost(&mut path, key, value, meta)?;
+            self.add_entries(1)?;
+        }
+
+        Ok(())
     }
 
-    /// Recursive insert
-    ///
-    /// Returns Some((separator_key, new_page_id)) if a split occurred.
-    fn insert_recursive(
-        &self,
-        page_id: PageId,
+    /// Append one entry to the rightmost leaf, updating `path` if the
+    /// rightmost edge changes
+    fn append_to_rightmost(
+        &mut self,
+        path: &mut Vec<PageId>,
         key: &[u8],
         value: &[u8],
-    ) -> Result<Option<(Vec<u8>, PageId)>> {
-        let guard = self.buffer_pool.fetch_page_mut(page_id)?;
-
+        meta: CellMeta,
+    ) -> Result<()> {
+        let cell = self.new_leaf_cell(key, value, meta)?;
+        let leaf_id = *path.last().unwrap();
+        {
+            let guard = self.fetch_page_mut(leaf_id)?;
+            let mut page = guard.write();
+            self.format_leaf(&mut page)?;
+            if page.can_fit(page.cell_size(&cell)) && page.cell_count() < self.config.max_leaf_keys {
+                page.insert_cell_by(&cell, self.order())?;
+                return Ok(());
+            }
+        }
+
+        // Split rightward: the full leaf stays as is but for its link
+        StructureCounters::bump(&self.counters.leaf_splits);
+        let (new_leaf_id, guard) = self.new_page()?;
+        {
+            let mut page = guard.write();
+            self.format_leaf(&mut page)?;
+            page.insert_cell_by(&cell, self.order())?;
+        }
+        drop(guard);
+        self.fetch_page_mut(leaf_id)?.write().set_next_leaf(new_leaf_id);
+
+        let mut level = path.len() - 1;
+        path[level] = new_leaf_id;
+        let mut carry = Some((key.to_vec(), new_leaf_id));
+
+        while let Some((separator, child_id)) = carry {
+            if level == 0 {
+                self.split_root(separator, child_id)?;
+                path.insert(0, self.root_page);
+                break;
+            }
+            level -= 1;
+
+            let guard = self.fetch_page_mut(path[level])?;
+            carry = self.insert_into_interior(guard, &separator, child_id, true)?;
+            // The appended key is the largest, so it lands in the new right half
+            if let Some((_, new_page_id)) = &carry {
+                path[level] = *new_page_id;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Build the tree bottom-up from entries sorted by key
+    ///
+    /// Leaves are filled left to right as full as they go, then each
+    /// interior level is built over the one below and the root is recorded
+    /// last, so no page is ever split. The tree must be empty and keys must
+    /// be strictly increasing; otherwise nothing is loaded and the pages
+    /// written so far are freed.
+    pub fn bulk_load(&mut self, entries: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>) -> Result<()> {
+        if self.root_page.value() != 0 {
+            return Err(StorageError::invalid_operation(
+                "bulk_load needs an empty tree",
+            ));
+        }
+
+        let mut written = Vec::new();
+        let built = match self.build_levels(entries, &mut written) {
+            Ok(built) => built,
+            Err(e) => {
+                self.discard_pages(&written)?;
+                return Err(e);
+            }
+        };
+        let Some((root_page, height, count)) = built else {
+            return Ok(());
+        };
+
+        self.version += 1;
+        self.root_page = root_page;
+        self.height = height;
+        self.entry_count = self.entry_count.map(|_| count);
+        self.persist_root()
+    }
+
+    /// Write the leaves and interior levels for `bulk_load`
+    ///
+    /// Returns the root page, height and entry count, or `None` if there
+    /// were no entries. Every page written is added to `written`.
+    fn build_levels(
+        &self,
+        entries: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>,
+        written: &mut Vec<PageId>,
+    ) -> Result<Option<(PageId, usize, u64)>> {
+        // The first key and page of each leaf
+        let mut level: Vec<(Vec<u8>, PageId)> = Vec::new();
+        let mut leaf: Option<PageGuardMut<'_>> = None;
+        let mut last_key: Option<Vec<u8>> = None;
+        let mut count = 0;
+
+        for (key, value) in entries {
+            self.validate_entry(&key, &value)?;
+            if last_key
+                .as_ref()
+                .is_some_and(|last| self.config.compare_keys(last, &key).is_ge())
+            {
+                return Err(StorageError::invalid_operation(
+                    "bulk_load keys must be strictly increasing",
+                ));
+            }
+
+            let cell = self.new_leaf_cell(&key, &value, CellMeta::default())?;
+            let fits = leaf.as_ref().is_some_and(|guard| {
+                let page = guard.read();
+                page.can_fit(page.cell_size(&cell)) && page.cell_count() < self.config.max_leaf_keys
+            });
+            if !fits {
+                let (page_id, guard) = self.new_page()?;
+                if let Some(prev) = leaf.take() {
+                    prev.write().set_next_leaf(page_id);
+                }
+                written.push(page_id);
+                self.format_leaf(&mut guard.write())?;
+                level.push((key.clone(), page_id));
+                leaf = Some(guard);
+            }
+            leaf.as_ref().unwrap().write().insert_cell_by(&cell, self.order())?;
+            last_key = Some(key);
+            count += 1;
+        }
+        drop(leaf);
+
+        let mut height = 1;
+        while level.len() > 1 {
+            level = self.build_interior_level(&level, written)?;
+            height += 1;
+        }
+        Ok(level.pop().map(|(_, page_id)| (page_id, height, count)))
+    }
+
+    /// Build one interior level over `children`, given as the first key
+    /// and page of each child in key order
+    ///
+    /// Pages are filled left to right; the last page takes a child from
+    /// the one before it if it would otherwise have no separator. Returns
+    /// the first key and page of each new page.
+    fn build_interior_level(
+        &self,
+        children: &[(Vec<u8>, PageId)],
+        written: &mut Vec<PageId>,
+    ) -> Result<Vec<(Vec<u8>, PageId)>> {
+        let mut parents: Vec<(Vec<u8>, PageId)> = Vec::new();
+        let mut parent: Option<PageGuardMut<'_>> = None;
+
+        for (key, child_id) in children {
+            let mut cell = Cell::new_interior(key.clone(), *child_id);
+            cell.value = self.inline_payload(*child_id, key)?;
+            let fits = parent.as_ref().is_some_and(|guard| {
+                let page = guard.read();
+                page.can_fit(page.cell_size(&cell))
+                    && page.cell_count() < self.config.max_interior_keys
+            });
+            if fits {
+                parent.as_ref().unwrap().write().insert_cell_by(&cell, self.order())?;
+                continue;
+            }
+
+            drop(parent.take());
+            let (page_id, guard) = self.new_page()?;
+            written.push(page_id);
+            {
+                let mut page = guard.write();
+                *page = SlottedPage::new_interior();
+                page.set_right_child(*child_id);
+            }
+            parents.push((key.clone(), page_id));
+            parent = Some(guard);
+        }
+        drop(parent);
+
+        if let [.., (_, prev_id), (last_key, last_id)] = parents.as_mut_slice() {
+            let last_guard = self.fetch_page_mut(*last_id)?;
+            let prev_guard = self.fetch_page_mut(*prev_id)?;
+            let (mut last, mut prev) = (last_guard.write(), prev_guard.write());
+            if last.cell_count() == 0 && prev.cell_count() > 1 {
+                // The old leftmost child moves behind a separator, and the
+                // child taken from the page before becomes the leftmost
+                let last_index = prev.cell_count() - 1;
+                let moved = prev.delete_cell(last_index)?;
+                let old_leftmost = last.right_child();
+                last.set_right_child(moved.left_child);
+                let mut cell = Cell::new_interior(last_key.clone(), old_leftmost);
+                cell.value = self.inline_payload(old_leftmost, last_key)?;
+                last.insert_cell_by(&cell, self.order())?;
+                *last_key = moved.key;
+            }
+        }
+
+        Ok(parents)
+    }
+
+    /// Free pages written by a `bulk_load` that failed, along with the
+    /// overflow chains of their cells
+    fn discard_pages(&self, pages: &[PageId]) -> Result<()> {
+        for &page_id in pages {
+            let cells = {
+                let guard = self.buffer_pool.fetch_page(page_id)?;
+                let page = guard.read();
+                if page.is_leaf() {
+                    page.get_all_cells()?
+                } else {
+                    Vec::new()
+                }
+            };
+            for cell in &cells {
+                self.free_overflow(cell)?;
+            }
+            self.free_page(page_id)?;
+        }
+        Ok(())
+    }
+
+    /// Page IDs from the root down to the rightmost leaf
+    fn rightmost_path(&self) -> Result<Vec<PageId>> {
+        let mut path = vec![self.root_page];
+        loop {
+            let guard = self.buffer_pool.fetch_page(*path.last().unwrap())?;
+            let page = guard.read();
+            if page.is_leaf() {
+                return Ok(path);
+            }
+
+            let child_id = match page.cell_count() {
+                0 => page.right_child(),
+                n => page.get_cell(n - 1)?.left_child,
+            };
+            path.push(child_id);
+        }
+    }
+
+    /// Get the largest key on a leaf page
+    fn last_key_in(&self, page_id: PageId) -> Result<Option<Vec<u8>>> {
+        let guard = self.buffer_pool.fetch_page(page_id)?;
+        let page = guard.read();
+        match page.cell_count() {
+            0 => Ok(None),
+            n => Ok(Some(page.get_cell(n - 1)?.key)),
+        }
+    }
+
+    /// Build a leaf cell with the expiry and modification time in `meta`,
+    /// stamping it with the current time if configured and `meta` has none
+    ///
+    /// A value too large for the cell is spilled to a new overflow chain.
+    fn new_leaf_cell(&self, key: &[u8], value: &[u8], meta: CellMeta) -> Result<Cell> {
+        let (inline, chain) = overflow::spill(&*self.buffer_pool, value)?;
+        let mut cell = Cell::new_leaf(key.to_vec(), inline.to_vec());
+        cell.meta.overflow = chain;
+        cell.meta.expires = meta.expires;
+        cell.meta.modified = meta.modified;
+        if self.config.track_modified && cell.meta.modified.is_none() {
+            cell.meta.modified = Some(now_millis());
+        }
+        Ok(cell)
+    }
+
+    /// Remove the expired cells of a leaf, freeing their overflow chains
+    ///
+    /// Returns the number of cells removed. The page is only latched for
+    /// writing if it holds an expired cell.
+    fn purge_expired(&self, guard: &PageGuardMut<'_>) -> Result<u64> {
+        let now = now_millis();
+        let expired: Vec<usize> = {
+            let page = guard.read();
+            if !page.has_cell_meta() {
+                return Ok(0);
+            }
+            let mut expired = Vec::new();
+            for i in 0..page.cell_count() {
+                if page.get_leaf_ref(i)?.2.is_expired(now) {
+                    expired.push(i);
+                }
+            }
+            expired
+        };
+        if expired.is_empty() {
+            return Ok(0);
+        }
+
+        let mut page = guard.write();
+        for &i in expired.iter().rev() {
+            let cell = page.delete_cell(i)?;
+            self.free_overflow(&cell)?;
+        }
+        Ok(expired.len() as u64)
+    }
+
+    /// Free the overflow chain of a leaf cell that is no longer stored
+    fn free_overflow(&self, cell: &Cell) -> Result<()> {
+        let Some(chain) = cell.meta.overflow else {
+            return Ok(());
+        };
+        for page_id in overflow::chain_pages(&*self.buffer_pool, cell.value.len(), chain)? {
+            self.free_page(page_id)?;
+        }
+        Ok(())
+    }
+
+    /// Check key and value sizes and the configured key layout
+    pub(crate) fn validate_entry(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        if key.len() > MAX_KEY_SIZE {
+            return Err(StorageError::KeyTooLarge {
+                size: key.len(),
+                max: MAX_KEY_SIZE,
+            });
+        }
+        if value.len() > MAX_VALUE_SIZE {
+            return Err(StorageError::ValueTooLarge {
+                size: value.len(),
+                max: MAX_VALUE_SIZE,
+            });
+        }
+        if value.is_empty() && !self.config.allow_empty_values {
+            return Err(StorageError::EmptyValue);
+        }
+        self.config.validate_key(key)
+    }
+
+    /// Remove every entry, freeing all of the tree's pages
+    ///
+    /// Leaves an empty tree with no root page and returns the number of
+    /// entries removed. While a snapshot is live the pages are held back
+    /// for it as usual.
+    pub fn clear(&mut self) -> Result<usize> {
+        if self.root_page.value() == 0 {
+            return Ok(0);
+        }
+
+        let removed = self.free_subtree(self.root_page)?;
+        self.root_page = PageId::new(0);
+        self.height = 0;
+        self.entry_count = Some(0);
+        self.leaf_filters.lock().clear();
+        self.version += 1;
+        self.persist_root()?;
+        Ok(removed)
+    }
+
+    /// Check whether an entry passes the size and key checks of `put`
+    pub fn would_fit(&self, key: &[u8], value: &[u8]) -> bool {
+        self.validate_entry(key, value).is_ok()
+    }
+
+    /// Delete a key from the tree
+    ///
+    /// Returns true if the key was found and deleted.
+    pub fn delete(&mut self, key: &[u8]) -> Result<bool> {
+        if self.root_page.value() == 0 {
+            return Ok(false);
+        }
+
+        let deleted = self.delete_recursive(self.root_page, key)?;
+        if deleted {
+            self.version += 1;
+            self.add_entries(-1)?;
+            self.collapse_root()?;
+        }
+        Ok(deleted)
+    }
+
+    /// Delete every key in `[start, end)` and return how many were removed
+    ///
+    /// Subtrees lying entirely inside the range are unlinked from their
+    /// parent and freed whole; only the pages on the two boundary paths
+    /// are edited cell by cell.
+    pub fn delete_range(&mut self, start: Option<&[u8]>, end: Option<&[u8]>) -> Result<usize> {
+        if self.root_page.value() == 0 {
+            return Ok(0);
+        }
+
+        let removed = self.delete_range_recursive(self.root_page, start, end)?;
+        if removed > 0 {
+            self.version += 1;
+            self.add_entries(-(removed as i64))?;
+        }
+        // Empty leaves may have been freed even if nothing was removed
+        self.collapse_root()?;
+        self.relink_range(start, end)?;
+        Ok(removed)
+    }
+
+    /// Rewrite the links of the leaves around `[start, end)` after
+    /// `delete_range`
+    ///
+    /// Leaves kept inside the range, and the leaf before it, may still link
+    /// to freed pages. Whole subtrees in the range were freed, so walking
+    /// from the leaf before `start` to the first leaf at or past `end`
+    /// only visits a few pages per level.
+    fn relink_range(&self, start: Option<&[u8]>, end: Option<&[u8]>) -> Result<()> {
+        if self.root_page.value() == 0 {
+            return Ok(());
+        }
+        let order = self.order();
+
+        // Each interior page on the path as its separators, its children
+        // and the index of the child taken
+        let mut path: Vec<(Vec<Cell>, Vec<PageId>, usize)> = Vec::new();
+        let mut leaf = self.root_page;
+        while let Some((cells, children)) = self.interior_children(leaf)? {
+            let idx = start.map_or(0, |s| cells.partition_point(|c| order.compare(&c.key, s).is_le()));
+            leaf = children[idx];
+            path.push((cells, children, idx));
+        }
+
+        let before = path.iter().rev().find(|(_, _, idx)| *idx > 0);
+        if let Some((_, children, idx)) = before {
+            let prev = self.edge_leaf(children[idx - 1], true)?;
+            self.set_next_leaf(prev, leaf)?;
+        }
+
+        loop {
+            // Climb to the nearest page with a child after the one taken
+            let step = loop {
+                let Some((cells, children, idx)) = path.last_mut() else {
+                    break None;
+                };
+                if *idx + 1 < children.len() {
+                    *idx += 1;
+                    break Some((cells[*idx - 1].key.clone(), children[*idx]));
+                }
+                path.pop();
+            };
+            let Some((lower, mut next)) = step else {
+                return self.set_next_leaf(leaf, PageId::new(0));
+            };
+            while let Some((cells, children)) = self.interior_children(next)? {
+                next = children[0];
+                path.push((cells, children, 0));
+            }
+
+            self.set_next_leaf(leaf, next)?;
+            if end.is_some_and(|e| order.compare(&lower, e).is_ge()) {
+                return Ok(());
+            }
+            leaf = next;
+        }
+    }
+
+    /// Get an interior page's separators and children in key order, or
+    /// `None` for a leaf
+    #[allow(clippy::type_complexity)]
+    fn interior_children(&self, page_id: PageId) -> Result<Option<(Vec<Cell>, Vec<PageId>)>> {
+        let guard = self.buffer_pool.fetch_page(page_id)?;
+        let page = guard.read();
+        if page.is_leaf() {
+            return Ok(None);
+        }
+        let cells = page.get_all_cells()?;
+        let mut children = vec![page.right_child()];
+        children.extend(cells.iter().map(|c| c.left_child));
+        Ok(Some((cells, children)))
+    }
+
+    /// Get the first or last leaf under `page_id`
+    fn edge_leaf(&self, page_id: PageId, last: bool) -> Result<PageId> {
+        let mut page_id = page_id;
+        loop {
+            let guard = self.buffer_pool.fetch_page(page_id)?;
+            let page = guard.read();
+            if page.is_leaf() {
+                return Ok(page_id);
+            }
+            page_id = match page.cell_count() {
+                n if last && n > 0 => page.get_cell(n - 1)?.left_child,
+                _ => page.right_child(),
+            };
+        }
+    }
+
+    /// Rewrite a leaf's link if it records one and it differs
+    fn set_next_leaf(&self, leaf_id: PageId, next: PageId) -> Result<()> {
+        let stale = {
+            let guard = self.buffer_pool.fetch_page(leaf_id)?;
+            let page = guard.read();
+            page.next_leaf().is_some_and(|current| current != next)
+        };
+        if stale {
+            self.fetch_page_mut(leaf_id)?.write().set_next_leaf(next);
+        }
+        Ok(())
+    }
+
+    /// Move the entry stored under `old_key` to `new_key`
+    ///
+    /// The entry keeps its expiry and modification time. Fails if `new_key`
+    /// already exists unless `overwrite` is set. Returns false, changing
+    /// nothing, if `old_key` does not exist. If the old key cannot be
+    /// removed, the new key is put back as it was.
+    pub fn rename(&mut self, old_key: &[u8], new_key: &[u8], overwrite: bool) -> Result<bool> {
+        let Some((value, meta)) = self.get_with_meta(old_key)? else {
+            return Ok(false);
+        };
+        if old_key == new_key {
+            return Ok(true);
+        }
+        let replaced = self.get_with_meta(new_key)?;
+        if !overwrite && replaced.is_some() {
+            return Err(StorageError::invalid_operation(
+                "rename target key already exists",
+            ));
+        }
+
+        self.put_entry(new_key, &value, meta)?;
+        if let Err(e) = self.delete(old_key) {
+            let _ = match replaced {
+                Some((value, meta)) => self.put_entry(new_key, &value, meta).map(drop),
+                None => self.delete(new_key).map(drop),
+            };
+            return Err(e);
+        }
+        Ok(true)
+    }
+
+    /// Scan a range of keys
+    ///
+    /// Returns all key-value pairs where start <= key < end.
+    /// If start is None, scan from the beginning.
+    /// If end is None, scan to the end.
+    pub fn scan(
+        &self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        if self.root_page.value() == 0 {
+            return Ok(Vec::new());
+        }
+
+        let mut results = Vec::new();
+        self.scan_recursive(self.root_page, start, end, &mut results)?;
+        Ok(results)
+    }
+
+    /// Range scan restricted to the subtree rooted at `page_id`
+    ///
+    /// Only entries stored under that page are returned; the bounds filter
+    /// them the same way as `scan`.
+    pub fn scan_subtree(
+        &self,
+        page_id: PageId,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        if page_id == PageId::HEADER || page_id.value() as usize >= self.buffer_pool.page_count() {
+            return Err(StorageError::PageNotFound(page_id));
+        }
+        {
+            let guard = self.buffer_pool.fetch_page(page_id)?;
+            let page = guard.read();
+            if !page.is_leaf() && !page.is_interior() {
+                return Err(StorageError::invalid_page(format!(
+                    "page {} is not a tree page",
+                    page_id
+                )));
+            }
+        }
+
+        let mut results = Vec::new();
+        self.scan_recursive(page_id, start, end, &mut results)?;
+        Ok(results)
+    }
+
+    /// Visit key-value pairs in `[start, end)` without copying them
+    ///
+    /// `visit` sees borrowed slices while the leaf is latched and returns
+    /// `false` to stop the scan early.
+    pub fn scan_with<F>(&self, start: Option<&[u8]>, end: Option<&[u8]>, mut visit: F) -> Result<()>
+    where
+        F: FnMut(&[u8], &[u8]) -> Result<bool>,
+    {
+        self.scan_lazy_with(start, end, |key, value| visit(key, &value.load()?))
+    }
+
+    /// Visit keys in `[start, end)`, reading values only when asked
+    ///
+    /// Like `scan_with`, but an overflow chain is only read when the
+    /// visitor calls `LazyValue::load`, so visitors that look at keys or
+    /// value lengths skip the chains of large values.
+    pub fn scan_lazy_with<F>(
+        &self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        mut visit: F,
+    ) -> Result<()>
+    where
+        F: FnMut(&[u8], &LazyValue<'_>) -> Result<bool>,
+    {
+        if self.root_page.value() == 0 {
+            return Ok(());
+        }
+
+        self.visit_recursive(self.root_page, start, end, &mut visit)?;
+        Ok(())
+    }
+
+    /// Visit entries under `page_id`; returns `false` once the visitor stops
+    fn visit_recursive<F>(
+        &self,
+        page_id: PageId,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        visit: &mut F,
+    ) -> Result<bool>
+    where
+        F: FnMut(&[u8], &LazyValue<'_>) -> Result<bool>,
+    {
+        let guard = self.buffer_pool.fetch_page(page_id)?;
+        let page = guard.read();
+
+        let order = self.order();
+        if page.is_leaf() {
+            let now = now_millis();
+            for i in 0..page.cell_count() {
+                let (key, value, meta) = page.get_leaf_ref(i)?;
+                if start.is_some_and(|s| order.compare(key, s).is_lt()) || meta.is_expired(now) {
+                    continue;
+                }
+                if end.is_some_and(|e| order.compare(key, e).is_ge()) {
+                    return Ok(false);
+                }
+                let value = LazyValue::new(&*self.buffer_pool, value, meta);
+                if !visit(key, &value)? {
+                    return Ok(false);
+                }
+            }
+            return Ok(true);
+        }
+
+        let children = Self::children_in_range(&page, start, end, order)?;
+        drop(page);
+        drop(guard);
+
+        for child_id in children {
+            if !self.visit_recursive(child_id, start, end, visit)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Collect key-value pairs in `[start, end)` from the highest key down
+    ///
+    /// Stops after `limit` entries, visiting only the pages needed to find
+    /// them.
+    pub fn scan_rev(
+        &self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        limit: Option<usize>,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let mut results = Vec::new();
+        if self.root_page.value() == 0 || limit == Some(0) {
+            return Ok(results);
+        }
+
+        let limit = limit.unwrap_or(usize::MAX);
+        self.scan_rev_recursive(self.root_page, start, end, limit, &mut results)?;
+        Ok(results)
+    }
+
+    /// Reverse scan under `page_id`; returns `false` once `limit` is reached
+    fn scan_rev_recursive(
+        &self,
+        page_id: PageId,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        limit: usize,
+        results: &mut Vec<(Vec<u8>, Vec<u8>)>,
+    ) -> Result<bool> {
+        let guard = self.buffer_pool.fetch_page(page_id)?;
+        let page = guard.read();
+
+        let order = self.order();
+        if page.is_leaf() {
+            let now = now_millis();
+            for i in (0..page.cell_count()).rev() {
+                let (key, value, meta) = page.get_leaf_ref(i)?;
+                if end.is_some_and(|e| order.compare(key, e).is_ge()) || meta.is_expired(now) {
+                    continue;
+                }
+                if start.is_some_and(|s| order.compare(key, s).is_lt()) {
+                    return Ok(false);
+                }
+                let value = overflow::load(&*self.buffer_pool, value, &meta)?;
+                results.push((key.to_vec(), value.into_owned()));
+                if results.len() >= limit {
+                    return Ok(false);
+                }
+            }
+            return Ok(true);
+        }
+
+        let children = Self::children_in_range(&page, start, end, order)?;
+        drop(page);
+        drop(guard);
+
+        for child_id in children.into_iter().rev() {
+            if !self.scan_rev_recursive(child_id, start, end, limit, results)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Children of an interior page whose key ranges overlap `[start, end)`,
+    /// in key order
+    fn children_in_range(
+        page: &SlottedPage,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        order: KeyOrder,
+    ) -> Result<Vec<PageId>> {
+        let cells = page.get_all_cells()?;
+
+        // Child i covers [lower_i, upper_i): right_child is below the first
+        // separator, cell[i].left_child covers [key[i], key[i + 1])
+        let mut children = Vec::with_capacity(cells.len() + 1);
+        for i in 0..=cells.len() {
+            let lower = i.checked_sub(1).map(|j| cells[j].key.as_slice());
+            let upper = cells.get(i).map(|c| c.key.as_slice());
+
+            let below_end = match (lower, end) {
+                (Some(lo), Some(e)) => order.compare(lo, e).is_lt(),
+                _ => true,
+            };
+            let above_start = match (upper, start) {
+                (Some(hi), Some(s)) => order.compare(hi, s).is_gt(),
+                _ => true,
+            };
+            if below_end && above_start {
+                children.push(if i == 0 { page.right_child() } else { cells[i - 1].left_child });
+            }
+        }
+        Ok(children)
+    }
+
+    /// Search for a key, skipping leaves whose bloom filter rules it out
+    fn search(&self, page_id: PageId, key: &[u8]) -> Result<Option<Cell>> {
+        let mut page_id = page_id;
+        loop {
+            if let Some(filter) = self.leaf_filters.lock().get(&page_id) {
+                if !filter.may_contain(key) {
+                    return Ok(None);
+                }
+            }
+
+            let guard = self.buffer_pool.fetch_page(page_id)?;
+            let page = guard.read();
+
+            if page.is_leaf() {
+                if let Some(filter) = page.bloom_filter() {
+                    self.leaf_filters.lock().insert(page_id, filter);
+                    if !filter.may_contain(key) {
+                        return Ok(None);
+                    }
+                }
+                // Search in leaf
+                if let Some(idx) = page.search_by(key, self.order())? {
+                    return Ok(Some(page.get_cell(idx)?));
+                }
+                return Ok(None);
+            }
+
+            if let Some(value) = page.inline_value_by(key, self.order())? {
+                return Ok(Some(Cell::new_leaf(key.to_vec(), value)));
+            }
+
+            // Find child to descend into
+            page_id = page.find_child_by(key, self.order())?;
+        }
+    }
+
+    /// Fetch a page for writing, dropping its cached bloom filter
+    fn fetch_page_mut(&self, page_id: PageId) -> Result<PageGuardMut<'_>> {
+        self.leaf_filters.lock().remove(&page_id);
+        let guard = self.buffer_pool.fetch_page_mut(page_id)?;
+        if self.snapshots.is_active() {
+            self.snapshots.preserve(page_id, &guard.write());
+        }
+        Ok(guard)
+    }
+
+    /// Allocate a page, dropping any bloom filter cached for a previous
+    /// use of its ID
+    fn new_page(&self) -> Result<(PageId, PageGuardMut<'_>)> {
+        let (page_id, guard) = self.buffer_pool.new_page()?;
+        self.leaf_filters.lock().remove(&page_id);
+        Ok((page_id, guard))
+    }
+
+    /// Add a bloom filter to a leaf if configured and it still fits
+    fn format_leaf(&self, page: &mut SlottedPage) -> Result<()> {
+        if !self.config.bloom_filters || page.has_bloom() {
+            return Ok(());
+        }
+        match page.enable_bloom() {
+            Err(StorageError::PageFull { .. }) => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Recursive insert
+    ///
+    /// `rightmost` says whether the page is on the tree's rightmost path,
+    /// where keys past the end land.
+    fn insert_recursive(
+        &self,
+        page_id: PageId,
+        key: &[u8],
+        value: &[u8],
+        meta: CellMeta,
+        rightmost: bool,
+    ) -> Result<InsertOutcome> {
+        let guard = self.fetch_page_mut(page_id)?;
+
+        {
+            let page = guard.read();
+
+            if page.is_leaf() {
+                drop(page);
+                // Insert into leaf
+                return self.insert_into_leaf(guard, key, value, meta, rightmost);
+            }
+
+            // Interior node - find child
+            let child_id = page.find_child_by(key, self.order())?;
+            let last_child = page.child_index_by(key, self.order())? == page.cell_count();
+            drop(page);
+            drop(guard);
+
+            // Recursive insert into child
+            let mut outcome =
+                self.insert_recursive(child_id, key, value, meta, rightmost && last_child)?;
+            // Expiring values are never inlined
+            let inline = if meta.expires.is_some() { &[] } else { value };
+            self.refresh_inline_value(page_id, key, inline)?;
+
+            // Handle child split
+            if let Some((separator, new_child_id)) = outcome.split.take() {
+                let guard = self.fetch_page_mut(page_id)?;
+                outcome.split =
+                    self.insert_into_interior(guard, &separator, new_child_id, rightmost)?;
+            }
+            Ok(outcome)
+        }
+    }
+
+    /// Insert into a leaf page, first removing its expired cells
+    fn insert_into_leaf(
+        &self,
+        guard: PageGuardMut<'_>,
+        key: &[u8],
+        value: &[u8],
+        meta: CellMeta,
+        rightmost: bool,
+    ) -> Result<InsertOutcome> {
+        let page_id = guard.page_id();
+        let expired = self.purge_expired(&guard)?;
+        let cell = self.new_leaf_cell(key, value, meta)?;
+        let outcome = |split, result| InsertOutcome {
+            split,
+            result,
+            expired,
+        };
+        match self.place_leaf_cell(guard, key, &cell, rightmost) {
+            Ok((split, None)) => Ok(outcome(split, PutResult::Inserted)),
+            Ok((split, Some(old))) => {
+                self.free_overflow(&old)?;
+                Ok(outcome(split, PutResult::Updated))
+            }
+            Err(e) => {
+                // The cell was not stored, so its overflow chain is unused
+                let _ = self.free_overflow(&cell);
+                Err(e.on_page(page_id))
+            }
+        }
+    }
+
+    /// Store a new leaf cell in a leaf page, splitting it if needed
+    ///
+    /// Returns the split result and the cell it replaced, if any.
+    #[allow(clippy::type_complexity)]
+    fn place_leaf_cell(
+        &self,
+        guard: PageGuardMut<'_>,
+        key: &[u8],
+        cell: &Cell,
+        rightmost: bool,
+    ) -> Result<(Option<(Vec<u8>, PageId)>, Option<Cell>)> {
+        self.format_leaf(&mut guard.write())?;
+
+        {
+            let page = guard.read();
+
+            // Check if key already exists
+            if let Some(idx) = page.search_by(key, self.order())? {
+                // Update existing
+                drop(page);
+                let mut page = guard.write();
+                let old = page.get_cell(idx)?;
+                page.replace_cell(idx, cell)?;
+                return Ok((None, Some(old)));
+            }
+
+            // Check if we have space and haven't exceeded key limit
+            let has_space = page.can_fit(page.cell_size(cell));
+            let under_limit = page.cell_count() < self.config.max_leaf_keys;
+
+            if has_space && under_limit {
+                drop(page);
+                let mut page = guard.write();
+                page.insert_cell_by(cell, self.order())?;
+                return Ok((None, None));
+            }
+        }
+
+        // Need to split
+        let mut page = guard.write();
+
+        // Insert the cell first (may trigger split)
+        let split_result = self.split_and_insert_leaf(&mut page, cell.clone(), rightmost)?;
+
+        Ok((Some(split_result), None))
+    }
+
+    /// Split a leaf page and insert a cell
+    fn split_and_insert_leaf(
+        &self,
+        page: &mut SlottedPage,
+        cell: Cell,
+        rightmost: bool,
+    ) -> Result<(Vec<u8>, PageId)> {
+        // Build both halves before touching the page so a failure leaves
+        // the original page intact
+        let left_share = self.split_share(page, &cell.key, rightmost)?;
+        let (mut left, right, separator) =
+            page.split_insert_biased(&cell, self.order(), left_share)?;
+        StructureCounters::bump(&self.counters.leaf_splits);
+
+        // Write new page to disk
+        let (new_page_id, new_guard) = self.new_page()?;
+        {
+            let mut new_page_mut = new_guard.write();
+            // Copy the data from the right half to the allocated page; it
+            // inherits the link to the old page's next leaf
+            *new_page_mut = right;
+        }
+        left.set_next_leaf(new_page_id);
+        *page = left;
+
+        Ok((separator, new_page_id))
+    }
+
+    /// Insert into an interior page
+    ///
+    /// `rightmost` says whether the page is on the tree's rightmost path.
+    fn insert_into_interior(
+        &self,
+        guard: PageGuardMut<'_>,
+        separator: &[u8],
+        new_child_id: PageId,
+        rightmost: bool,
+    ) -> Result<Option<(Vec<u8>, PageId)>> {
+        let mut cell = Cell::new_interior(separator.to_vec(), new_child_id);
+        cell.value = self.inline_payload(new_child_id, separator)?;
+
+        {
+            let page = guard.read();
+
+            // Check if we have space and haven't exceeded key limit
+            let has_space = page.can_fit(page.cell_size(&cell));
+            let under_limit = page.cell_count() < self.config.max_interior_keys;
+
+            if has_space && under_limit {
+                drop(page);
+                let mut page = guard.write();
+                // The new child holds the keys from the separator up; the
+                // child before it keeps the keys below
+                page.insert_cell_by(&cell, self.order())?;
+                return Ok(None);
+            }
+        }
+
+        // Need to split interior node
+        let mut page = guard.write();
+        let split_result = self
+            .split_and_insert_interior(&mut page, cell, rightmost)
+            .map_err(|e| e.on_page(guard.page_id()))?;
+
+        Ok(Some(split_result))
+    }
+
+    /// Check whether a value may be copied into an interior separator
+    ///
+    /// Empty values are never inlined, since an empty inline value means
+    /// none is stored. With `track_modified` the timestamp lives only in the
+    /// leaf, so nothing is inlined.
+    fn inlinable(&self, value: &[u8]) -> bool {
+        !value.is_empty()
+            && value.len() <= self.config.inline_interior_max
+            && !self.config.track_modified
+    }
+
+    /// Get the value to inline with a new separator pointing at `child_id`
+    ///
+    /// Only a leaf child whose first key is the separator has a value for
+    /// it; anything else gets an empty value.
+    fn inline_payload(&self, child_id: PageId, separator: &[u8]) -> Result<Vec<u8>> {
+        if self.config.inline_interior_max == 0 {
+            return Ok(Vec::new());
+        }
+        let guard = self.buffer_pool.fetch_page(child_id)?;
+        let page = guard.read();
+        if !page.is_leaf() || page.cell_count() == 0 {
+            return Ok(Vec::new());
+        }
+        let (key, value, meta) = page.get_leaf_ref(0)?;
+        if key == separator
+            && meta.overflow.is_none()
+            && meta.expires.is_none()
+            && self.inlinable(value)
+        {
+            Ok(value.to_vec())
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    /// Bring the inline value of a separator equal to `key` up to date
+    ///
+    /// Called on every interior page along the path of a write, after the
+    /// leaf has changed, so an inline value always matches the leaf. An
+    /// empty `value` (or one too large to inline) clears it.
+    fn refresh_inline_value(&self, page_id: PageId, key: &[u8], value: &[u8]) -> Result<()> {
+        let value = if self.inlinable(value) { value } else { &[] };
+
+        // Check under a read latch first so unaffected pages stay clean
+        let idx = {
+            let guard = self.buffer_pool.fetch_page(page_id)?;
+            let page = guard.read();
+            if !page.has_inline_values() {
+                return Ok(());
+            }
+            match page.search_by(key, self.order())? {
+                Some(idx) if page.get_cell(idx)?.value != value => idx,
+                _ => return Ok(()),
+            }
+        };
+
+        let guard = self.fetch_page_mut(page_id)?;
+        let mut page = guard.write();
+        match page.set_inline_value(idx, value) {
+            // Dropping the inline value always fits
+            Err(StorageError::PageFull { .. }) => page.set_inline_value(idx, &[]),
+            result => result,
+        }
+    }
+
+    /// Split an interior page and insert a cell
+    ///
+    /// The page keeps the lower half of the cells, the new one included.
+    /// The separator returned for the parent is the cell at the split
+    /// point, whose child becomes the new page's `right_child`.
+    fn split_and_insert_interior(
+        &self,
+        page: &mut SlottedPage,
+        cell: Cell,
+        rightmost: bool,
+    ) -> Result<(Vec<u8>, PageId)> {
+        let left_share = self.split_share(page, &cell.key, rightmost)?;
+        let (left, right, separator) =
+            page.split_insert_interior_by(&cell, self.order(), left_share)?;
+        StructureCounters::bump(&self.counters.interior_splits);
+
+        let (new_page_id, new_guard) = self.new_page()?;
+        *new_guard.write() = right;
+        *page = left;
+
+        Ok((separator, new_page_id))
+    }
+
+    /// Get the share of cells to keep on the left when splitting `page`
+    /// around `key`
+    ///
+    /// A key past every other on the rightmost path is taken as an append,
+    /// so the left page is left nearly full; anything else splits evenly.
+    fn split_share(&self, page: &SlottedPage, key: &[u8], rightmost: bool) -> Result<f64> {
+        let count = page.cell_count();
+        if !rightmost || count == 0 {
+            return Ok(0.5);
+        }
+        let last = page.get_cell(count - 1)?;
+        if self.config.compare_keys(key, &last.key).is_gt() {
+            Ok(self.config.append_split_ratio)
+        } else {
+            Ok(0.5)
+        }
+    }
+
+    /// Split the root, creating a new root
+    fn split_root(&mut self, separator: Vec<u8>, new_child_id: PageId) -> Result<()> {
+        let old_root_id = self.root_page;
+
+        // Create new root
+        // After split: old_root has keys < separator, new_child has keys >= separator
+        let (new_root_id, guard) = self.new_page()?;
+        {
+            let mut new_root = guard.write();
+            // Convert to interior page
+            *new_root = SlottedPage::new_interior();
+            
+            // In our semantics:
+            // - right_child stores keys < first separator (old_root)
+            // - cell.left_child stores keys >= separator (new_child)
+            new_root.set_right_child(old_root_id);
+            let mut cell = Cell::new_interior(separator, new_child_id);
+            cell.value = self.inline_payload(new_child_id, &cell.key)?;
+            new_root.insert_cell_by(&cell, self.order())?;
+        }
+        drop(guard);
+
+        self.root_page = new_root_id;
+        self.height += 1;
+        StructureCounters::bump(&self.counters.root_splits);
+        self.persist_root()
+    }
+
+    /// Re-read the root page and height from the file header
+    ///
+    /// For after the header has been replaced underneath the tree.
+    pub fn reload_root(&mut self) {
+        self.root_page = self.buffer_pool.root_page();
+        self.height = self.buffer_pool.tree_height() as usize;
+        self.entry_count = Self::stored_count(&*self.buffer_pool, self.root_page);
+        self.leaf_filters.lock().clear();
+    }
+
+    /// Measure the height and repair the stored value if it differs
+    ///
+    /// Updates the stored height, in memory and in the file header, if it
+    /// differs. Returns the measured height.
+    pub fn recompute_height(&mut self) -> Result<usize> {
+        let height = self.measure_height()?;
+        if height != self.height {
+            self.height = height;
+            self.persist_root()?;
+        }
+        Ok(height)
+    }
+
+    /// Cheap structural sanity check of the root
+    ///
+    /// Checks that the root page exists and is a tree page and that the
+    /// leftmost path matches the stored height. Reads one page per level.
+    pub fn check_root(&self) -> Result<()> {
+        if self.root_page.value() != 0
+            && self.root_page.value() as usize >= self.buffer_pool.page_count()
         {
+            return Err(StorageError::corruption(format!(
+                "root page {} is past the end of the file ({} pages)",
+                self.root_page,
+                self.buffer_pool.page_count()
+            )));
+        }
+
+        let height = self.measure_height()?;
+        if height != self.height {
+            return Err(StorageError::corruption(format!(
+                "stored tree height is {} but the tree is {} levels deep",
+                self.height, height
+            )));
+        }
+        Ok(())
+    }
+
+    /// Measure the height by descending to the leftmost leaf
+    fn measure_height(&self) -> Result<usize> {
+        let mut height = 0;
+        if self.root_page.value() == 0 {
+            return Ok(height);
+        }
+
+        let mut page_id = self.root_page;
+        loop {
+            height += 1;
+            let guard = self.buffer_pool.fetch_page(page_id)?;
             let page = guard.read();
+            if page.is_leaf() {
+                return Ok(height);
+            }
+            if !page.is_interior() {
+                return Err(StorageError::corruption(format!(
+                    "page {} on the leftmost path is not a tree page",
+                    page_id
+                )));
+            }
+            if height > self.buffer_pool.page_count() {
+                return Err(StorageError::corruption("cycle on the leftmost path"));
+            }
+            page_id = page.right_child();
+        }
+    }
+
+    /// Record the root page and height in the file header, or in the
+    /// catalog for a named tree
+    fn persist_root(&self) -> Result<()> {
+        if self.detached {
+            return Ok(());
+        }
+        if let Some((catalog, name)) = &self.catalog_entry {
+            if self.root_page.value() != 0 {
+                self.buffer_pool.flush_page(self.root_page)?;
+            }
+            catalog::store(&*self.buffer_pool, *catalog, name, self.root_page, self.height as u32)?;
+            return self.buffer_pool.flush_page(*catalog);
+        }
+        if let Some(count) = self.entry_count {
+            self.buffer_pool.set_entry_count(count)?;
+        }
+        self.buffer_pool.set_root_page(self.root_page, self.height as u32)?;
+        if self.root_page.value() != 0 {
+            self.buffer_pool.flush_page(self.root_page)?;
+        }
+        Ok(())
+    }
+
+    /// Rebuild the tree with transformed keys under a new configuration
+    ///
+    /// Every key is passed through `f` and the entries are bulk-loaded into
+    /// a new tree that only replaces this one, by switching the root in the
+    /// file header, once it is complete. The transformed keys must be
+    /// strictly increasing under the new configuration's key order;
+    /// otherwise nothing is changed. Entries keep their expiry and
+    /// modification time; expired entries are dropped.
+    pub fn reencode_keys<F>(&mut self, f: F, config: BTreeConfig) -> Result<()>
+    where
+        F: Fn(&[u8]) -> Vec<u8>,
+    {
+        let mut entries = Vec::new();
+        self.scan_lazy_with(None, None, |key, value| {
+            entries.push((f(key), value.load()?.into_owned(), value.meta()));
+            Ok(true)
+        })?;
+        if let Some(i) = entries
+            .windows(2)
+            .position(|w| config.compare_keys(&w[0].0, &w[1].0).is_ge())
+        {
+            return Err(StorageError::invalid_operation(format!(
+                "re-encoded keys are out of order at entry {}",
+                i + 1
+            )));
+        }
 
-            if page.is_leaf() {
-                drop(page);
-                // Insert into leaf
-                return self.insert_into_leaf(guard, key, value);
+        let mut old_pages = self.overflow_pages()?;
+        if self.root_page.value() != 0 {
+            self.collect_pages(self.root_page, &mut old_pages)?;
+        }
+
+        let mut tree = BTree {
+            buffer_pool: self.buffer_pool.clone(),
+            root_page: PageId::new(0),
+            height: 0,
+            entry_count: self.entry_count.map(|_| 0),
+            config,
+            version: self.version + 1,
+            detached: true,
+            catalog_entry: self.catalog_entry.clone(),
+            leaf_filters: Mutex::new(HashMap::new()),
+            counters: StructureCounters::default(),
+            snapshots: SnapshotRegistry::default(),
+        };
+        if let Err(e) = tree.append_batch_with_meta(&entries) {
+            // Nothing refers to the partial tree, so give its pages back
+            if tree.root_page.value() != 0 {
+                let _ = tree.free_subtree(tree.root_page);
             }
+            return Err(e);
+        }
+        tree.detached = false;
+        tree.persist_root()?;
+
+        tree.counters = std::mem::take(&mut self.counters);
+        tree.snapshots = std::mem::take(&mut self.snapshots);
+        *self = tree;
+        for page_id in old_pages {
+            self.free_page(page_id)?;
+        }
+        Ok(())
+    }
 
-            // Interior node - find child
-            let child_id = page.find_child(key)?;
-            drop(page);
-            drop(guard);
+    /// Collect the IDs of all pages under `page_id`
+    fn collect_pages(&self, page_id: PageId, pages: &mut Vec<PageId>) -> Result<()> {
+        pages.push(page_id);
+        let guard = self.buffer_pool.fetch_page(page_id)?;
+        let page = guard.read();
+        if page.is_leaf() {
+            return Ok(());
+        }
 
-            // Recursive insert into child
-            let result = self.insert_recursive(child_id, key, value)?;
+        let mut children = vec![page.right_child()];
+        for cell in page.get_all_cells()? {
+            children.push(cell.left_child);
+        }
+        drop(page);
+        drop(guard);
 
-            // Handle child split
-            if let Some((separator, new_child_id)) = result {
-                let guard = self.buffer_pool.fetch_page_mut(page_id)?;
-                return self.insert_into_interior(guard, &separator, new_child_id);
-            }
+        for child_id in children {
+            self.collect_pages(child_id, pages)?;
         }
+        Ok(())
+    }
 
-        Ok(None)
+    /// Free every page under `page_id`, with the overflow chains of its
+    /// cells, and return the number of entries it held
+    fn free_subtree(&self, page_id: PageId) -> Result<usize> {
+        let mut pages = Vec::new();
+        self.collect_pages(page_id, &mut pages)?;
+
+        let mut count = 0;
+        for page_id in pages {
+            let cells = {
+                let guard = self.buffer_pool.fetch_page(page_id)?;
+                let page = guard.read();
+                if page.is_leaf() {
+                    page.get_all_cells()?
+                } else {
+                    Vec::new()
+                }
+            };
+            count += cells.len();
+            for cell in &cells {
+                self.free_overflow(cell)?;
+            }
+            self.free_page(page_id)?;
+        }
+        Ok(count)
     }
 
-    /// Insert into a leaf page
-    fn insert_into_leaf(
-        &self,
-        guard: crate::buffer::PageGuardMut<'_>,
-        key: &[u8],
-        value: &[u8],
-    ) -> Result<Option<(Vec<u8>, PageId)>> {
-        let cell = Cell::new_leaf(key.to_vec(), value.to_vec());
-        let cell_size = cell.encoded_size();
+    /// Range delete under `page_id`
+    ///
+    /// On an interior page, children whose whole key range lies inside
+    /// `[start, end)` are unlinked and freed, keeping at least one child;
+    /// children that only overlap the range are recursed into and then
+    /// rebalanced.
+    fn delete_range_recursive(
+        &mut self,
+        page_id: PageId,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+    ) -> Result<usize> {
+        let order = self.order();
+        let in_range = |key: &[u8]| {
+            start.is_none_or(|s| order.compare(key, s).is_ge())
+                && end.is_none_or(|e| order.compare(key, e).is_lt())
+        };
+
+        let guard = self.fetch_page_mut(page_id)?;
+        if guard.read().is_leaf() {
+            let mut page = guard.write();
+            let mut removed = Vec::new();
+            for i in (0..page.cell_count()).rev() {
+                if in_range(page.get_leaf_ref(i)?.0) {
+                    removed.push(page.delete_cell(i)?);
+                }
+            }
+            drop(page);
+            drop(guard);
+            for cell in &removed {
+                self.free_overflow(cell)?;
+            }
+            return Ok(removed.len());
+        }
 
-        {
-            let page = guard.read();
+        // Child i covers [key[i - 1], key[i]); the first and last are open
+        let cells = guard.read().get_all_cells()?;
+        let mut covered = Vec::new();
+        let mut partial = Vec::new();
+        for i in 0..=cells.len() {
+            let lower = i.checked_sub(1).map(|j| cells[j].key.as_slice());
+            let upper = cells.get(i).map(|c| c.key.as_slice());
+            let overlaps = match (lower, end) {
+                (Some(lo), Some(e)) => order.compare(lo, e).is_lt(),
+                _ => true,
+            } && match (upper, start) {
+                (Some(hi), Some(s)) => order.compare(hi, s).is_gt(),
+                _ => true,
+            };
+            if !overlaps {
+                continue;
+            }
+            let inside = match (lower, start) {
+                (_, None) => true,
+                (Some(lo), Some(s)) => order.compare(lo, s).is_ge(),
+                (None, Some(_)) => false,
+            } && match (upper, end) {
+                (_, None) => true,
+                (Some(hi), Some(e)) => order.compare(hi, e).is_le(),
+                (None, Some(_)) => false,
+            };
+            if inside {
+                covered.push(i);
+            } else {
+                partial.push(i);
+            }
+        }
+        if covered.len() == cells.len() + 1 {
+            // Keep one child so the page still routes somewhere
+            partial.push(covered.remove(0));
+        }
 
-            // Check if key already exists
-            if let Some(idx) = page.search(key)? {
-                // Update existing
-                drop(page);
-                let mut page = guard.write();
-                page.update_cell(idx, value)?;
-                return Ok(None);
+        let child_at = |i: usize| if i == 0 { None } else { Some(cells[i - 1].left_child) };
+        let mut unlinked = Vec::new();
+        let mut partial_children = Vec::new();
+        {
+            let mut page = guard.write();
+            for &i in &partial {
+                partial_children.push(child_at(i).unwrap_or(page.right_child()));
             }
+            // Unlinking child i > 0 drops separator i - 1 and widens child
+            // i - 1; unlinking child 0 promotes the current first separator's
+            // child in its place
+            for &i in covered.iter().rev() {
+                if i == 0 {
+                    unlinked.push(page.right_child());
+                    let first = page.delete_cell(0)?;
+                    page.set_right_child(first.left_child);
+                } else {
+                    unlinked.push(cells[i - 1].left_child);
+                    page.delete_cell(i - 1)?;
+                }
+            }
+            if page.has_inline_values() {
+                for i in 0..page.cell_count() {
+                    let cell = page.get_cell(i)?;
+                    if in_range(&cell.key) && !cell.value.is_empty() {
+                        page.set_inline_value(i, &[])?;
+                    }
+                }
+            }
+        }
+        drop(guard);
 
-            // Check if we have space and haven't exceeded key limit
-            let has_space = page.can_fit(cell_size);
-            let under_limit = page.cell_count() < self.config.max_leaf_keys;
+        let mut removed = 0;
+        for child_id in unlinked {
+            removed += self.free_subtree(child_id)?;
+        }
+        for &child_id in &partial_children {
+            removed += self.delete_range_recursive(child_id, start, end)?;
+        }
+        // The right boundary child first: it merges into its left sibling,
+        // so the other boundary child is still linked when its turn comes
+        for &child_id in partial_children.iter().rev() {
+            self.rebalance_child(page_id, child_id)?;
+        }
+        Ok(removed)
+    }
 
-            if has_space && under_limit {
+    /// Recursive delete
+    fn delete_recursive(&mut self, page_id: PageId, key: &[u8]) -> Result<bool> {
+        let guard = self.fetch_page_mut(page_id)?;
+        let page = guard.read();
+
+        if page.is_leaf() {
+            drop(page);
+            let mut page = guard.write();
+
+            if let Some(idx) = page.search_by(key, self.order())? {
+                let cell = page.delete_cell(idx)?;
                 drop(page);
-                let mut page = guard.write();
-                page.insert_cell(&cell)?;
-                return Ok(None);
+                self.free_overflow(&cell)?;
+                return Ok(true);
             }
+            return Ok(false);
         }
 
-        // Need to split
-        let mut page = guard.write();
-
-        // Insert the cell first (may trigger split)
-        let split_result = self.split_and_insert_leaf(&mut page, cell)?;
+        // Interior node - find child
+        let child_id = page.find_child_by(key, self.order())?;
+        drop(page);
+        drop(guard);
 
-        Ok(Some(split_result))
+        // Recursive delete
+        let deleted = self.delete_recursive(child_id, key)?;
+        if deleted {
+            self.refresh_inline_value(page_id, key, &[])?;
+            self.rebalance_child(page_id, child_id)?;
+        }
+        Ok(deleted)
     }
 
-    /// Split a leaf page and insert a cell
-    fn split_and_insert_leaf(
-        &self,
-        page: &mut SlottedPage,
-        cell: Cell,
-    ) -> Result<(Vec<u8>, PageId)> {
-        // First insert the cell (page will be overfull but we handle it)
-        // Actually, let's split first then figure out which side gets the new cell
+    /// Fix up a child of `parent_id` that dropped below its minimum
+    ///
+    /// Leaves keep `MIN_KEYS` cells and interior pages
+    /// `min_interior_keys`. The child is merged with a neighbouring sibling
+    /// if both fit on one page, otherwise it borrows one cell from the
+    /// sibling. If neither is possible (the sibling is at the minimum
+    /// itself, or cells are too large to move) the child is left underfull,
+    /// which is still a valid tree.
+    fn rebalance_child(&mut self, parent_id: PageId, child_id: PageId) -> Result<()> {
+        let min_cells = {
+            let guard = self.buffer_pool.fetch_page(child_id)?;
+            let page = guard.read();
+            let min_cells = if page.is_leaf() {
+                MIN_KEYS
+            } else {
+                self.config.min_interior_keys()
+            };
+            if page.cell_count() >= min_cells {
+                return Ok(());
+            }
+            min_cells
+        };
+
+        let mut parent = self.buffer_pool.fetch_page(parent_id)?.read().clone();
+        let separators = parent.get_all_cells()?;
+        let mut children = vec![parent.right_child()];
+        children.extend(separators.iter().map(|c| c.left_child));
+        let Some(idx) = children.iter().position(|&c| c == child_id) else {
+            return Err(StorageError::corruption(format!(
+                "page {} is not a child of page {}",
+                child_id, parent_id
+            )));
+        };
+        if children.len() < 2 {
+            return Ok(());
+        }
 
-        let (mut new_page, separator) = page.split()?;
+        // Pair the child with its left sibling, or its right one if it is
+        // the leftmost child; separator `j` sits between the two
+        let j = idx.saturating_sub(1);
+        let (left_id, right_id) = (children[j], children[j + 1]);
+        let separator = &separators[j].key;
+        let mut left = self.buffer_pool.fetch_page(left_id)?.read().clone();
+        let mut right = self.buffer_pool.fetch_page(right_id)?.read().clone();
 
-        // Determine which page gets the new cell
-        if cell.key.as_slice() < separator.as_slice() {
-            page.insert_cell(&cell)?;
+        let merged = if left.is_leaf() {
+            Self::merge_leaves(&left, &right, self.config.max_leaf_keys, self.order())
         } else {
-            new_page.insert_cell(&cell)?;
+            Self::merge_interiors(
+                &left,
+                separator,
+                &right,
+                self.config.max_interior_keys,
+                self.order(),
+            )
+        };
+        if let Some(merged) = merged {
+            parent.delete_cell(j)?;
+            *self.fetch_page_mut(left_id)?.write() = merged;
+            *self.fetch_page_mut(parent_id)?.write() = parent;
+            self.free_page(right_id)?;
+            StructureCounters::bump(&self.counters.merges);
+            return Ok(());
         }
 
-        // Write new page to disk
-        let (new_page_id, new_guard) = self.buffer_pool.new_page()?;
-        {
-            let mut new_page_mut = new_guard.write();
-            // Copy the data from new_page to the allocated page
-            *new_page_mut = new_page;
+        // Borrow from whichever of the pair is not the underfull child
+        let child_is_left = idx == j;
+        let sibling_count = if child_is_left { right.cell_count() } else { left.cell_count() };
+        if sibling_count <= min_cells {
+            return Ok(());
+        }
+        let new_separator = if left.is_leaf() {
+            Self::borrow_leaf(&mut left, &mut right, child_is_left, self.order())
+        } else {
+            Self::borrow_interior(&mut left, separator, &mut right, child_is_left, self.order())
+        };
+        let Ok(new_separator) = new_separator else {
+            return Ok(());
+        };
+        if Self::replace_separator(&mut parent, j, new_separator, right_id, self.order()).is_err() {
+            return Ok(());
         }
 
-        Ok((separator, new_page_id))
+        *self.fetch_page_mut(left_id)?.write() = left;
+        *self.fetch_page_mut(right_id)?.write() = right;
+        *self.fetch_page_mut(parent_id)?.write() = parent;
+        StructureCounters::bump(&self.counters.borrows);
+        Ok(())
     }
 
-    /// Insert into an interior page
-    fn insert_into_interior(
-        &self,
-        guard: crate::buffer::PageGuardMut<'_>,
+    /// Build one leaf holding the cells of both, if they fit
+    fn merge_leaves(
+        left: &SlottedPage,
+        right: &SlottedPage,
+        max_keys: usize,
+        order: KeyOrder,
+    ) -> Option<SlottedPage> {
+        if left.cell_count() + right.cell_count() > max_keys {
+            return None;
+        }
+        let mut merged = left.clone();
+        merged.defragment().ok()?;
+        for cell in right.get_all_cells().ok()? {
+            merged.insert_cell_by(&cell, order).ok()?;
+        }
+        if let Some(next) = right.next_leaf() {
+            merged.set_next_leaf(next);
+        }
+        Some(merged)
+    }
+
+    /// Build one interior page from both, pulling the separator down
+    /// between them, if everything fits
+    fn merge_interiors(
+        left: &SlottedPage,
         separator: &[u8],
-        new_child_id: PageId,
-    ) -> Result<Option<(Vec<u8>, PageId)>> {
-        let cell = Cell::new_interior(separator.to_vec(), new_child_id);
-        let cell_size = cell.encoded_size();
+        right: &SlottedPage,
+        max_keys: usize,
+        order: KeyOrder,
+    ) -> Option<SlottedPage> {
+        if left.cell_count() + 1 + right.cell_count() > max_keys {
+            return None;
+        }
+        let mut merged = left.clone();
+        merged.defragment().ok()?;
+        // The separator now routes to the right page's leftmost child
+        merged
+            .insert_cell_by(&Cell::new_interior(separator.to_vec(), right.right_child()), order)
+            .ok()?;
+        for cell in right.get_all_cells().ok()? {
+            merged
+                .insert_cell_by(&Cell::new_interior(cell.key, cell.left_child), order)
+                .ok()?;
+        }
+        Some(merged)
+    }
 
-        {
-            let page = guard.read();
+    /// Move one cell between neighbouring leaves into the underfull one
+    ///
+    /// Returns the new separator: the right leaf's first key.
+    fn borrow_leaf(
+        left: &mut SlottedPage,
+        right: &mut SlottedPage,
+        to_left: bool,
+        order: KeyOrder,
+    ) -> Result<Vec<u8>> {
+        let (from, to, index) = if to_left {
+            (&mut *right, &mut *left, 0)
+        } else {
+            let last = left.cell_count() - 1;
+            (&mut *left, &mut *right, last)
+        };
+        let cell = from.delete_cell(index)?;
+        to.defragment()?;
+        to.insert_cell_by(&cell, order)?;
+        Ok(right.get_cell(0)?.key)
+    }
 
-            // Check if we have space and haven't exceeded key limit
-            let has_space = page.can_fit(cell_size);
-            let under_limit = page.cell_count() < self.config.max_interior_keys;
+    /// Rotate one child between neighbouring interior pages through their
+    /// separator, into the underfull one
+    ///
+    /// Returns the new separator.
+    fn borrow_interior(
+        left: &mut SlottedPage,
+        separator: &[u8],
+        right: &mut SlottedPage,
+        to_left: bool,
+        order: KeyOrder,
+    ) -> Result<Vec<u8>> {
+        if to_left {
+            // The separator comes down over the right page's leftmost child,
+            // and the right page's first key goes up
+            let first = right.delete_cell(0)?;
+            left.defragment()?;
+            left.insert_cell_by(&Cell::new_interior(separator.to_vec(), right.right_child()), order)?;
+            right.set_right_child(first.left_child);
+            Ok(first.key)
+        } else {
+            // The left page's last key goes up, and its child becomes the
+            // right page's leftmost child
+            let last = left.delete_cell(left.cell_count() - 1)?;
+            right.defragment()?;
+            right.insert_cell_by(&Cell::new_interior(separator.to_vec(), right.right_child()), order)?;
+            right.set_right_child(last.left_child);
+            Ok(last.key)
+        }
+    }
 
-            if has_space && under_limit {
-                drop(page);
-                let mut page = guard.write();
-                page.insert_cell(&cell)?;
+    /// Replace the key of separator `index`, which points at `child_id`
+    fn replace_separator(
+        parent: &mut SlottedPage,
+        index: usize,
+        key: Vec<u8>,
+        child_id: PageId,
+        order: KeyOrder,
+    ) -> Result<()> {
+        parent.delete_cell(index)?;
+        parent.defragment()?;
+        parent.insert_cell_by(&Cell::new_interior(key, child_id), order)?;
+        Ok(())
+    }
 
-                // The new child becomes the left child of this separator
-                // and the old child at that position becomes... wait, we need to handle pointers
+    /// Replace an interior root left without separators by its only child
+    fn collapse_root(&mut self) -> Result<()> {
+        loop {
+            let child_id = {
+                let guard = self.buffer_pool.fetch_page(self.root_page)?;
+                let page = guard.read();
+                if page.is_leaf() || page.cell_count() > 0 {
+                    return Ok(());
+                }
+                page.right_child()
+            };
 
-                // Actually for interior nodes, when we insert a separator:
-                // - The separator's left_child is the new_child_id
-                // - The existing child to the right of insertion point remains correct
+            let old_root = self.root_page;
+            self.root_page = child_id;
+            self.height -= 1;
+            self.persist_root()?;
+            self.free_page(old_root)?;
+        }
+    }
 
-                return Ok(None);
-            }
+    /// Free a page, dropping its cached bloom filter
+    ///
+    /// While a snapshot is live the page is held back until it is gone.
+    pub(crate) fn free_page(&self, page_id: PageId) -> Result<()> {
+        self.leaf_filters.lock().remove(&page_id);
+        if self.snapshots.is_active() {
+            self.snapshots.defer_free(page_id);
+            return Ok(());
         }
+        self.buffer_pool.free_page(page_id)
+    }
 
-        // Need to split interior node
-        let mut page = guard.write();
-        let split_result = self.split_and_insert_interior(&mut page, cell)?;
+    /// Take a copy-on-write snapshot of the tree as it is now
+    pub(crate) fn snapshot(&self) -> FrozenTree {
+        let images = self.snapshots.register();
+        FrozenTree::new(self.buffer_pool.clone(), self.root_page, images, self.order())
+    }
 
-        Ok(Some(split_result))
+    /// Get the pages freed while a snapshot was live and not yet released
+    pub(crate) fn deferred_pages(&self) -> Vec<PageId> {
+        self.snapshots.deferred_frees()
     }
 
-    /// Split an interior page and insert a cell
-    fn split_and_insert_interior(
-        &self,
-        page: &mut SlottedPage,
-        cell: Cell,
-    ) -> Result<(Vec<u8>, PageId)> {
-        let (mut new_page, separator) = page.split()?;
+    /// Free the pages held back for snapshots, once none is left
+    pub(crate) fn release_snapshot_pages(&self) -> Result<()> {
+        for page_id in self.snapshots.take_deferred_frees() {
+            self.buffer_pool.free_page(page_id)?;
+        }
+        Ok(())
+    }
 
-        // Determine which page gets the new cell
-        if cell.key.as_slice() < separator.as_slice() {
-            page.insert_cell(&cell)?;
-        } else {
-            new_page.insert_cell(&cell)?;
+    /// Defragment every page and verify tree invariants in one pass
+    ///
+    /// Checks page types, key ordering, separator bounds and uniform leaf
+    /// depth. Pages that fail to decode are reported and left untouched.
+    /// Running it again on a healthy tree changes nothing.
+    pub fn maintenance(&self) -> Result<MaintenanceReport> {
+        let mut report = MaintenanceReport::default();
+        if self.root_page.value() != 0 {
+            self.maintain_page(self.root_page, None, None, 1, &mut report)?;
         }
+        Ok(report)
+    }
 
-        // Write new page to disk
-        let (new_page_id, new_guard) = self.buffer_pool.new_page()?;
-        {
-            let mut new_page_ref = new_guard.write();
-            // Need to make this an interior page
-            let mut interior_page = SlottedPage::new_interior();
-            interior_page.set_right_child(new_page.right_child());
-            for i in 0..new_page.cell_count() {
-                let c = new_page.get_cell(i)?;
-                interior_page.insert_cell(&Cell::new_interior(c.key, c.left_child))?;
+    /// Compact and check one page, then recurse into its children
+    fn maintain_page(
+        &self,
+        page_id: PageId,
+        lower: Option<&[u8]>,
+        upper: Option<&[u8]>,
+        depth: usize,
+        report: &mut MaintenanceReport,
+    ) -> Result<()> {
+        report.pages_visited += 1;
+
+        let guard = self.buffer_pool.fetch_page(page_id)?;
+        let page = guard.read();
+        let cells = match page.get_all_cells() {
+            Ok(cells) => cells,
+            Err(e) => {
+                report.problems.push(format!("page {}: {}", page_id, e));
+                return Ok(());
             }
-            *new_page_ref = interior_page;
+        };
+
+        let is_leaf = page.is_leaf();
+        let problems = self.node_problems(&page, &cells, lower, upper, depth);
+        report
+            .problems
+            .extend(problems.iter().map(|p| format!("page {}: {}", page_id, p)));
+        if !is_leaf && !page.is_interior() {
+            return Ok(());
         }
 
-        Ok((separator, new_page_id))
-    }
-
-    /// Split the root, creating a new root
-    fn split_root(&mut self, separator: Vec<u8>, new_child_id: PageId) -> Result<()> {
-        let old_root_id = self.root_page;
+        let right_child = page.right_child();
+        let free_before = page.free_space();
+        let fragmented = page.header().fragmented_bytes > 0;
+        drop(page);
+        drop(guard);
 
-        // Create new root
-        // After split: old_root has keys < separator, new_child has keys >= separator
-        let (new_root_id, guard) = self.buffer_pool.new_page()?;
-        {
-            let mut new_root = guard.write();
-            // Convert to interior page
-            *new_root = SlottedPage::new_interior();
-            
-            // In our semantics:
-            // - right_child stores keys < first separator (old_root)
-            // - cell.left_child stores keys >= separator (new_child)
-            new_root.set_right_child(old_root_id);
-            let cell = Cell::new_interior(separator, new_child_id);
-            new_root.insert_cell(&cell)?;
+        report.free_bytes_before += free_before;
+        if fragmented {
+            let guard = self.fetch_page_mut(page_id)?;
+            let mut page = guard.write();
+            page.defragment()?;
+            report.free_bytes_after += page.free_space();
+            report.pages_compacted += 1;
+        } else {
+            report.free_bytes_after += free_before;
         }
 
-        self.root_page = new_root_id;
-        self.height += 1;
+        if is_leaf {
+            return Ok(());
+        }
 
-        // Persist the new root to the file header
-        self.buffer_pool.set_root_page(new_root_id, self.height as u32)?;
-        self.buffer_pool.flush_page(new_root_id)?;
+        // Children in key order: right_child holds keys below the first
+        // separator, cell[i].left_child holds keys in [key[i], key[i + 1])
+        let first_upper = cells.first().map(|c| c.key.as_slice()).or(upper);
+        self.maintain_page(right_child, lower, first_upper, depth + 1, report)?;
+        for (i, cell) in cells.iter().enumerate() {
+            let child_upper = cells.get(i + 1).map(|c| c.key.as_slice()).or(upper);
+            self.maintain_page(cell.left_child, Some(&cell.key), child_upper, depth + 1, report)?;
+        }
 
         Ok(())
     }
 
-    /// Recursive delete
-    fn delete_recursive(&mut self, page_id: PageId, key: &[u8]) -> Result<bool> {
-        let guard = self.buffer_pool.fetch_page_mut(page_id)?;
-        let page = guard.read();
-
-        if page.is_leaf() {
-            drop(page);
-            let mut page = guard.write();
+    /// Describe every invariant a decoded page breaks
+    ///
+    /// Covers the page type, leaf depth, key order within the page and the
+    /// `[lower, upper)` range set by the parent's separators.
+    fn node_problems(
+        &self,
+        page: &SlottedPage,
+        cells: &[Cell],
+        lower: Option<&[u8]>,
+        upper: Option<&[u8]>,
+        depth: usize,
+    ) -> Vec<String> {
+        let mut problems = Vec::new();
+        if !page.is_leaf() && !page.is_interior() {
+            problems.push(format!("unexpected page type {:?}", page.page_type()));
+            return problems;
+        }
+        if page.is_leaf() && depth != self.height {
+            problems.push(format!(
+                "leaf at depth {} but tree height is {}",
+                depth, self.height
+            ));
+        }
 
-            if let Some(idx) = page.search(key)? {
-                page.delete_cell(idx)?;
-                return Ok(true);
+        if cells
+            .windows(2)
+            .any(|pair| self.config.compare_keys(&pair[0].key, &pair[1].key).is_ge())
+        {
+            problems.push("keys out of order".to_string());
+        }
+        if let (Some(first), Some(lo)) = (cells.first(), lower) {
+            if self.config.compare_keys(&first.key, lo).is_lt() {
+                problems.push("key below parent separator".to_string());
             }
-            return Ok(false);
         }
+        if let (Some(last), Some(hi)) = (cells.last(), upper) {
+            if self.config.compare_keys(&last.key, hi).is_ge() {
+                problems.push("key above parent separator".to_string());
+            }
+        }
+        problems
+    }
 
-        // Interior node - find child
-        let child_id = page.find_child(key)?;
+    /// Check the tree's structural invariants without changing any page
+    ///
+    /// Checks key order within each page, that every key lies within the
+    /// separators above it, that all leaves sit at the same depth and that
+    /// no page is reached twice. A page reached twice is not descended into
+    /// again, so cycles end the walk instead of looping.
+    pub fn verify(&self) -> Result<VerifyReport> {
+        let mut report = VerifyReport::default();
+        let mut seen = HashSet::new();
+        if self.root_page.value() != 0 {
+            self.verify_page(self.root_page, None, None, 1, &mut seen, &mut report)?;
+        }
+        Ok(report)
+    }
+
+    /// Check one page, then recurse into its children
+    fn verify_page(
+        &self,
+        page_id: PageId,
+        lower: Option<&[u8]>,
+        upper: Option<&[u8]>,
+        depth: usize,
+        seen: &mut HashSet<PageId>,
+        report: &mut VerifyReport,
+    ) -> Result<()> {
+        if !seen.insert(page_id) {
+            report.violations.push(Violation {
+                page_id,
+                message: "page referenced more than once".to_string(),
+            });
+            return Ok(());
+        }
+        report.pages_visited += 1;
+
+        let guard = self.buffer_pool.fetch_page(page_id)?;
+        let page = guard.read();
+        let cells = match page.get_all_cells() {
+            Ok(cells) => cells,
+            Err(e) => {
+                report.violations.push(Violation {
+                    page_id,
+                    message: e.to_string(),
+                });
+                return Ok(());
+            }
+        };
+        let problems = self.node_problems(&page, &cells, lower, upper, depth);
+        report
+            .violations
+            .extend(problems.into_iter().map(|message| Violation { page_id, message }));
+        if !page.is_interior() {
+            return Ok(());
+        }
+        let right_child = page.right_child();
         drop(page);
         drop(guard);
 
-        // Recursive delete
-        self.delete_recursive(child_id, key)
-
-        // Note: In v1, we don't rebalance after deletion.
-        // A production implementation would merge underflowing nodes.
+        let first_upper = cells.first().map(|c| c.key.as_slice()).or(upper);
+        self.verify_page(right_child, lower, first_upper, depth + 1, seen, report)?;
+        for (i, cell) in cells.iter().enumerate() {
+            let child_upper = cells.get(i + 1).map(|c| c.key.as_slice()).or(upper);
+            self.verify_page(cell.left_child, Some(&cell.key), child_upper, depth + 1, seen, report)?;
+        }
+        Ok(())
     }
 
     /// Recursive scan
@@ -471,101 +2764,67 @@ impl BTree {
         let guard = self.buffer_pool.fetch_page(page_id)?;
         let page = guard.read();
 
+        let order = self.order();
         if page.is_leaf() {
-            // Scan all cells in range
+            // Scan all unexpired cells in range
+            let now = now_millis();
             for i in 0..page.cell_count() {
                 let cell = page.get_cell(i)?;
+                if cell.meta.is_expired(now) {
+                    continue;
+                }
 
                 // Check start bound
                 if let Some(s) = start {
-                    if cell.key.as_slice() < s {
+                    if order.compare(&cell.key, s).is_lt() {
                         continue;
                     }
                 }
 
                 // Check end bound
                 if let Some(e) = end {
-                    if cell.key.as_slice() >= e {
+                    if order.compare(&cell.key, e).is_ge() {
                         break;
                     }
                 }
 
-                results.push((cell.key, cell.value));
+                let value = overflow::load(&*self.buffer_pool, &cell.value, &cell.meta)?;
+                results.push((cell.key, value.into_owned()));
             }
         } else {
-            // Interior node traversal with new semantics:
-            // - right_child contains keys < first separator
-            // - cell[i].left_child contains keys >= cell[i].key and < cell[i+1].key
-            // - last cell's left_child contains keys >= last separator
-            
-            let cell_count = page.cell_count();
-            
-            // Collect children in order: right_child first (smallest keys), then cells
-            let mut children_to_scan: Vec<PageId> = Vec::new();
-            
-            // First: right_child (keys < first separator, or all keys if no cells)
-            let right_child = page.right_child();
-            let first_sep = if cell_count > 0 {
-                Some(page.get_cell(0)?.key.clone())
-            } else {
-                None
-            };
-            
-            // Check if right_child should be scanned
-            let scan_right_child = match (start, &first_sep) {
-                (None, _) => true, // No start bound
-                (Some(_), None) => true, // No separator, scan everything
-                (Some(s), Some(fs)) => s < fs.as_slice(), // Start < first sep
-            };
-            
-            if scan_right_child {
-                // Also check end bound
-                let include_right = end.is_none() || first_sep.is_none() || 
-                    end.unwrap() > [].as_slice(); // always true unless we want to optimize
-                if include_right {
-                    children_to_scan.push(right_child);
-                }
-            }
-            
-            // Then each cell's child
-            for i in 0..cell_count {
-                let cell = page.get_cell(i)?;
-                let cell_lower = cell.key.as_slice();
-                let cell_upper = if i + 1 < cell_count {
-                    Some(page.get_cell(i + 1)?.key.clone())
-                } else {
-                    None // Last cell - no upper bound
+            // Children in key order: right_child holds keys below the first
+            // separator, cell[i].left_child holds keys in [key[i], key[i + 1]).
+            // Descend only into children whose key range overlaps [start, end).
+            let cells = page.get_all_cells()?;
+            let mut children_to_scan = Vec::new();
+            let mut lower: Option<&[u8]> = None;
+            let mut child_id = page.right_child();
+            for i in 0..=cells.len() {
+                let upper = cells.get(i).map(|cell| cell.key.as_slice());
+                let below_end = match (lower, end) {
+                    (Some(lo), Some(e)) => order.compare(lo, e).is_lt(),
+                    _ => true,
                 };
-                
-                // Check if this child's range overlaps with [start, end)
-                let overlaps = match (start, end, &cell_upper) {
-                    (None, None, _) => true,
-                    (Some(s), None, _) => {
-                        // Start bound only: overlaps if cell covers any keys >= s
-                        cell_upper.is_none() || cell_upper.as_ref().unwrap().as_slice() > s
-                    }
-                    (None, Some(e), _) => {
-                        // End bound only: overlaps if cell covers any keys < e
-                        cell_lower < e
-                    }
-                    (Some(s), Some(e), _) => {
-                        // Both bounds: check overlap
-                        let range_start_ok = cell_upper.is_none() || cell_upper.as_ref().unwrap().as_slice() > s;
-                        let range_end_ok = cell_lower < e;
-                        range_start_ok && range_end_ok
-                    }
+                let above_start = match (upper, start) {
+                    (Some(hi), Some(s)) => order.compare(s, hi).is_lt(),
+                    _ => true,
                 };
-                
-                if overlaps {
-                    children_to_scan.push(cell.left_child);
+                if below_end && above_start {
+                    children_to_scan.push(child_id);
+                }
+                if let Some(cell) = cells.get(i) {
+                    lower = Some(&cell.key);
+                    child_id = cell.left_child;
                 }
             }
-            
+
             drop(page);
             drop(guard);
-            
-            // Scan children
-            for child_id in children_to_scan {
+
+            // Read the next few children ahead while scanning each one
+            for (i, &child_id) in children_to_scan.iter().enumerate() {
+                let ahead = (i + 1 + SCAN_PREFETCH).min(children_to_scan.len());
+                self.buffer_pool.prefetch(&children_to_scan[i + 1..ahead]);
                 self.scan_recursive(child_id, start, end, results)?;
             }
         }
@@ -577,7 +2836,8 @@ impl BTree {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::storage::DiskManagerImpl;
+    use crate::buffer::BufferPoolImpl;
+    use crate::storage::{DiskManager, DiskManagerImpl};
     use tempfile::tempdir;
 
     fn create_test_btree() -> Result<(BTree, tempfile::TempDir)> {
@@ -690,4 +2950,678 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_btree_maintenance() -> Result<()> {
+        let (mut btree, _dir) = create_test_btree()?;
+
+        for i in 0..100 {
+            btree.put(format!("key{:03}", i).as_bytes(), b"value")?;
+        }
+        for i in (0..100).step_by(3) {
+            btree.delete(format!("key{:03}", i).as_bytes())?;
+        }
+
+        let report = btree.maintenance()?;
+        assert!(report.is_ok(), "{:?}", report.problems);
+        assert!(report.pages_compacted > 0);
+        assert!(report.reclaimed_bytes() > 0);
+
+        // A second pass finds nothing left to compact
+        let report = btree.maintenance()?;
+        assert!(report.is_ok());
+        assert_eq!(report.pages_compacted, 0);
+
+        assert_eq!(btree.get(b"key001")?, Some(b"value".to_vec()));
+        assert_eq!(btree.get(b"key003")?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_btree_verify() -> Result<()> {
+        let (mut btree, _dir) = create_test_btree()?;
+
+        for i in 0..100 {
+            btree.put(format!("key{:03}", i).as_bytes(), b"value")?;
+        }
+        assert!(btree.height() > 1);
+        let report = btree.verify()?;
+        assert!(report.is_ok(), "{:?}", report.violations);
+        assert!(report.pages_visited > 2);
+
+        // Rewrite the first key of the leftmost leaf so it sorts after the second
+        let leaf_id = btree.edge_leaf(btree.root_page(), false)?;
+        {
+            let guard = btree.fetch_page_mut(leaf_id)?;
+            let mut page = guard.write();
+            let mut bytes = page.as_bytes().to_vec();
+            for at in 0..bytes.len() - 6 {
+                if &bytes[at..at + 6] == b"key000" {
+                    bytes[at + 3] = b'9';
+                }
+            }
+            *page = SlottedPage::from_bytes(&bytes)?;
+        }
+
+        let report = btree.verify()?;
+        assert!(!report.is_ok());
+        assert!(report.violations.iter().all(|v| v.page_id == leaf_id));
+        assert!(report.violations.iter().any(|v| v.message == "keys out of order"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_btree_scan_subtree() -> Result<()> {
+        let (mut btree, _dir) = create_test_btree()?;
+
+        for i in 0..20 {
+            btree.put(format!("key{:02}", i).as_bytes(), b"v")?;
+        }
+        assert!(btree.height() > 1);
+
+        // The root's leftmost child holds everything below the first separator
+        let (left, first_sep) = {
+            let guard = btree.buffer_pool.fetch_page(btree.root_page())?;
+            let page = guard.read();
+            (page.right_child(), page.get_cell(0)?.key)
+        };
+
+        let entries = btree.scan_subtree(left, None, None)?;
+        assert!(!entries.is_empty());
+        assert!(entries.iter().all(|(k, _)| k < &first_sep));
+        assert_eq!(entries[0].0, b"key00");
+
+        let bounded = btree.scan_subtree(left, Some(b"key01"), None)?;
+        assert_eq!(bounded.len(), entries.len() - 1);
+
+        let whole = btree.scan_subtree(btree.root_page(), None, None)?;
+        assert_eq!(whole.len(), 20);
+
+        assert!(btree.scan_subtree(PageId::HEADER, None, None).is_err());
+        assert!(btree.scan_subtree(PageId::new(10_000), None, None).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_btree_split_progress_shared_prefixes() -> Result<()> {
+        use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+        let dir = tempdir().unwrap();
+        let dm = Arc::new(DiskManagerImpl::open(&dir.path().join("test.db"), false)?);
+        let pool = Arc::new(BufferPoolImpl::new(dm, 100));
+        let mut btree = BTree::with_config(pool, BTreeConfig::high_capacity())?;
+
+        // Long common prefixes make every split boundary a near-collision,
+        // and mixed value sizes make count-based midpoints unbalanced. Cells
+        // stay under half a page, where a two-way split always exists.
+        let mut rng = StdRng::seed_from_u64(1469);
+        let prefix = vec![b'p'; 900];
+        let mut ids: Vec<u32> = (0..400).collect();
+        ids.shuffle(&mut rng);
+
+        let mut expected = std::collections::BTreeMap::new();
+        for id in ids {
+            let mut key = prefix.clone();
+            key.extend(format!("{:04}", id).bytes());
+            let value = vec![b'v'; if rng.gen_bool(0.3) { 1000 } else { 10 }];
+            btree.put(&key, &value)?;
+            expected.insert(key, value);
+        }
+
+        for (key, value) in &expected {
+            assert_eq!(btree.get(key)?.as_ref(), Some(value));
+        }
+        let all = btree.scan(None, None)?;
+        assert_eq!(all.len(), expected.len());
+        assert!(btree.maintenance()?.is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_btree_append_batch() -> Result<()> {
+        let (mut btree, _dir) = create_test_btree()?;
+
+        btree.put(b"key0050", b"existing")?;
+
+        // The first keys sort before the existing one and take the normal path
+        let entries: Vec<(Vec<u8>, Vec<u8>)> = (0..500)
+            .map(|i| (format!("key{:04}", i * 2).into_bytes(), format!("v{}", i).into_bytes()))
+            .collect();
+        btree.append_batch(&entries)?;
+
+        for (key, value) in &entries {
+            assert_eq!(btree.get(key)?.as_ref(), Some(value));
+        }
+        assert_eq!(btree.scan(None, None)?.len(), 500);
+        assert!(btree.height() > 2);
+        assert!(btree.maintenance()?.is_ok());
+
+        // Later puts still route correctly
+        btree.put(b"key0001", b"odd")?;
+        assert_eq!(btree.get(b"key0001")?, Some(b"odd".to_vec()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_btree_append_batch_rejects_unsorted() -> Result<()> {
+        let (mut btree, _dir) = create_test_btree()?;
+
+        let entries = [(b"b", b"1"), (b"a", b"2")];
+        assert!(btree.append_batch(&entries).is_err());
+        assert_eq!(btree.get(b"b")?, None);
+
+        let entries = [(b"a", b"1"), (b"a", b"2")];
+        assert!(btree.append_batch(&entries).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bloom_filter_skips_leaf_reads() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let dm = Arc::new(DiskManagerImpl::open(&path, false)?);
+        let pool = Arc::new(BufferPoolImpl::new(dm, 8));
+        let config = BTreeConfig::default().bloom_filters(true);
+        let mut btree = BTree::with_config(pool.clone(), config)?;
+
+        for i in 0..200 {
+            btree.put(format!("key{:04}", i).as_bytes(), b"value")?;
+        }
+        for i in 0..200 {
+            assert!(btree.get(format!("key{:04}", i).as_bytes())?.is_some());
+        }
+
+        // Find the leaf an absent key routes to
+        let missing = b"key0005x";
+        let mut leaf_id = btree.root_page();
+        loop {
+            let guard = pool.fetch_page(leaf_id)?;
+            let page = guard.read();
+            if page.is_leaf() {
+                assert!(page.has_bloom());
+                break;
+            }
+            leaf_id = page.find_child(missing)?;
+        }
+
+        // Push that leaf out of the pool, then look the key up again
+        for i in 150..200 {
+            btree.get(format!("key{:04}", i).as_bytes())?;
+        }
+        assert!(!pool.is_cached(leaf_id));
+        assert_eq!(btree.get(missing)?, None);
+        assert!(!pool.is_cached(leaf_id));
+
+        // Writing to the leaf keeps lookups correct
+        btree.put(missing, b"late")?;
+        assert_eq!(btree.get(missing)?, Some(b"late".to_vec()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_inline_interior_values() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let dm = Arc::new(DiskManagerImpl::open(&path, false)?);
+        let pool = Arc::new(BufferPoolImpl::new(dm, 100));
+        let config = BTreeConfig::default().inline_interior_values(16);
+        let mut btree = BTree::with_config(pool.clone(), config)?;
+
+        for i in 0..200 {
+            btree.put(format!("key{:04}", i).as_bytes(), format!("v{}", i).as_bytes())?;
+        }
+
+        // Separators promoted from leaf splits carry their key's value
+        let inline_values = |btree: &BTree| -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+            let mut pages = Vec::new();
+            btree.collect_pages(btree.root_page(), &mut pages)?;
+            let mut values = Vec::new();
+            for page_id in pages {
+                let guard = pool.fetch_page(page_id)?;
+                let page = guard.read();
+                if page.has_inline_values() {
+                    for cell in page.get_all_cells()? {
+                        if !cell.value.is_empty() {
+                            values.push((cell.key, cell.value));
+                        }
+                    }
+                }
+            }
+            Ok(values)
+        };
+        let values = inline_values(&btree)?;
+        assert!(!values.is_empty());
+        for (key, value) in &values {
+            assert_eq!(btree.get(key)?.as_ref(), Some(value));
+        }
+        let sep = values[0].0.clone();
+        let inline_of = |btree: &BTree, key: &[u8]| -> Result<Option<Vec<u8>>> {
+            Ok(inline_values(btree)?.into_iter().find(|(k, _)| k == key).map(|(_, v)| v))
+        };
+
+        // Updates and deletes keep the separator's copy in step
+        btree.put(&sep, b"updated")?;
+        assert_eq!(inline_of(&btree, &sep)?, Some(b"updated".to_vec()));
+        assert_eq!(btree.get(&sep)?, Some(b"updated".to_vec()));
+
+        btree.put(&sep, &[b'x'; 32])?;
+        assert_eq!(inline_of(&btree, &sep)?, None);
+        assert_eq!(btree.get(&sep)?, Some(vec![b'x'; 32]));
+
+        btree.put(&sep, b"small")?;
+        assert!(btree.delete(&sep)?);
+        assert_eq!(inline_of(&btree, &sep)?, None);
+        assert_eq!(btree.get(&sep)?, None);
+
+        for i in 0..200 {
+            let key = format!("key{:04}", i);
+            if key.as_bytes() != sep.as_slice() {
+                assert_eq!(btree.get(key.as_bytes())?, Some(format!("v{}", i).into_bytes()));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_rebalances() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let dm = Arc::new(DiskManagerImpl::open(&path, false)?);
+        let pool = Arc::new(BufferPoolImpl::new(dm.clone(), 100));
+        let mut btree = BTree::new(pool)?;
+
+        for i in 0..200 {
+            btree.put(format!("key{:03}", i).as_bytes(), format!("value{}", i).as_bytes())?;
+        }
+        let live_pages = |btree: &BTree| -> Result<usize> {
+            let mut pages = Vec::new();
+            btree.collect_pages(btree.root_page(), &mut pages)?;
+            Ok(pages.len())
+        };
+        let pages_before = live_pages(&btree)?;
+        let height_before = btree.height();
+
+        // Delete from both ends and the middle, keeping every 20th key
+        for i in (0..200).filter(|i| i % 20 != 0) {
+            assert!(btree.delete(format!("key{:03}", i).as_bytes())?);
+        }
+
+        for i in 0..200 {
+            let expected = (i % 20 == 0).then(|| format!("value{}", i).into_bytes());
+            assert_eq!(btree.get(format!("key{:03}", i).as_bytes())?, expected);
+        }
+        assert_eq!(btree.scan(None, None)?.len(), 10);
+        assert!(btree.maintenance()?.is_ok());
+
+        let stats = btree.structure_stats();
+        assert!(stats.merges > 0);
+        assert!(live_pages(&btree)? < pages_before / 4);
+        assert!(btree.height() < height_before);
+        assert_eq!(btree.measure_height()?, btree.height());
+
+        // Freed pages are handed out again instead of growing the file
+        let header = dm.header();
+        assert!(header.free_page_count as usize >= pages_before - live_pages(&btree)?);
+        assert_eq!(header.tree_height as usize, btree.height());
+        for i in 0..50 {
+            btree.put(format!("new{:03}", i).as_bytes(), b"v")?;
+        }
+        assert_eq!(dm.header().page_count, header.page_count);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_skips_children_outside_range() -> Result<()> {
+        let (mut btree, _dir) = create_test_btree()?;
+        for i in 0..500 {
+            btree.put(format!("key{:04}", i).as_bytes(), b"v")?;
+        }
+
+        let (leftmost, first_sep) = {
+            let guard = btree.buffer_pool.fetch_page(btree.root_page())?;
+            let page = guard.read();
+            assert!(!page.is_leaf());
+            (page.right_child(), page.get_cell(0)?.key)
+        };
+
+        // Plant a key in the leftmost child that a scan would return if it
+        // visited that child
+        {
+            let mut planted = SlottedPage::new_leaf();
+            planted.insert_cell(&Cell::new_leaf(b"zzz".to_vec(), b"v".to_vec()))?;
+            let guard = btree.buffer_pool.fetch_page_mut(leftmost)?;
+            *guard.write() = planted;
+        }
+
+        // Ranges entirely at or above the first separator skip it
+        let results = btree.scan(Some(&first_sep), None)?;
+        assert_eq!(results.first().unwrap().0, first_sep);
+        assert!(results.iter().all(|(key, _)| key.as_slice() != b"zzz"));
+        let results = btree.scan(Some(&first_sep), Some(b"zzzz"))?;
+        assert!(results.iter().all(|(key, _)| key.as_slice() != b"zzz"));
+
+        // A range starting below it does visit it
+        let results = btree.scan(Some(b"key"), None)?;
+        assert!(results.iter().any(|(key, _)| key.as_slice() == b"zzz"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bulk_load_matches_puts() -> Result<()> {
+        let entries: Vec<(Vec<u8>, Vec<u8>)> = (0..5000)
+            .map(|i| (format!("key{:05}", i).into_bytes(), format!("value{}", i).into_bytes()))
+            .collect();
+
+        let (mut loaded, _dir) = create_test_btree()?;
+        loaded.bulk_load(entries.clone())?;
+        let (mut inserted, _dir2) = create_test_btree()?;
+        for (key, value) in &entries {
+            inserted.put(key, value)?;
+        }
+
+        assert_eq!(loaded.scan(None, None)?, inserted.scan(None, None)?);
+        assert_eq!(loaded.scan(Some(b"key01000"), Some(b"key01010"))?.len(), 10);
+        for (key, value) in entries.iter().step_by(7) {
+            assert_eq!(loaded.get(key)?.as_ref(), Some(value));
+        }
+        assert_eq!(loaded.len()?, 5000);
+        assert!(loaded.maintenance()?.is_ok());
+        loaded.check_root()?;
+
+        // The loaded tree takes further writes
+        loaded.put(b"key02500x", b"new")?;
+        assert!(loaded.delete(b"key00000")?);
+        assert_eq!(loaded.get(b"key02500x")?, Some(b"new".to_vec()));
+        assert_eq!(loaded.first()?.unwrap().0, b"key00001");
+
+        // Small nodes give a deep tree
+        let dir = tempdir().unwrap();
+        let dm = Arc::new(DiskManagerImpl::open(&dir.path().join("small.db"), false)?);
+        let pool = Arc::new(BufferPoolImpl::new(dm, 100));
+        let mut small = BTree::with_config(pool, BTreeConfig::new(3, 2))?;
+        small.bulk_load(entries.iter().take(100).cloned())?;
+        assert!(small.height() > 3);
+        assert!(small.maintenance()?.is_ok());
+        assert_eq!(small.scan(None, None)?, entries[..100].to_vec());
+
+        // Unsorted input loads nothing, and a loaded tree takes no more
+        let (mut unsorted, _dir3) = create_test_btree()?;
+        assert!(unsorted.bulk_load(vec![(b"b".to_vec(), b"1".to_vec()), (b"a".to_vec(), b"2".to_vec())]).is_err());
+        assert!(unsorted.is_empty()?);
+        assert!(loaded.bulk_load(entries).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_btree_in_memory() -> Result<()> {
+        let dm = Arc::new(crate::storage::MemDiskManager::new());
+        let pool = Arc::new(BufferPoolImpl::new(dm.clone(), 16));
+        let mut btree = BTree::with_config(pool.clone(), BTreeConfig::new(3, 2))?;
+
+        for i in 0..500u32 {
+            btree.put(format!("key{:04}", i).as_bytes(), &i.to_le_bytes())?;
+        }
+        for i in (0..500u32).step_by(2) {
+            btree.delete(format!("key{:04}", i).as_bytes())?;
+        }
+        pool.flush_all()?;
+
+        assert_eq!(btree.len()?, 250);
+        assert_eq!(btree.get(b"key0001")?, Some(1u32.to_le_bytes().to_vec()));
+        assert_eq!(btree.get(b"key0002")?, None);
+        assert_eq!(dm.header().root_page, btree.root_page());
+        assert!(dm.header().page_count > 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_btree_random_inserts_all_retrievable() -> Result<()> {
+        use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+        let dir = tempdir().unwrap();
+        let dm = Arc::new(DiskManagerImpl::open(&dir.path().join("test.db"), false)?);
+        let pool = Arc::new(BufferPoolImpl::new(dm, 4000));
+        let mut btree = BTree::with_config(pool.clone(), BTreeConfig::new(4, 3))?;
+
+        // A small fanout makes for many levels of interior splits
+        let mut ids: Vec<u32> = (0..10_000).collect();
+        ids.shuffle(&mut StdRng::seed_from_u64(1550));
+        for &id in &ids {
+            btree.put(format!("key{:05}", id).as_bytes(), &id.to_be_bytes())?;
+        }
+
+        for id in 0..10_000u32 {
+            let value = btree.get(format!("key{:05}", id).as_bytes())?;
+            assert_eq!(value, Some(id.to_be_bytes().to_vec()), "key{:05}", id);
+        }
+        assert!(btree.verify()?.is_ok());
+
+        // Splits never leave an interior page without a separator
+        let mut pending = vec![btree.root_page()];
+        while let Some(page_id) = pending.pop() {
+            let guard = pool.fetch_page(page_id)?;
+            let page = guard.read();
+            if page.is_interior() {
+                assert!(page.cell_count() > 0, "page {} is empty", page_id);
+                pending.push(page.right_child());
+                for i in 0..page.cell_count() {
+                    pending.push(page.get_cell(i)?.left_child);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_btree_append_split_packs_leaves() -> Result<()> {
+        // Average fill of the leaves after ascending inserts
+        let fill = |config: BTreeConfig| -> Result<f64> {
+            let dir = tempdir().unwrap();
+            let dm = Arc::new(DiskManagerImpl::open(&dir.path().join("test.db"), false)?);
+            let pool = Arc::new(BufferPoolImpl::new(dm, 1000));
+            let mut btree = BTree::with_config(pool.clone(), config)?;
+            for i in 0..3000 {
+                btree.put(format!("key{:06}", i).as_bytes(), &[b'v'; 100])?;
+            }
+            assert_eq!(btree.scan(None, None)?.len(), 3000);
+            assert!(btree.verify()?.is_ok());
+
+            let mut pending = vec![btree.root_page()];
+            let mut fills = Vec::new();
+            while let Some(page_id) = pending.pop() {
+                let guard = pool.fetch_page(page_id)?;
+                let page = guard.read();
+                if page.is_leaf() {
+                    fills.push(1.0 - page.free_space() as f64 / crate::types::PAGE_SIZE as f64);
+                    continue;
+                }
+                pending.push(page.right_child());
+                for i in 0..page.cell_count() {
+                    pending.push(page.get_cell(i)?.left_child);
+                }
+            }
+            Ok(fills.iter().sum::<f64>() / fills.len() as f64)
+        };
+
+        let even = fill(BTreeConfig::high_capacity().append_split_ratio(0.5))?;
+        let packed = fill(BTreeConfig::high_capacity())?;
+        assert!(even < 0.6, "even splits filled {:.2}", even);
+        assert!(packed > 0.85, "append splits filled {:.2}", packed);
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_full_reports_page_id() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let dm = Arc::new(DiskManagerImpl::open(&dir.path().join("test.db"), false)?);
+        let pool = Arc::new(BufferPoolImpl::new(dm, 100));
+        let mut btree = BTree::with_config(pool, BTreeConfig::high_capacity())?;
+
+        // Two cells that just share a leaf, then one between them that
+        // fits beside neither, so no two-way split exists
+        btree.put(&[b'a'; MAX_KEY_SIZE], &[b'v'; 1005])?;
+        btree.put(&[b'c'; MAX_KEY_SIZE], &[b'v'; 1005])?;
+        let leaf = btree.root_page();
+        match btree.put(&[b'b'; MAX_KEY_SIZE], &[b'v'; 1024]) {
+            Err(StorageError::PageFull { page_id, .. }) => assert_eq!(page_id, leaf),
+            other => panic!("expected PageFull, got {:?}", other),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_merges_interiors_up_to_root() -> Result<()> {
+        use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+        let dir = tempdir().unwrap();
+        let dm = Arc::new(DiskManagerImpl::open(&dir.path().join("test.db"), false)?);
+        let pool = Arc::new(BufferPoolImpl::new(dm, 1000));
+        let mut btree = BTree::with_config(pool, BTreeConfig::new(3, 2))?;
+        let mut keys: Vec<u32> = (0..600).collect();
+        keys.shuffle(&mut StdRng::seed_from_u64(11));
+        for key in &keys {
+            btree.put(&key.to_be_bytes(), b"v")?;
+        }
+        assert!(btree.height() >= 4);
+
+        // Fewest separators on any interior page below the root
+        fn min_separators(btree: &BTree, page_id: PageId, is_root: bool) -> Result<usize> {
+            let page = btree.buffer_pool.fetch_page(page_id)?.read().clone();
+            if page.is_leaf() {
+                return Ok(usize::MAX);
+            }
+            let mut min = if is_root { usize::MAX } else { page.cell_count() };
+            let mut children = vec![page.right_child()];
+            children.extend(page.get_all_cells()?.iter().map(|c| c.left_child));
+            for child in children {
+                min = min.min(min_separators(btree, child, false)?);
+            }
+            Ok(min)
+        }
+
+        keys.shuffle(&mut StdRng::seed_from_u64(12));
+        let mut height = btree.height();
+        for (n, key) in keys.iter().enumerate() {
+            assert!(btree.delete(&key.to_be_bytes())?);
+            // The root collapses one level at a time, never skipping one
+            assert!(btree.height() == height || btree.height() == height - 1);
+            height = btree.height();
+            assert_eq!(btree.measure_height()?, height);
+
+            if n % 50 == 0 {
+                assert!(btree.verify()?.violations.is_empty());
+                assert!(min_separators(&btree, btree.root_page(), true)? >= 1);
+                for survivor in &keys[n + 1..] {
+                    assert!(btree.get(&survivor.to_be_bytes())?.is_some());
+                }
+            }
+        }
+        assert_eq!(btree.height(), 1);
+        assert!(btree.scan(None, None)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_splits_at_configured_key_counts() -> Result<()> {
+        let (mut btree, _dir) = create_test_btree()?;
+        let config = btree.config().clone();
+        assert_eq!((config.max_leaf_keys, config.max_interior_keys), (4, 3));
+
+        // Four small keys leave most of the leaf free, but a fifth splits it
+        for key in [b"a", b"b", b"c", b"d"] {
+            btree.put(key, b"v")?;
+        }
+        assert_eq!(btree.height(), 1);
+        btree.put(b"e", b"v")?;
+        assert_eq!(btree.height(), 2);
+        let root = btree.buffer_pool.fetch_page(btree.root_page())?.read().clone();
+        assert_eq!(root.cell_count(), 1);
+
+        // No page ever holds more keys than its limit
+        fn check_limits(btree: &BTree, page_id: PageId) -> Result<()> {
+            let page = btree.buffer_pool.fetch_page(page_id)?.read().clone();
+            if page.is_leaf() {
+                assert!(page.cell_count() <= btree.config().max_leaf_keys);
+                return Ok(());
+            }
+            assert!(page.cell_count() <= btree.config().max_interior_keys);
+            check_limits(btree, page.right_child())?;
+            for cell in page.get_all_cells()? {
+                check_limits(btree, cell.left_child)?;
+            }
+            Ok(())
+        }
+        for i in 0..200 {
+            btree.put(format!("key{:03}", (i * 37) % 200).as_bytes(), b"v")?;
+            check_limits(&btree, btree.root_page())?;
+        }
+        assert!(btree.height() >= 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_config_limits_keys_per_page() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let open = |name: &str, config: BTreeConfig| -> Result<BTree> {
+            let dm = Arc::new(DiskManagerImpl::open(&dir.path().join(name), false)?);
+            BTree::with_config(Arc::new(BufferPoolImpl::new(dm, 100)), config)
+        };
+        let mut small = open("small.db", BTreeConfig::default())?;
+        let mut large = open("large.db", BTreeConfig::high_capacity())?;
+        assert_eq!(large.config().max_leaf_keys, 1000);
+
+        for i in 0..100 {
+            let key = format!("key{:03}", i);
+            small.put(key.as_bytes(), b"v")?;
+            large.put(key.as_bytes(), b"v")?;
+        }
+
+        // All 100 small entries fit in one leaf unless the config says not
+        assert_eq!(large.height(), 1);
+        assert!(small.height() >= 3);
+        let leaf = large.buffer_pool.fetch_page(large.root_page())?.read().clone();
+        assert_eq!(leaf.cell_count(), 100);
+        assert_eq!(small.scan(None, None)?, large.scan(None, None)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rename_keeps_ttl() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let dm = Arc::new(DiskManagerImpl::open(&path, false)?);
+        let pool = Arc::new(BufferPoolImpl::new(dm, 100));
+        let config = BTreeConfig::default().track_modified(true);
+        let mut btree = BTree::with_config(pool, config)?;
+
+        let big = vec![7u8; 10_000];
+        btree.put_with_ttl(b"tmp", &big, Duration::from_secs(3600))?;
+        btree.put(b"target", b"old")?;
+        btree.put_with_ttl(b"gone", b"x", Duration::ZERO)?;
+        let (_, before) = btree.get_with_meta(b"tmp")?.unwrap();
+
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(btree.rename(b"tmp", b"target", true)?);
+        let (value, after) = btree.get_with_meta(b"target")?.unwrap();
+        assert_eq!(value, big);
+        assert!(after.expires.is_some());
+        assert_eq!(after.expires, before.expires);
+        assert_eq!(after.modified, before.modified);
+        assert_eq!(btree.get(b"tmp")?, None);
+
+        // An expired entry is not there to rename
+        assert!(!btree.rename(b"gone", b"back", false)?);
+        assert_eq!(btree.get(b"back")?, None);
+        Ok(())
+    }
 }
@@ -140,9 +140,8 @@ impl LruCache {
         }
     }
 
-    /// Get the number of items in the cache (test only)
-    #[cfg(test)]
-    fn len(&self) -> usize {
+    /// Get the number of items in the cache
+    pub fn len(&self) -> usize {
         self.positions.len()
     }
 
@@ -6,4 +6,7 @@
 mod lru;
 mod pool;
 
-pub use pool::{BufferPool, BufferPoolImpl, PageGuard, PageGuardMut};
+pub use pool::{
+    BufferFrame, BufferPool, BufferPoolImpl, CacheStats, PageGuard, PageGuardMut, PageRef,
+    PageRefMut,
+};
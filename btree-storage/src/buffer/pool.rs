@@ -1,15 +1,17 @@
 //! Buffer pool implementation.
 //!
 //! The buffer pool manages a fixed number of in-memory page frames,
-//! caching pages read from disk and writing dirty pages back.
+//! caching pages read from disk and writing dirty pages back. Frames are
+//! preallocated in an arena and recycled, never allocated per page.
 
 use crate::buffer::lru::LruCache;
 use crate::error::{Result, StorageError};
 use crate::page::SlottedPage;
 use crate::storage::DiskManager;
 use crate::types::PageId;
-use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use parking_lot::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 
 /// Trait for buffer pool operations
@@ -17,6 +19,12 @@ pub trait BufferPool: Send + Sync {
     /// Fetch a page from the buffer pool
     fn fetch_page(&self, page_id: PageId) -> Result<PageGuard<'_>>;
 
+    /// Fetch a page only if it is already resident, never reading from disk
+    fn fetch_cached(&self, page_id: PageId) -> Option<PageGuard<'_>>;
+
+    /// Check whether a page is resident in the buffer pool
+    fn is_cached(&self, page_id: PageId) -> bool;
+
     /// Fetch a page for writing
     fn fetch_page_mut(&self, page_id: PageId) -> Result<PageGuardMut<'_>>;
 
@@ -24,20 +32,39 @@ pub trait BufferPool: Send + Sync {
     fn new_page(&self) -> Result<(PageId, PageGuardMut<'_>)>;
 
     /// Flush a specific page to disk
+    ///
+    /// The page is written but not synced; only `flush_all` is a durability
+    /// barrier.
     fn flush_page(&self, page_id: PageId) -> Result<()>;
 
-    /// Flush all dirty pages to disk
+    /// Flush all dirty pages to disk, then sync the file header
+    ///
+    /// Ends with one `DiskManager::sync`, so everything written before it
+    /// returns is durable.
     fn flush_all(&self) -> Result<()>;
 
+    /// Flush and sync all dirty pages without writing the file header
+    fn flush_data(&self) -> Result<()>;
+
     /// Deallocate a page
     fn free_page(&self, page_id: PageId) -> Result<()>;
 
+    /// Drop every resident page without writing it back, so the next
+    /// fetch of each reads the file
+    ///
+    /// Fails with `InvalidOperation`, dropping nothing, if a page is pinned.
+    fn discard_all(&self) -> Result<()>;
+
     /// Get the total number of pages in the database
     fn page_count(&self) -> usize;
 
     /// Get the buffer pool capacity
     fn capacity(&self) -> usize;
 
+    /// Get the number of resident pages changed since they were last
+    /// written to disk
+    fn dirty_page_count(&self) -> usize;
+
     /// Get the root page ID from the file header
     fn root_page(&self) -> PageId;
 
@@ -46,136 +73,380 @@ pub trait BufferPool: Send + Sync {
 
     /// Set the root page and height in the file header
     fn set_root_page(&self, page_id: PageId, height: u32) -> Result<()>;
+
+    /// Get the main tree's entry count from the file header
+    fn entry_count(&self) -> u64;
+
+    /// Set the main tree's entry count in the file header
+    fn set_entry_count(&self, count: u64) -> Result<()>;
+
+    /// Read pages into the pool ahead of use
+    ///
+    /// A hint: pages already resident are skipped and failures are ignored,
+    /// leaving them to surface on the actual fetch. Does nothing by default.
+    fn prefetch(&self, _page_ids: &[PageId]) {}
+
+    /// Release a guard's pin on a frame
+    ///
+    /// Called when a page guard is dropped. Pools that track recency can
+    /// override this to record the access before unpinning.
+    fn release(&self, _page_id: PageId, frame: &BufferFrame) {
+        frame.unpin();
+    }
 }
 
 /// A frame in the buffer pool
-struct BufferFrame {
-    /// The page data
-    page: SlottedPage,
+///
+/// Frames are allocated once when the pool is created and recycled for
+/// different pages afterwards. The pin count and dirty flag live outside the
+/// page latch so eviction can inspect them without blocking on a latched page.
+///
+/// Custom `BufferPool` implementations hold their pages in frames and hand
+/// pinned frames to `PageGuard::new` / `PageGuardMut::new`.
+pub struct BufferFrame {
+    /// The page data, protected by the page latch
+    page: RwLock<SlottedPage>,
     /// Whether the page has been modified
-    dirty: bool,
+    dirty: AtomicBool,
     /// Pin count (number of active references)
-    pin_count: u32,
+    pin_count: AtomicU32,
+}
+
+impl Default for BufferFrame {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl BufferFrame {
-    fn new(page: SlottedPage) -> Self {
+    /// Create an unpinned, clean frame holding an empty leaf page
+    pub fn new() -> Self {
         Self {
-            page,
-            dirty: false,
-            pin_count: 0,
+            page: RwLock::new(SlottedPage::new_leaf()),
+            dirty: AtomicBool::new(false),
+            pin_count: AtomicU32::new(0),
         }
     }
+
+    /// Get the page latch
+    pub fn page(&self) -> &RwLock<SlottedPage> {
+        &self.page
+    }
+
+    /// Increment the pin count
+    pub fn pin(&self) {
+        self.pin_count.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Decrement the pin count
+    pub fn unpin(&self) {
+        let _ = self
+            .pin_count
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| count.checked_sub(1));
+    }
+
+    /// Check whether any guard holds this frame
+    pub fn is_pinned(&self) -> bool {
+        self.pin_count.load(Ordering::Acquire) > 0
+    }
+
+    /// Check whether the page has unflushed modifications
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.load(Ordering::Acquire)
+    }
+
+    /// Set the dirty flag, returning its previous value
+    pub fn set_dirty(&self, dirty: bool) -> bool {
+        self.dirty.swap(dirty, Ordering::AcqRel)
+    }
+}
+
+/// Counters for page fetches served from the pool or read from disk
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Fetches of pages that were already resident
+    pub hits: u64,
+    /// Fetches that had to read the page from disk
+    pub misses: u64,
 }
 
 /// Buffer pool implementation
+///
+/// All frames are preallocated in an arena when the pool is created.
+/// Steady-state operation only recycles those frames, so page management
+/// does no heap allocation outside `new` and `resize`.
 pub struct BufferPoolImpl {
     /// The disk manager for I/O
     disk_manager: Arc<dyn DiskManager>,
-    /// Cached frames indexed by page ID
-    frames: RwLock<HashMap<PageId, Arc<RwLock<BufferFrame>>>>,
+    /// Arena of frames, allocated in `new` and grown by `resize`
+    frames: RwLock<Vec<Arc<BufferFrame>>>,
+    /// Maps resident page IDs to their frame index in the arena
+    page_table: RwLock<HashMap<PageId, usize>>,
+    /// Indices of frames that currently hold no page
+    free_frames: Mutex<Vec<usize>>,
     /// LRU cache for eviction
     lru: RwLock<LruCache>,
-    /// Maximum number of frames
-    capacity: usize,
+    /// Maximum number of resident pages
+    capacity: AtomicUsize,
+    /// Fetches served from resident pages
+    hits: AtomicU64,
+    /// Fetches that read from disk
+    misses: AtomicU64,
 }
 
 impl BufferPoolImpl {
     /// Create a new buffer pool
     pub fn new(disk_manager: Arc<dyn DiskManager>, capacity: usize) -> Self {
+        let frames = (0..capacity).map(|_| Arc::new(BufferFrame::new())).collect();
+        // Hand out low indices first
+        let free_frames = (0..capacity).rev().collect();
+
         Self {
             disk_manager,
-            frames: RwLock::new(HashMap::with_capacity(capacity)),
+            frames: RwLock::new(frames),
+            page_table: RwLock::new(HashMap::with_capacity(capacity)),
+            free_frames: Mutex::new(free_frames),
             lru: RwLock::new(LruCache::new(capacity)),
-            capacity,
+            capacity: AtomicUsize::new(capacity),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Get the cache hit and miss counts since the pool was created
+    ///
+    /// Prefetches are not counted; a fetch of a prefetched page is a hit.
+    pub fn cache_stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Get the number of pages currently held in the pool
+    pub fn resident_pages(&self) -> usize {
+        self.lru.read().len()
+    }
+
+    /// Get the fraction of the capacity that resident pages take up
+    ///
+    /// Can exceed 1.0 for a while after shrinking past pinned pages.
+    pub fn fill_ratio(&self) -> f64 {
+        self.resident_pages() as f64 / self.capacity() as f64
+    }
+
+    /// Keep a page resident without holding a guard
+    ///
+    /// Loads the page if needed and adds a pin that only `unpin` removes,
+    /// so eviction passes the page over until then. Pins nest: each call
+    /// needs its own `unpin`.
+    pub fn pin(&self, page_id: PageId) -> Result<()> {
+        self.pin_frame(page_id)?;
+        Ok(())
+    }
+
+    /// Release a pin taken with `pin`
+    ///
+    /// Does nothing for a page that is not resident. The pin count stops at
+    /// zero, so extra calls are harmless once every pin is gone.
+    pub fn unpin(&self, page_id: PageId) {
+        if let Some(&index) = self.page_table.read().get(&page_id) {
+            self.frame(index).unpin();
         }
     }
 
-    /// Get or load a frame for a page
-    fn get_frame(&self, page_id: PageId) -> Result<Arc<RwLock<BufferFrame>>> {
-        // Check if already in buffer
+    /// Change the number of pages the pool holds
+    ///
+    /// Growing allocates the extra frames. Shrinking evicts least recently
+    /// used pages, writing dirty ones back, until the resident pages fit.
+    /// Pinned pages are never evicted, so the pool stays over the new
+    /// capacity until they are released and later loads evict them.
+    pub fn resize(&self, new_capacity: usize) -> Result<()> {
+        if new_capacity == 0 {
+            return Err(StorageError::invalid_operation(
+                "buffer pool capacity must be at least 1",
+            ));
+        }
+
+        let mut page_table = self.page_table.write();
+        self.capacity.store(new_capacity, Ordering::Relaxed);
         {
-            let frames = self.frames.read();
-            if let Some(frame) = frames.get(&page_id) {
-                let mut lru = self.lru.write();
-                lru.access(page_id.value());
-                return Ok(Arc::clone(frame));
+            let mut frames = self.frames.write();
+            let mut free_frames = self.free_frames.lock();
+            while frames.len() < new_capacity {
+                free_frames.push(frames.len());
+                frames.push(Arc::new(BufferFrame::new()));
+            }
+        }
+
+        let excess = self.lru.read().len().saturating_sub(new_capacity);
+        for _ in 0..excess {
+            match self.evict_one(&mut page_table) {
+                Ok(index) => self.free_frames.lock().push(index),
+                Err(StorageError::BufferPoolExhausted) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the frame for a page, loading it from disk if necessary
+    ///
+    /// The returned frame is already pinned on behalf of the caller.
+    fn pin_frame(&self, page_id: PageId) -> Result<Arc<BufferFrame>> {
+        // Check if already in buffer. Pinning under the page table lock
+        // keeps the frame from being evicted and recycled underneath us.
+        {
+            let page_table = self.page_table.read();
+            if let Some(&index) = page_table.get(&page_id) {
+                let frame = self.frame(index);
+                frame.pin();
+                drop(page_table);
+                self.lru.write().access(page_id.value());
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(frame);
             }
         }
 
         // Need to load from disk
+        self.misses.fetch_add(1, Ordering::Relaxed);
         self.load_page(page_id)
     }
 
-    /// Load a page from disk into the buffer pool
-    fn load_page(&self, page_id: PageId) -> Result<Arc<RwLock<BufferFrame>>> {
+    /// Pin a page's frame only if it is already resident
+    fn pin_resident(&self, page_id: PageId) -> Option<Arc<BufferFrame>> {
+        let page_table = self.page_table.read();
+        let &index = page_table.get(&page_id)?;
+        let frame = self.frame(index);
+        frame.pin();
+        Some(frame)
+    }
+
+    /// Load a page from disk into a free or evicted frame
+    fn load_page(&self, page_id: PageId) -> Result<Arc<BufferFrame>> {
+        let mut page_table = self.page_table.write();
+
+        // Another thread may have loaded the page while we waited
+        if let Some(&index) = page_table.get(&page_id) {
+            let frame = self.frame(index);
+            frame.pin();
+            drop(page_table);
+            self.lru.write().access(page_id.value());
+            return Ok(frame);
+        }
+
         // Read from disk
         let page_buf = self.disk_manager.read_page(page_id)?;
         let page = SlottedPage::from_bytes(page_buf.as_bytes())?;
 
-        // Evict if necessary
-        {
-            let frames = self.frames.read();
-            if frames.len() >= self.capacity {
-                drop(frames);
-                self.evict_one()?;
-            }
-        }
+        let index = self.claim_frame(&mut page_table)?;
+        let frame = self.frame(index);
+        *frame.page.write() = page;
+        frame.dirty.store(false, Ordering::Release);
+        frame.pin();
+        page_table.insert(page_id, index);
+        drop(page_table);
 
-        // Insert into buffer
-        let frame = Arc::new(RwLock::new(BufferFrame::new(page)));
-        {
-            let mut frames = self.frames.write();
-            frames.insert(page_id, Arc::clone(&frame));
-        }
-        {
-            let mut lru = self.lru.write();
-            lru.access(page_id.value());
-        }
+        self.lru.write().access(page_id.value());
 
         Ok(frame)
     }
 
-    /// Evict one page from the buffer pool
-    fn evict_one(&self) -> Result<()> {
+    /// Get the frame at an arena index
+    fn frame(&self, index: usize) -> Arc<BufferFrame> {
+        Arc::clone(&self.frames.read()[index])
+    }
+
+    /// Take a frame from the free list, evicting a page if none is free or
+    /// the pool is at capacity
+    fn claim_frame(&self, page_table: &mut HashMap<PageId, usize>) -> Result<usize> {
+        if page_table.len() < self.capacity() {
+            if let Some(index) = self.free_frames.lock().pop() {
+                return Ok(index);
+            }
+        }
+        self.evict_one(page_table)
+    }
+
+    /// Evict one page from the buffer pool, returning the freed frame index
+    fn evict_one(&self, page_table: &mut HashMap<PageId, usize>) -> Result<usize> {
         let mut lru = self.lru.write();
 
-        // Find an unpinned page to evict
-        loop {
+        // Each resident page is considered at most once; pinned pages are
+        // moved back to the front so the sweep terminates.
+        for _ in 0..lru.len() {
             let page_id = match lru.pop_lru() {
                 Some(id) => PageId::new(id),
-                None => return Err(StorageError::BufferPoolExhausted),
+                None => break,
             };
 
-            let frames = self.frames.read();
-            if let Some(frame) = frames.get(&page_id) {
-                let frame_guard = frame.read();
-                if frame_guard.pin_count == 0 {
-                    drop(frame_guard);
-                    drop(frames);
-
-                    // Write back if dirty
-                    self.flush_page(page_id)?;
+            let index = match page_table.get(&page_id) {
+                Some(&index) => index,
+                None => continue,
+            };
 
-                    // Remove from buffer
-                    let mut frames = self.frames.write();
-                    frames.remove(&page_id);
-                    return Ok(());
-                }
+            let frame = self.frame(index);
+            if frame.is_pinned() {
                 // Page is pinned, try next
-                lru.access(page_id.value()); // Put back in LRU
+                lru.access(page_id.value());
+                continue;
+            }
+
+            // Write back if dirty
+            if let Err(e) = self.write_back(page_id, &frame) {
+                lru.access(page_id.value());
+                return Err(e);
             }
+
+            page_table.remove(&page_id);
+            return Ok(index);
         }
+
+        Err(StorageError::BufferPoolExhausted)
+    }
+
+    /// Write every dirty resident page back to disk
+    fn write_back_all(&self) -> Result<()> {
+        // Pin every resident frame first so the page table lock is not held
+        // while waiting on page latches
+        let resident: Vec<(PageId, Arc<BufferFrame>)> = {
+            let page_table = self.page_table.read();
+            page_table
+                .iter()
+                .map(|(&page_id, &index)| {
+                    let frame = self.frame(index);
+                    frame.pin();
+                    (page_id, frame)
+                })
+                .collect()
+        };
+
+        let mut result = Ok(());
+        for (page_id, frame) in &resident {
+            if result.is_ok() {
+                result = self.write_back(*page_id, frame);
+            }
+            frame.unpin();
+        }
+        result
+    }
+
+    /// Write a frame's page to disk if it is dirty
+    fn write_back(&self, page_id: PageId, frame: &BufferFrame) -> Result<()> {
+        if frame.dirty.swap(false, Ordering::AcqRel) {
+            let page = frame.page.read();
+            if let Err(e) = self.disk_manager.write_page(page_id, page.as_bytes()) {
+                frame.dirty.store(true, Ordering::Release);
+                return Err(e);
+            }
+        }
+        Ok(())
     }
 }
 
 impl BufferPool for BufferPoolImpl {
     fn fetch_page(&self, page_id: PageId) -> Result<PageGuard<'_>> {
-        let frame = self.get_frame(page_id)?;
-        {
-            let mut f = frame.write();
-            f.pin_count += 1;
-        }
+        let frame = self.pin_frame(page_id)?;
         Ok(PageGuard {
             page_id,
             frame,
@@ -183,13 +454,34 @@ impl BufferPool for BufferPoolImpl {
         })
     }
 
-    fn fetch_page_mut(&self, page_id: PageId) -> Result<PageGuardMut<'_>> {
-        let frame = self.get_frame(page_id)?;
-        {
-            let mut f = frame.write();
-            f.pin_count += 1;
-            f.dirty = true;
+    fn fetch_cached(&self, page_id: PageId) -> Option<PageGuard<'_>> {
+        let frame = self.pin_resident(page_id)?;
+        Some(PageGuard {
+            page_id,
+            frame,
+            pool: self,
+        })
+    }
+
+    fn is_cached(&self, page_id: PageId) -> bool {
+        self.page_table.read().contains_key(&page_id)
+    }
+
+    fn prefetch(&self, page_ids: &[PageId]) {
+        for &page_id in page_ids {
+            if self.is_cached(page_id) {
+                continue;
+            }
+            match self.load_page(page_id) {
+                Ok(frame) => frame.unpin(),
+                Err(_) => return,
+            }
         }
+    }
+
+    fn fetch_page_mut(&self, page_id: PageId) -> Result<PageGuardMut<'_>> {
+        let frame = self.pin_frame(page_id)?;
+        frame.dirty.store(true, Ordering::Release);
         Ok(PageGuardMut {
             page_id,
             frame,
@@ -198,25 +490,27 @@ impl BufferPool for BufferPoolImpl {
     }
 
     fn new_page(&self) -> Result<(PageId, PageGuardMut<'_>)> {
+        let mut page_table = self.page_table.write();
+        let index = self.claim_frame(&mut page_table)?;
+
         // Allocate from disk manager
-        let page_id = self.disk_manager.allocate_page()?;
+        let page_id = match self.disk_manager.allocate_page() {
+            Ok(page_id) => page_id,
+            Err(e) => {
+                self.free_frames.lock().push(index);
+                return Err(e);
+            }
+        };
 
         // Create a new leaf page by default
-        let page = SlottedPage::new_leaf();
-        let frame = Arc::new(RwLock::new(BufferFrame {
-            page,
-            dirty: true,
-            pin_count: 1,
-        }));
+        let frame = self.frame(index);
+        *frame.page.write() = SlottedPage::new_leaf();
+        frame.dirty.store(true, Ordering::Release);
+        frame.pin();
+        page_table.insert(page_id, index);
+        drop(page_table);
 
-        {
-            let mut frames = self.frames.write();
-            frames.insert(page_id, Arc::clone(&frame));
-        }
-        {
-            let mut lru = self.lru.write();
-            lru.access(page_id.value());
-        }
+        self.lru.write().access(page_id.value());
 
         Ok((
             page_id,
@@ -229,37 +523,40 @@ impl BufferPool for BufferPoolImpl {
     }
 
     fn flush_page(&self, page_id: PageId) -> Result<()> {
-        let frames = self.frames.read();
-        if let Some(frame) = frames.get(&page_id) {
-            let mut frame_guard = frame.write();
-            if frame_guard.dirty {
-                let data = frame_guard.page.as_bytes();
-                self.disk_manager.write_page(page_id, data)?;
-                frame_guard.dirty = false;
-            }
+        if let Some(frame) = self.pin_resident(page_id) {
+            let result = self.write_back(page_id, &frame);
+            frame.unpin();
+            result?;
         }
         Ok(())
     }
 
     fn flush_all(&self) -> Result<()> {
-        let frames = self.frames.read();
-        for (&page_id, frame) in frames.iter() {
-            let mut frame_guard = frame.write();
-            if frame_guard.dirty {
-                let data = frame_guard.page.as_bytes();
-                self.disk_manager.write_page(page_id, data)?;
-                frame_guard.dirty = false;
-            }
-        }
-        self.disk_manager.sync()?;
-        Ok(())
+        self.write_back_all()?;
+        self.disk_manager.sync()
+    }
+
+    fn flush_data(&self) -> Result<()> {
+        self.write_back_all()?;
+        self.disk_manager.sync_data()
     }
 
     fn free_page(&self, page_id: PageId) -> Result<()> {
         // Remove from buffer
         {
-            let mut frames = self.frames.write();
-            frames.remove(&page_id);
+            let mut page_table = self.page_table.write();
+            if let Some(&index) = page_table.get(&page_id) {
+                let frame = self.frame(index);
+                if frame.is_pinned() {
+                    return Err(StorageError::invalid_operation(format!(
+                        "cannot free pinned page {}",
+                        page_id
+                    )));
+                }
+                page_table.remove(&page_id);
+                frame.dirty.store(false, Ordering::Release);
+                self.free_frames.lock().push(index);
+            }
         }
         {
             let mut lru = self.lru.write();
@@ -271,12 +568,47 @@ impl BufferPool for BufferPoolImpl {
         Ok(())
     }
 
+    fn discard_all(&self) -> Result<()> {
+        let discarded: Vec<PageId> = {
+            let mut page_table = self.page_table.write();
+            if let Some(page_id) = page_table
+                .iter()
+                .find(|&(_, &index)| self.frame(index).is_pinned())
+                .map(|(&page_id, _)| page_id)
+            {
+                return Err(StorageError::invalid_operation(format!(
+                    "cannot discard pinned page {}",
+                    page_id
+                )));
+            }
+            let mut free_frames = self.free_frames.lock();
+            page_table
+                .drain()
+                .map(|(page_id, index)| {
+                    self.frame(index).dirty.store(false, Ordering::Release);
+                    free_frames.push(index);
+                    page_id
+                })
+                .collect()
+        };
+        let mut lru = self.lru.write();
+        for page_id in discarded {
+            lru.remove(page_id.value());
+        }
+        Ok(())
+    }
+
     fn page_count(&self) -> usize {
         self.disk_manager.header().page_count as usize
     }
 
     fn capacity(&self) -> usize {
-        self.capacity
+        self.capacity.load(Ordering::Relaxed)
+    }
+
+    fn dirty_page_count(&self) -> usize {
+        // Frames holding no page are never dirty, so scanning all is exact
+        self.frames.read().iter().filter(|frame| frame.is_dirty()).count()
     }
 
     fn root_page(&self) -> PageId {
@@ -290,16 +622,41 @@ impl BufferPool for BufferPoolImpl {
     fn set_root_page(&self, page_id: PageId, height: u32) -> Result<()> {
         self.disk_manager.set_root_page(page_id, height)
     }
+
+    fn entry_count(&self) -> u64 {
+        self.disk_manager.header().entry_count
+    }
+
+    fn set_entry_count(&self, count: u64) -> Result<()> {
+        self.disk_manager.set_entry_count(count)
+    }
+
+    fn release(&self, page_id: PageId, frame: &BufferFrame) {
+        // Update LRU before unpinning so an evicted page is never re-added
+        self.lru.write().access(page_id.value());
+        frame.unpin();
+    }
 }
 
 /// RAII guard for read access to a page
 pub struct PageGuard<'a> {
     page_id: PageId,
-    frame: Arc<RwLock<BufferFrame>>,
-    pool: &'a BufferPoolImpl,
+    frame: Arc<BufferFrame>,
+    pool: &'a dyn BufferPool,
 }
 
 impl<'a> PageGuard<'a> {
+    /// Create a guard over a frame that is already pinned for it
+    ///
+    /// The pin is released through `BufferPool::release` on drop.
+    pub fn new(page_id: PageId, frame: Arc<BufferFrame>, pool: &'a dyn BufferPool) -> Self {
+        Self {
+            page_id,
+            frame,
+            pool,
+        }
+    }
+
     /// Get the page ID
     pub fn page_id(&self) -> PageId {
         self.page_id
@@ -308,42 +665,49 @@ impl<'a> PageGuard<'a> {
     /// Get a read lock on the page
     pub fn read(&self) -> PageRef<'_> {
         PageRef {
-            guard: self.frame.read(),
+            guard: self.frame.page.read(),
         }
     }
 }
 
 impl<'a> Drop for PageGuard<'a> {
     fn drop(&mut self) {
-        let mut frame = self.frame.write();
-        frame.pin_count = frame.pin_count.saturating_sub(1);
-        // Update LRU
-        let mut lru = self.pool.lru.write();
-        lru.access(self.page_id.value());
+        self.pool.release(self.page_id, &self.frame);
     }
 }
 
 /// Reference to a page (through a read lock)
 pub struct PageRef<'a> {
-    guard: RwLockReadGuard<'a, BufferFrame>,
+    guard: RwLockReadGuard<'a, SlottedPage>,
 }
 
 impl<'a> std::ops::Deref for PageRef<'a> {
     type Target = SlottedPage;
 
     fn deref(&self) -> &Self::Target {
-        &self.guard.page
+        &self.guard
     }
 }
 
 /// RAII guard for write access to a page
 pub struct PageGuardMut<'a> {
     page_id: PageId,
-    frame: Arc<RwLock<BufferFrame>>,
-    pool: &'a BufferPoolImpl,
+    frame: Arc<BufferFrame>,
+    pool: &'a dyn BufferPool,
 }
 
 impl<'a> PageGuardMut<'a> {
+    /// Create a guard over a frame that is already pinned for it
+    ///
+    /// The pin is released through `BufferPool::release` on drop.
+    pub fn new(page_id: PageId, frame: Arc<BufferFrame>, pool: &'a dyn BufferPool) -> Self {
+        Self {
+            page_id,
+            frame,
+            pool,
+        }
+    }
+
     /// Get the page ID
     pub fn page_id(&self) -> PageId {
         self.page_id
@@ -351,45 +715,68 @@ impl<'a> PageGuardMut<'a> {
 
     /// Get a write lock on the page
     pub fn write(&self) -> PageRefMut<'_> {
-        let mut guard = self.frame.write();
-        guard.dirty = true;
+        let guard = self.frame.page.write();
+        self.frame.dirty.store(true, Ordering::Release);
         PageRefMut { guard }
     }
 
     /// Get a read lock on the page
     pub fn read(&self) -> PageRef<'_> {
         PageRef {
-            guard: self.frame.read(),
+            guard: self.frame.page.read(),
+        }
+    }
+
+    /// Convert into a read guard without releasing the pin
+    ///
+    /// The page stays pinned throughout, so it cannot be evicted in between.
+    /// To keep the latch itself across the transition, use
+    /// [`PageRefMut::downgrade`].
+    pub fn downgrade(self) -> PageGuard<'a> {
+        // Take the read guard's pin before this guard's drop releases its own
+        self.frame.pin();
+        PageGuard {
+            page_id: self.page_id,
+            frame: Arc::clone(&self.frame),
+            pool: self.pool,
         }
     }
 }
 
 impl<'a> Drop for PageGuardMut<'a> {
     fn drop(&mut self) {
-        let mut frame = self.frame.write();
-        frame.pin_count = frame.pin_count.saturating_sub(1);
-        // Update LRU
-        let mut lru = self.pool.lru.write();
-        lru.access(self.page_id.value());
+        self.pool.release(self.page_id, &self.frame);
     }
 }
 
 /// Mutable reference to a page (through a write lock)
 pub struct PageRefMut<'a> {
-    guard: RwLockWriteGuard<'a, BufferFrame>,
+    guard: RwLockWriteGuard<'a, SlottedPage>,
+}
+
+impl<'a> PageRefMut<'a> {
+    /// Atomically downgrade the write latch to a read latch
+    ///
+    /// No other writer can acquire the latch between the modification and
+    /// the subsequent reads.
+    pub fn downgrade(self) -> PageRef<'a> {
+        PageRef {
+            guard: RwLockWriteGuard::downgrade(self.guard),
+        }
+    }
 }
 
 impl<'a> std::ops::Deref for PageRefMut<'a> {
     type Target = SlottedPage;
 
     fn deref(&self) -> &Self::Target {
-        &self.guard.page
+        &self.guard
     }
 }
 
 impl<'a> std::ops::DerefMut for PageRefMut<'a> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.guard.page
+        &mut self.guard
     }
 }
 
@@ -450,4 +837,223 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_buffer_pool_recycles_frames() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+
+        let dm = Arc::new(DiskManagerImpl::open(&path, false)?);
+        let pool = BufferPoolImpl::new(dm, 2);
+
+        // Create more pages than there are frames
+        let mut page_ids = Vec::new();
+        for i in 0..5 {
+            let (page_id, guard) = pool.new_page()?;
+            let mut page = guard.write();
+            let key = format!("key{}", i);
+            page.insert_cell(&Cell::new_leaf(key.into_bytes(), b"value".to_vec()))?;
+            page_ids.push(page_id);
+        }
+
+        // Evicted pages were written back and can be faulted in again
+        for (i, &page_id) in page_ids.iter().enumerate() {
+            let guard = pool.fetch_page(page_id)?;
+            let page = guard.read();
+            assert_eq!(page.get_cell(0)?.key, format!("key{}", i).into_bytes());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_buffer_pool_exhausted_when_all_pinned() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+
+        let dm = Arc::new(DiskManagerImpl::open(&path, false)?);
+        let pool = BufferPoolImpl::new(dm, 2);
+
+        let (_, _guard1) = pool.new_page()?;
+        let (_, _guard2) = pool.new_page()?;
+
+        assert!(matches!(
+            pool.new_page(),
+            Err(StorageError::BufferPoolExhausted)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_downgrade_write_latch() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+
+        let dm = Arc::new(DiskManagerImpl::open(&path, false)?);
+        let pool = BufferPoolImpl::new(dm, 1);
+
+        let (page_id, guard) = pool.new_page()?;
+        {
+            let mut page = guard.write();
+            page.insert_cell(&Cell::new_leaf(b"key".to_vec(), b"value".to_vec()))?;
+
+            // Modification is visible through the downgraded latch
+            let page = page.downgrade();
+            assert_eq!(page.get_cell(0)?.key, b"key");
+
+            // Other readers can share the latch now
+            let other = guard.read();
+            assert_eq!(other.cell_count(), 1);
+        }
+
+        // The downgraded guard keeps the page pinned
+        let guard = guard.downgrade();
+        assert_eq!(guard.page_id(), page_id);
+        assert!(matches!(
+            pool.new_page(),
+            Err(StorageError::BufferPoolExhausted)
+        ));
+
+        drop(guard);
+        pool.new_page()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fetch_cached_does_not_fault() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+
+        let dm = Arc::new(DiskManagerImpl::open(&path, false)?);
+        let pool = BufferPoolImpl::new(dm, 1);
+
+        let (first, guard) = pool.new_page()?;
+        drop(guard);
+        assert!(pool.is_cached(first));
+        assert!(pool.fetch_cached(first).is_some());
+
+        // Evicts the first page from the single frame
+        let (second, guard) = pool.new_page()?;
+        drop(guard);
+        assert!(!pool.is_cached(first));
+        assert!(pool.fetch_cached(first).is_none());
+        assert!(!pool.is_cached(first));
+        assert!(pool.is_cached(second));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefetch_turns_fetch_into_hit() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let dm = Arc::new(DiskManagerImpl::open(&path, false)?);
+
+        let page_ids = {
+            let pool = BufferPoolImpl::new(dm.clone(), 10);
+            let ids: Vec<PageId> = (0..3).map(|_| Ok(pool.new_page()?.0)).collect::<Result<_>>()?;
+            pool.flush_all()?;
+            ids
+        };
+
+        let pool = BufferPoolImpl::new(dm, 10);
+        pool.fetch_page(page_ids[0])?;
+        assert_eq!(pool.cache_stats(), CacheStats { hits: 0, misses: 1 });
+
+        pool.prefetch(&page_ids);
+        assert!(page_ids.iter().all(|&id| pool.is_cached(id)));
+        assert_eq!(pool.cache_stats(), CacheStats { hits: 0, misses: 1 });
+
+        pool.fetch_page(page_ids[1])?;
+        pool.fetch_page(page_ids[2])?;
+        assert_eq!(pool.cache_stats(), CacheStats { hits: 2, misses: 1 });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_buffer_pool_resize() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+
+        let dm = Arc::new(DiskManagerImpl::open(&path, false)?);
+        let pool = BufferPoolImpl::new(dm, 8);
+
+        let mut page_ids = Vec::new();
+        for i in 0..8 {
+            let (page_id, guard) = pool.new_page()?;
+            let key = format!("key{}", i);
+            guard.write().insert_cell(&Cell::new_leaf(key.into_bytes(), b"value".to_vec()))?;
+            page_ids.push(page_id);
+        }
+        assert_eq!(pool.resident_pages(), 8);
+        assert_eq!(pool.fill_ratio(), 1.0);
+
+        // Shrinking a full pool evicts down to the new capacity
+        pool.resize(3)?;
+        assert_eq!(pool.capacity(), 3);
+        assert_eq!(pool.resident_pages(), 3);
+        for (i, &page_id) in page_ids.iter().enumerate() {
+            let guard = pool.fetch_page(page_id)?;
+            assert_eq!(guard.read().get_cell(0)?.key, format!("key{}", i).into_bytes());
+        }
+        assert_eq!(pool.resident_pages(), 3);
+
+        // Pinned pages stay resident past the target
+        let guards = page_ids[..2]
+            .iter()
+            .map(|&page_id| pool.fetch_page(page_id))
+            .collect::<Result<Vec<_>>>()?;
+        pool.resize(1)?;
+        assert_eq!(pool.resident_pages(), 2);
+        drop(guards);
+
+        // Growing adds frames that fill up before anything is evicted
+        pool.resize(10)?;
+        for &page_id in &page_ids {
+            pool.fetch_page(page_id)?;
+        }
+        assert_eq!(pool.resident_pages(), 8);
+        assert!(pool.resize(0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_pinned_page_survives_eviction() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+
+        let dm = Arc::new(DiskManagerImpl::open(&path, false)?);
+        let pool = BufferPoolImpl::new(dm, 2);
+
+        let (root, guard) = pool.new_page()?;
+        drop(guard);
+        pool.pin(root)?;
+
+        // New pages cycle through the one unpinned frame
+        for _ in 0..5 {
+            drop(pool.new_page()?.1);
+        }
+        assert!(pool.is_cached(root));
+
+        // Extra unpins stop at zero, after which the page can be evicted
+        pool.unpin(root);
+        pool.unpin(root);
+        for _ in 0..2 {
+            drop(pool.new_page()?.1);
+        }
+        assert!(!pool.is_cached(root));
+
+        // Unpinning a page that is not resident does nothing
+        pool.unpin(root);
+        let guard = pool.fetch_page(root)?;
+        drop(guard);
+        drop(pool.new_page()?.1);
+        drop(pool.new_page()?.1);
+        assert!(!pool.is_cached(root));
+
+        Ok(())
+    }
 }
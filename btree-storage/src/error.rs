@@ -49,13 +49,37 @@ pub enum StorageError {
     #[error("Invalid operation: {0}")]
     InvalidOperation(String),
 
+    /// Empty value written while `allow_empty_values` is off
+    #[error("Empty values are not allowed")]
+    EmptyValue,
+
     /// Key not found (for operations that require existing key)
     #[error("Key not found")]
     KeyNotFound,
 
+    /// An operation would exceed its resource budget
+    #[error("Quota exceeded: {used} bytes (max: {max})")]
+    QuotaExceeded { used: usize, max: usize },
+
+    /// A read asked for a version of the data that is not retained
+    #[error("Version {version} is not available (current: {current})")]
+    VersionUnavailable { version: u64, current: u64 },
+
+    /// A batch write stopped partway; the entries before the failing one
+    /// stay written
+    #[error("Batch failed after {applied} entries: {source}")]
+    BatchFailed {
+        applied: usize,
+        source: Box<StorageError>,
+    },
+
     /// Database file is corrupted or has invalid format
     #[error("Invalid database file: {0}")]
     InvalidDatabaseFile(String),
+
+    /// Database file was written with a page size this build does not use
+    #[error("Incompatible page size: file uses {found} bytes, this build {expected}")]
+    IncompatiblePageSize { found: u32, expected: u32 },
 }
 
 impl StorageError {
@@ -78,4 +102,23 @@ impl StorageError {
     pub fn invalid_db(msg: impl Into<String>) -> Self {
         Self::InvalidDatabaseFile(msg.into())
     }
+
+    /// Fill in the page of a `PageFull` error raised without one
+    ///
+    /// A `SlottedPage` does not know its own ID, so callers that do attach
+    /// it on the way out.
+    pub fn on_page(self, page_id: PageId) -> Self {
+        match self {
+            Self::PageFull {
+                page_id: PageId::INVALID,
+                needed,
+                available,
+            } => Self::PageFull {
+                page_id,
+                needed,
+                available,
+            },
+            other => other,
+        }
+    }
 }
@@ -0,0 +1,164 @@
+//! Trees that hold several values per key.
+//!
+//! An `IndexTree` is a named tree whose stored keys are the key, escaped,
+//! followed by the value, with nothing stored as the cell value. Escaping
+//! writes each 0x00 byte of the key as 0x00 0xFF and ends the key with
+//! 0x00 0x01, so stored keys sort by key first and value second and the
+//! values of one key form a contiguous range. Key and value together, plus
+//! the escaping, must fit in `MAX_KEY_SIZE`.
+//!
+//! The tree uses ordinary table pages. The encoding relies on bytewise key
+//! order, so index trees need a file whose trees sort bytewise.
+
+use crate::btree::PutResult;
+use crate::error::{Result, StorageError};
+use crate::tree_handle::TreeHandle;
+
+/// Escape byte for 0x00 within a key
+const ESCAPED_ZERO: u8 = 0xFF;
+
+/// Byte after 0x00 that ends a key
+const KEY_END: u8 = 0x01;
+
+/// A named tree mapping each key to any number of values
+pub struct IndexTree<'a> {
+    tree: TreeHandle<'a>,
+}
+
+impl<'a> IndexTree<'a> {
+    /// Wrap a named tree opened with the index tree configuration
+    pub(crate) fn new(tree: TreeHandle<'a>) -> Self {
+        Self { tree }
+    }
+
+    /// Get the name of this tree
+    pub fn name(&self) -> &str {
+        self.tree.name()
+    }
+
+    /// Add `value` under `key`
+    ///
+    /// Returns `false` if the tree already held this exact pair.
+    pub fn insert(&self, key: &[u8], value: &[u8]) -> Result<bool> {
+        let result = self.tree.put(&encode_entry(key, value), &[])?;
+        Ok(result == PutResult::Inserted)
+    }
+
+    /// Remove `value` from under `key`
+    ///
+    /// Returns `true` if the pair existed.
+    pub fn remove(&self, key: &[u8], value: &[u8]) -> Result<bool> {
+        self.tree.delete(&encode_entry(key, value))
+    }
+
+    /// Get every value under `key` in sorted order
+    pub fn get_all(&self, key: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let start = encode_key(key);
+        let mut end = start.clone();
+        *end.last_mut().unwrap() = KEY_END + 1;
+        self.tree
+            .range(Some(&start), Some(&end))?
+            .into_iter()
+            .map(|(stored, _)| decode_entry(&stored).map(|(_, value)| value))
+            .collect()
+    }
+
+    /// Get every key-value pair with a key in `[start, end)`, sorted by
+    /// key and then value
+    pub fn range(&self, start: Option<&[u8]>, end: Option<&[u8]>) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let start = start.map(encode_key);
+        let end = end.map(encode_key);
+        self.tree
+            .range(start.as_deref(), end.as_deref())?
+            .into_iter()
+            .map(|(stored, _)| decode_entry(&stored))
+            .collect()
+    }
+}
+
+/// Escape a key and mark its end, giving the lowest stored key for it
+fn encode_key(key: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(key.len() + 2);
+    for &b in key {
+        out.push(b);
+        if b == 0 {
+            out.push(ESCAPED_ZERO);
+        }
+    }
+    out.extend_from_slice(&[0, KEY_END]);
+    out
+}
+
+/// Build the stored key for a pair
+fn encode_entry(key: &[u8], value: &[u8]) -> Vec<u8> {
+    let mut out = encode_key(key);
+    out.extend_from_slice(value);
+    out
+}
+
+/// Split a stored key back into its key and value
+fn decode_entry(stored: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+    let mut key = Vec::new();
+    let mut rest = stored;
+    loop {
+        match rest {
+            [0, ESCAPED_ZERO, tail @ ..] => {
+                key.push(0);
+                rest = tail;
+            }
+            [0, KEY_END, value @ ..] => return Ok((key, value.to_vec())),
+            [b, tail @ ..] if *b != 0 => {
+                key.push(*b);
+                rest = tail;
+            }
+            _ => {
+                return Err(StorageError::corruption(format!(
+                    "malformed index key {:?}",
+                    String::from_utf8_lossy(stored)
+                )))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, Db};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_index_tree_holds_many_values_per_key() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        {
+            let db = Db::open(Config::new(&path).allow_empty_values(false))?;
+            let index = db.create_index("by_color")?;
+            assert!(index.insert(b"red", b"row3")?);
+            assert!(index.insert(b"red", b"row1")?);
+            assert!(index.insert(b"red", b"row2")?);
+            assert!(!index.insert(b"red", b"row2")?);
+            index.insert(b"re", b"row9")?;
+            index.insert(b"red\0", b"row8")?;
+            index.insert(b"blue", b"row4")?;
+
+            assert_eq!(index.get_all(b"red")?, vec![b"row1".to_vec(), b"row2".to_vec(), b"row3".to_vec()]);
+            assert_eq!(index.get_all(b"red\0")?, vec![b"row8".to_vec()]);
+            assert!(index.get_all(b"green")?.is_empty());
+            db.flush()?;
+        }
+
+        let db = Db::open(Config::new(&path))?;
+        let index = db.open_index("by_color")?;
+        assert!(index.remove(b"red", b"row1")?);
+        assert!(!index.remove(b"red", b"row1")?);
+        let entries = index.range(Some(b"re"), Some(b"red\0"))?;
+        let expected: Vec<(Vec<u8>, Vec<u8>)> = [(&b"re"[..], &b"row9"[..]), (b"red", b"row2"), (b"red", b"row3")]
+            .iter()
+            .map(|(k, v)| (k.to_vec(), v.to_vec()))
+            .collect();
+        assert_eq!(entries, expected);
+        assert_eq!(index.range(None, None)?.len(), 5);
+        Ok(())
+    }
+}
@@ -11,6 +11,9 @@
 //! - **Buffer Pool** (`buffer`): LRU page cache with dirty tracking
 //! - **B-Tree Layer** (`btree`): Core B-tree operations and cursor iteration
 //!
+//! `Db::open` wires up the default file-backed components. Use
+//! `Db::with_components` to supply your own `DiskManager` or `BufferPool`.
+//!
 //! ## Usage
 //!
 //! ```rust,ignore
@@ -35,25 +38,68 @@
 //! }
 //! ```
 
+#[cfg(feature = "async")]
+pub mod async_db;
 pub mod buffer;
 pub mod btree;
 pub mod error;
+pub mod index_tree;
+mod ndjson;
 pub mod page;
+pub mod snapshot;
 pub mod storage;
+pub mod tree_handle;
+pub mod txn;
 pub mod types;
+#[cfg(feature = "workload")]
+pub mod workload;
 
 pub use error::{Result, StorageError};
-pub use types::{BTreeConfig, PageId, PAGE_SIZE};
+pub use types::{
+    decode_i32_key, decode_i64_key, decode_u32_key, decode_u64_key, encode_i32_key,
+    encode_i64_key, encode_u32_key, encode_u64_key, BTreeConfig, KeyOrder, PageId, PageType,
+    PAGE_SIZE,
+};
 
 // Re-export main public API
-pub use btree::BTree;
-pub use buffer::{BufferPool, BufferPoolImpl};
-pub use storage::{DiskManager, DiskManagerImpl};
+pub use btree::{
+    BTree, CachedGet, Cursor, LazyValue, MaintenanceReport, PutResult, StructureStats, Violation,
+    VerifyReport,
+};
+#[cfg(feature = "async")]
+pub use async_db::AsyncDb;
+pub use buffer::{BufferFrame, BufferPool, BufferPoolImpl, CacheStats};
+pub use snapshot::Snapshot;
+pub use index_tree::IndexTree;
+pub use tree_handle::TreeHandle;
+pub use txn::Txn;
+pub use storage::{DiskManager, DiskManagerImpl, IoStats, RetryPolicy};
+
+use btree::catalog;
+use storage::FileHeader;
 
+use page::SlottedPage;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use parking_lot::RwLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use parking_lot::{Mutex, RwLock, RwLockWriteGuard};
+use std::sync::mpsc::Receiver;
+
+/// Maximum number of entries `Db::ingest` applies under one write lock
+const INGEST_BATCH_SIZE: usize = 256;
+
+/// Number of entries `Db::ingest` writes between flushes
+const INGEST_FLUSH_INTERVAL: usize = 4096;
+
+/// Number of entries `Db::compact` copies per batch
+const COMPACT_BATCH_SIZE: usize = 1024;
+
+/// Number of entries `Db::import_ndjson` writes per `put_batch`
+const IMPORT_BATCH_SIZE: usize = 1024;
 
 /// Database configuration
 #[derive(Debug, Clone)]
@@ -63,9 +109,32 @@ pub struct Config {
     /// Buffer pool size in number of pages (default: 1000)
     pub buffer_pool_size: usize,
     /// Whether to sync writes immediately (default: false for performance)
+    ///
+    /// Without it, writes are durable once `Db::flush` returns.
     pub sync_on_write: bool,
     /// B-tree configuration for node limits
     pub btree_config: BTreeConfig,
+    /// How transient I/O errors are retried
+    pub retry_policy: RetryPolicy,
+    /// Whether to sanity check the tree root when opening
+    pub verify_on_open: bool,
+    /// Whether file header changes wait for `flush`/`flush_metadata`
+    pub defer_metadata: bool,
+    /// Whether to bypass the OS page cache with direct I/O
+    pub direct_io: bool,
+    /// Whether to read back and validate every page after writing it
+    pub paranoid: bool,
+    /// Whether writes go through a write-ahead log
+    pub wal: bool,
+    /// Whether pages carry checksums that are checked on every read
+    pub verify_checksums: bool,
+    /// Whether leaf pages are compressed when written
+    pub compress_pages: bool,
+    /// Whether the database is opened for reading only
+    pub read_only: bool,
+    /// Whether a file header that fails its checksum is rebuilt from the
+    /// data pages when opening
+    pub recover: bool,
 }
 
 impl Config {
@@ -76,6 +145,16 @@ impl Config {
             buffer_pool_size: 1000,
             sync_on_write: false,
             btree_config: BTreeConfig::default(),
+            retry_policy: RetryPolicy::default(),
+            verify_on_open: false,
+            defer_metadata: false,
+            direct_io: false,
+            paranoid: false,
+            wal: false,
+            verify_checksums: false,
+            compress_pages: false,
+            read_only: false,
+            recover: false,
         }
     }
 
@@ -91,11 +170,134 @@ impl Config {
         self
     }
 
+    /// Set how transient I/O errors (EINTR, EAGAIN) are retried
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Check the root page and tree height when opening, failing with
+    /// `StorageError::Corruption` if the file is obviously broken
+    pub fn verify_on_open(mut self, enabled: bool) -> Self {
+        self.verify_on_open = enabled;
+        self
+    }
+
+    /// Keep root, height and page count changes in memory until
+    /// `Db::flush` or `Db::flush_metadata`, instead of writing the file
+    /// header on every change
+    pub fn defer_metadata(mut self, enabled: bool) -> Self {
+        self.defer_metadata = enabled;
+        self
+    }
+
+    /// Open the file with direct I/O so only the buffer pool caches pages
+    ///
+    /// Linux only; opening fails on platforms or file systems without
+    /// `O_DIRECT` support.
+    pub fn direct_io(mut self, enabled: bool) -> Self {
+        self.direct_io = enabled;
+        self
+    }
+
+    /// Read back and validate every page right after it is written
+    ///
+    /// Turns write-path corruption into an immediate error. Expensive; for
+    /// debugging only.
+    pub fn paranoid(mut self, enabled: bool) -> Self {
+        self.paranoid = enabled;
+        self
+    }
+
+    /// Log page writes to a write-ahead log next to the database file
+    ///
+    /// Every write is committed to the log before it returns, so a crash
+    /// loses no completed write and never leaves a half-written tree.
+    /// `Db::flush` checkpoints the log into the database file.
+    pub fn wal(mut self, enabled: bool) -> Self {
+        self.wal = enabled;
+        self
+    }
+
+    /// Store a CRC32 in every page written and check it on every read,
+    /// failing reads of damaged pages with `StorageError::Corruption`
+    ///
+    /// Files written without checksums still open; their pages gain one as
+    /// they are rewritten.
+    pub fn verify_checksums(mut self, enabled: bool) -> Self {
+        self.verify_checksums = enabled;
+        self
+    }
+
+    /// Compress leaf pages as they are written (default: off)
+    ///
+    /// Pages that do not shrink are written uncompressed. Compressed pages
+    /// are read back whatever this is set to, so it can be turned on or off
+    /// for an existing file.
+    pub fn compress_pages(mut self, enabled: bool) -> Self {
+        self.compress_pages = enabled;
+        self
+    }
+
     /// Set B-tree configuration
     pub fn btree_config(mut self, config: BTreeConfig) -> Self {
         self.btree_config = config;
         self
     }
+
+    /// Record a last-modified timestamp with every write
+    pub fn track_modified(mut self, enabled: bool) -> Self {
+        self.btree_config.track_modified = enabled;
+        self
+    }
+
+    /// Accept empty values (default) or reject them with
+    /// `StorageError::EmptyValue`
+    pub fn allow_empty_values(mut self, allowed: bool) -> Self {
+        self.btree_config.allow_empty_values = allowed;
+        self
+    }
+
+    /// Keep a bloom filter on each leaf so lookups for absent keys can
+    /// skip reading the leaf
+    pub fn enable_bloom(mut self, enabled: bool) -> Self {
+        self.btree_config.bloom_filters = enabled;
+        self
+    }
+
+    /// Open an existing database without write access
+    ///
+    /// Writes and flushes fail with `InvalidOperation("read-only")` and the
+    /// file is never changed. A write-ahead log is not replayed; open the
+    /// database for writing once to recover it.
+    pub fn read_only(mut self, enabled: bool) -> Self {
+        self.read_only = enabled;
+        self
+    }
+
+    /// Rebuild the file header from the data pages if it fails its
+    /// checksum when opening
+    ///
+    /// Best-effort: see `DiskManagerImpl::recover_header`. Not done for a
+    /// read-only open.
+    pub fn recover(mut self, enabled: bool) -> Self {
+        self.recover = enabled;
+        self
+    }
+
+    /// Copy values of at most `max_len` bytes into interior separators so
+    /// lookups for those keys skip the leaf (experimental)
+    pub fn inline_interior_values(mut self, max_len: usize) -> Self {
+        self.btree_config.inline_interior_max = max_len;
+        self
+    }
+
+    /// Keep `ratio` of the cells on the left when a key past the end of
+    /// the tree splits a page; 0.5 splits evenly
+    pub fn append_split_ratio(mut self, ratio: f64) -> Self {
+        self.btree_config = self.btree_config.append_split_ratio(ratio);
+        self
+    }
 }
 
 /// Node type for visualization
@@ -112,6 +314,15 @@ pub struct TreeNode {
     pub values: Vec<String>,
     /// Child nodes (only for interior nodes)
     pub children: Vec<TreeNode>,
+    /// Bytes still free on the page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub free_bytes: Option<usize>,
+    /// Percentage of the page in use
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fill_percent: Option<f32>,
+    /// Number of cells on the page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cell_count: Option<usize>,
 }
 
 /// Main database handle providing key-value storage backed by a B-tree
@@ -120,36 +331,110 @@ pub struct TreeNode {
 /// It provides a clean API for other database layers to use.
 pub struct Db {
     btree: Arc<RwLock<BTree>>,
-    buffer_pool: Arc<BufferPoolImpl>,
-    #[allow(dead_code)]
-    disk_manager: Arc<DiskManagerImpl>,
+    buffer_pool: Arc<dyn BufferPool>,
+    disk_manager: Arc<dyn DiskManager>,
     config: Config,
+    /// Named trees opened so far, shared by all their handles
+    trees: Mutex<HashMap<String, Arc<RwLock<BTree>>>>,
 }
 
 impl Db {
     /// Open or create a database at the given path
     pub fn open(config: Config) -> Result<Self> {
-        let disk_manager = Arc::new(DiskManagerImpl::open(&config.path, config.sync_on_write)?);
+        let open_file = || {
+            if config.read_only {
+                DiskManagerImpl::open_read_only(&config.path)
+            } else if config.direct_io {
+                DiskManagerImpl::open_direct(&config.path, config.sync_on_write)
+            } else {
+                DiskManagerImpl::open(&config.path, config.sync_on_write)
+            }
+        };
+        // Opening only fails with corruption when the header checksum does
+        let disk_manager = match open_file() {
+            Err(StorageError::Corruption(_)) if config.recover && !config.read_only => {
+                DiskManagerImpl::recover_header(&config.path, config.btree_config.key_order)?;
+                open_file()?
+            }
+            result => result?,
+        };
+        let mut disk_manager = disk_manager
+            .with_retry_policy(config.retry_policy)
+            .with_deferred_header(config.defer_metadata)
+            .with_paranoid(config.paranoid)
+            .with_checksums(config.verify_checksums)
+            .with_compression(config.compress_pages);
+        if config.wal && !config.read_only {
+            disk_manager = disk_manager.with_wal(&storage::wal_path(&config.path))?;
+        }
+        let disk_manager: Arc<dyn DiskManager> = Arc::new(disk_manager);
         let buffer_pool = Arc::new(BufferPoolImpl::new(
             disk_manager.clone(),
             config.buffer_pool_size,
         ));
-        let btree = Arc::new(RwLock::new(BTree::with_config(
-            buffer_pool.clone(),
-            config.btree_config.clone(),
-        )?));
+        Self::with_components(disk_manager, buffer_pool, config)
+    }
+
+    /// Open a database over caller-supplied components
+    ///
+    /// `buffer_pool` must be backed by `disk_manager`. `config.path`,
+    /// `buffer_pool_size` and `sync_on_write` are not used to build anything
+    /// here; the components are taken as configured.
+    pub fn with_components(
+        disk_manager: Arc<dyn DiskManager>,
+        buffer_pool: Arc<dyn BufferPool>,
+        config: Config,
+    ) -> Result<Self> {
+        Self::check_key_order(&*disk_manager, config.btree_config.key_order)?;
+        let btree = BTree::with_config(buffer_pool.clone(), config.btree_config.clone())?;
+        if config.verify_on_open {
+            btree.check_root()?;
+        }
+        let btree = Arc::new(RwLock::new(btree));
 
         Ok(Self {
             btree,
             buffer_pool,
             disk_manager,
             config,
+            trees: Mutex::new(HashMap::new()),
         })
     }
 
+    /// Check the configured key order against the one the file was created
+    /// with, recording it if the file holds no trees yet
+    fn check_key_order(disk_manager: &dyn DiskManager, order: KeyOrder) -> Result<()> {
+        let header = disk_manager.header();
+        if header.key_order == order {
+            return Ok(());
+        }
+        if header.root_page.value() != 0 || header.catalog_page.value() != 0 {
+            return Err(StorageError::invalid_db(format!(
+                "database keys are in {:?} order, but {:?} order was configured",
+                header.key_order, order
+            )));
+        }
+        disk_manager.set_key_order(order)
+    }
+
+    /// Get the configuration the database was opened with
+    ///
+    /// The B-tree settings may since have changed; see `btree_config`.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
     /// Get the current B-tree configuration
     pub fn btree_config(&self) -> BTreeConfig {
-        self.config.btree_config.clone()
+        self.btree.read().config().clone()
+    }
+
+    /// Compare two keys the same way the tree orders them
+    ///
+    /// Use this to sort or validate input for ordered APIs such as
+    /// `append_batch` instead of assuming an order.
+    pub fn compare_keys(&self, a: &[u8], b: &[u8]) -> Ordering {
+        self.btree.read().config().compare_keys(a, b)
     }
 
     /// Get a value by key
@@ -160,218 +445,3730 @@ impl Db {
         btree.get(key)
     }
 
-    /// Insert or update a key-value pair
-    pub fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
-        let mut btree = self.btree.write();
-        btree.put(key, value)
-    }
-
-    /// Delete a key-value pair
+    /// Get the values of several keys under one read lock
     ///
-    /// Returns `true` if the key existed and was deleted.
-    pub fn delete(&self, key: &[u8]) -> Result<bool> {
-        let mut btree = self.btree.write();
-        btree.delete(key)
+    /// Results are in the order of `keys`, with `None` for missing keys.
+    /// The keys are looked up in sorted order so that nearby keys share the
+    /// interior pages read for the previous one.
+    pub fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>> {
+        let btree = self.btree.read();
+        btree.multi_get(keys)
     }
 
-    /// Check if a key exists
-    pub fn contains(&self, key: &[u8]) -> Result<bool> {
+    /// Get a value by key along with when it was last written
+    ///
+    /// The timestamp is `None` for values written without
+    /// `Config::track_modified` enabled.
+    pub fn get_with_meta(&self, key: &[u8]) -> Result<Option<(Vec<u8>, Option<SystemTime>)>> {
         let btree = self.btree.read();
-        Ok(btree.get(key)?.is_some())
+        Ok(btree.get_with_meta(key)?.map(|(value, meta)| {
+            let modified = meta
+                .modified
+                .map(|millis| UNIX_EPOCH + Duration::from_millis(millis));
+            (value, modified)
+        }))
     }
 
-    /// Iterate over all key-value pairs in sorted order
-    pub fn iter(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
-        let btree = self.btree.read();
-        btree.scan(None, None)
+    /// Get the current data version
+    ///
+    /// Starts at 0 when the database is opened and goes up with every write.
+    pub fn version(&self) -> u64 {
+        self.btree.read().version()
     }
 
-    /// Iterate over key-value pairs in a range
+    /// Get a value by key as it was at `version`
     ///
-    /// Both bounds are optional; `None` means unbounded on that side.
-    pub fn range(&self, start: Option<&[u8]>, end: Option<&[u8]>) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    /// Old versions are not retained, so this only succeeds for the current
+    /// version and fails with `StorageError::VersionUnavailable` otherwise.
+    pub fn get_as_of(&self, version: u64, key: &[u8]) -> Result<Option<Vec<u8>>> {
         let btree = self.btree.read();
-        btree.scan(start, end)
+        if version != btree.version() {
+            return Err(StorageError::VersionUnavailable {
+                version,
+                current: btree.version(),
+            });
+        }
+        btree.get(key)
     }
 
-    /// Flush all dirty pages to disk
-    pub fn flush(&self) -> Result<()> {
-        self.buffer_pool.flush_all()
+    /// Run `f` and apply the writes it makes through the `Txn` all
+    /// together
+    ///
+    /// Writes are buffered until `f` returns `Ok`; if it returns `Err` or
+    /// panics, none of them reach the tree. Reads through the `Txn` see its
+    /// own pending writes, but other writers are not blocked while `f` runs.
+    /// If applying the writes fails, those already applied are undone; if
+    /// that fails too, the error is `Corruption`.
+    pub fn transaction<T>(&self, f: impl FnOnce(&mut Txn<'_>) -> Result<T>) -> Result<T> {
+        let mut txn = Txn::new(self);
+        let out = f(&mut txn)?;
+        txn.commit()?;
+        Ok(out)
     }
 
-    /// Debug trace a key lookup
-    pub fn debug_get(&self, key: &[u8]) -> Result<Vec<String>> {
+    /// Take a snapshot of the current version for point-in-time reads
+    ///
+    /// Reads through the snapshot do not block writers. While it is live,
+    /// writers copy each page before first changing it and freed pages are
+    /// not reused, so drop it once done.
+    pub fn snapshot(&self) -> Snapshot<'_> {
         let btree = self.btree.read();
-        btree.debug_get(key)
+        Snapshot::new(self, btree.version(), btree.snapshot())
     }
 
-    /// Get statistics about the database
-    pub fn stats(&self) -> DbStats {
+    /// Get a value by key using only pages already in the buffer pool
+    ///
+    /// Never reads from disk. Returns `CachedGet::NotCached` if a page on the
+    /// lookup path is not resident; callers can fall back to `get`.
+    pub fn get_cached(&self, key: &[u8]) -> Result<CachedGet> {
         let btree = self.btree.read();
-        DbStats {
-            page_count: self.buffer_pool.page_count(),
-            buffer_pool_size: self.buffer_pool.capacity(),
-            tree_height: btree.height(),
-        }
+        btree.get_cached(key)
     }
 
-    /// Export the tree structure for visualization
-    pub fn export_tree(&self) -> Result<Option<TreeNode>> {
-        let btree = self.btree.read();
-        let root_page = btree.root_page();
-
-        if root_page.value() == 0 {
-            return Ok(None);
-        }
-
-        self.export_node(root_page)
+    /// Check whether `put` would accept a key and value
+    ///
+    /// Checks the key and value sizes against `MAX_KEY_SIZE` and
+    /// `MAX_VALUE_SIZE`, empty values and the composite key prefix. Any
+    /// entry that passes fits on a page, since long values spill into
+    /// overflow pages. Does not check whether the database is writable.
+    pub fn would_fit(&self, key: &[u8], value: &[u8]) -> bool {
+        self.btree.read().would_fit(key, value)
     }
 
-    fn export_node(&self, page_id: PageId) -> Result<Option<TreeNode>> {
-        // First, read the page and determine if it's a leaf
-        let is_leaf = {
-            let guard = self.buffer_pool.fetch_page(page_id)?;
-            let page = guard.read();
-            page.is_leaf()
-        };
+    /// Insert or update a key-value pair
+    ///
+    /// Returns whether the key was new or an existing value was replaced.
+    pub fn put(&self, key: &[u8], value: &[u8]) -> Result<PutResult> {
+        let mut btree = self.write_lock()?;
+        let result = btree.put(key, value)?;
+        self.commit()?;
+        Ok(result)
+    }
 
-        if is_leaf {
-            self.export_leaf_node(page_id)
-        } else {
-            self.export_interior_node(page_id)
+    /// Insert a key-value pair only if the key is not already present
+    ///
+    /// Returns `false`, leaving the stored value alone, if the key exists.
+    /// The lookup and the insert happen under one write lock.
+    pub fn put_if_absent(&self, key: &[u8], value: &[u8]) -> Result<bool> {
+        let mut btree = self.write_lock()?;
+        let inserted = btree.put_if_absent(key, value)?;
+        if inserted {
+            self.commit()?;
         }
+        Ok(inserted)
     }
 
-    fn export_leaf_node(&self, page_id: PageId) -> Result<Option<TreeNode>> {
-        let guard = self.buffer_pool.fetch_page(page_id)?;
-        let page = guard.read();
-
-        let mut keys = Vec::new();
-        let mut values = Vec::new();
-
-        for i in 0..page.cell_count() {
-            let cell = page.get_cell(i)?;
-            keys.push(String::from_utf8_lossy(&cell.key).to_string());
-            values.push(String::from_utf8_lossy(&cell.value).to_string());
+    /// Get the value for a key, storing the result of `f` first if the key
+    /// is absent
+    ///
+    /// The lookup and the insert happen under one write lock, so `f` runs
+    /// at most once and no other writer can store the key in between.
+    pub fn get_or_insert_with(&self, key: &[u8], f: impl FnOnce() -> Vec<u8>) -> Result<Vec<u8>> {
+        let mut btree = self.write_lock()?;
+        if let Some(value) = btree.get(key)? {
+            return Ok(value);
         }
-
-        Ok(Some(TreeNode {
-            page_id: page_id.value(),
-            is_leaf: true,
-            keys,
-            values,
-            children: Vec::new(),
-        }))
+        let value = f();
+        btree.put(key, &value)?;
+        self.commit()?;
+        Ok(value)
     }
 
-    fn export_interior_node(&self, page_id: PageId) -> Result<Option<TreeNode>> {
-        let guard = self.buffer_pool.fetch_page(page_id)?;
-        let page = guard.read();
+    /// Insert or update a key-value pair that expires after `ttl`
+    ///
+    /// Once expired the entry reads as absent. It is removed by the next
+    /// insert into the same leaf and counts toward `len` until then.
+    pub fn put_with_ttl(&self, key: &[u8], value: &[u8], ttl: Duration) -> Result<PutResult> {
+        let mut btree = self.write_lock()?;
+        let result = btree.put_with_ttl(key, value, ttl)?;
+        self.commit()?;
+        Ok(result)
+    }
 
-        let mut keys = Vec::new();
-        let mut child_ids = Vec::new();
+    /// Insert or update many key-value pairs under one write lock, then
+    /// flush once
+    ///
+    /// Pairs are applied in order. If one fails, the pairs before it stay
+    /// written and are flushed, and the error is returned as
+    /// `StorageError::BatchFailed` with the number applied.
+    pub fn put_batch(&self, pairs: &[(&[u8], &[u8])]) -> Result<()> {
+        let result = {
+            let mut btree = self.write_lock()?;
+            let result = pairs
+                .iter()
+                .enumerate()
+                .try_for_each(|(i, (key, value))| match btree.put(key, value) {
+                    Ok(_) => Ok(()),
+                    Err(e) => Err(StorageError::BatchFailed {
+                        applied: i,
+                        source: Box::new(e),
+                    }),
+                });
+            self.commit()?;
+            result
+        };
+        self.flush()?;
+        result
+    }
 
-        // Collect right_child first (leftmost child)
-        let right_child = page.right_child();
-        if right_child.value() != 0 {
-            child_ids.push(right_child);
-        }
+    /// Insert key-value pairs received from a channel until it closes
+    ///
+    /// Entries already waiting in the channel are applied together under
+    /// one write lock, up to 256 at a time, and dirty pages are flushed
+    /// every 4096 entries and once the channel closes.
+    /// Returns the number of entries written. On error, earlier entries
+    /// stay written and the rest of the channel is left unread.
+    pub fn ingest(&self, rx: Receiver<(Vec<u8>, Vec<u8>)>) -> Result<usize> {
+        let mut total = 0;
+        let mut unflushed = 0;
+        let mut batch = Vec::with_capacity(INGEST_BATCH_SIZE);
 
-        // Collect all keys and their left_child pointers
-        for i in 0..page.cell_count() {
-            let cell = page.get_cell(i)?;
-            keys.push(String::from_utf8_lossy(&cell.key).to_string());
-            if cell.left_child.value() != 0 {
-                child_ids.push(cell.left_child);
+        while let Ok(entry) = rx.recv() {
+            batch.push(entry);
+            while batch.len() < INGEST_BATCH_SIZE {
+                match rx.try_recv() {
+                    Ok(entry) => batch.push(entry),
+                    Err(_) => break,
+                }
             }
-        }
 
-        drop(page);
-        drop(guard);
+            {
+                let mut btree = self.write_lock()?;
+                for (key, value) in batch.drain(..) {
+                    btree.put(&key, &value)?;
+                    total += 1;
+                    unflushed += 1;
+                }
+                self.commit()?;
+            }
 
-        // Now export all children
-        let mut children = Vec::new();
-        for child_id in child_ids {
-            if let Some(child_node) = self.export_node(child_id)? {
-                children.push(child_node);
+            if unflushed >= INGEST_FLUSH_INTERVAL {
+                self.flush()?;
+                unflushed = 0;
             }
         }
 
-        Ok(Some(TreeNode {
-            page_id: page_id.value(),
-            is_leaf: false,
-            keys,
-            values: Vec::new(),
-            children,
-        }))
+        self.flush()?;
+        Ok(total)
     }
-}
 
-/// Database statistics
-#[derive(Debug, Clone)]
-pub struct DbStats {
-    /// Total number of pages in the database
-    pub page_count: usize,
-    /// Buffer pool capacity
-    pub buffer_pool_size: usize,
-    /// Height of the B-tree
-    pub tree_height: usize,
-}
+    /// Append a batch of entries with strictly increasing keys
+    ///
+    /// Optimized for ingesting sorted data past the current maximum key:
+    /// entries are appended to the rightmost leaf after a single descent.
+    /// Returns an error without writing anything if the keys are not
+    /// strictly increasing.
+    pub fn append_batch<K: AsRef<[u8]>, V: AsRef<[u8]>>(&self, entries: &[(K, V)]) -> Result<()> {
+        let mut btree = self.write_lock()?;
+        btree.append_batch(entries)?;
+        self.commit()
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tempfile::tempdir;
+    /// Load entries with strictly increasing keys into an empty database
+    ///
+    /// Builds the tree bottom-up with full pages, which is much faster than
+    /// inserting the entries one by one. Fails, loading nothing, if the
+    /// database is not empty or the keys are out of order.
+    pub fn bulk_load(&self, entries: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>) -> Result<()> {
+        let mut btree = self.write_lock()?;
+        btree.bulk_load(entries)?;
+        self.commit()
+    }
 
-    #[test]
-    fn test_basic_operations() -> Result<()> {
-        let dir = tempdir().unwrap();
-        let path = dir.path().join("test.db");
-        let config = Config::new(&path);
-        let db = Db::open(config)?;
+    /// Delete a key-value pair
+    ///
+    /// Returns `true` if the key existed and was deleted.
+    pub fn delete(&self, key: &[u8]) -> Result<bool> {
+        let mut btree = self.write_lock()?;
+        let deleted = btree.delete(key)?;
+        self.commit()?;
+        Ok(deleted)
+    }
 
-        // Test put and get
-        db.put(b"key1", b"value1")?;
-        assert_eq!(db.get(b"key1")?, Some(b"value1".to_vec()));
+    /// Delete every key in `[start, end)` and return how many were removed
+    ///
+    /// Runs under a single write lock. Leaves and subtrees that fall
+    /// entirely inside the range are freed whole.
+    pub fn delete_range(&self, start: Option<&[u8]>, end: Option<&[u8]>) -> Result<usize> {
+        let mut btree = self.write_lock()?;
+        let removed = btree.delete_range(start, end)?;
+        self.commit()?;
+        Ok(removed)
+    }
 
-        // Test update
-        db.put(b"key1", b"value2")?;
-        assert_eq!(db.get(b"key1")?, Some(b"value2".to_vec()));
+    /// Remove every entry from the main tree, keeping the file open
+    ///
+    /// All of the tree's pages go back to the free list and leave the buffer
+    /// pool, and the file header records an empty tree. Named trees and the
+    /// configuration are left alone, and live snapshots keep seeing the
+    /// entries they were taken with.
+    pub fn clear(&self) -> Result<()> {
+        let mut btree = self.write_lock()?;
+        btree.clear()?;
+        self.commit()
+    }
 
-        // Test delete
-        assert!(db.delete(b"key1")?);
-        assert_eq!(db.get(b"key1")?, None);
+    /// Set `key` to `new` if its current value equals `expected`
+    ///
+    /// `None` as `expected` means the key must be absent, and `None` as
+    /// `new` deletes it. Returns whether the swap happened; the check and
+    /// the write happen under one write lock.
+    pub fn compare_and_swap(
+        &self,
+        key: &[u8],
+        expected: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool> {
+        let mut btree = self.write_lock()?;
+        if btree.get(key)?.as_deref() != expected {
+            return Ok(false);
+        }
+        match new {
+            Some(value) => {
+                btree.put(key, value)?;
+            }
+            None => {
+                btree.delete(key)?;
+            }
+        }
+        self.commit()?;
+        Ok(true)
+    }
 
-        // Test non-existent key
-        assert_eq!(db.get(b"nonexistent")?, None);
-        assert!(!db.delete(b"nonexistent")?);
+    /// Atomically move a value from `old_key` to `new_key`
+    ///
+    /// Fails with `InvalidOperation` if `new_key` already exists, unless
+    /// `overwrite` is set. Returns `true` if `old_key` existed.
+    pub fn rename(&self, old_key: &[u8], new_key: &[u8], overwrite: bool) -> Result<bool> {
+        let mut btree = self.write_lock()?;
+        let renamed = btree.rename(old_key, new_key, overwrite)?;
+        self.commit()?;
+        Ok(renamed)
+    }
 
-        Ok(())
+    /// Rewrite every key with `f` and rebuild the tree under `config`
+    ///
+    /// Use this to migrate to a new key encoding or key order. The new tree
+    /// is built alongside the old one and swapped in atomically; the old
+    /// pages are freed afterwards. Fails without changing anything if the
+    /// rewritten keys are not strictly increasing under `config`.
+    pub fn reencode_keys<F>(&self, f: F, config: BTreeConfig) -> Result<()>
+    where
+        F: Fn(&[u8]) -> Vec<u8>,
+    {
+        let mut btree = self.write_lock()?;
+        btree.reencode_keys(f, config)?;
+        self.commit()
+    }
+
+    /// Create an empty named tree
+    ///
+    /// The tree is stored in the same file as the main tree but holds its
+    /// own keys. Fails with `InvalidOperation` if a tree with that name
+    /// already exists.
+    pub fn create_tree(&self, name: &str) -> Result<TreeHandle<'_>> {
+        let config = self.btree.read().config().clone();
+        self.create_named_tree(name, &config)
+    }
+
+    /// Create an empty named tree whose tree uses `config`
+    fn create_named_tree(&self, name: &str, config: &BTreeConfig) -> Result<TreeHandle<'_>> {
+        let _btree = self.write_lock()?;
+        let mut catalog = self.disk_manager.header().catalog_page;
+        if catalog.value() == 0 {
+            catalog = catalog::create(&*self.buffer_pool)?;
+            self.disk_manager.set_catalog_page(catalog)?;
+        }
+        if catalog::lookup(&*self.buffer_pool, catalog, name.as_bytes())?.is_some() {
+            return Err(StorageError::invalid_operation(format!(
+                "tree {:?} already exists",
+                name
+            )));
+        }
+        catalog::store(&*self.buffer_pool, catalog, name.as_bytes(), PageId::new(0), 0)?;
+        self.buffer_pool.flush_page(catalog)?;
+        self.commit()?;
+        self.named_tree(name, config)
+    }
+
+    /// Open an existing named tree
+    ///
+    /// Fails with `InvalidOperation` if there is no tree with that name.
+    pub fn open_tree(&self, name: &str) -> Result<TreeHandle<'_>> {
+        let btree = self.btree.read();
+        self.named_tree(name, btree.config())
+    }
+
+    /// Create an empty named tree that holds several values per key
+    ///
+    /// See `IndexTree`. Fails with `InvalidOperation` if a tree with that
+    /// name already exists or the file's key order is not bytewise.
+    pub fn create_index(&self, name: &str) -> Result<IndexTree<'_>> {
+        let config = self.index_config()?;
+        self.create_named_tree(name, &config).map(IndexTree::new)
+    }
+
+    /// Open an existing named tree created by `create_index`
+    pub fn open_index(&self, name: &str) -> Result<IndexTree<'_>> {
+        let config = self.index_config()?;
+        let _btree = self.btree.read();
+        self.named_tree(name, &config).map(IndexTree::new)
+    }
+
+    /// Get the tree configuration for index trees
+    ///
+    /// Their stored values are empty and their stored keys do not follow
+    /// any composite layout.
+    fn index_config(&self) -> Result<BTreeConfig> {
+        let mut config = self.btree.read().config().clone();
+        if config.key_order != KeyOrder::Bytewise {
+            return Err(StorageError::invalid_operation(
+                "index trees need bytewise key order",
+            ));
+        }
+        config.allow_empty_values = true;
+        config.composite_prefix_len = None;
+        Ok(config)
+    }
+
+    /// Get the names of all named trees in name order
+    pub fn tree_names(&self) -> Result<Vec<String>> {
+        let _btree = self.btree.read();
+        let catalog = self.disk_manager.header().catalog_page;
+        if catalog.value() == 0 {
+            return Ok(Vec::new());
+        }
+        Ok(catalog::entries(&*self.buffer_pool, catalog)?
+            .into_iter()
+            .map(|(name, _, _)| String::from_utf8_lossy(&name).into_owned())
+            .collect())
+    }
+
+    /// Get a handle to a named tree, opening it on first use
+    ///
+    /// Callers hold the main tree's lock so the catalog does not change.
+    fn named_tree(&self, name: &str, config: &BTreeConfig) -> Result<TreeHandle<'_>> {
+        let mut trees = self.trees.lock();
+        if let Some(tree) = trees.get(name) {
+            return Ok(TreeHandle::new(self, name, tree.clone()));
+        }
+
+        let missing = || StorageError::invalid_operation(format!("no tree named {:?}", name));
+        let catalog = self.disk_manager.header().catalog_page;
+        if catalog.value() == 0 {
+            return Err(missing());
+        }
+        let tree = BTree::open_named(
+            self.buffer_pool.clone(),
+            config.clone(),
+            catalog,
+            name.as_bytes(),
+        )?
+        .ok_or_else(missing)?;
+        let tree = Arc::new(RwLock::new(tree));
+        trees.insert(name.to_string(), tree.clone());
+        Ok(TreeHandle::new(self, name, tree))
+    }
+
+    /// Get the number of entries in the main tree
+    ///
+    /// Reads the running count kept in the file header rather than
+    /// scanning. Without `Config::wal` the stored count can be stale after
+    /// a crash, like the rest of the unflushed tree. Entries written with
+    /// `put_with_ttl` count until they are purged, even once expired.
+    pub fn len(&self) -> Result<usize> {
+        self.btree.read().len()
+    }
+
+    /// Check whether the main tree has no entries
+    ///
+    /// Expired entries do not count, so a tree holding only expired entries
+    /// is empty even though `len` is not zero.
+    pub fn is_empty(&self) -> Result<bool> {
+        self.btree.read().is_empty()
+    }
+
+    /// Get the entry with the smallest key
+    ///
+    /// Reads one page per tree level. Returns `None` if the tree is empty.
+    pub fn first(&self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let btree = self.btree.read();
+        btree.first()
+    }
+
+    /// Get the entry with the largest key
+    ///
+    /// Reads one page per tree level. Returns `None` if the tree is empty.
+    pub fn last(&self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let btree = self.btree.read();
+        btree.last()
+    }
+
+    /// Check if a key exists
+    pub fn contains(&self, key: &[u8]) -> Result<bool> {
+        let btree = self.btree.read();
+        Ok(btree.get(key)?.is_some())
+    }
+
+    /// Iterate over all key-value pairs in sorted order
+    pub fn iter(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let btree = self.btree.read();
+        btree.scan(None, None)
+    }
+
+    /// Open a cursor at the first entry of the main tree
+    ///
+    /// The cursor is also an `Iterator` over the entries from there on. It
+    /// holds no tree lock, so writes made while it is in use may or may not
+    /// be seen; use a snapshot for a stable view.
+    pub fn cursor(&self) -> Result<Cursor<dyn BufferPool>> {
+        let btree = self.btree.read();
+        Cursor::new(self.buffer_pool.clone(), btree.root_page())
+    }
+
+    /// Iterate over key-value pairs in a range
+    ///
+    /// Both bounds are optional; `None` means unbounded on that side.
+    pub fn range(&self, start: Option<&[u8]>, end: Option<&[u8]>) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let btree = self.btree.read();
+        btree.scan(start, end)
+    }
+
+    /// Iterate lazily over key-value pairs in `[start, end)`
+    ///
+    /// Entries are read one at a time as the iterator advances. No page
+    /// latch or tree lock is held between calls to `next`, so writers are
+    /// not blocked; if the tree changes in between, iteration resumes after
+    /// the last key returned. Iteration stops after the first error.
+    pub fn range_iter(
+        &self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+    ) -> impl Iterator<Item = Result<(Vec<u8>, Vec<u8>)>> + '_ {
+        RangeIter::new(self, start, end, false)
+    }
+
+    /// Iterate lazily over key-value pairs in `[start, end)` from the
+    /// highest key down
+    ///
+    /// Behaves like `range_iter`, including resuming below the last key
+    /// returned if the tree changes between calls.
+    pub fn range_rev(
+        &self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+    ) -> impl Iterator<Item = Result<(Vec<u8>, Vec<u8>)>> + '_ {
+        RangeIter::new(self, start, end, true)
+    }
+
+    /// Read one page of entries with keys strictly after `after`
+    ///
+    /// Returns up to `limit` entries, starting from the first key when
+    /// `after` is `None`, along with the key to pass as `after` for the
+    /// next page: the last key returned, or `None` once the end is reached.
+    /// Fails with `InvalidOperation` if `limit` is 0.
+    #[allow(clippy::type_complexity)]
+    pub fn scan_page(
+        &self,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<(Vec<(Vec<u8>, Vec<u8>)>, Option<Vec<u8>>)> {
+        if limit == 0 {
+            return Err(StorageError::invalid_operation("page limit must be at least 1"));
+        }
+
+        let btree = self.btree.read();
+        let pool = self.buffer_pool.clone();
+        let mut cursor = match after {
+            Some(key) => {
+                let mut cursor = Cursor::seek_by(pool, btree.root_page(), key, btree.config().key_order)?;
+                if cursor.current()?.is_some_and(|(found, _)| found == key) {
+                    cursor.next()?;
+                }
+                cursor
+            }
+            None => Cursor::new(pool, btree.root_page())?,
+        };
+
+        let mut entries = Vec::new();
+        while entries.len() < limit {
+            let Some(entry) = cursor.current()? else {
+                break;
+            };
+            entries.push(entry);
+            cursor.next()?;
+        }
+        let next = match cursor.is_valid() {
+            true => entries.last().map(|(key, _)| key.clone()),
+            false => None,
+        };
+        Ok((entries, next))
+    }
+
+    /// Iterate over key-value pairs in a range, bounded by value size
+    ///
+    /// Fails with `QuotaExceeded` as soon as the accumulated value bytes
+    /// would exceed `max_bytes`, before the offending value is copied.
+    pub fn range_with_budget(
+        &self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        max_bytes: usize,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let btree = self.btree.read();
+        let mut results = Vec::new();
+        let mut used = 0usize;
+        btree.scan_lazy_with(start, end, |key, value| {
+            used += value.len();
+            if used > max_bytes {
+                return Err(StorageError::QuotaExceeded { used, max: max_bytes });
+            }
+            results.push((key.to_vec(), value.load()?.into_owned()));
+            Ok(true)
+        })?;
+        Ok(results)
+    }
+
+    /// Count the entries in a range without copying them
+    ///
+    /// Both bounds are optional; `None` means unbounded on that side.
+    /// Subtrees outside the range and overflow pages are never read.
+    pub fn count_range(&self, start: Option<&[u8]>, end: Option<&[u8]>) -> Result<usize> {
+        let btree = self.btree.read();
+        let mut count = 0;
+        btree.scan_lazy_with(start, end, |_, _| {
+            count += 1;
+            Ok(true)
+        })?;
+        Ok(count)
+    }
+
+    /// Iterate over key-value pairs in a range that satisfy `pred`
+    ///
+    /// The predicate sees borrowed key and value slices while the page is
+    /// latched; only matching pairs are copied into the result.
+    pub fn scan_filter(
+        &self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        pred: impl Fn(&[u8], &[u8]) -> bool,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let btree = self.btree.read();
+        let mut results = Vec::new();
+        btree.scan_with(start, end, |key, value| {
+            if pred(key, value) {
+                results.push((key.to_vec(), value.to_vec()));
+            }
+            Ok(true)
+        })?;
+        Ok(results)
+    }
+
+    /// Iterate over key-value pairs in a range whose key satisfies `pred`
+    ///
+    /// Like `scan_filter`, but only the values of matching keys are read,
+    /// so rejected large values never have their overflow pages fetched.
+    pub fn scan_filter_keys(
+        &self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        pred: impl Fn(&[u8]) -> bool,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let btree = self.btree.read();
+        let mut results = Vec::new();
+        btree.scan_lazy_with(start, end, |key, value| {
+            if pred(key) {
+                results.push((key.to_vec(), value.load()?.into_owned()));
+            }
+            Ok(true)
+        })?;
+        Ok(results)
+    }
+
+    /// Get all entries whose key starts with `prefix`, in key order
+    ///
+    /// An empty prefix returns every entry.
+    pub fn scan_prefix(&self, prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let btree = self.btree.read();
+        Self::scan_with_prefix(&btree, prefix)
+    }
+
+    /// Scan the entries of `btree` whose key starts with `prefix`
+    ///
+    /// Under bytewise order they form one range; other orders can spread
+    /// them out, so the whole tree is scanned and filtered.
+    fn scan_with_prefix(btree: &BTree, prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        if !btree.config().key_order.is_bytewise() {
+            let mut entries = Vec::new();
+            btree.scan_lazy_with(None, None, |key, value| {
+                if key.starts_with(prefix) {
+                    entries.push((key.to_vec(), value.load()?.into_owned()));
+                }
+                Ok(true)
+            })?;
+            return Ok(entries);
+        }
+        let end = types::prefix_end(prefix);
+        btree.scan(Some(prefix), end.as_deref())
+    }
+
+    /// Get all entries whose key starts with `prefix` as a map
+    ///
+    /// The map is keyed by the rest of each key, with the prefix stripped.
+    /// A key equal to `prefix` maps from the empty suffix.
+    pub fn get_prefix_map(&self, prefix: &[u8]) -> Result<BTreeMap<Vec<u8>, Vec<u8>>> {
+        let btree = self.btree.read();
+        let (start, end) = if btree.config().key_order.is_bytewise() {
+            (Some(prefix), types::prefix_end(prefix))
+        } else {
+            (None, None)
+        };
+
+        let mut map = BTreeMap::new();
+        btree.scan_lazy_with(start, end.as_deref(), |key, value| {
+            if key.starts_with(prefix) {
+                map.insert(key[prefix.len()..].to_vec(), value.load()?.into_owned());
+            }
+            Ok(true)
+        })?;
+        Ok(map)
+    }
+
+    /// Collect key-value pairs in a range, highest key first
+    ///
+    /// With a `limit`, only the last `limit` entries of the range are
+    /// returned and the scan stops as soon as they are found, so "latest N"
+    /// queries do not read the whole range.
+    pub fn range_collect_rev(
+        &self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        limit: Option<usize>,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let btree = self.btree.read();
+        btree.scan_rev(start, end, limit)
+    }
+
+    /// Iterate over key-value pairs under a single subtree
+    ///
+    /// `page_id` can be any tree page, e.g. a child taken from
+    /// `export_tree`. Bounds behave as in `range`.
+    pub fn scan_subtree(
+        &self,
+        page_id: PageId,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let btree = self.btree.read();
+        btree.scan_subtree(page_id, start, end)
+    }
+
+    /// Scan all entries in one composite key partition
+    ///
+    /// Entries come back ordered by key suffix. Requires composite keys to
+    /// be configured and `prefix` to have the configured prefix length.
+    pub fn scan_partition(&self, prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let btree = self.btree.read();
+        btree.config().validate_prefix(prefix)?;
+        Self::scan_with_prefix(&btree, prefix)
+    }
+
+    /// Iterate over every page after the header in file order
+    ///
+    /// Yields tree, free and orphaned pages alike, without following the
+    /// tree structure. Resident pages are read from the buffer pool so
+    /// unflushed changes are visible; other pages are read straight from
+    /// disk without being cached. Pages are read one at a time, so the
+    /// iteration is not a consistent snapshot under concurrent writes.
+    pub fn iter_pages_physical(&self) -> impl Iterator<Item = Result<(PageId, SlottedPage)>> + '_ {
+        let page_count = self.buffer_pool.page_count() as u32;
+        (1..page_count).map(move |id| {
+            let page_id = PageId::new(id);
+            if let Some(guard) = self.buffer_pool.fetch_cached(page_id) {
+                return Ok((page_id, guard.read().clone()));
+            }
+            let buf = self.disk_manager.read_page(page_id)?;
+            Ok((page_id, SlottedPage::from_bytes(&buf)?))
+        })
+    }
+
+    /// Describe the layout of one page, read through the buffer pool
+    ///
+    /// Page 0 is reported as the file header. Fails with `InvalidPage` for
+    /// a page past the end of the file.
+    pub fn inspect_page(&self, page_id: PageId) -> Result<PageInspection> {
+        if page_id == PageId::HEADER {
+            return Ok(PageInspection::FileHeader(self.disk_manager.header()));
+        }
+        if page_id.value() as usize >= self.buffer_pool.page_count() {
+            return Err(StorageError::invalid_page(format!(
+                "page {} is past the end of the file",
+                page_id
+            )));
+        }
+
+        let guard = self.buffer_pool.fetch_page(page_id)?;
+        let page = guard.read();
+        let cells = page
+            .get_all_cells()?
+            .into_iter()
+            .map(|cell| {
+                let leaf = page.is_leaf();
+                let value_len = cell
+                    .meta
+                    .overflow
+                    .map_or(cell.value.len(), |overflow| overflow.total_len as usize);
+                CellInspection {
+                    key: cell.key,
+                    value_len: leaf.then_some(value_len),
+                    child: (!leaf).then_some(cell.left_child),
+                }
+            })
+            .collect();
+        Ok(PageInspection::Slotted {
+            page_type: page.page_type(),
+            cell_count: page.cell_count(),
+            free_bytes: page.free_space(),
+            fragmented_bytes: page.dead_space()?,
+            right_child: page.right_child(),
+            cells,
+        })
+    }
+
+    /// Commit a completed write to the write-ahead log, if there is one
+    ///
+    /// Callers hold the tree's write lock so no other write's pages are
+    /// committed half done.
+    fn commit(&self) -> Result<()> {
+        if self.config.wal {
+            self.buffer_pool.flush_data()?;
+        }
+        Ok(())
+    }
+
+    /// Take the tree's write lock for a write
+    ///
+    /// Fails with `InvalidOperation` on a read-only database, before
+    /// anything is changed.
+    fn write_lock(&self) -> Result<RwLockWriteGuard<'_, BTree>> {
+        self.check_writable()?;
+        Ok(self.btree.write())
+    }
+
+    /// Fail with `InvalidOperation` if the database is read-only
+    fn check_writable(&self) -> Result<()> {
+        if self.config.read_only {
+            return Err(StorageError::invalid_operation("read-only"));
+        }
+        Ok(())
+    }
+
+    /// Flush all dirty pages to disk, then the file header
+    ///
+    /// This is the durability barrier when `sync_on_write` is off: it ends
+    /// with a single `DiskManager::sync`, which syncs the whole file, and
+    /// fails if that sync does. With `Config::wal`, this checkpoints the log
+    /// into the database file and empties it.
+    pub fn flush(&self) -> Result<()> {
+        self.check_writable()?;
+        self.buffer_pool.flush_all()
+    }
+
+    /// Write and sync dirty data pages without touching the file header
+    ///
+    /// With `Config::defer_metadata`, call this before `flush_metadata` so
+    /// the header never refers to pages that are not on disk yet.
+    pub fn flush_data(&self) -> Result<()> {
+        self.check_writable()?;
+        self.buffer_pool.flush_data()
+    }
+
+    /// Write and sync the file header (root page, height, page counts)
+    pub fn flush_metadata(&self) -> Result<()> {
+        self.check_writable()?;
+        self.disk_manager.sync()
+    }
+
+    /// Write every entry as newline-delimited JSON
+    ///
+    /// Each line is `{"key":...,"value":...}` with both fields base64
+    /// encoded, in key order. Entries are read lazily, so the database is
+    /// not held in memory.
+    pub fn export_ndjson(&self, writer: impl Write) -> Result<()> {
+        let mut writer = BufWriter::new(writer);
+        for entry in self.range_iter(None, None) {
+            let (key, value) = entry?;
+            writeln!(writer, "{}", ndjson::encode_record(&key, &value))?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Load entries written by `export_ndjson`, returning how many were
+    /// written
+    ///
+    /// Entries go through `put_batch` in batches, so a target with a
+    /// different `BTreeConfig` lays them out by its own settings. Blank
+    /// lines are skipped. A malformed line fails with `InvalidOperation`
+    /// naming its line number; the entries before it stay written.
+    pub fn import_ndjson(&self, reader: impl Read) -> Result<usize> {
+        let mut total = 0;
+        let mut batch = Vec::with_capacity(IMPORT_BATCH_SIZE);
+        for (index, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry = ndjson::decode_record(&line).map_err(|e| {
+                StorageError::invalid_operation(format!("line {}: {}", index + 1, e))
+            })?;
+            batch.push(entry);
+            if batch.len() == IMPORT_BATCH_SIZE {
+                total += self.import_batch(&mut batch)?;
+            }
+        }
+        total += self.import_batch(&mut batch)?;
+        Ok(total)
+    }
+
+    /// Write and clear one batch of imported entries
+    fn import_batch(&self, batch: &mut Vec<(Vec<u8>, Vec<u8>)>) -> Result<usize> {
+        if batch.is_empty() {
+            return Ok(0);
+        }
+        let pairs: Vec<(&[u8], &[u8])> = batch
+            .iter()
+            .map(|(key, value)| (key.as_slice(), value.as_slice()))
+            .collect();
+        self.put_batch(&pairs)?;
+        let count = batch.len();
+        batch.clear();
+        Ok(count)
+    }
+
+    /// Serialize the file header and free list
+    ///
+    /// Keep the result as a known-good snapshot for `restore_metadata`.
+    /// The layout is the header page followed by a big-endian `u32` count
+    /// of free pages and their IDs.
+    pub fn dump_metadata(&self) -> Result<Vec<u8>> {
+        let free_pages = self.disk_manager.free_pages();
+        let mut out = vec![0u8; PAGE_SIZE];
+        self.disk_manager.header().write(&mut out);
+        out.extend_from_slice(&(free_pages.len() as u32).to_be_bytes());
+        for page_id in free_pages {
+            out.extend_from_slice(&page_id.to_be_bytes());
+        }
+        Ok(out)
+    }
+
+    /// Replace the file header with a snapshot from `dump_metadata`,
+    /// leaving data pages untouched
+    ///
+    /// The snapshot is checked against the file (page count against file
+    /// length, root within the file) before anything is written. Dirty
+    /// pages are written out first and the cache is emptied after. Pages
+    /// allocated since the snapshot may be in use by now, so its free list
+    /// is dropped and the page count never shrinks; pages that were free
+    /// stay unused.
+    pub fn restore_metadata(&self, bytes: &[u8]) -> Result<()> {
+        let (mut header, _) = parse_metadata(bytes)?;
+        let mut btree = self.write_lock()?;
+        self.buffer_pool.flush_data()?;
+        header.page_count = header.page_count.max(self.disk_manager.header().page_count);
+        self.disk_manager.restore_metadata(header, &[])?;
+        self.buffer_pool.discard_all()?;
+        btree.reload_root();
+        self.trees.lock().clear();
+        Ok(())
+    }
+
+    /// Write the header from a `dump_metadata` snapshot into a database
+    /// file that cannot be opened
+    ///
+    /// Only the header is restored; the free list starts empty when the
+    /// file is next opened.
+    pub fn restore_metadata_at(path: impl AsRef<Path>, bytes: &[u8]) -> Result<()> {
+        let (header, _) = parse_metadata(bytes)?;
+        DiskManagerImpl::restore_header(path.as_ref(), &header)
+    }
+
+    /// Get the overflow pages backing a key's value
+    ///
+    /// Values stored inline in their leaf cell have an empty chain.
+    /// Returns `KeyNotFound` if the key does not exist.
+    pub fn overflow_chain(&self, key: &[u8]) -> Result<OverflowChain> {
+        let btree = self.btree.read();
+        let (pages, total_len) = btree.overflow_chain(key)?.ok_or(StorageError::KeyNotFound)?;
+        Ok(OverflowChain { pages, total_len })
+    }
+
+    /// Find overflow pages that no leaf cell refers to
+    ///
+    /// Such pages can be left behind by a crash between writing a value's
+    /// overflow pages and committing the leaf cell that points at them.
+    pub fn orphaned_overflow_pages(&self) -> Result<Vec<PageId>> {
+        let btree = self.btree.read();
+        self.find_orphaned_overflow_pages(&btree)
+    }
+
+    /// Free every orphaned overflow page and return how many were freed
+    ///
+    /// Freed pages join the on-disk free chain as `Free` pages, so they are
+    /// neither found again nor lost after a reopen.
+    pub fn reclaim_orphans(&self) -> Result<usize> {
+        let btree = self.write_lock()?;
+        let orphans = self.find_orphaned_overflow_pages(&btree)?;
+        for &page_id in &orphans {
+            btree.free_page(page_id)?;
+        }
+        Ok(orphans.len())
+    }
+
+    /// Scan the file for overflow pages missing from every value's chain
+    ///
+    /// Pages on the free list, or held back for a live snapshot, are not
+    /// reported.
+    fn find_orphaned_overflow_pages(&self, btree: &BTree) -> Result<Vec<PageId>> {
+        let mut referenced: HashSet<PageId> = btree.overflow_pages()?.into_iter().collect();
+        referenced.extend(self.disk_manager.free_pages());
+        referenced.extend(btree.deferred_pages());
+        let catalog = self.disk_manager.header().catalog_page;
+        if catalog.value() != 0 {
+            for (name, _, _) in catalog::entries(&*self.buffer_pool, catalog)? {
+                let config = btree.config().clone();
+                let tree = BTree::open_named(self.buffer_pool.clone(), config, catalog, &name)?;
+                if let Some(tree) = tree {
+                    referenced.extend(tree.overflow_pages()?);
+                }
+            }
+        }
+
+        let mut orphans = Vec::new();
+        for page in self.iter_pages_physical() {
+            let (page_id, page) = page?;
+            if page.page_type() == PageType::Overflow && !referenced.contains(&page_id) {
+                orphans.push(page_id);
+            }
+        }
+        Ok(orphans)
+    }
+
+    /// Compact all pages and verify tree integrity in one pass
+    ///
+    /// Blocks writers for the duration. Safe to run repeatedly; the
+    /// database remains fully usable afterward.
+    pub fn maintenance(&self) -> Result<MaintenanceReport> {
+        let btree = self.write_lock()?;
+        btree.maintenance()
+    }
+
+    /// Rewrite the database into a fresh file holding only live entries
+    ///
+    /// Copies the main tree and every named tree, in key order, into a new
+    /// file next to the database, then renames it over the original and
+    /// reopens it. The new file has no free pages and densely packed
+    /// leaves. If anything fails before the rename, the original file is
+    /// left as it was. Only for databases opened with `Db::open`. Entries
+    /// of the main tree keep their expiry and modification time; those of
+    /// named trees are rewritten as new.
+    pub fn compact(&mut self) -> Result<()> {
+        self.flush()?;
+
+        let path = compact_path(&self.config.path);
+        let _ = std::fs::remove_file(&path);
+        let config = Config {
+            path: path.clone(),
+            sync_on_write: false,
+            wal: false,
+            ..self.config.clone()
+        };
+        if let Err(e) = self.copy_into(config) {
+            let _ = std::fs::remove_file(&path);
+            return Err(e);
+        }
+
+        std::fs::rename(&path, &self.config.path)?;
+        *self = Db::open(self.config.clone())?;
+        Ok(())
+    }
+
+    /// Copy every live entry into a new database opened with `config`
+    fn copy_into(&self, config: Config) -> Result<()> {
+        let target = Db::open(config)?;
+
+        let btree = self.btree.read();
+        let mut copy = target.write_lock()?;
+        let mut batch = Vec::with_capacity(COMPACT_BATCH_SIZE);
+        btree.scan_lazy_with(None, None, |key, value| {
+            batch.push((key.to_vec(), value.load()?.into_owned(), value.meta()));
+            if batch.len() == COMPACT_BATCH_SIZE {
+                copy.append_batch_with_meta(&batch)?;
+                batch.clear();
+            }
+            Ok(true)
+        })?;
+        copy.append_batch_with_meta(&batch)?;
+        drop(copy);
+        drop(btree);
+
+        for name in self.tree_names()? {
+            let source = self.open_tree(&name)?;
+            let copy = target.create_tree(&name)?;
+            for (key, value) in source.range(None, None)? {
+                copy.put(&key, &value)?;
+            }
+        }
+
+        target.flush()
+    }
+
+    /// Debug trace a key lookup
+    pub fn debug_get(&self, key: &[u8]) -> Result<Vec<String>> {
+        let btree = self.btree.read();
+        btree.debug_get(key)
+    }
+
+    /// Get statistics about the database
+    pub fn stats(&self) -> DbStats {
+        let btree = self.btree.read();
+        DbStats {
+            page_count: self.buffer_pool.page_count(),
+            buffer_pool_size: self.buffer_pool.capacity(),
+            dirty_pages: self.buffer_pool.dirty_page_count(),
+            tree_height: btree.height(),
+            structure: btree.structure_stats(),
+        }
+    }
+
+    /// Reset the split and merge counts reported by `stats`
+    pub fn reset_structure_stats(&self) {
+        self.btree.read().reset_structure_stats();
+    }
+
+    /// Measure the tree height and repair the stored value if it is stale
+    ///
+    /// Returns the measured height.
+    pub fn recompute_height(&self) -> Result<usize> {
+        let mut btree = self.write_lock()?;
+        let height = btree.recompute_height()?;
+        self.commit()?;
+        Ok(height)
+    }
+
+    /// Get counters for I/O operations that were retried or failed
+    pub fn io_stats(&self) -> IoStats {
+        self.disk_manager.io_stats()
+    }
+
+    /// Export the tree structure for visualization
+    pub fn export_tree(&self) -> Result<Option<TreeNode>> {
+        let btree = self.btree.read();
+        let root_page = btree.root_page();
+
+        if root_page.value() == 0 {
+            return Ok(None);
+        }
+
+        self.export_node(root_page)
+    }
+
+    /// Export the tree structure as a GraphViz DOT graph
+    ///
+    /// Each page becomes a node labelled with its keys; edges run from
+    /// parents to children in key order. Render with `dot -Tpng`.
+    pub fn export_dot(&self) -> Result<String> {
+        let btree = self.btree.read();
+        let root_page = btree.root_page();
+
+        let mut out = String::from("digraph btree {\n");
+        out.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+        if root_page.value() != 0 {
+            self.export_dot_node(root_page, &mut out)?;
+        }
+        out.push_str("}\n");
+        Ok(out)
+    }
+
+    fn export_dot_node(&self, page_id: PageId, out: &mut String) -> Result<()> {
+        let guard = self.buffer_pool.fetch_page(page_id)?;
+        let page = guard.read();
+
+        let cells = page.get_all_cells()?;
+        let keys: Vec<String> = cells.iter().map(|c| dot_escape(&c.key)).collect();
+        let is_leaf = page.is_leaf();
+        let mut child_ids = Vec::new();
+        if !is_leaf {
+            child_ids.push(page.right_child());
+            child_ids.extend(cells.iter().map(|c| c.left_child));
+        }
+        drop(page);
+        drop(guard);
+
+        let style = if is_leaf { ", style=filled, fillcolor=lightgrey" } else { "" };
+        out.push_str(&format!(
+            "    p{} [label=\"page {}\\n{}\"{}];\n",
+            page_id.value(),
+            page_id.value(),
+            keys.join(" | "),
+            style
+        ));
+
+        for child_id in child_ids {
+            out.push_str(&format!("    p{} -> p{};\n", page_id.value(), child_id.value()));
+            self.export_dot_node(child_id, out)?;
+        }
+
+        Ok(())
+    }
+
+    fn export_node(&self, page_id: PageId) -> Result<Option<TreeNode>> {
+        // First, read the page and determine if it's a leaf
+        let is_leaf = {
+            let guard = self.buffer_pool.fetch_page(page_id)?;
+            let page = guard.read();
+            page.is_leaf()
+        };
+
+        if is_leaf {
+            self.export_leaf_node(page_id)
+        } else {
+            self.export_interior_node(page_id)
+        }
+    }
+
+    fn export_leaf_node(&self, page_id: PageId) -> Result<Option<TreeNode>> {
+        let guard = self.buffer_pool.fetch_page(page_id)?;
+        let page = guard.read();
+
+        let mut keys = Vec::new();
+        let mut values = Vec::new();
+
+        for i in 0..page.cell_count() {
+            let cell = page.get_cell(i)?;
+            keys.push(String::from_utf8_lossy(&cell.key).to_string());
+            values.push(String::from_utf8_lossy(&cell.value).to_string());
+        }
+
+        Ok(Some(TreeNode {
+            page_id: page_id.value(),
+            is_leaf: true,
+            keys,
+            values,
+            children: Vec::new(),
+            free_bytes: Some(page.free_space()),
+            fill_percent: Some(page.fill_percent()),
+            cell_count: Some(page.cell_count()),
+        }))
+    }
+
+    fn export_interior_node(&self, page_id: PageId) -> Result<Option<TreeNode>> {
+        let guard = self.buffer_pool.fetch_page(page_id)?;
+        let page = guard.read();
+
+        let mut keys = Vec::new();
+        let mut child_ids = Vec::new();
+        let free_bytes = page.free_space();
+        let fill_percent = page.fill_percent();
+        let cell_count = page.cell_count();
+
+        // Collect right_child first (leftmost child)
+        let right_child = page.right_child();
+        if right_child.value() != 0 {
+            child_ids.push(right_child);
+        }
+
+        // Collect all keys and their left_child pointers
+        for i in 0..page.cell_count() {
+            let cell = page.get_cell(i)?;
+            keys.push(String::from_utf8_lossy(&cell.key).to_string());
+            if cell.left_child.value() != 0 {
+                child_ids.push(cell.left_child);
+            }
+        }
+
+        drop(page);
+        drop(guard);
+
+        // Now export all children
+        let mut children = Vec::new();
+        for child_id in child_ids {
+            if let Some(child_node) = self.export_node(child_id)? {
+                children.push(child_node);
+            }
+        }
+
+        Ok(Some(TreeNode {
+            page_id: page_id.value(),
+            is_leaf: false,
+            keys,
+            values: Vec::new(),
+            children,
+            free_bytes: Some(free_bytes),
+            fill_percent: Some(fill_percent),
+            cell_count: Some(cell_count),
+        }))
+    }
+}
+
+/// Parse a `Db::dump_metadata` snapshot into a header and free pages
+fn parse_metadata(bytes: &[u8]) -> Result<(FileHeader, Vec<PageId>)> {
+    if bytes.len() < PAGE_SIZE + 4 {
+        return Err(StorageError::invalid_db("metadata snapshot too short"));
+    }
+    let header = FileHeader::read(&bytes[..PAGE_SIZE])?;
+
+    let count = u32::from_be_bytes(bytes[PAGE_SIZE..PAGE_SIZE + 4].try_into().unwrap()) as usize;
+    let ids = &bytes[PAGE_SIZE + 4..];
+    if ids.len() != count * 4 {
+        return Err(StorageError::invalid_db(format!(
+            "metadata snapshot lists {} free pages but has {} bytes of IDs",
+            count,
+            ids.len()
+        )));
+    }
+    let free_pages = ids
+        .chunks_exact(4)
+        .map(|id| PageId::from_be_bytes(id.try_into().unwrap()))
+        .collect();
+    Ok((header, free_pages))
+}
+
+/// Path of the file `Db::compact` builds before renaming it into place
+fn compact_path(db_path: &Path) -> PathBuf {
+    let mut path = db_path.as_os_str().to_owned();
+    path.push("-compact");
+    PathBuf::from(path)
+}
+
+/// Escape a key for a DOT label, hex-escaping anything not printable ASCII
+fn dot_escape(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len());
+    for &b in bytes {
+        if (b.is_ascii_graphic() || b == b' ') && b != b'"' && b != b'\\' {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("\\\\x{:02x}", b));
+        }
+    }
+    out
+}
+
+/// Database statistics
+#[derive(Debug, Clone)]
+pub struct DbStats {
+    /// Total number of pages in the database
+    pub page_count: usize,
+    /// Buffer pool capacity
+    pub buffer_pool_size: usize,
+    /// Resident pages not yet written back; `flush` clears them
+    pub dirty_pages: usize,
+    /// Height of the B-tree
+    pub tree_height: usize,
+    /// Split and merge counts since open or the last reset
+    pub structure: StructureStats,
+}
+
+/// Layout of one page, as reported by `Db::inspect_page`
+#[derive(Debug, Clone)]
+pub enum PageInspection {
+    /// Page 0, which holds the file header
+    FileHeader(FileHeader),
+    /// Any other page, read as a slotted page
+    Slotted {
+        /// Type byte of the page
+        page_type: PageType,
+        /// Number of cells on the page
+        cell_count: usize,
+        /// Bytes between the cell pointers and the cell content
+        free_bytes: usize,
+        /// Bytes in the cell content area held by no live cell
+        fragmented_bytes: usize,
+        /// Leftmost child of an interior page, or the next leaf of a leaf
+        right_child: PageId,
+        /// Cells in slot order
+        cells: Vec<CellInspection>,
+    },
+}
+
+/// One cell of an inspected page
+#[derive(Debug, Clone)]
+pub struct CellInspection {
+    /// Key bytes
+    pub key: Vec<u8>,
+    /// Full value length of a leaf cell, overflow included
+    pub value_len: Option<usize>,
+    /// Child pointer of an interior cell
+    pub child: Option<PageId>,
+}
+
+/// Lazy range iterator returned by `Db::range_iter` and `Db::range_rev`
+struct RangeIter<'a> {
+    db: &'a Db,
+    /// Cursor at the last entry returned, `None` before the first step
+    cursor: Option<Cursor<dyn BufferPool>>,
+    /// Tree version and root page the cursor was positioned against
+    position: (u64, PageId),
+    start: Option<Vec<u8>>,
+    end: Option<Vec<u8>>,
+    last_key: Option<Vec<u8>>,
+    /// Whether to walk from the highest key down
+    reverse: bool,
+    done: bool,
+}
+
+impl<'a> RangeIter<'a> {
+    fn new(db: &'a Db, start: Option<&[u8]>, end: Option<&[u8]>, reverse: bool) -> Self {
+        Self {
+            db,
+            cursor: None,
+            position: (0, PageId::HEADER),
+            start: start.map(<[u8]>::to_vec),
+            end: end.map(<[u8]>::to_vec),
+            last_key: None,
+            reverse,
+            done: false,
+        }
+    }
+
+    /// Position a new cursor at the first entry not yet returned
+    fn seek(&self, btree: &BTree) -> Result<Cursor<dyn BufferPool>> {
+        let pool = self.db.buffer_pool.clone();
+        let root = btree.root_page();
+        let order = btree.config().key_order;
+
+        if self.reverse {
+            // Step back from the first key at or above the bound
+            return match self.last_key.as_ref().or(self.end.as_ref()) {
+                Some(bound) => {
+                    let mut cursor = Cursor::seek_by(pool.clone(), root, bound, order)?;
+                    if !cursor.is_valid() {
+                        return Cursor::last(pool, root);
+                    }
+                    cursor.prev()?;
+                    Ok(cursor)
+                }
+                None => Cursor::last(pool, root),
+            };
+        }
+
+        let start = self.last_key.as_ref().or(self.start.as_ref());
+        let mut cursor = Cursor::seek_range_by(
+            pool,
+            root,
+            start.map(Vec::as_slice),
+            self.end.as_deref(),
+            order,
+        )?;
+        if let (Some(last), Some((key, _))) = (&self.last_key, cursor.current()?) {
+            if &key == last {
+                cursor.next()?;
+            }
+        }
+        Ok(cursor)
+    }
+
+    /// Advance to the next entry under the tree's read lock
+    fn step(&mut self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let btree = self.db.btree.read();
+        let position = (btree.version(), btree.root_page());
+
+        match self.cursor.as_mut() {
+            Some(cursor) if position == self.position => {
+                if self.reverse {
+                    cursor.prev()?;
+                } else {
+                    cursor.next()?;
+                }
+            }
+            _ => {
+                // First step, or the tree changed since the last one:
+                // seek from the root past the last key returned
+                self.cursor = Some(self.seek(&btree)?);
+                self.position = position;
+            }
+        }
+
+        let entry = self.cursor.as_ref().unwrap().current()?;
+        // Forward cursors stop at the end bound themselves
+        let order = btree.config().key_order;
+        let out_of_range = |key: &Vec<u8>| {
+            self.reverse
+                && self.start.as_ref().is_some_and(|start| order.compare(key, start).is_lt())
+        };
+        match entry {
+            Some((key, _)) if out_of_range(&key) => Ok(None),
+            Some((key, value)) => {
+                self.last_key = Some(key.clone());
+                Ok(Some((key, value)))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+impl Iterator for RangeIter<'_> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.step().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+        result
+    }
+}
+
+/// Overflow pages backing a single value
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OverflowChain {
+    /// Overflow pages in chain order
+    pub pages: Vec<PageId>,
+    /// Total payload bytes stored in the chain
+    pub total_len: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_basic_operations() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let config = Config::new(&path);
+        let db = Db::open(config)?;
+
+        // Test put and get
+        db.put(b"key1", b"value1")?;
+        assert_eq!(db.get(b"key1")?, Some(b"value1".to_vec()));
+
+        // Test update
+        db.put(b"key1", b"value2")?;
+        assert_eq!(db.get(b"key1")?, Some(b"value2".to_vec()));
+
+        // Test delete
+        assert!(db.delete(b"key1")?);
+        assert_eq!(db.get(b"key1")?, None);
+
+        // Test non-existent key
+        assert_eq!(db.get(b"nonexistent")?, None);
+        assert!(!db.delete(b"nonexistent")?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_range_scan() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let config = Config::new(&path);
+        let db = Db::open(config)?;
+
+        // Insert some data
+        db.put(b"apple", b"1")?;
+        db.put(b"banana", b"2")?;
+        db.put(b"cherry", b"3")?;
+        db.put(b"date", b"4")?;
+
+        // Full scan
+        let all = db.iter()?;
+        assert_eq!(all.len(), 4);
+
+        // Range scan
+        let range = db.range(Some(b"banana"), Some(b"date"))?;
+        assert_eq!(range.len(), 2);
+        assert_eq!(range[0].0, b"banana".to_vec());
+        assert_eq!(range[1].0, b"cherry".to_vec());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_cached() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+
+        {
+            let db = Db::open(Config::new(&path))?;
+            for i in 0..50 {
+                db.put(format!("key{:02}", i).as_bytes(), b"value")?;
+            }
+            db.flush()?;
+        }
+
+        // Nothing is resident right after reopening
+        let db = Db::open(Config::new(&path))?;
+        assert_eq!(db.get_cached(b"key10")?, CachedGet::NotCached);
+
+        db.get(b"key10")?;
+        assert_eq!(db.get_cached(b"key10")?, CachedGet::Found(b"value".to_vec()));
+        assert_eq!(db.get_cached(b"key10x")?, CachedGet::Absent);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_overflow_chain_inline_value() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let db = Db::open(Config::new(dir.path().join("test.db")))?;
+
+        db.put(b"key", b"value")?;
+        assert_eq!(db.overflow_chain(b"key")?, OverflowChain::default());
+        assert!(matches!(db.overflow_chain(b"missing"), Err(StorageError::KeyNotFound)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_large_value_overflow() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let value: Vec<u8> = (0..50 * 1024).map(|i| (i * 7 % 251) as u8).collect();
+
+        {
+            let db = Db::open(Config::new(&path))?;
+            db.put(b"a", b"small")?;
+            db.put(b"blob", &value)?;
+            db.put(b"c", b"small")?;
+
+            assert_eq!(db.get(b"blob")?, Some(value.clone()));
+            let chain = db.overflow_chain(b"blob")?;
+            assert_eq!(chain.total_len, value.len() - types::MAX_INLINE_VALUE_SIZE);
+            assert_eq!(chain.pages.len(), chain.total_len.div_ceil(page::OVERFLOW_PAGE_CAPACITY));
+            assert_eq!(db.iter()?[1], (b"blob".to_vec(), value.clone()));
+            assert!(db.orphaned_overflow_pages()?.is_empty());
+            db.flush()?;
+        }
+
+        // The chain survives a reopen, and shrinking the value frees it
+        let db = Db::open(Config::new(&path))?;
+        assert_eq!(db.get(b"blob")?, Some(value.clone()));
+        let free_before = db.disk_manager.free_pages().len();
+        db.put(b"blob", b"small now")?;
+        assert_eq!(db.overflow_chain(b"blob")?, OverflowChain::default());
+        assert!(db.disk_manager.free_pages().len() > free_before);
+        assert!(db.orphaned_overflow_pages()?.is_empty());
+
+        db.put(b"blob", &value)?;
+        assert!(db.delete(b"blob")?);
+        assert!(db.orphaned_overflow_pages()?.is_empty());
+
+        let too_large = vec![0u8; types::MAX_VALUE_SIZE + 1];
+        assert!(matches!(db.put(b"big", &too_large), Err(StorageError::ValueTooLarge { .. })));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_range_iter() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        {
+            let db = Db::open(Config::new(&path))?;
+            for i in 0..2000 {
+                db.put(format!("key{:04}", i).as_bytes(), format!("value{}", i).as_bytes())?;
+            }
+            db.flush()?;
+        }
+
+        // Count the pages a fresh buffer pool had to read
+        let resident = |db: &Db| {
+            (1..db.stats().page_count as u32)
+                .filter(|&id| db.buffer_pool.is_cached(PageId::new(id)))
+                .count()
+        };
+
+        let db = Db::open(Config::new(&path).buffer_pool_size(2000))?;
+        let first: Vec<_> = db
+            .range_iter(Some(b"key0100"), None)
+            .take(3)
+            .collect::<Result<_>>()?;
+        assert_eq!(first, db.range(Some(b"key0100"), Some(b"key0103"))?);
+        let lazy_reads = resident(&db);
+
+        let all = db.range_iter(None, None).collect::<Result<Vec<_>>>()?;
+        assert_eq!(all, db.iter()?);
+        assert!(lazy_reads * 10 < resident(&db));
+
+        // The end bound is exclusive, and writes between steps are picked up
+        let mut iter = db.range_iter(Some(b"key0500"), Some(b"key0510"));
+        assert_eq!(iter.next().unwrap()?.0, b"key0500");
+        db.delete(b"key0501")?;
+        db.put(b"key0502a", b"new")?;
+        let rest: Vec<Vec<u8>> = iter.map(|entry| entry.map(|(k, _)| k)).collect::<Result<_>>()?;
+        let mut expected: Vec<Vec<u8>> = (502..510).map(|i| format!("key{:04}", i).into_bytes()).collect();
+        expected.insert(1, b"key0502a".to_vec());
+        assert_eq!(rest, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_range_rev() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let db = Db::open(Config::new(dir.path().join("test.db")))?;
+        for i in 0..500 {
+            db.put(format!("key{:03}", i).as_bytes(), b"v")?;
+        }
+
+        let mut expected = db.range(Some(b"key100"), Some(b"key400"))?;
+        expected.reverse();
+        let rows = db.range_rev(Some(b"key100"), Some(b"key400")).collect::<Result<Vec<_>>>()?;
+        assert_eq!(rows, expected);
+
+        // Most recent N: the end bound need not be a stored key
+        let keys: Vec<Vec<u8>> = db
+            .range_rev(None, Some(b"key2505"))
+            .take(3)
+            .map(|entry| entry.map(|(k, _)| k))
+            .collect::<Result<_>>()?;
+        assert_eq!(keys, vec![b"key250".to_vec(), b"key249".to_vec(), b"key248".to_vec()]);
+
+        let mut all = db.iter()?;
+        all.reverse();
+        assert_eq!(db.range_rev(None, None).collect::<Result<Vec<_>>>()?, all);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_partition() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let config = Config::new(dir.path().join("test.db"))
+            .btree_config(BTreeConfig::default().composite_prefix(2));
+        let db = Db::open(config)?;
+        let keys = db.btree_config();
+
+        for partition in [b"p1", b"p2", b"p3"] {
+            for ts in [3u32, 1, 2] {
+                db.put(&keys.composite_key(partition, &ts.to_be_bytes())?, b"v")?;
+            }
+        }
+        assert!(db.put(b"p", b"too short").is_err());
+
+        let rows = db.scan_partition(b"p2")?;
+        let suffixes: Vec<&[u8]> = rows.iter().map(|(k, _)| keys.split_key(k).unwrap().1).collect();
+        assert_eq!(suffixes, vec![&1u32.to_be_bytes()[..], &2u32.to_be_bytes(), &3u32.to_be_bytes()]);
+        assert!(db.scan_partition(b"p22").is_err());
+
+        Ok(())
+    }
+
+    /// Buffer pool wrapper that counts fetches, standing in for a custom
+    /// cache; while `full` is set it refuses to allocate pages
+    struct CountingPool {
+        inner: BufferPoolImpl,
+        fetches: std::sync::atomic::AtomicUsize,
+        full: std::sync::atomic::AtomicBool,
+    }
+
+    impl BufferPool for CountingPool {
+        fn fetch_page(&self, page_id: PageId) -> Result<buffer::PageGuard<'_>> {
+            self.fetches.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            self.inner.fetch_page(page_id)
+        }
+        fn fetch_cached(&self, page_id: PageId) -> Option<buffer::PageGuard<'_>> {
+            self.inner.fetch_cached(page_id)
+        }
+        fn is_cached(&self, page_id: PageId) -> bool {
+            self.inner.is_cached(page_id)
+        }
+        fn fetch_page_mut(&self, page_id: PageId) -> Result<buffer::PageGuardMut<'_>> {
+            self.fetches.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            self.inner.fetch_page_mut(page_id)
+        }
+        fn new_page(&self) -> Result<(PageId, buffer::PageGuardMut<'_>)> {
+            if self.full.load(std::sync::atomic::Ordering::Relaxed) {
+                return Err(StorageError::invalid_operation("no pages left"));
+            }
+            self.inner.new_page()
+        }
+        fn flush_page(&self, page_id: PageId) -> Result<()> {
+            self.inner.flush_page(page_id)
+        }
+        fn flush_all(&self) -> Result<()> {
+            self.inner.flush_all()
+        }
+        fn flush_data(&self) -> Result<()> {
+            self.inner.flush_data()
+        }
+        fn free_page(&self, page_id: PageId) -> Result<()> {
+            self.inner.free_page(page_id)
+        }
+        fn discard_all(&self) -> Result<()> {
+            self.inner.discard_all()
+        }
+        fn page_count(&self) -> usize {
+            self.inner.page_count()
+        }
+        fn capacity(&self) -> usize {
+            self.inner.capacity()
+        }
+        fn dirty_page_count(&self) -> usize {
+            self.inner.dirty_page_count()
+        }
+        fn root_page(&self) -> PageId {
+            self.inner.root_page()
+        }
+        fn tree_height(&self) -> u32 {
+            self.inner.tree_height()
+        }
+        fn set_root_page(&self, page_id: PageId, height: u32) -> Result<()> {
+            self.inner.set_root_page(page_id, height)
+        }
+        fn entry_count(&self) -> u64 {
+            self.inner.entry_count()
+        }
+        fn set_entry_count(&self, count: u64) -> Result<()> {
+            self.inner.set_entry_count(count)
+        }
+    }
+
+    #[test]
+    fn test_first_and_last() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let config = Config::new(dir.path().join("test.db")).btree_config(BTreeConfig::new(3, 2));
+        let disk_manager: Arc<dyn DiskManager> = Arc::new(DiskManagerImpl::open(&config.path, false)?);
+        let pool = Arc::new(CountingPool {
+            inner: BufferPoolImpl::new(disk_manager.clone(), 100),
+            fetches: Default::default(),
+            full: Default::default(),
+        });
+        let db = Db::with_components(disk_manager, pool.clone(), config)?;
+
+        assert_eq!(db.first()?, None);
+        assert_eq!(db.last()?, None);
+
+        for i in (0..300).rev() {
+            db.put(format!("key{:03}", i).as_bytes(), format!("v{}", i).as_bytes())?;
+        }
+        let height = db.stats().tree_height;
+        assert!(height >= 4, "need at least two interior levels, got height {}", height);
+
+        let fetches = || pool.fetches.load(std::sync::atomic::Ordering::Relaxed);
+        let before = fetches();
+        assert_eq!(db.first()?, Some((b"key000".to_vec(), b"v0".to_vec())));
+        assert_eq!(fetches() - before, height);
+        let before = fetches();
+        assert_eq!(db.last()?, Some((b"key299".to_vec(), b"v299".to_vec())));
+        assert_eq!(fetches() - before, height);
+
+        db.delete(b"key000")?;
+        db.delete(b"key299")?;
+        assert_eq!(db.first()?.unwrap().0, b"key001");
+        assert_eq!(db.last()?.unwrap().0, b"key298");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_components() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let config = Config::new(dir.path().join("test.db"));
+        let disk_manager: Arc<dyn DiskManager> = Arc::new(DiskManagerImpl::open(&config.path, false)?);
+        let pool = Arc::new(CountingPool {
+            inner: BufferPoolImpl::new(disk_manager.clone(), 16),
+            fetches: Default::default(),
+            full: Default::default(),
+        });
+
+        let db = Db::with_components(disk_manager, pool.clone(), config)?;
+        db.put(b"key", b"value")?;
+        assert_eq!(db.get(b"key")?, Some(b"value".to_vec()));
+        assert!(pool.fetches.load(std::sync::atomic::Ordering::Relaxed) > 0);
+        assert_eq!(db.stats().buffer_pool_size, 16);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_dot() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let db = Db::open(Config::new(dir.path().join("test.db")))?;
+
+        assert_eq!(db.export_dot()?, "digraph btree {\n    node [shape=box, fontname=\"monospace\"];\n}\n");
+
+        for i in 0..10 {
+            db.put(format!("key{}", i).as_bytes(), b"v")?;
+        }
+        db.put(b"bin\x00\"", b"v")?;
+
+        let dot = db.export_dot()?;
+        let root = db.btree.read().root_page().value();
+        assert!(dot.starts_with("digraph btree {"));
+        assert!(dot.contains(&format!("p{} -> p", root)));
+        assert!(dot.contains("key5"));
+        assert!(dot.contains("bin\\\\x00\\\\x22"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_tree_fill() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let db = Db::open(Config::new(dir.path().join("test.db")))?;
+
+        db.put(b"small", b"v")?;
+        let sparse = db.export_tree()?.unwrap();
+        assert!(sparse.fill_percent.unwrap() < 5.0);
+        assert_eq!(sparse.cell_count, Some(1));
+
+        db.put(b"large", &[b'v'; 1000])?;
+        let dense = db.export_tree()?.unwrap();
+        assert!(dense.fill_percent.unwrap() > 20.0);
+        assert!(dense.free_bytes.unwrap() < sparse.free_bytes.unwrap());
+
+        // Long keys and values fill the leaf before its key limit
+        for key in [b'x', b'y'] {
+            db.put(&[key; 350], &[b'v'; 1024])?;
+        }
+        let full = db.export_tree()?.unwrap();
+        assert!(full.is_leaf);
+        assert!(full.fill_percent.unwrap() > 90.0, "{:?}", full.fill_percent);
+        assert_eq!(full.cell_count, Some(4));
+
+        let json = serde_json::to_string(&full).unwrap();
+        assert!(json.contains("\"fillPercent\""));
+        assert!(json.contains("\"cellCount\":4"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_range_with_budget() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let db = Db::open(Config::new(dir.path().join("test.db")))?;
+
+        for i in 0..50 {
+            db.put(format!("key{:02}", i).as_bytes(), &[b'v'; 10])?;
+        }
+
+        let rows = db.range_with_budget(Some(b"key10"), Some(b"key20"), 100)?;
+        assert_eq!(rows.len(), 10);
+        assert_eq!(rows, db.range(Some(b"key10"), Some(b"key20"))?);
+
+        let err = db.range_with_budget(Some(b"key10"), Some(b"key20"), 99).unwrap_err();
+        assert!(matches!(err, StorageError::QuotaExceeded { used: 100, max: 99 }));
+
+        assert_eq!(db.range_with_budget(None, None, 500)?.len(), 50);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_count_range() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let db = Db::open(Config::new(dir.path().join("test.db")))?;
+        assert_eq!(db.count_range(None, None)?, 0);
+
+        for i in 0..100 {
+            db.put(format!("key{:03}", i).as_bytes(), b"value")?;
+        }
+
+        assert_eq!(db.count_range(None, None)?, 100);
+        assert_eq!(db.count_range(Some(b"key010"), Some(b"key035"))?, 25);
+        assert_eq!(db.count_range(Some(b"key090"), None)?, 10);
+        assert_eq!(db.count_range(Some(b"zzz"), None)?, 0);
+        assert_eq!(
+            db.count_range(Some(b"key042"), Some(b"key077"))?,
+            db.range(Some(b"key042"), Some(b"key077"))?.len()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_filter() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let db = Db::open(Config::new(dir.path().join("test.db")))?;
+
+        for i in 0..50u8 {
+            db.put(format!("key{:02}", i).as_bytes(), &[i])?;
+        }
+
+        let rows = db.scan_filter(Some(b"key10"), Some(b"key40"), |_, value| value[0] % 10 == 0)?;
+        let keys: Vec<_> = rows.iter().map(|(k, _)| k.as_slice()).collect();
+        assert_eq!(keys, vec![&b"key10"[..], b"key20", b"key30"]);
+
+        assert!(db.scan_filter(None, None, |_, _| false)?.is_empty());
+        assert_eq!(db.scan_filter(None, None, |_, _| true)?, db.iter()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_iter_pages_physical() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let db = Db::open(Config::new(dir.path().join("test.db")).buffer_pool_size(4))?;
+        for i in 0..100 {
+            db.put(format!("key{:03}", i).as_bytes(), b"value")?;
+        }
+
+        let pages = db.iter_pages_physical().collect::<Result<Vec<_>>>()?;
+        assert_eq!(pages.len(), db.stats().page_count - 1);
+        let ids: Vec<u32> = pages.iter().map(|(id, _)| id.value()).collect();
+        assert_eq!(ids, (1..db.stats().page_count as u32).collect::<Vec<_>>());
+
+        // Every key shows up on exactly one leaf
+        let mut keys = Vec::new();
+        for (_, page) in pages.iter().filter(|(_, page)| page.is_leaf()) {
+            for cell in page.get_all_cells()? {
+                keys.push(cell.key);
+            }
+        }
+        keys.sort();
+        let expected: Vec<Vec<u8>> = db.iter()?.into_iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_range_collect_rev() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let db = Db::open(Config::new(dir.path().join("test.db")))?;
+        for i in 0..200 {
+            db.put(format!("event{:03}", i).as_bytes(), format!("{}", i).as_bytes())?;
+        }
+
+        let mut forward = db.range(Some(b"event050"), Some(b"event150"))?;
+        forward.reverse();
+        assert_eq!(db.range_collect_rev(Some(b"event050"), Some(b"event150"), None)?, forward);
+
+        let latest = db.range_collect_rev(None, Some(b"event150"), Some(10))?;
+        let keys: Vec<Vec<u8>> = latest.into_iter().map(|(k, _)| k).collect();
+        let expected: Vec<Vec<u8>> = (140..150).rev().map(|i| format!("event{:03}", i).into_bytes()).collect();
+        assert_eq!(keys, expected);
+
+        assert_eq!(db.range_collect_rev(None, None, Some(500))?.len(), 200);
+        assert!(db.range_collect_rev(None, None, Some(0))?.is_empty());
+        assert!(db.range_collect_rev(Some(b"z"), None, Some(5))?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reencode_keys() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+
+        {
+            let db = Db::open(Config::new(&path))?;
+            for i in 0..100u32 {
+                db.put(format!("{}", i).as_bytes(), &i.to_be_bytes())?;
+            }
+
+            // Decimal strings do not sort numerically, so a plain rewrite fails
+            let to_be = |key: &[u8]| {
+                let n: u32 = std::str::from_utf8(key).unwrap().parse().unwrap();
+                n.to_be_bytes().to_vec()
+            };
+            assert!(db.reencode_keys(to_be, BTreeConfig::default()).is_err());
+            assert_eq!(db.get(b"42")?, Some(42u32.to_be_bytes().to_vec()));
+
+            // Prefixing every key keeps the order
+            let config = BTreeConfig::default().composite_prefix(2);
+            db.reencode_keys(|key| [b"v2".as_slice(), key].concat(), config)?;
+            assert_eq!(db.btree_config().composite_prefix_len, Some(2));
+            assert_eq!(db.get(b"42")?, None);
+            assert_eq!(db.scan_partition(b"v2")?.len(), 100);
+            db.flush()?;
+        }
+
+        let db = Db::open(Config::new(&path))?;
+        assert_eq!(db.get(b"v242")?, Some(42u32.to_be_bytes().to_vec()));
+        assert_eq!(db.iter()?.len(), 100);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_structure_stats() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let db = Db::open(Config::new(dir.path().join("test.db")))?;
+        assert_eq!(db.stats().structure, StructureStats::default());
+
+        for i in 0..100 {
+            db.put(format!("key{:03}", i).as_bytes(), b"value")?;
+        }
+        let stats = db.stats();
+        assert!(stats.structure.leaf_splits > 0);
+        assert!(stats.structure.interior_splits > 0);
+        assert_eq!(stats.structure.root_splits as usize, stats.tree_height - 1);
+        assert_eq!(stats.structure.merges, 0);
+
+        // Updates do not split anything
+        db.reset_structure_stats();
+        db.put(b"key050", b"other")?;
+        assert_eq!(db.stats().structure, StructureStats::default());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_prefix_map() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let db = Db::open(Config::new(dir.path().join("test.db")))?;
+
+        db.put(b"row:1:age", b"30")?;
+        db.put(b"row:1:name", b"ada")?;
+        db.put(b"row:10:name", b"bob")?;
+        db.put(b"row:2:name", b"cy")?;
+
+        let row = db.get_prefix_map(b"row:1:")?;
+        assert_eq!(row.len(), 2);
+        assert_eq!(row.get(b"age".as_slice()), Some(&b"30".to_vec()));
+        assert_eq!(row.get(b"name".as_slice()), Some(&b"ada".to_vec()));
+
+        assert!(db.get_prefix_map(b"row:3:")?.is_empty());
+        assert_eq!(db.get_prefix_map(b"")?.len(), 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_allow_empty_values() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+
+        {
+            let db = Db::open(Config::new(&path))?;
+            db.put(b"marker", b"")?;
+            assert_eq!(db.get(b"marker")?, Some(Vec::new()));
+            db.flush()?;
+        }
+
+        let db = Db::open(Config::new(&path).allow_empty_values(false))?;
+        assert!(matches!(db.put(b"other", b""), Err(StorageError::EmptyValue)));
+        assert!(matches!(
+            db.append_batch(&[(b"z".to_vec(), Vec::new())]),
+            Err(StorageError::EmptyValue)
+        ));
+        assert_eq!(db.get(b"other")?, None);
+
+        // Existing empty values stay readable
+        assert_eq!(db.get(b"marker")?, Some(Vec::new()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_recompute_height() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+
+        let height = {
+            let db = Db::open(Config::new(&path))?;
+            assert_eq!(db.recompute_height()?, 0);
+            for i in 0..100 {
+                db.put(format!("key{:03}", i).as_bytes(), b"value")?;
+            }
+            db.flush()?;
+            db.stats().tree_height
+        };
+        assert!(height > 1);
+
+        // Corrupt the stored height
+        {
+            let dm = DiskManagerImpl::open(&path, true)?;
+            let root = dm.header().root_page;
+            dm.set_root_page(root, 1)?;
+        }
+
+        {
+            let db = Db::open(Config::new(&path))?;
+            assert_eq!(db.stats().tree_height, 1);
+            assert!(!db.maintenance()?.is_ok());
+            assert_eq!(db.recompute_height()?, height);
+            assert!(db.maintenance()?.is_ok());
+        }
+
+        let db = Db::open(Config::new(&path))?;
+        assert_eq!(db.stats().tree_height, height);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ingest_from_channel() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+
+        {
+            let db = Db::open(Config::new(&path))?;
+            let (tx, rx) = std::sync::mpsc::sync_channel(16);
+            let producer = std::thread::spawn(move || {
+                for i in (0..5000u32).rev() {
+                    tx.send((format!("key{:05}", i).into_bytes(), i.to_be_bytes().to_vec()))
+                        .unwrap();
+                }
+            });
+
+            assert_eq!(db.ingest(rx)?, 5000);
+            producer.join().unwrap();
+        }
+
+        // Ingested data was flushed without an explicit flush
+        let db = Db::open(Config::new(&path))?;
+        assert_eq!(db.iter()?.len(), 5000);
+        assert_eq!(db.get(b"key04321")?, Some(4321u32.to_be_bytes().to_vec()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_paranoid_mode() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let db = Db::open(Config::new(dir.path().join("test.db")).paranoid(true).buffer_pool_size(8))?;
+
+        for i in 0..300 {
+            db.put(format!("key{:03}", i).as_bytes(), &[b'v'; 40])?;
+        }
+        for i in (0..300).step_by(3) {
+            db.delete(format!("key{:03}", i).as_bytes())?;
+        }
+        db.flush()?;
+        assert_eq!(db.iter()?.len(), 200);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dump_and_restore_metadata() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+
+        let snapshot = {
+            let db = Db::open(Config::new(&path))?;
+            for i in 0..100 {
+                db.put(format!("key{:03}", i).as_bytes(), b"value")?;
+            }
+            db.flush()?;
+            let snapshot = db.dump_metadata()?;
+
+            // Point the header at the wrong page, then put it back
+            db.disk_manager.set_root_page(PageId::new(1), 1)?;
+            db.btree.write().reload_root();
+            assert_ne!(db.get(b"key050")?, Some(b"value".to_vec()));
+            db.restore_metadata(&snapshot)?;
+            assert_eq!(db.get(b"key050")?, Some(b"value".to_vec()));
+
+            // A snapshot claiming more pages than the file holds is refused
+            let mut header = db.disk_manager.header();
+            header.page_count += 1000;
+            let mut bad = snapshot.clone();
+            header.write(&mut bad[..PAGE_SIZE]);
+            assert!(matches!(
+                db.restore_metadata(&bad),
+                Err(StorageError::InvalidDatabaseFile(_))
+            ));
+            assert!(db.restore_metadata(&snapshot[..100]).is_err());
+            snapshot
+        };
+
+        // Trash the header on disk so the file no longer opens
+        {
+            use std::io::Write;
+            let mut file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+            file.write_all(&[0xAB; 64]).unwrap();
+        }
+        assert!(Db::open(Config::new(&path)).is_err());
+
+        Db::restore_metadata_at(&path, &snapshot)?;
+        let db = Db::open(Config::new(&path))?;
+        assert_eq!(db.iter()?.len(), 100);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_on_open() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+
+        Db::open(Config::new(&path).verify_on_open(true))?;
+        {
+            let db = Db::open(Config::new(&path).verify_on_open(true))?;
+            for i in 0..100 {
+                db.put(format!("key{:03}", i).as_bytes(), b"value")?;
+            }
+            db.flush()?;
+        }
+        Db::open(Config::new(&path).verify_on_open(true))?;
+
+        // Point the root past the end of the file
+        let root = {
+            let dm = DiskManagerImpl::open(&path, true)?;
+            let header = dm.header();
+            dm.set_root_page(PageId::new(header.page_count + 10), header.tree_height)?;
+            header.root_page
+        };
+        assert!(Db::open(Config::new(&path)).is_ok());
+        assert!(matches!(
+            Db::open(Config::new(&path).verify_on_open(true)),
+            Err(StorageError::Corruption(_))
+        ));
+
+        // A stale height is caught too
+        DiskManagerImpl::open(&path, true)?.set_root_page(root, 1)?;
+        assert!(Db::open(Config::new(&path).verify_on_open(true)).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deferred_metadata() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let config = Config::new(&path).defer_metadata(true);
+
+        // Without a metadata flush the file still describes the empty tree
+        {
+            let db = Db::open(config.clone())?;
+            for i in 0..100 {
+                db.put(format!("key{:03}", i).as_bytes(), b"value")?;
+            }
+            db.flush_data()?;
+        }
+        {
+            let db = Db::open(config.clone().verify_on_open(true))?;
+            assert!(db.iter()?.is_empty());
+
+            for i in 0..100 {
+                db.put(format!("key{:03}", i).as_bytes(), b"value")?;
+            }
+            db.flush_data()?;
+            db.flush_metadata()?;
+        }
+
+        let db = Db::open(config.verify_on_open(true))?;
+        assert_eq!(db.iter()?.len(), 100);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reclaim_orphans() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+
+        let orphans = {
+            let db = Db::open(Config::new(&path))?;
+            db.put(b"key", b"value")?;
+
+            // Simulate overflow pages whose leaf cell was never committed
+            let mut orphans = Vec::new();
+            for _ in 0..2 {
+                let (orphan, guard) = db.buffer_pool.new_page()?;
+                let mut bytes = [0u8; PAGE_SIZE];
+                bytes[0] = PageType::Overflow as u8;
+                *guard.write() = SlottedPage::from_bytes(&bytes)?;
+                orphans.push(orphan);
+            }
+            db.flush()?;
+            orphans
+        };
+
+        let db = Db::open(Config::new(&path))?;
+        assert_eq!(db.orphaned_overflow_pages()?, orphans);
+        assert_eq!(db.reclaim_orphans()?, 2);
+        assert!(db.orphaned_overflow_pages()?.is_empty());
+        assert_eq!(db.get(b"key")?, Some(b"value".to_vec()));
+        db.flush()?;
+        drop(db);
+
+        // The freed pages stay on the free list across a reopen
+        let db = Db::open(Config::new(&path))?;
+        assert_eq!(db.reclaim_orphans()?, 0);
+        let mut free = db.disk_manager.free_pages();
+        free.sort();
+        assert_eq!(free, orphans);
+        assert_eq!(db.disk_manager.header().free_page_count, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rename() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let db = Db::open(Config::new(dir.path().join("test.db")))?;
+
+        db.put(b"tmp:abc", b"payload")?;
+        db.put(b"final:xyz", b"other")?;
+
+        assert!(db.rename(b"tmp:abc", b"final:abc", false)?);
+        assert_eq!(db.get(b"tmp:abc")?, None);
+        assert_eq!(db.get(b"final:abc")?, Some(b"payload".to_vec()));
+
+        // Missing source changes nothing
+        assert!(!db.rename(b"tmp:abc", b"final:xyz", true)?);
+        assert_eq!(db.get(b"final:xyz")?, Some(b"other".to_vec()));
+
+        // Existing target needs the overwrite flag
+        assert!(db.rename(b"final:abc", b"final:xyz", false).is_err());
+        assert_eq!(db.get(b"final:abc")?, Some(b"payload".to_vec()));
+        assert!(db.rename(b"final:abc", b"final:xyz", true)?);
+        assert_eq!(db.get(b"final:xyz")?, Some(b"payload".to_vec()));
+        assert_eq!(db.iter()?.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_as_of() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let db = Db::open(Config::new(dir.path().join("test.db")))?;
+        assert_eq!(db.version(), 0);
+
+        db.put(b"key", b"v1")?;
+        let snapshot = db.snapshot();
+        assert_eq!(snapshot.version(), 1);
+        assert_eq!(snapshot.get(b"key")?, Some(b"v1".to_vec()));
+        assert_eq!(db.get_as_of(1, b"missing")?, None);
+
+        // Deleting an absent key changes nothing
+        assert!(!db.delete(b"missing")?);
+        assert_eq!(db.version(), 1);
+
+        // Older versions are only retained by snapshots, and future ones do
+        // not exist
+        db.put(b"key", b"v2")?;
+        assert_eq!(snapshot.get(b"key")?, Some(b"v1".to_vec()));
+        assert!(matches!(
+            db.get_as_of(1, b"key"),
+            Err(StorageError::VersionUnavailable { version: 1, current: 2 })
+        ));
+        assert!(db.get_as_of(3, b"key").is_err());
+        assert_eq!(db.get_as_of(2, b"key")?, Some(b"v2".to_vec()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compare_keys_matches_tree_order() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let db = Db::open(Config::new(dir.path().join("test.db")))?;
+
+        assert_eq!(db.compare_keys(b"a", b"b"), Ordering::Less);
+        assert_eq!(db.compare_keys(b"ab", b"a"), Ordering::Greater);
+        assert_eq!(db.compare_keys(b"", b""), Ordering::Equal);
+
+        // Input sorted with the tree's order is accepted by append_batch
+        let mut entries: Vec<(Vec<u8>, Vec<u8>)> = [b"m".as_slice(), b"\xff", b"a", b"ab"]
+            .iter()
+            .map(|k| (k.to_vec(), b"v".to_vec()))
+            .collect();
+        entries.sort_by(|a, b| db.compare_keys(&a.0, &b.0));
+        db.append_batch(&entries)?;
+        let keys: Vec<Vec<u8>> = db.iter()?.into_iter().map(|(k, _)| k).collect();
+        let expected: Vec<Vec<u8>> = entries.into_iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bloom_filters_persist() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+
+        {
+            let db = Db::open(Config::new(&path))?;
+            for i in 0..50 {
+                db.put(format!("key{:03}", i).as_bytes(), b"plain")?;
+            }
+            db.flush()?;
+        }
+
+        // Existing leaves pick up a filter as they are written
+        {
+            let db = Db::open(Config::new(&path).enable_bloom(true))?;
+            for i in 50..100 {
+                db.put(format!("key{:03}", i).as_bytes(), b"bloom")?;
+            }
+            db.delete(b"key075")?;
+            db.flush()?;
+        }
+
+        let db = Db::open(Config::new(&path).enable_bloom(true))?;
+        for i in 0..100 {
+            let expected = match i {
+                75 => None,
+                0..=49 => Some(b"plain".to_vec()),
+                _ => Some(b"bloom".to_vec()),
+            };
+            assert_eq!(db.get(format!("key{:03}", i).as_bytes())?, expected);
+            assert_eq!(db.get(format!("key{:03}x", i).as_bytes())?, None);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_with_meta() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+
+        {
+            let db = Db::open(Config::new(&path))?;
+            db.put(b"old", b"untracked")?;
+            db.flush()?;
+        }
+
+        let db = Db::open(Config::new(&path).track_modified(true))?;
+        assert_eq!(db.get_with_meta(b"old")?, Some((b"untracked".to_vec(), None)));
+
+        let before = SystemTime::now() - Duration::from_millis(1);
+        db.put(b"new", b"tracked")?;
+        let (value, modified) = db.get_with_meta(b"new")?.unwrap();
+        assert_eq!(value, b"tracked");
+        let modified = modified.unwrap();
+        assert!(modified >= before && modified <= SystemTime::now());
+
+        // Overwriting refreshes the timestamp
+        std::thread::sleep(Duration::from_millis(5));
+        db.put(b"new", b"again")?;
+        let (_, updated) = db.get_with_meta(b"new")?.unwrap();
+        assert!(updated.unwrap() > modified);
+
+        assert_eq!(db.get(b"old")?, Some(b"untracked".to_vec()));
+        assert_eq!(db.get_with_meta(b"missing")?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_wal_recovers_after_crash() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let config = || Config::new(&path).wal(true).buffer_pool_size(16);
+        let batch = |range: std::ops::Range<u32>| -> Vec<(Vec<u8>, Vec<u8>)> {
+            range
+                .map(|i| (format!("key{:05}", i).into_bytes(), vec![b'v'; 100]))
+                .collect()
+        };
+
+        let checkpointed = {
+            let db = Db::open(config())?;
+            db.append_batch(&batch(0..500))?;
+            db.flush()?;
+            let checkpointed = db.disk_manager.header();
+            db.append_batch(&batch(500..1000))?;
+            db.append_batch(&batch(1000..1500))?;
+            db.delete(b"key00007")?;
+            checkpointed
+            // Dropped without flushing
+        };
+        // Nothing since the checkpoint reached the database file
+        let header = DiskManagerImpl::open(&path, false)?.header();
+        assert_eq!(header.page_count, checkpointed.page_count);
+        assert_eq!(header.root_page, checkpointed.root_page);
+
+        {
+            let db = Db::open(config())?;
+            assert!(db.maintenance()?.is_ok());
+            assert_eq!(db.iter()?.len(), 1499);
+            assert_eq!(db.get(b"key01499")?, Some(vec![b'v'; 100]));
+            assert_eq!(db.get(b"key00007")?, None);
+        }
+
+        // Recovery checkpointed the log, so the file alone is complete
+        let db = Db::open(Config::new(&path))?;
+        assert_eq!(db.iter()?.len(), 1499);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_checksums_catch_bit_rot() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+
+        // A file written without checksums opens with them on
+        {
+            let db = Db::open(Config::new(&path))?;
+            db.put(b"key", b"value")?;
+            db.flush()?;
+        }
+        {
+            let db = Db::open(Config::new(&path).verify_checksums(true))?;
+            assert_eq!(db.get(b"key")?, Some(b"value".to_vec()));
+            db.put(b"other", b"value")?;
+            db.flush()?;
+        }
+
+        // Flip a byte of the root leaf's last cell
+        let root = DiskManagerImpl::open(&path, false)?.header().root_page;
+        {
+            use std::io::{Seek, SeekFrom, Write};
+            let mut file = std::fs::OpenOptions::new().write(true).open(&path)?;
+            file.seek(SeekFrom::Start(root.file_offset(PAGE_SIZE) + PAGE_SIZE as u64 - 1))?;
+            file.write_all(b"X")?;
+        }
+
+        let db = Db::open(Config::new(&path).verify_checksums(true))?;
+        let err = db.get(b"key").unwrap_err();
+        assert!(matches!(err, StorageError::Corruption(_)), "{}", err);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_put_batch_matches_puts() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let entries: Vec<(Vec<u8>, Vec<u8>)> = (0..10_000u32)
+            .map(|i| {
+                let key = format!("key{:05}", (i * 7919) % 10_000).into_bytes();
+                (key, format!("value{}", i).into_bytes())
+            })
+            .collect();
+        let pairs: Vec<(&[u8], &[u8])> = entries
+            .iter()
+            .map(|(k, v)| (k.as_slice(), v.as_slice()))
+            .collect();
+
+        let batched = Db::open(Config::new(dir.path().join("batch.db")))?;
+        batched.put_batch(&pairs)?;
+        let single = Db::open(Config::new(dir.path().join("single.db")))?;
+        for (key, value) in &pairs {
+            single.put(key, value)?;
+        }
+        assert_eq!(batched.iter()?, single.iter()?);
+        assert_eq!(batched.iter()?.len(), 10_000);
+
+        // A failing pair stops the batch; the ones before it stay
+        let too_long = vec![b'k'; types::MAX_KEY_SIZE + 1];
+        let err = batched
+            .put_batch(&[(b"a", b"1"), (b"b", b"2"), (&too_long, b"3"), (b"c", b"4")])
+            .unwrap_err();
+        assert!(matches!(err, StorageError::BatchFailed { applied: 2, .. }), "{}", err);
+        assert_eq!(batched.get(b"b")?, Some(b"2".to_vec()));
+        assert_eq!(batched.get(b"c")?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_named_trees_are_isolated() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+
+        {
+            let db = Db::open(Config::new(&path).btree_config(BTreeConfig::new(4, 4)))?;
+            db.put(b"key000", b"main")?;
+            let users = db.create_tree("users")?;
+            let orders = db.create_tree("orders")?;
+            for i in 0..200 {
+                let key = format!("key{:03}", i);
+                users.put(key.as_bytes(), format!("user{}", i).as_bytes())?;
+                orders.put(key.as_bytes(), format!("order{}", i).as_bytes())?;
+            }
+            orders.delete(b"key005")?;
+
+            // Root splits went to the catalog, not the file header
+            assert_eq!(db.stats().tree_height, 1);
+            assert!(matches!(db.create_tree("users"), Err(StorageError::InvalidOperation(_))));
+            assert!(matches!(db.open_tree("items"), Err(StorageError::InvalidOperation(_))));
+            db.flush()?;
+        }
+
+        let db = Db::open(Config::new(&path))?;
+        assert_eq!(db.tree_names()?, vec!["orders", "users"]);
+        let users = db.open_tree("users")?;
+        let orders = db.open_tree("orders")?;
+        assert_eq!(db.iter()?, vec![(b"key000".to_vec(), b"main".to_vec())]);
+        assert_eq!(users.get(b"key000")?, Some(b"user0".to_vec()));
+        assert_eq!(orders.get(b"key000")?, Some(b"order0".to_vec()));
+        assert_eq!(users.get(b"key005")?, Some(b"user5".to_vec()));
+        assert_eq!(orders.get(b"key005")?, None);
+        assert_eq!(users.range(None, None)?.len(), 200);
+        assert_eq!(orders.range(Some(b"key100"), Some(b"key110"))?.len(), 10);
+
+        // Handles to the same tree share its root
+        db.open_tree("users")?.put(b"key999", b"late")?;
+        assert_eq!(users.get(b"key999")?, Some(b"late".to_vec()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_len_tracks_inserts_and_deletes() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+
+        {
+            let db = Db::open(Config::new(&path).btree_config(BTreeConfig::new(4, 4)))?;
+            assert_eq!(db.len()?, 0);
+            assert!(db.is_empty()?);
+
+            for i in 0..200 {
+                db.put(format!("key{:03}", i).as_bytes(), b"value")?;
+            }
+            assert_eq!(db.len()?, 200);
+            assert!(!db.is_empty()?);
+
+            // Updates leave the count alone
+            for i in 0..50 {
+                db.put(format!("key{:03}", i).as_bytes(), b"updated")?;
+            }
+            assert_eq!(db.len()?, 200);
+
+            for i in 0..80 {
+                assert!(db.delete(format!("key{:03}", i).as_bytes())?);
+            }
+            assert!(!db.delete(b"missing")?);
+            db.append_batch(&[(b"zz1", b"v"), (b"zz2", b"v")])?;
+            assert_eq!(db.len()?, 122);
+            assert_eq!(db.len()?, db.iter()?.len());
+            db.flush()?;
+        }
+
+        // The count is persisted in the header
+        let db = Db::open(Config::new(&path))?;
+        assert_eq!(db.len()?, 122);
+
+        // A header without a count is recounted on open
+        db.disk_manager.set_entry_count(0)?;
+        db.flush()?;
+        drop(db);
+        let db = Db::open(Config::new(&path))?;
+        assert_eq!(db.len()?, 122);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_prefix() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let db = Db::open(Config::new(dir.path().join("test.db")))?;
+
+        db.put(b"user:1", b"a")?;
+        db.put(b"user:1:posts:1", b"b")?;
+        db.put(b"user:1:posts:2", b"c")?;
+        db.put(b"user:10", b"d")?;
+        db.put(b"user:2:posts:1", b"e")?;
+        db.put(b"\xff\xff", b"f")?;
+        db.put(b"\xff\xff\x01", b"g")?;
+
+        let keys = |prefix: &[u8]| -> Result<Vec<Vec<u8>>> {
+            Ok(db.scan_prefix(prefix)?.into_iter().map(|(k, _)| k).collect())
+        };
+        assert_eq!(
+            keys(b"user:1:")?,
+            vec![b"user:1:posts:1".to_vec(), b"user:1:posts:2".to_vec()]
+        );
+        assert_eq!(keys(b"user:1")?.len(), 4);
+        assert!(keys(b"user:3")?.is_empty());
+        assert_eq!(
+            keys(b"\xff")?,
+            vec![b"\xff\xff".to_vec(), b"\xff\xff\x01".to_vec()]
+        );
+        assert_eq!(keys(b"")?.len(), 7);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compare_and_swap() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let db = Db::open(Config::new(dir.path().join("test.db")))?;
+
+        // Absent -> present
+        assert!(db.compare_and_swap(b"key", None, Some(b"v1"))?);
+        assert_eq!(db.get(b"key")?, Some(b"v1".to_vec()));
+        assert!(!db.compare_and_swap(b"key", None, Some(b"v2"))?);
+
+        // Present -> updated
+        assert!(db.compare_and_swap(b"key", Some(b"v1"), Some(b"v2"))?);
+        assert_eq!(db.get(b"key")?, Some(b"v2".to_vec()));
+
+        // Mismatch leaves the value alone
+        assert!(!db.compare_and_swap(b"key", Some(b"v1"), Some(b"v3"))?);
+        assert!(!db.compare_and_swap(b"other", Some(b"v2"), None)?);
+        assert_eq!(db.get(b"key")?, Some(b"v2".to_vec()));
+
+        // Present -> deleted
+        assert!(db.compare_and_swap(b"key", Some(b"v2"), None)?);
+        assert_eq!(db.get(b"key")?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_sees_old_values() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let config = Config::new(dir.path().join("test.db")).btree_config(BTreeConfig::new(4, 4));
+        let db = Db::open(config)?;
+        let big = vec![b'x'; 10_000];
+
+        for i in 0..100 {
+            db.put(format!("key{:03}", i).as_bytes(), b"old")?;
+        }
+        db.put(b"big", &big)?;
+        let before = db.iter()?;
+        let pages_before = db.stats().page_count;
+
+        let snapshot = db.snapshot();
+        for i in 0..100 {
+            db.put(format!("key{:03}", i).as_bytes(), b"new")?;
+        }
+        for i in 100..200 {
+            db.put(format!("key{:03}", i).as_bytes(), b"new")?;
+        }
+        for i in 0..50 {
+            db.delete(format!("key{:03}", i).as_bytes())?;
+        }
+        db.put(b"big", b"small")?;
+
+        assert_eq!(snapshot.get(b"key010")?, Some(b"old".to_vec()));
+        assert_eq!(snapshot.get(b"key150")?, None);
+        assert_eq!(snapshot.get(b"big")?, Some(big.clone()));
+        assert_eq!(snapshot.range(None, None)?, before);
+        assert_eq!(snapshot.range(Some(b"key020"), Some(b"key030"))?.len(), 10);
+        assert_eq!(db.get(b"key010")?, None);
+        assert_eq!(db.get(b"key060")?, Some(b"new".to_vec()));
+
+        // Pages freed while the snapshot lived are reused once it is gone
+        drop(snapshot);
+        assert!(!db.disk_manager.free_pages().is_empty());
+        let pages_after = db.stats().page_count;
+        db.put(b"big", &big)?;
+        assert_eq!(db.stats().page_count, pages_after);
+        assert!(pages_after > pages_before);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_put_reports_insert_or_update() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let config = Config::new(dir.path().join("test.db")).btree_config(BTreeConfig::new(4, 4));
+        let db = Db::open(config)?;
+
+        // The first insert creates the root
+        assert_eq!(db.put(b"key000", b"v")?, PutResult::Inserted);
+        assert_eq!(db.put(b"key000", b"v2")?, PutResult::Updated);
+
+        // Through interior levels, including inserts that split
+        for i in 1..100 {
+            let key = format!("key{:03}", i);
+            assert_eq!(db.put(key.as_bytes(), b"v")?, PutResult::Inserted);
+        }
+        for i in (0..100).step_by(7) {
+            let key = format!("key{:03}", i);
+            assert_eq!(db.put(key.as_bytes(), b"new")?, PutResult::Updated);
+        }
+        assert!(db.stats().tree_height >= 3);
+
+        let tree = db.create_tree("other")?;
+        assert_eq!(tree.put(b"key000", b"v")?, PutResult::Inserted);
+        assert_eq!(tree.put(b"key000", b"v")?, PutResult::Updated);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compact_shrinks_file() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let mut db = Db::open(Config::new(&path))?;
+
+        let value = vec![b'v'; 200];
+        for i in 0..2000 {
+            db.put(format!("key{:05}", i).as_bytes(), &value)?;
+        }
+        for i in 0..2000 {
+            if i % 10 != 0 {
+                db.delete(format!("key{:05}", i).as_bytes())?;
+            }
+        }
+        let tree = db.create_tree("side")?;
+        tree.put(b"a", b"1")?;
+        drop(tree);
+
+        let before = db.stats().page_count;
+        db.compact()?;
+        let after = db.stats().page_count;
+        assert!(after * 4 < before, "{} pages before, {} after", before, after);
+        assert!(!compact_path(&path).exists());
+
+        // Everything left survives, here and after reopening
+        let check = |db: &Db| -> Result<()> {
+            assert_eq!(db.len()?, 200);
+            for i in (0..2000).step_by(10) {
+                assert_eq!(db.get(format!("key{:05}", i).as_bytes())?, Some(value.clone()));
+            }
+            assert_eq!(db.get(b"key00001")?, None);
+            assert_eq!(db.open_tree("side")?.get(b"a")?, Some(b"1".to_vec()));
+            Ok(())
+        };
+        check(&db)?;
+        drop(db);
+        check(&Db::open(Config::new(&path))?)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_put_with_ttl() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let db = Db::open(Config::new(dir.path().join("test.db")))?;
+
+        db.put(b"keep", b"1")?;
+        db.put_with_ttl(b"later", b"3", Duration::from_secs(3600))?;
+        db.put_with_ttl(b"gone", b"2", Duration::ZERO)?;
+
+        assert_eq!(db.get(b"gone")?, None);
+        assert!(!db.contains(b"gone")?);
+        assert_eq!(db.get(b"keep")?, Some(b"1".to_vec()));
+        assert_eq!(db.get(b"later")?, Some(b"3".to_vec()));
+        let keys: Vec<Vec<u8>> = db.iter()?.into_iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, vec![b"keep".to_vec(), b"later".to_vec()]);
+
+        // The next insert into the leaf removes the expired entry
+        assert_eq!(db.len()?, 3);
+        assert_eq!(db.put_with_ttl(b"gone", b"4", Duration::ZERO)?, PutResult::Inserted);
+        assert_eq!(db.len()?, 3);
+        db.put(b"new", b"5")?;
+        assert_eq!(db.len()?, 3);
+        assert_eq!(db.first()?.unwrap().0, b"keep");
+        assert_eq!(db.len()?, db.iter()?.len());
+
+        db.put_with_ttl(b"stale", b"6", Duration::ZERO)?;
+        let keys: Vec<Vec<u8>> = db.range_collect_rev(None, None, None)?.into_iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, vec![b"new".to_vec(), b"later".to_vec(), b"keep".to_vec()]);
+        assert!(matches!(db.get_cached(b"stale")?, CachedGet::Absent));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_only() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        {
+            let db = Db::open(Config::new(&path))?;
+            db.put(b"a", b"1")?;
+            db.put(b"b", b"2")?;
+            db.flush()?;
+        }
+        let modified = std::fs::metadata(&path)?.modified()?;
+
+        let db = Db::open(Config::new(&path).read_only(true))?;
+        assert_eq!(db.get(b"a")?, Some(b"1".to_vec()));
+        assert_eq!(db.range(Some(b"b"), None)?, vec![(b"b".to_vec(), b"2".to_vec())]);
+
+        let is_read_only = |result: Result<()>| {
+            matches!(result, Err(StorageError::InvalidOperation(msg)) if msg == "read-only")
+        };
+        assert!(is_read_only(db.put(b"c", b"3").map(|_| ())));
+        assert!(is_read_only(db.delete(b"a").map(|_| ())));
+        assert!(is_read_only(db.flush()));
+        assert_eq!(db.get(b"c")?, None);
+        drop(db);
+        assert_eq!(std::fs::metadata(&path)?.modified()?, modified);
+
+        // A missing file is not created
+        assert!(Db::open(Config::new(dir.path().join("missing.db")).read_only(true)).is_err());
+        assert!(!dir.path().join("missing.db").exists());
+
+        Ok(())
     }
 
     #[test]
-    fn test_range_scan() -> Result<()> {
+    fn test_reverse_key_order() -> Result<()> {
         let dir = tempdir().unwrap();
         let path = dir.path().join("test.db");
-        let config = Config::new(&path);
+        let btree_config = BTreeConfig::new(3, 2).key_order(KeyOrder::Reverse);
+        let config = Config::new(&path).btree_config(btree_config);
+
+        let db = Db::open(config.clone())?;
+        for i in 0..200u32 {
+            let n = (i * 37) % 200;
+            db.put(format!("key{:03}", n).as_bytes(), format!("v{}", n).as_bytes())?;
+        }
+        for i in (0..200).step_by(3) {
+            db.delete(format!("key{:03}", i).as_bytes())?;
+        }
+        assert_eq!(db.get(b"key199")?, Some(b"v199".to_vec()));
+        assert_eq!(db.get(b"key000")?, None);
+        assert!(db.maintenance()?.problems.is_empty());
+
+        // The leftmost leaf holds the highest keys, stored in descending order
+        let mut node = db.export_tree()?.unwrap();
+        while !node.is_leaf {
+            node = node.children.remove(0);
+        }
+        assert_eq!(node.keys[0], "key199");
+        assert!(node.keys.windows(2).all(|w| w[0] > w[1]));
+
+        let keys: Vec<Vec<u8>> = db.iter()?.into_iter().map(|(k, _)| k).collect();
+        assert_eq!(keys.len(), 133);
+        assert!(keys.windows(2).all(|w| w[0] > w[1]));
+        let range: Vec<Vec<u8>> = db
+            .range_iter(Some(b"key105"), Some(b"key100"))
+            .map(|entry| entry.map(|(k, _)| k))
+            .collect::<Result<_>>()?;
+        assert_eq!(range, vec![b"key104".to_vec(), b"key103".to_vec(), b"key101".to_vec()]);
+        db.flush()?;
+        drop(db);
+
+        // The order is fixed once the file holds data
+        assert!(Db::open(Config::new(&path)).is_err());
         let db = Db::open(config)?;
+        assert_eq!(db.get(b"key101")?, Some(b"v101".to_vec()));
+        Ok(())
+    }
 
-        // Insert some data
-        db.put(b"apple", b"1")?;
-        db.put(b"banana", b"2")?;
-        db.put(b"cherry", b"3")?;
-        db.put(b"date", b"4")?;
+    #[test]
+    fn test_delete_range() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let config = Config::new(dir.path().join("test.db")).btree_config(BTreeConfig::new(4, 3));
+        let db = Db::open(config)?;
+        for i in 0..300 {
+            db.put(format!("key{:03}", i).as_bytes(), b"value")?;
+        }
+        let pages_before = db.disk_manager.free_pages().len();
 
-        // Full scan
-        let all = db.iter()?;
-        assert_eq!(all.len(), 4);
+        assert_eq!(db.delete_range(Some(b"key100"), Some(b"key200"))?, 100);
+        assert_eq!(db.len()?, 200);
+        assert!(db.disk_manager.free_pages().len() > pages_before);
+        assert!(db.maintenance()?.problems.is_empty());
+        let keys: Vec<Vec<u8>> = db.iter()?.into_iter().map(|(k, _)| k).collect();
+        let expected: Vec<Vec<u8>> = (0..100)
+            .chain(200..300)
+            .map(|i| format!("key{:03}", i).into_bytes())
+            .collect();
+        assert_eq!(keys, expected);
+        assert_eq!(db.get(b"key150")?, None);
+        assert_eq!(db.get(b"key200")?, Some(b"value".to_vec()));
 
-        // Range scan
-        let range = db.range(Some(b"banana"), Some(b"date"))?;
-        assert_eq!(range.len(), 2);
-        assert_eq!(range[0].0, b"banana".to_vec());
-        assert_eq!(range[1].0, b"cherry".to_vec());
+        assert_eq!(db.delete_range(Some(b"key100"), Some(b"key200"))?, 0);
+        assert_eq!(db.delete_range(None, None)?, 200);
+        assert!(db.iter()?.is_empty());
+        Ok(())
+    }
+
+    /// Disk manager wrapper that counts syncs and can be told to fail them
+    struct SyncProbe {
+        inner: DiskManagerImpl,
+        syncs: std::sync::atomic::AtomicUsize,
+        fail_sync: std::sync::atomic::AtomicBool,
+    }
+
+    impl DiskManager for SyncProbe {
+        fn read_page(&self, page_id: PageId) -> Result<page::PageBuf> {
+            self.inner.read_page(page_id)
+        }
+        fn write_page(&self, page_id: PageId, data: &[u8]) -> Result<()> {
+            self.inner.write_page(page_id, data)
+        }
+        fn allocate_page(&self) -> Result<PageId> {
+            self.inner.allocate_page()
+        }
+        fn deallocate_page(&self, page_id: PageId) -> Result<()> {
+            self.inner.deallocate_page(page_id)
+        }
+        fn sync(&self) -> Result<()> {
+            self.syncs.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            if self.fail_sync.load(std::sync::atomic::Ordering::Relaxed) {
+                return Err(StorageError::Io(std::io::Error::other("injected sync failure")));
+            }
+            self.inner.sync()
+        }
+        fn sync_data(&self) -> Result<()> {
+            self.inner.sync_data()
+        }
+        fn header(&self) -> FileHeader {
+            self.inner.header()
+        }
+        fn set_root_page(&self, page_id: PageId, height: u32) -> Result<()> {
+            self.inner.set_root_page(page_id, height)
+        }
+        fn set_entry_count(&self, count: u64) -> Result<()> {
+            self.inner.set_entry_count(count)
+        }
+        fn set_catalog_page(&self, page_id: PageId) -> Result<()> {
+            self.inner.set_catalog_page(page_id)
+        }
+        fn set_key_order(&self, order: KeyOrder) -> Result<()> {
+            self.inner.set_key_order(order)
+        }
+        fn free_pages(&self) -> Vec<PageId> {
+            self.inner.free_pages()
+        }
+    }
+
+    #[test]
+    fn test_flush_syncs_once() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let config = Config::new(dir.path().join("test.db")).btree_config(BTreeConfig::new(3, 2));
+        let probe = Arc::new(SyncProbe {
+            inner: DiskManagerImpl::open(&config.path, false)?,
+            syncs: Default::default(),
+            fail_sync: Default::default(),
+        });
+        let disk_manager: Arc<dyn DiskManager> = probe.clone();
+        let pool = Arc::new(BufferPoolImpl::new(disk_manager.clone(), 100));
+        let db = Db::with_components(disk_manager, pool, config)?;
+        let syncs = || probe.syncs.load(std::sync::atomic::Ordering::Relaxed);
+
+        // Writes, including root splits that write the new root, never sync
+        for i in 0..100 {
+            db.put(format!("key{:03}", i).as_bytes(), b"value")?;
+        }
+        assert_eq!(syncs(), 0);
+
+        db.flush()?;
+        assert_eq!(syncs(), 1);
+        db.put(b"key100", b"value")?;
+        db.flush()?;
+        assert_eq!(syncs(), 2);
+
+        // A failed sync fails the flush
+        probe.fail_sync.store(true, std::sync::atomic::Ordering::Relaxed);
+        db.put(b"key101", b"value")?;
+        assert!(db.flush().is_err());
+        assert_eq!(syncs(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ndjson_round_trip() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let source = Db::open(Config::new(dir.path().join("source.db")))?;
+        for i in 0..500u32 {
+            source.put(&i.to_be_bytes(), format!("value\n\"{}\"", i).as_bytes())?;
+        }
+        source.put(&[0xFF, 0x00], &[0u8; 3000])?;
+
+        let mut exported = Vec::new();
+        source.export_ndjson(&mut exported)?;
+        assert_eq!(exported.iter().filter(|&&b| b == b'\n').count(), 501);
+
+        let config = Config::new(dir.path().join("target.db")).btree_config(BTreeConfig::new(3, 2));
+        let target = Db::open(config)?;
+        assert_eq!(target.import_ndjson(exported.as_slice())?, 501);
+        assert_eq!(target.iter()?, source.iter()?);
+
+        let err = target.import_ndjson(&b"\n{\"key\":\"AA==\"}\n"[..]).unwrap_err();
+        assert!(err.to_string().contains("line 2"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_get_matches_get() -> Result<()> {
+        let dir = tempdir().unwrap();
+        for (name, order) in [("fwd.db", KeyOrder::Bytewise), ("rev.db", KeyOrder::Reverse)] {
+            let btree_config = BTreeConfig::new(4, 4).key_order(order);
+            let db = Db::open(Config::new(dir.path().join(name)).btree_config(btree_config))?;
+            for i in (0..500).step_by(2) {
+                db.put(format!("key{:03}", i).as_bytes(), format!("value{}", i).as_bytes())?;
+            }
+
+            // Unsorted, with absent keys, repeats and keys past either end
+            let keys: Vec<Vec<u8>> = (0..300u32)
+                .map(|i| format!("key{:03}", (i * 37) % 520).into_bytes())
+                .chain([b"a".to_vec(), b"key010".to_vec(), b"z".to_vec()])
+                .collect();
+            let refs: Vec<&[u8]> = keys.iter().map(Vec::as_slice).collect();
+            let values = db.multi_get(&refs)?;
+            assert_eq!(values.len(), keys.len());
+            for (key, value) in keys.iter().zip(&values) {
+                assert_eq!(*value, db.get(key)?);
+            }
+            assert!(values.iter().any(Option::is_none));
+            assert_eq!(values[keys.len() - 2], Some(b"value10".to_vec()));
+            assert!(db.multi_get(&[])?.is_empty());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_compressed_pages_round_trip() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(11);
+        let text = b"lorem ipsum dolor sit amet ".repeat(20);
+        let noise: Vec<Vec<u8>> = (0..50)
+            .map(|_| (0..500).map(|_| rng.gen()).collect())
+            .collect();
+
+        {
+            let db = Db::open(Config::new(&path).compress_pages(true).verify_checksums(true))?;
+            for i in 0..200 {
+                db.put(format!("text{:03}", i).as_bytes(), &text)?;
+            }
+            for (i, value) in noise.iter().enumerate() {
+                db.put(format!("noise{:03}", i).as_bytes(), value)?;
+            }
+            db.flush()?;
+        }
+
+        // Some pages are stored compressed
+        let page_count = DiskManagerImpl::open(&path, false)?.header().page_count;
+        let file = std::fs::read(&path)?;
+        let compressed = (1..page_count as usize)
+            .filter(|&page| file[page * PAGE_SIZE] == 0x0C)
+            .count();
+        assert!(compressed > 0);
+
+        // Both read back, with compression on or off
+        for enabled in [true, false] {
+            let db = Db::open(Config::new(&path).compress_pages(enabled))?;
+            assert_eq!(db.get(b"text042")?, Some(text.clone()));
+            assert_eq!(db.get(b"noise007")?.as_ref(), Some(&noise[7]));
+            assert_eq!(db.len()?, 250);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_rejects_other_page_size() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        {
+            let db = Db::open(Config::new(&path))?;
+            db.put(b"key", b"value")?;
+            db.flush()?;
+        }
+
+        // Rewrite the header as a build with 8 KiB pages would
+        let mut header = DiskManagerImpl::open(&path, false)?.header();
+        header.page_size = 8192;
+        let mut buf = page::PageBuf::new();
+        header.write(&mut buf);
+        let mut file = std::fs::read(&path)?;
+        file[..PAGE_SIZE].copy_from_slice(&buf);
+        std::fs::write(&path, file)?;
+
+        match Db::open(Config::new(&path)) {
+            Err(StorageError::IncompatiblePageSize { found, expected }) => {
+                assert_eq!((found, expected), (8192, PAGE_SIZE as u32));
+            }
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("opened a file with 8 KiB pages"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_would_fit_matches_put() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let db = Db::open(Config::new(dir.path().join("test.db")).allow_empty_values(false))?;
+
+        let cases: [(usize, usize, bool); 5] = [
+            (types::MAX_KEY_SIZE, types::MAX_VALUE_SIZE, true),
+            (8, types::MAX_VALUE_SIZE - 1, true),
+            (8, types::MAX_VALUE_SIZE + 1, false),
+            (types::MAX_KEY_SIZE + 1, 8, false),
+            (8, 0, false),
+        ];
+        for (i, (key_len, value_len, fits)) in cases.into_iter().enumerate() {
+            let key = vec![b'a' + i as u8; key_len];
+            let value = vec![b'v'; value_len];
+            assert_eq!(db.would_fit(&key, &value), fits, "case {}", i);
+            assert_eq!(db.put(&key, &value).is_ok(), fits, "case {}", i);
+        }
+        assert!(page::Cell::max_inline_payload() < SlottedPage::new_leaf().free_space());
+        Ok(())
+    }
+
+    #[test]
+    fn test_clear_empties_tree_in_place() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let db = Db::open(Config::new(&path).btree_config(BTreeConfig::new(4, 4)))?;
+        for i in 0..200 {
+            db.put(format!("key{:03}", i).as_bytes(), &[b'v'; 100])?;
+        }
+        db.put(b"big", &[b'b'; 10_000])?;
+        let pages = db.disk_manager.header().page_count;
+
+        let snapshot = db.snapshot();
+        db.clear()?;
+        assert!(db.is_empty()?);
+        assert_eq!(db.len()?, 0);
+        assert_eq!(db.get(b"key005")?, None);
+        assert!(db.iter()?.is_empty());
+        assert_eq!(db.disk_manager.header().root_page.value(), 0);
+        assert_eq!(snapshot.get(b"key005")?, Some(vec![b'v'; 100]));
+        drop(snapshot);
+
+        // The freed pages are reused for new entries
+        for i in 0..200 {
+            db.put(format!("new{:03}", i).as_bytes(), &[b'n'; 100])?;
+        }
+        assert_eq!(db.len()?, 200);
+        assert_eq!(db.get(b"new150")?, Some(vec![b'n'; 100]));
+        assert_eq!(db.disk_manager.header().page_count, pages);
+        db.flush()?;
+        drop(db);
+
+        let db = Db::open(Config::new(&path).btree_config(BTreeConfig::new(4, 4)))?;
+        assert_eq!(db.len()?, 200);
+        assert_eq!(db.get(b"key005")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_page_pagination() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let db = Db::open(Config::new(dir.path().join("test.db")))?;
+        for i in (0..250).rev() {
+            db.put(format!("key{:03}", i).as_bytes(), format!("value{}", i).as_bytes())?;
+        }
+
+        let mut pages = Vec::new();
+        let mut after = None;
+        loop {
+            let (entries, next) = db.scan_page(after.as_deref(), 100)?;
+            pages.push(entries);
+            match next {
+                Some(key) => after = Some(key),
+                None => break,
+            }
+        }
+        assert_eq!(pages.iter().map(Vec::len).collect::<Vec<_>>(), vec![100, 100, 50]);
+        assert_eq!(pages[1][0].0, b"key100");
+        assert_eq!(pages.concat(), db.iter()?);
+
+        // A full last page still reports the end, and keys between entries
+        // work as tokens too
+        let (entries, next) = db.scan_page(Some(b"key149"), 100)?;
+        assert_eq!((entries.len(), next), (100, None));
+        let (entries, _) = db.scan_page(Some(b"key0995"), 1)?;
+        assert_eq!(entries[0].0, b"key100");
+        assert!(db.scan_page(None, 0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_put_if_absent_keeps_existing_value() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let db = Db::open(Config::new(dir.path().join("test.db")))?;
+
+        assert!(db.put_if_absent(b"key", b"first")?);
+        assert!(!db.put_if_absent(b"key", b"second")?);
+        assert_eq!(db.get(b"key")?, Some(b"first".to_vec()));
+        assert_eq!(db.len()?, 1);
+
+        // An expired entry no longer counts as present
+        db.put_with_ttl(b"ttl", b"old", Duration::ZERO)?;
+        assert!(db.put_if_absent(b"ttl", b"new")?);
+        assert_eq!(db.get(b"ttl")?, Some(b"new".to_vec()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cursor_collects_like_iter() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let db = Db::open(Config::new(dir.path().join("test.db")))?;
+        assert_eq!(db.cursor()?.count(), 0);
+
+        for i in (0..50).rev() {
+            db.put(format!("key{:02}", i).as_bytes(), format!("value{}", i).as_bytes())?;
+        }
+        let entries = db.cursor()?.collect::<Result<Vec<_>>>()?;
+        assert_eq!(entries, db.iter()?);
+
+        let mut seen = 0;
+        for entry in db.cursor()? {
+            let (key, value) = entry?;
+            assert_eq!(db.get(&key)?, Some(value));
+            seen += 1;
+        }
+        assert_eq!(seen, 50);
+        Ok(())
+    }
+
+    #[test]
+    fn test_transaction_is_all_or_nothing() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let db = Db::open(Config::new(dir.path().join("test.db")))?;
+        db.put(b"a", b"original")?;
+
+        let result: Result<()> = db.transaction(|txn| {
+            txn.put(b"a", b"changed")?;
+            txn.put(b"b", b"new")?;
+            txn.put(b"c", b"new")?;
+            assert_eq!(txn.get(b"a")?, Some(b"changed".to_vec()));
+            Err(StorageError::invalid_operation("abort"))
+        });
+        assert!(result.is_err());
+        assert_eq!(db.iter()?, vec![(b"a".to_vec(), b"original".to_vec())]);
+
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            db.transaction::<()>(|txn| {
+                txn.put(b"b", b"new")?;
+                panic!("abort");
+            })
+        }));
+        assert!(panicked.is_err());
+        assert_eq!(db.len()?, 1);
+
+        let count = db.transaction(|txn| {
+            txn.delete(b"a");
+            for i in 0..100 {
+                txn.put(format!("key{:03}", i).as_bytes(), b"v")?;
+            }
+            Ok(100)
+        })?;
+        assert_eq!(count, 100);
+        assert_eq!(db.get(b"a")?, None);
+        assert_eq!(db.len()?, 100);
+        Ok(())
+    }
+
+    #[test]
+    fn test_inspect_page() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let db = Db::open(Config::new(dir.path().join("test.db")))?;
+        db.put(b"a", b"1")?;
+        db.put(b"b", &[b'v'; 5000])?;
+        db.put(b"c", b"333")?;
+        db.delete(b"c")?;
+
+        let root = db.btree.read().root_page();
+        let PageInspection::Slotted { page_type, cell_count, free_bytes, fragmented_bytes, cells, .. } =
+            db.inspect_page(root)?
+        else {
+            panic!("root inspected as the file header");
+        };
+        assert!(page_type.is_leaf());
+        assert_eq!(cell_count, 2);
+        assert!(free_bytes > 0 && free_bytes < PAGE_SIZE);
+        assert!(fragmented_bytes > 0);
+        let summary: Vec<_> = cells.iter().map(|c| (c.key.clone(), c.value_len, c.child)).collect();
+        assert_eq!(summary, vec![(b"a".to_vec(), Some(1), None), (b"b".to_vec(), Some(5000), None)]);
+
+        let PageInspection::FileHeader(header) = db.inspect_page(PageId::HEADER)? else {
+            panic!("page 0 not inspected as the file header");
+        };
+        assert_eq!(header.page_size as usize, PAGE_SIZE);
+        let past_end = PageId::new(db.stats().page_count as u32);
+        assert!(matches!(db.inspect_page(past_end), Err(StorageError::InvalidPage(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_or_insert_with_calls_f_only_when_absent() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let db = Db::open(Config::new(dir.path().join("test.db")))?;
+
+        let mut calls = 0;
+        let value = db.get_or_insert_with(b"key", || {
+            calls += 1;
+            b"default".to_vec()
+        })?;
+        assert_eq!(value, b"default");
+        assert_eq!(calls, 1);
+        assert_eq!(db.get(b"key")?, Some(b"default".to_vec()));
+
+        db.put(b"key", b"stored")?;
+        let value = db.get_or_insert_with(b"key", || {
+            calls += 1;
+            b"other".to_vec()
+        })?;
+        assert_eq!(value, b"stored");
+        assert_eq!(calls, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats_count_dirty_pages_until_flush() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let db = Db::open(Config::new(dir.path().join("test.db")))?;
+        assert_eq!(db.stats().dirty_pages, 0);
+
+        for i in 0..20 {
+            db.put(format!("key{:02}", i).as_bytes(), b"value")?;
+        }
+        let dirty = db.stats().dirty_pages;
+        assert!(dirty > 0);
+        assert!(dirty <= db.stats().page_count);
+
+        db.flush()?;
+        assert_eq!(db.stats().dirty_pages, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover_rebuilds_damaged_header() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let config = || Config::new(&path).btree_config(BTreeConfig::new(3, 2));
+        {
+            let db = Db::open(config())?;
+            for i in 0..300 {
+                db.put(format!("key{:03}", i).as_bytes(), format!("value{}", i).as_bytes())?;
+            }
+            let tree = db.create_tree("side")?;
+            for i in 0..20 {
+                tree.put(format!("side{:02}", i).as_bytes(), b"v")?;
+            }
+            db.flush()?;
+        }
+
+        // Damage only the header's page count, so its checksum fails
+        let mut bytes = std::fs::read(&path)?;
+        bytes[20] ^= 0xFF;
+        std::fs::write(&path, &bytes)?;
+        assert!(matches!(Db::open(config()), Err(StorageError::Corruption(_))));
+
+        let db = Db::open(config().recover(true))?;
+        assert_eq!(db.len()?, 300);
+        for i in 0..300 {
+            let key = format!("key{:03}", i);
+            assert_eq!(db.get(key.as_bytes())?, Some(format!("value{}", i).into_bytes()));
+        }
+        assert!(db.maintenance()?.is_ok());
+        assert_eq!(db.open_tree("side")?.range(None, None)?.len(), 20);
+
+        // The page count is right, so new pages do not overwrite old ones
+        for i in 300..400 {
+            db.put(format!("key{:03}", i).as_bytes(), b"new")?;
+        }
+        assert_eq!(db.get(b"key000")?, Some(b"value0".to_vec()));
+        assert_eq!(db.open_tree("side")?.get(b"side00")?, Some(b"v".to_vec()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_reclaim_orphans_spares_snapshot_pages() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let db = Db::open(Config::new(dir.path().join("test.db")))?;
+        let big = vec![b'x'; 10_000];
+        db.put(b"big", &big)?;
+
+        // Overwriting frees the chain, but the snapshot still reads it
+        let snapshot = db.snapshot();
+        db.put(b"big", b"small")?;
+        assert!(db.orphaned_overflow_pages()?.is_empty());
+        assert_eq!(db.reclaim_orphans()?, 0);
+        assert_eq!(snapshot.get(b"big")?, Some(big));
+
+        drop(snapshot);
+        assert!(db.orphaned_overflow_pages()?.is_empty());
+        assert_eq!(db.get(b"big")?, Some(b"small".to_vec()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_metadata_keeps_pages_reused_since() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let config = || Config::new(&path).btree_config(BTreeConfig::new(8, 8));
+        {
+            let db = Db::open(config())?;
+            for i in 0..300 {
+                db.put(format!("key{:03}", i).as_bytes(), b"value")?;
+            }
+            for i in 100..180 {
+                db.delete(format!("key{:03}", i).as_bytes())?;
+            }
+            db.flush()?;
+            assert!(db.disk_manager.header().free_page_count > 0);
+            let snapshot = db.dump_metadata()?;
+            let root = db.disk_manager.header().root_page;
+
+            // New keys reuse the free pages and grow the file
+            for i in 100..180 {
+                db.put(format!("key{:03}", i).as_bytes(), &[b'n'; 200])?;
+            }
+            assert_eq!(db.disk_manager.header().root_page, root);
+            assert_eq!(db.disk_manager.header().free_page_count, 0);
+
+            db.restore_metadata(&snapshot)?;
+            db.flush()?;
+        }
+
+        let db = Db::open(config())?;
+        for i in 0..300 {
+            let expected = if (100..180).contains(&i) { vec![b'n'; 200] } else { b"value".to_vec() };
+            assert_eq!(db.get(format!("key{:03}", i).as_bytes())?, Some(expected));
+        }
+        assert_eq!(db.iter()?.len(), 300);
+        assert!(db.maintenance()?.is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_empty_skips_expired_entries() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let db = Db::open(Config::new(dir.path().join("test.db")))?;
+
+        db.put_with_ttl(b"a", b"1", Duration::ZERO)?;
+        assert_eq!(db.len()?, 1);
+        assert!(db.is_empty()?);
+        assert!(db.iter()?.is_empty());
+
+        // Purged by the next insert into the leaf
+        db.put_with_ttl(b"c", b"3", Duration::from_secs(3600))?;
+        assert_eq!(db.len()?, 1);
+        assert!(!db.is_empty()?);
+        assert_eq!(db.len()?, db.iter()?.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_reencode_keeps_snapshots_and_counters() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let db = Db::open(Config::new(dir.path().join("test.db")))?;
+        for i in 0..100 {
+            db.put(format!("key{:03}", i).as_bytes(), b"value")?;
+        }
+        let structure = db.stats().structure;
+        assert!(structure.leaf_splits > 0);
+        let snapshot = db.snapshot();
+
+        // The last key grows past the size limit, so the rebuild fails
+        let too_long = |key: &[u8]| match key {
+            b"key099" => [key, &[0; types::MAX_KEY_SIZE]].concat(),
+            _ => key.to_vec(),
+        };
+        assert!(db.reencode_keys(too_long, BTreeConfig::default()).is_err());
+        assert_eq!(db.stats().structure, structure);
+
+        // The snapshot is still registered, so writes preserve what it reads
+        db.put(b"key042", b"changed")?;
+        assert_eq!(snapshot.get(b"key042")?, Some(b"value".to_vec()));
+        assert_eq!(db.get(b"key042")?, Some(b"changed".to_vec()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_scans_skip_overflow_pages() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let config = Config::new(dir.path().join("test.db"));
+        let disk_manager: Arc<dyn DiskManager> = Arc::new(DiskManagerImpl::open(&config.path, false)?);
+        let pool = Arc::new(CountingPool {
+            inner: BufferPoolImpl::new(disk_manager.clone(), 100),
+            fetches: Default::default(),
+            full: Default::default(),
+        });
+        let db = Db::with_components(disk_manager, pool.clone(), config)?;
+
+        let big = vec![b'x'; 20_000];
+        db.put(b"big1", &big)?;
+        db.put(b"big2", &big)?;
+        db.put(b"small", b"v")?;
+
+        let fetches = || pool.fetches.load(std::sync::atomic::Ordering::Relaxed);
+        let before = fetches();
+        assert_eq!(db.count_range(None, None)?, 3);
+        let leaf_reads = fetches() - before;
+        assert_eq!(leaf_reads, db.stats().tree_height);
+
+        let before = fetches();
+        let rows = db.scan_filter_keys(None, None, |key| key == b"small")?;
+        assert_eq!(rows, vec![(b"small".to_vec(), b"v".to_vec())]);
+        assert_eq!(fetches() - before, leaf_reads);
+
+        // Loading a value still reads its whole chain
+        let rows = db.scan_filter_keys(None, None, |key| key == b"big2")?;
+        assert_eq!(rows, vec![(b"big2".to_vec(), big.clone())]);
+        let before = fetches();
+        assert_eq!(db.scan_filter(None, None, |_, _| true)?.len(), 3);
+        assert!(fetches() - before > leaf_reads);
+
+        assert!(matches!(
+            db.range_with_budget(None, None, 25_000),
+            Err(StorageError::QuotaExceeded { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_compact_keeps_ttl() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let mut db = Db::open(Config::new(dir.path().join("test.db")))?;
+        db.put(b"plain", b"1")?;
+        db.put_with_ttl(b"later", &vec![b'x'; 5_000], Duration::from_secs(3600))?;
+        db.put_with_ttl(b"gone", b"3", Duration::ZERO)?;
+        let expires = |db: &Db, key: &[u8]| -> Result<Option<u64>> {
+            Ok(db.btree.read().get_with_meta(key)?.and_then(|(_, meta)| meta.expires))
+        };
+        let before = expires(&db, b"later")?;
+        assert!(before.is_some());
+
+        db.compact()?;
+        assert_eq!(expires(&db, b"later")?, before);
+        assert_eq!(expires(&db, b"plain")?, None);
+        assert_eq!(db.get(b"later")?, Some(vec![b'x'; 5_000]));
+        assert_eq!(db.len()?, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reencode_keys_keeps_ttl() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let db = Db::open(Config::new(dir.path().join("test.db")))?;
+        db.put(b"plain", b"1")?;
+        db.put_with_ttl(b"later", b"2", Duration::from_secs(3600))?;
+        db.put_with_ttl(b"gone", b"3", Duration::ZERO)?;
+        let before = db.btree.read().get_with_meta(b"later")?.unwrap().1.expires;
+        assert!(before.is_some());
+
+        db.reencode_keys(|key| [b"v2:".as_slice(), key].concat(), BTreeConfig::default())?;
+        let btree = db.btree.read();
+        assert_eq!(btree.get_with_meta(b"v2:later")?.unwrap().1.expires, before);
+        assert_eq!(btree.get_with_meta(b"v2:plain")?.unwrap().1.expires, None);
+        assert_eq!(btree.get(b"v2:gone")?, None);
+        assert_eq!(btree.len()?, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_transaction_rollback_restores_metadata() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let config = Config::new(dir.path().join("test.db"));
+        let disk_manager: Arc<dyn DiskManager> = Arc::new(DiskManagerImpl::open(&config.path, false)?);
+        let pool = Arc::new(CountingPool {
+            inner: BufferPoolImpl::new(disk_manager.clone(), 100),
+            fetches: Default::default(),
+            full: Default::default(),
+        });
+        let db = Db::with_components(disk_manager, pool.clone(), config)?;
+        let set_full = |full| pool.full.store(full, std::sync::atomic::Ordering::Relaxed);
+        let big = vec![b'x'; 20_000];
+
+        db.put_with_ttl(b"b", b"original", Duration::from_secs(3600))?;
+        let before = db.btree.read().get_with_meta(b"b")?.unwrap().1;
+        assert!(before.expires.is_some());
+
+        // "b" is overwritten in place, then "z" fails to get overflow pages
+        let result = db.transaction(|txn| {
+            txn.put(b"b", b"changed")?;
+            txn.put(b"z", &big)?;
+            set_full(true);
+            Ok(())
+        });
+        set_full(false);
+        assert!(matches!(result, Err(StorageError::InvalidOperation(_))));
+        assert_eq!(
+            db.btree.read().get_with_meta(b"b")?,
+            Some((b"original".to_vec(), before))
+        );
+        assert_eq!(db.get(b"z")?, None);
 
+        // Putting "a" back needs new pages too, so the undo cannot finish
+        db.put(b"a", &big)?;
+        let result = db.transaction(|txn| {
+            txn.delete(b"a");
+            txn.put(b"z", &big)?;
+            set_full(true);
+            Ok(())
+        });
+        set_full(false);
+        assert!(matches!(result, Err(StorageError::Corruption(_))));
         Ok(())
     }
 }
@@ -0,0 +1,98 @@
+//! Newline-delimited JSON records for export and import.
+//!
+//! Each line is one `{"key":...,"value":...}` object with both fields in
+//! standard padded base64, so binary keys and values survive the trip.
+
+use crate::error::{Result, StorageError};
+use serde::{Deserialize, Serialize};
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// One exported entry
+#[derive(Serialize, Deserialize)]
+struct Record {
+    key: String,
+    value: String,
+}
+
+/// Format an entry as one line, without the trailing newline
+pub(crate) fn encode_record(key: &[u8], value: &[u8]) -> String {
+    let record = Record {
+        key: encode_base64(key),
+        value: encode_base64(value),
+    };
+    serde_json::to_string(&record).expect("a record of two strings always serializes")
+}
+
+/// Parse a line written by `encode_record`
+pub(crate) fn decode_record(line: &str) -> Result<(Vec<u8>, Vec<u8>)> {
+    let record: Record = serde_json::from_str(line)
+        .map_err(|e| StorageError::invalid_operation(format!("bad record: {}", e)))?;
+    Ok((decode_base64(&record.key)?, decode_base64(&record.value)?))
+}
+
+fn encode_base64(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i)) as usize & 0x3F] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn decode_base64(text: &str) -> Result<Vec<u8>> {
+    let invalid = || StorageError::invalid_operation(format!("bad base64: {:?}", text));
+    let bytes = text.as_bytes();
+    if !bytes.len().is_multiple_of(4) {
+        return Err(invalid());
+    }
+
+    let mut out = Vec::with_capacity(bytes.len() / 4 * 3);
+    for (index, chunk) in bytes.chunks(4).enumerate() {
+        let last = index == bytes.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|&&b| b == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return Err(invalid());
+        }
+
+        let mut n = 0u32;
+        for (i, &b) in chunk[..4 - padding].iter().enumerate() {
+            let digit = ALPHABET.iter().position(|&a| a == b).ok_or_else(invalid)?;
+            n |= (digit as u32) << (18 - 6 * i);
+        }
+        out.extend_from_slice(&n.to_be_bytes()[1..4 - padding]);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64_round_trip() -> Result<()> {
+        assert_eq!(encode_base64(b""), "");
+        assert_eq!(encode_base64(b"f"), "Zg==");
+        assert_eq!(encode_base64(b"fo"), "Zm8=");
+        assert_eq!(encode_base64(b"foobar"), "Zm9vYmFy");
+
+        let bytes: Vec<u8> = (0..=255).collect();
+        for len in 0..8 {
+            let text = encode_base64(&bytes[250 - len..]);
+            assert_eq!(decode_base64(&text)?, &bytes[250 - len..]);
+        }
+        assert!(decode_base64("Zg=").is_err());
+        assert!(decode_base64("Zg==Zg==").is_err());
+        assert!(decode_base64("Z!==").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+//! Per-leaf bloom filters.
+//!
+//! A leaf flagged with `PAGE_FLAG_BLOOM` keeps a small bloom filter of its
+//! keys right after the page header. Lookups that miss the filter can skip
+//! the leaf entirely. Deleting a key leaves its bits set, so the filter only
+//! grows stale (more false positives) until the page is rebuilt.
+
+use super::header::BLOOM_FILTER_SIZE;
+
+/// Number of bit positions set per key
+const BLOOM_HASHES: u32 = 3;
+
+/// A fixed-size bloom filter over a page's keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BloomFilter {
+    bits: [u8; BLOOM_FILTER_SIZE],
+}
+
+impl BloomFilter {
+    /// Create an empty filter
+    pub fn new() -> Self {
+        Self {
+            bits: [0u8; BLOOM_FILTER_SIZE],
+        }
+    }
+
+    /// Load a filter from its on-page bytes
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(Self {
+            bits: bytes.get(..BLOOM_FILTER_SIZE)?.try_into().ok()?,
+        })
+    }
+
+    /// Get the raw bytes of this filter
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bits
+    }
+
+    /// Add a key to the filter
+    pub fn insert(&mut self, key: &[u8]) {
+        for bit in Self::positions(key) {
+            self.bits[bit / 8] |= 1 << (bit % 8);
+        }
+    }
+
+    /// Check whether the key may be present
+    ///
+    /// `false` means the key is definitely absent.
+    pub fn may_contain(&self, key: &[u8]) -> bool {
+        Self::positions(key).all(|bit| self.bits[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    /// Get the bit positions for a key (double hashing over FNV-1a)
+    fn positions(key: &[u8]) -> impl Iterator<Item = usize> {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for &byte in key {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+        let h1 = hash as u32;
+        let h2 = ((hash >> 32) as u32) | 1;
+        let bits = (BLOOM_FILTER_SIZE * 8) as u32;
+        (0..BLOOM_HASHES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % bits) as usize)
+    }
+}
+
+impl Default for BloomFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_false_negatives() {
+        let mut filter = BloomFilter::new();
+        for i in 0..100u32 {
+            filter.insert(&i.to_be_bytes());
+        }
+        for i in 0..100u32 {
+            assert!(filter.may_contain(&i.to_be_bytes()));
+        }
+
+        let restored = BloomFilter::from_bytes(filter.as_bytes()).unwrap();
+        assert_eq!(restored, filter);
+    }
+
+    #[test]
+    fn test_empty_filter_rejects() {
+        let filter = BloomFilter::new();
+        assert!(!filter.may_contain(b"anything"));
+        assert!(BloomFilter::from_bytes(&[0u8; 3]).is_none());
+    }
+}
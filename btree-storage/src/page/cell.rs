@@ -4,7 +4,9 @@
 //! Each cell contains a key and optionally a value (for leaf pages)
 //! or a child page pointer (for interior pages).
 
-use crate::types::{decode_varint, encode_varint, PageId};
+use crate::types::{
+    decode_varint, encode_varint, PageId, MAX_INLINE_VALUE_SIZE, MAX_KEY_SIZE, PAGE_ID_SIZE,
+};
 
 /// Type of cell stored in a page
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -15,6 +17,58 @@ pub enum CellType {
     Interior,
 }
 
+/// Metadata flag: an 8-byte last-modified timestamp follows the lengths
+const META_MODIFIED: u8 = 0x01;
+
+/// Metadata flag: an overflow page pointer and total value length follow
+const META_OVERFLOW: u8 = 0x02;
+
+/// Metadata flag: an 8-byte expiry timestamp follows the overflow fields
+const META_EXPIRES: u8 = 0x04;
+
+/// Location of the value bytes a leaf cell spilled to overflow pages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OverflowRef {
+    /// First page of the overflow chain
+    pub first_page: PageId,
+    /// Length of the whole value, inline bytes included
+    pub total_len: u32,
+}
+
+/// Optional per-cell metadata
+///
+/// Only stored on leaf pages flagged with `PAGE_FLAG_CELL_META`, where every
+/// cell starts with a metadata flags byte.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CellMeta {
+    /// Last write time in milliseconds since the Unix epoch
+    pub modified: Option<u64>,
+    /// Overflow chain holding the rest of the value
+    pub overflow: Option<OverflowRef>,
+    /// Expiry time in milliseconds since the Unix epoch
+    pub expires: Option<u64>,
+}
+
+impl CellMeta {
+    /// Check whether no metadata is set
+    pub fn is_empty(&self) -> bool {
+        self.modified.is_none() && self.overflow.is_none() && self.expires.is_none()
+    }
+
+    /// Check whether the cell has expired at `now`, in milliseconds since
+    /// the Unix epoch
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    /// Get the encoded size of the metadata (flags byte included)
+    fn encoded_size(&self) -> usize {
+        1 + if self.modified.is_some() { 8 } else { 0 }
+            + if self.overflow.is_some() { PAGE_ID_SIZE + 4 } else { 0 }
+            + if self.expires.is_some() { 8 } else { 0 }
+    }
+}
+
 /// A cell within a B-tree page
 #[derive(Debug, Clone)]
 pub struct Cell {
@@ -22,10 +76,12 @@ pub struct Cell {
     pub cell_type: CellType,
     /// The key bytes
     pub key: Vec<u8>,
-    /// The value bytes (only for leaf cells)
+    /// The value bytes (leaf cells, or an inline value on interior cells)
     pub value: Vec<u8>,
     /// Left child page pointer (only for interior cells)
     pub left_child: PageId,
+    /// Per-cell metadata (only for leaf cells)
+    pub meta: CellMeta,
 }
 
 impl Cell {
@@ -36,6 +92,7 @@ impl Cell {
             key,
             value,
             left_child: PageId::INVALID,
+            meta: CellMeta::default(),
         }
     }
 
@@ -46,50 +103,129 @@ impl Cell {
             key,
             value: Vec::new(),
             left_child,
+            meta: CellMeta::default(),
         }
     }
 
+    /// Get the most key and value bytes a leaf cell keeps on its page
+    ///
+    /// Keys are at most `MAX_KEY_SIZE` bytes and values past
+    /// `MAX_INLINE_VALUE_SIZE` bytes spill into overflow pages, so any
+    /// entry within the size limits fits on an empty page.
+    pub const fn max_inline_payload() -> usize {
+        MAX_KEY_SIZE + MAX_INLINE_VALUE_SIZE
+    }
+
+    /// Attach metadata to this cell
+    pub fn with_meta(mut self, meta: CellMeta) -> Self {
+        self.meta = meta;
+        self
+    }
+
     /// Calculate the encoded size of this cell in bytes
+    ///
+    /// Leaf cells with metadata use the metadata format.
     pub fn encoded_size(&self) -> usize {
+        self.encoded_size_in(!self.meta.is_empty())
+    }
+
+    /// Calculate the encoded size of this cell on a page with or without
+    /// cell metadata
+    ///
+    /// For interior cells `with_meta` selects the inline value format.
+    pub fn encoded_size_in(&self, with_meta: bool) -> usize {
         match self.cell_type {
             CellType::Leaf => {
-                // key_len (varint) + value_len (varint) + key + value
+                // [meta] + key_len (varint) + value_len (varint) + key + value
                 let key_len_size = varint_len(self.key.len() as u64);
                 let value_len_size = varint_len(self.value.len() as u64);
-                key_len_size + value_len_size + self.key.len() + self.value.len()
+                let meta_size = if with_meta { self.meta.encoded_size() } else { 0 };
+                meta_size + key_len_size + value_len_size + self.key.len() + self.value.len()
             }
             CellType::Interior => {
-                // left_child (4 bytes) + key_len (varint) + key
+                // left_child + key_len (varint) + [value_len (varint)] + key + [value]
                 let key_len_size = varint_len(self.key.len() as u64);
-                4 + key_len_size + self.key.len()
+                let value_size = if with_meta {
+                    varint_len(self.value.len() as u64) + self.value.len()
+                } else {
+                    0
+                };
+                PAGE_ID_SIZE + key_len_size + self.key.len() + value_size
             }
         }
     }
 
     /// Encode this cell into bytes
     pub fn encode(&self) -> Vec<u8> {
-        let mut buf = Vec::with_capacity(self.encoded_size());
+        self.encode_in(!self.meta.is_empty())
+    }
+
+    /// Encode this cell for a page with or without cell metadata
+    ///
+    /// Metadata is dropped when `with_meta` is false. For interior cells
+    /// `with_meta` selects the inline value format; without it the value
+    /// is dropped.
+    pub fn encode_in(&self, with_meta: bool) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.encoded_size_in(with_meta));
 
         match self.cell_type {
             CellType::Leaf => {
                 // Leaf cell format:
+                // - meta_flags: u8 (pages with cell metadata only)
                 // - key_len: varint
                 // - value_len: varint
+                // - modified: u64 (big-endian, if META_MODIFIED is set)
+                // - first_page: [u8; PAGE_ID_SIZE], total_len: u32
+                //   (big-endian, if META_OVERFLOW is set)
+                // - expires: u64 (big-endian, if META_EXPIRES is set)
                 // - key: [u8; key_len]
-                // - value: [u8; value_len]
+                // - value: [u8; value_len] (the inline part if META_OVERFLOW)
+                let mut flags = 0;
+                if self.meta.modified.is_some() {
+                    flags |= META_MODIFIED;
+                }
+                if self.meta.overflow.is_some() {
+                    flags |= META_OVERFLOW;
+                }
+                if self.meta.expires.is_some() {
+                    flags |= META_EXPIRES;
+                }
+                if with_meta {
+                    buf.push(flags);
+                }
                 buf.extend(encode_varint(self.key.len() as u64));
                 buf.extend(encode_varint(self.value.len() as u64));
+                if with_meta {
+                    if let Some(modified) = self.meta.modified {
+                        buf.extend(&modified.to_be_bytes());
+                    }
+                    if let Some(overflow) = self.meta.overflow {
+                        buf.extend(&overflow.first_page.to_be_bytes());
+                        buf.extend(&overflow.total_len.to_be_bytes());
+                    }
+                    if let Some(expires) = self.meta.expires {
+                        buf.extend(&expires.to_be_bytes());
+                    }
+                }
                 buf.extend(&self.key);
                 buf.extend(&self.value);
             }
             CellType::Interior => {
                 // Interior cell format:
-                // - left_child: u32 (big-endian)
+                // - left_child: [u8; PAGE_ID_SIZE] (big-endian)
                 // - key_len: varint
+                // - value_len: varint (pages with inline values only)
                 // - key: [u8; key_len]
-                buf.extend(&self.left_child.value().to_be_bytes());
+                // - value: [u8; value_len] (pages with inline values only)
+                buf.extend(&self.left_child.to_be_bytes());
                 buf.extend(encode_varint(self.key.len() as u64));
+                if with_meta {
+                    buf.extend(encode_varint(self.value.len() as u64));
+                }
                 buf.extend(&self.key);
+                if with_meta {
+                    buf.extend(&self.value);
+                }
             }
         }
 
@@ -100,62 +236,116 @@ impl Cell {
     ///
     /// Returns the cell and the number of bytes consumed.
     pub fn decode_leaf(bytes: &[u8]) -> Option<(Self, usize)> {
+        Self::decode_leaf_in(bytes, false)
+    }
+
+    /// Decode a leaf cell from a page with or without cell metadata
+    ///
+    /// Returns the cell and the number of bytes consumed.
+    pub fn decode_leaf_in(bytes: &[u8], with_meta: bool) -> Option<(Self, usize)> {
+        let (key, value, meta, size) = Self::decode_leaf_ref(bytes, with_meta)?;
+        Some((Self::new_leaf(key.to_vec(), value.to_vec()).with_meta(meta), size))
+    }
+
+    /// Decode a leaf cell's key and value without copying them
+    ///
+    /// Returns the key, value, metadata and the number of bytes consumed.
+    pub fn decode_leaf_ref(bytes: &[u8], with_meta: bool) -> Option<(&[u8], &[u8], CellMeta, usize)> {
         let mut offset = 0;
 
+        // Read metadata flags
+        let flags = if with_meta {
+            offset += 1;
+            *bytes.first()?
+        } else {
+            0
+        };
+
         // Read key length
-        let (key_len, n) = decode_varint(&bytes[offset..])?;
+        let (key_len, n) = decode_varint(bytes.get(offset..)?)?;
         offset += n;
 
         // Read value length
-        let (value_len, n) = decode_varint(&bytes[offset..])?;
+        let (value_len, n) = decode_varint(bytes.get(offset..)?)?;
         offset += n;
 
-        // Read key
-        let key_len = key_len as usize;
-        if offset + key_len > bytes.len() {
-            return None;
+        // Read metadata fields
+        let mut meta = CellMeta::default();
+        if flags & META_MODIFIED != 0 {
+            let modified = bytes.get(offset..offset + 8)?.try_into().ok()?;
+            meta.modified = Some(u64::from_be_bytes(modified));
+            offset += 8;
+        }
+        if flags & META_OVERFLOW != 0 {
+            let first_page = bytes.get(offset..offset + PAGE_ID_SIZE)?.try_into().ok()?;
+            offset += PAGE_ID_SIZE;
+            let total_len = bytes.get(offset..offset + 4)?.try_into().ok()?;
+            offset += 4;
+            meta.overflow = Some(OverflowRef {
+                first_page: PageId::from_be_bytes(first_page),
+                total_len: u32::from_be_bytes(total_len),
+            });
+        }
+        if flags & META_EXPIRES != 0 {
+            let expires = bytes.get(offset..offset + 8)?.try_into().ok()?;
+            meta.expires = Some(u64::from_be_bytes(expires));
+            offset += 8;
         }
-        let key = bytes[offset..offset + key_len].to_vec();
-        offset += key_len;
+
+        // Read key
+        let key_end = offset.checked_add(usize::try_from(key_len).ok()?)?;
+        let key = bytes.get(offset..key_end)?;
+        offset = key_end;
 
         // Read value
-        let value_len = value_len as usize;
-        if offset + value_len > bytes.len() {
-            return None;
-        }
-        let value = bytes[offset..offset + value_len].to_vec();
-        offset += value_len;
+        let value_end = offset.checked_add(usize::try_from(value_len).ok()?)?;
+        let value = bytes.get(offset..value_end)?;
+        offset = value_end;
 
-        Some((Self::new_leaf(key, value), offset))
+        Some((key, value, meta, offset))
     }
 
     /// Decode an interior cell from bytes
     ///
     /// Returns the cell and the number of bytes consumed.
     pub fn decode_interior(bytes: &[u8]) -> Option<(Self, usize)> {
-        if bytes.len() < 4 {
-            return None;
-        }
-
-        let mut offset = 0;
+        Self::decode_interior_in(bytes, false)
+    }
 
+    /// Decode an interior cell from a page with or without inline values
+    ///
+    /// Returns the cell and the number of bytes consumed.
+    pub fn decode_interior_in(bytes: &[u8], with_value: bool) -> Option<(Self, usize)> {
         // Read left child pointer
-        let left_child = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
-        offset += 4;
+        let left_child = bytes.get(..PAGE_ID_SIZE)?.try_into().ok()?;
+        let mut offset = PAGE_ID_SIZE;
 
         // Read key length
         let (key_len, n) = decode_varint(&bytes[offset..])?;
         offset += n;
 
+        // Read value length
+        let value_len = if with_value {
+            let (value_len, n) = decode_varint(bytes.get(offset..)?)?;
+            offset += n;
+            value_len
+        } else {
+            0
+        };
+
         // Read key
-        let key_len = key_len as usize;
-        if offset + key_len > bytes.len() {
-            return None;
-        }
-        let key = bytes[offset..offset + key_len].to_vec();
-        offset += key_len;
+        let key_end = offset.checked_add(usize::try_from(key_len).ok()?)?;
+        let key = bytes.get(offset..key_end)?.to_vec();
+        offset = key_end;
 
-        Some((Self::new_interior(key, PageId::new(left_child)), offset))
+        // Read value
+        let value_end = offset.checked_add(usize::try_from(value_len).ok()?)?;
+        let value = bytes.get(offset..value_end)?.to_vec();
+        offset = value_end;
+
+        let mut cell = Self::new_interior(key, PageId::from_be_bytes(left_child));
+        cell.value = value;
+        Some((cell, offset))
     }
 }
 
@@ -199,6 +389,13 @@ mod tests {
 
         let cell = Cell::new_interior(b"key".to_vec(), PageId::new(100));
         assert_eq!(cell.encoded_size(), cell.encode().len());
+        assert_eq!(cell.encoded_size(), PAGE_ID_SIZE + 1 + 3);
+    }
+
+    #[test]
+    fn test_interior_cell_truncated_pointer() {
+        let encoded = Cell::new_interior(b"key".to_vec(), PageId::new(7)).encode();
+        assert!(Cell::decode_interior(&encoded[..PAGE_ID_SIZE - 1]).is_none());
     }
 
     #[test]
@@ -208,4 +405,78 @@ mod tests {
         let (decoded, _) = Cell::decode_leaf(&encoded).unwrap();
         assert!(decoded.value.is_empty());
     }
+
+    #[test]
+    fn test_interior_inline_value_roundtrip() {
+        let mut cell = Cell::new_interior(b"key".to_vec(), PageId::new(7));
+        cell.value = b"v1".to_vec();
+
+        let encoded = cell.encode_in(true);
+        assert_eq!(encoded.len(), cell.encoded_size_in(true));
+        let (decoded, size) = Cell::decode_interior_in(&encoded, true).unwrap();
+        assert_eq!(size, encoded.len());
+        assert_eq!(decoded.key, b"key");
+        assert_eq!(decoded.value, b"v1");
+        assert_eq!(decoded.left_child, PageId::new(7));
+
+        // The plain format drops the value
+        let (plain, _) = Cell::decode_interior(&cell.encode_in(false)).unwrap();
+        assert!(plain.value.is_empty());
+    }
+
+    #[test]
+    fn test_decode_huge_lengths() {
+        // A key length near usize::MAX must not overflow the bounds check
+        let mut bytes = encode_varint(u64::MAX);
+        bytes.extend(encode_varint(0));
+        assert!(Cell::decode_leaf(&bytes).is_none());
+
+        let mut bytes = vec![0u8; PAGE_ID_SIZE];
+        bytes.extend(encode_varint(u64::MAX));
+        assert!(Cell::decode_interior(&bytes).is_none());
+    }
+
+    #[test]
+    fn test_leaf_cell_meta_roundtrip() {
+        let meta = CellMeta {
+            modified: Some(1_700_000_000_000),
+            overflow: None,
+            expires: Some(1_700_000_060_000),
+        };
+        let cell = Cell::new_leaf(b"key".to_vec(), b"value".to_vec()).with_meta(meta);
+
+        let encoded = cell.encode_in(true);
+        assert_eq!(encoded.len(), cell.encoded_size_in(true));
+        let (decoded, size) = Cell::decode_leaf_in(&encoded, true).unwrap();
+        assert_eq!(size, encoded.len());
+        assert_eq!(decoded.meta, meta);
+        assert_eq!(decoded.value, b"value");
+
+        // Cells without metadata still carry the flags byte on such pages
+        let plain = Cell::new_leaf(b"key".to_vec(), b"value".to_vec());
+        let encoded = plain.encode_in(true);
+        assert_eq!(encoded.len(), plain.encoded_size_in(false) + 1);
+        let (decoded, _) = Cell::decode_leaf_in(&encoded, true).unwrap();
+        assert!(decoded.meta.is_empty());
+    }
+
+    #[test]
+    fn test_leaf_cell_overflow_roundtrip() {
+        let meta = CellMeta {
+            modified: None,
+            overflow: Some(OverflowRef {
+                first_page: PageId::new(9),
+                total_len: 50_000,
+            }),
+            expires: None,
+        };
+        let cell = Cell::new_leaf(b"key".to_vec(), vec![7; 100]).with_meta(meta);
+
+        let encoded = cell.encode_in(true);
+        assert_eq!(encoded.len(), cell.encoded_size_in(true));
+        let (decoded, size) = Cell::decode_leaf_in(&encoded, true).unwrap();
+        assert_eq!(size, encoded.len());
+        assert_eq!(decoded.meta, meta);
+        assert_eq!(decoded.value, vec![7; 100]);
+    }
 }
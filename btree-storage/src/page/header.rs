@@ -3,20 +3,52 @@
 //! The page header occupies the first bytes of each B-tree page and contains
 //! metadata about the page contents.
 
-use crate::types::PageType;
+use crate::types::{PageType, PAGE_ID_SIZE};
 
 /// Size of the page header for leaf pages (no right child pointer)
 pub const LEAF_HEADER_SIZE: usize = 8;
 
 /// Size of the page header for interior pages (includes right child pointer)
-pub const INTERIOR_HEADER_SIZE: usize = 12;
+pub const INTERIOR_HEADER_SIZE: usize = LEAF_HEADER_SIZE + PAGE_ID_SIZE;
+
+/// Size of the page header for linked leaf pages (includes next leaf pointer)
+pub const LINKED_LEAF_HEADER_SIZE: usize = LEAF_HEADER_SIZE + PAGE_ID_SIZE;
+
+/// Page flag: every leaf cell starts with a metadata flags byte
+pub const PAGE_FLAG_CELL_META: u8 = 0x80;
+
+/// Page flag: a CRC32 of the page ends the header
+pub const PAGE_FLAG_CHECKSUM: u8 = 0x40;
+
+/// Page flag: a leaf bloom filter follows the fixed header
+pub const PAGE_FLAG_BLOOM: u8 = 0x20;
+
+/// Page flag: every interior cell carries a value length and inline value
+pub const PAGE_FLAG_INLINE_VALUES: u8 = 0x10;
+
+/// Size of the leaf bloom filter region in bytes
+pub const BLOOM_FILTER_SIZE: usize = 64;
+
+/// Size of the page checksum in bytes
+pub const PAGE_CHECKSUM_SIZE: usize = 4;
+
+/// Size of the overflow page header (next page pointer and payload length)
+pub const OVERFLOW_HEADER_SIZE: usize = LEAF_HEADER_SIZE + PAGE_ID_SIZE + 2;
+
+/// Number of value bytes an overflow page holds, leaving room for a
+/// checksum
+pub const OVERFLOW_PAGE_CAPACITY: usize =
+    crate::types::PAGE_SIZE - OVERFLOW_HEADER_SIZE - PAGE_CHECKSUM_SIZE;
+
+/// Bits of the first header byte holding the page type; the rest are flags
+const PAGE_TYPE_MASK: u8 = 0x0F;
 
 /// Page header structure
 ///
 /// Layout (for leaf pages, 8 bytes):
 /// ```text
 /// Offset  Size  Description
-/// 0       1     Page type flag
+/// 0       1     Page type (low 4 bits) and page flags (high 4 bits)
 /// 1       2     Offset to first freeblock (0 if none)
 /// 3       2     Number of cells on this page
 /// 5       2     Offset to start of cell content area
@@ -27,19 +59,48 @@ pub const INTERIOR_HEADER_SIZE: usize = 12;
 /// ```text
 /// 8       4     Right-most child page pointer
 /// ```
+///
+/// Linked leaf pages (`PageType::LinkedLeafTable`) add 4 bytes at offset 8:
+/// ```text
+/// 8       4     Next leaf in key order (0 if last)
+/// ```
+/// Leaves written before the link existed are plain `LeafTable` pages.
+///
+/// Leaf pages flagged with `PAGE_FLAG_BLOOM` reserve `BLOOM_FILTER_SIZE`
+/// bytes for a bloom filter after the fixed header, before the cell pointer
+/// array.
+///
+/// Overflow pages have no cells and add 6 bytes at offset 8:
+/// ```text
+/// 8       4     Next overflow page in the chain (0 if last)
+/// 12      2     Number of value bytes on this page
+/// ```
+///
+/// Free pages have no cells and add 4 bytes at offset 8:
+/// ```text
+/// 8       4     Next page on the free list (0 if last)
+/// ```
+///
+/// Pages flagged with `PAGE_FLAG_CHECKSUM` end the header with a CRC32 of
+/// the rest of the page, after the right child, bloom filter or overflow
+/// fields.
 #[derive(Debug, Clone, Copy)]
 pub struct PageHeader {
     /// Type of this page (leaf, interior, etc.)
     pub page_type: PageType,
+    /// Page format flags (`PAGE_FLAG_*`)
+    pub flags: u8,
     /// Offset to the first freeblock, or 0 if there are no freeblocks
     pub first_freeblock: u16,
     /// Number of cells on this page
     pub cell_count: u16,
     /// Offset to the start of the cell content area
     pub cell_content_start: u16,
-    /// Number of fragmented free bytes within the cell content area
+    /// Number of fragmented free bytes within the cell content area,
+    /// saturating at 255; `SlottedPage::dead_space` gives the exact count
     pub fragmented_bytes: u8,
-    /// Right-most child pointer (only valid for interior pages)
+    /// Right-most child pointer for interior pages, next leaf for linked
+    /// leaf pages
     pub right_child: u32,
 }
 
@@ -48,7 +109,8 @@ impl PageHeader {
     pub fn new_leaf() -> Self {
         use crate::types::PAGE_SIZE;
         Self {
-            page_type: PageType::LeafTable,
+            page_type: PageType::LinkedLeafTable,
+            flags: 0,
             first_freeblock: 0,
             cell_count: 0,
             cell_content_start: PAGE_SIZE as u16,
@@ -62,6 +124,7 @@ impl PageHeader {
         use crate::types::PAGE_SIZE;
         Self {
             page_type: PageType::InteriorTable,
+            flags: 0,
             first_freeblock: 0,
             cell_count: 0,
             cell_content_start: PAGE_SIZE as u16,
@@ -70,35 +133,74 @@ impl PageHeader {
         }
     }
 
+    /// Check whether the header stores a page pointer at offset 8
+    fn has_pointer(&self) -> bool {
+        self.page_type.is_interior() || self.page_type == PageType::LinkedLeafTable
+    }
+
+    /// Get the offset of a leaf's bloom filter region, just past the fixed
+    /// header fields
+    pub fn bloom_offset(&self) -> usize {
+        if self.has_pointer() {
+            LINKED_LEAF_HEADER_SIZE
+        } else {
+            LEAF_HEADER_SIZE
+        }
+    }
+
     /// Get the size of this header in bytes
     pub fn size(&self) -> usize {
-        if self.page_type.is_interior() {
+        let size = if self.page_type.is_interior() {
             INTERIOR_HEADER_SIZE
+        } else if self.page_type == PageType::Overflow {
+            OVERFLOW_HEADER_SIZE
+        } else if self.flags & PAGE_FLAG_BLOOM != 0 {
+            self.bloom_offset() + BLOOM_FILTER_SIZE
         } else {
-            LEAF_HEADER_SIZE
+            self.bloom_offset()
+        };
+        if self.has_checksum() {
+            size + PAGE_CHECKSUM_SIZE
+        } else {
+            size
         }
     }
 
+    /// Check whether the header ends with a page checksum
+    pub fn has_checksum(&self) -> bool {
+        self.flags & PAGE_FLAG_CHECKSUM != 0
+    }
+
+    /// Get the offset of the page checksum, if the page has one
+    pub fn checksum_offset(&self) -> Option<usize> {
+        self.has_checksum().then(|| self.size() - PAGE_CHECKSUM_SIZE)
+    }
+
     /// Read a page header from bytes
     pub fn read(bytes: &[u8]) -> Option<Self> {
         if bytes.len() < LEAF_HEADER_SIZE {
             return None;
         }
 
-        let page_type = PageType::from_byte(bytes[0])?;
+        let page_type = PageType::from_byte(bytes[0] & PAGE_TYPE_MASK)?;
+        let flags = bytes[0] & !PAGE_TYPE_MASK;
         let first_freeblock = u16::from_be_bytes([bytes[1], bytes[2]]);
         let cell_count = u16::from_be_bytes([bytes[3], bytes[4]]);
         let cell_content_start = u16::from_be_bytes([bytes[5], bytes[6]]);
         let fragmented_bytes = bytes[7];
 
-        let right_child = if page_type.is_interior() && bytes.len() >= INTERIOR_HEADER_SIZE {
-            u32::from_be_bytes([bytes[8], bytes[9], bytes[10], bytes[11]])
+        let has_pointer = page_type.is_interior() || page_type == PageType::LinkedLeafTable;
+        let right_child = if has_pointer && bytes.len() >= INTERIOR_HEADER_SIZE {
+            let mut ptr = [0u8; PAGE_ID_SIZE];
+            ptr.copy_from_slice(&bytes[LEAF_HEADER_SIZE..INTERIOR_HEADER_SIZE]);
+            u32::from_be_bytes(ptr)
         } else {
             0
         };
 
         Some(Self {
             page_type,
+            flags,
             first_freeblock,
             cell_count,
             cell_content_start,
@@ -109,14 +211,15 @@ impl PageHeader {
 
     /// Write this header to bytes
     pub fn write(&self, bytes: &mut [u8]) {
-        bytes[0] = self.page_type as u8;
+        bytes[0] = self.page_type as u8 | self.flags;
         bytes[1..3].copy_from_slice(&self.first_freeblock.to_be_bytes());
         bytes[3..5].copy_from_slice(&self.cell_count.to_be_bytes());
         bytes[5..7].copy_from_slice(&self.cell_content_start.to_be_bytes());
         bytes[7] = self.fragmented_bytes;
 
-        if self.page_type.is_interior() && bytes.len() >= INTERIOR_HEADER_SIZE {
-            bytes[8..12].copy_from_slice(&self.right_child.to_be_bytes());
+        if self.has_pointer() && bytes.len() >= INTERIOR_HEADER_SIZE {
+            bytes[LEAF_HEADER_SIZE..INTERIOR_HEADER_SIZE]
+                .copy_from_slice(&self.right_child.to_be_bytes());
         }
     }
 
@@ -146,6 +249,7 @@ mod tests {
     fn test_header_roundtrip() {
         let header = PageHeader {
             page_type: PageType::LeafTable,
+            flags: PAGE_FLAG_CELL_META,
             first_freeblock: 100,
             cell_count: 5,
             cell_content_start: 3500,
@@ -158,6 +262,7 @@ mod tests {
 
         let read_header = PageHeader::read(&bytes).unwrap();
         assert_eq!(read_header.page_type, PageType::LeafTable);
+        assert_eq!(read_header.flags, PAGE_FLAG_CELL_META);
         assert_eq!(read_header.first_freeblock, 100);
         assert_eq!(read_header.cell_count, 5);
         assert_eq!(read_header.cell_content_start, 3500);
@@ -175,12 +280,30 @@ mod tests {
         let read_header = PageHeader::read(&bytes).unwrap();
         assert_eq!(read_header.page_type, PageType::InteriorTable);
         assert_eq!(read_header.right_child, 42);
+
+        // Linked leaves store their next leaf in the same slot
+        let mut header = PageHeader::new_leaf();
+        header.right_child = 7;
+        let mut bytes = [0u8; LINKED_LEAF_HEADER_SIZE];
+        header.write(&mut bytes);
+        assert_eq!(PageHeader::read(&bytes).unwrap().right_child, 7);
     }
 
     #[test]
     fn test_free_space() {
-        let header = PageHeader::new_leaf();
+        let mut header = PageHeader::new_leaf();
         // Fresh leaf page: all space after header is free
-        assert_eq!(header.free_space(), PAGE_SIZE - LEAF_HEADER_SIZE);
+        assert_eq!(header.free_space(), PAGE_SIZE - LINKED_LEAF_HEADER_SIZE);
+
+        // The bloom filter region is part of the header
+        header.flags |= PAGE_FLAG_BLOOM;
+        assert_eq!(
+            header.free_space(),
+            PAGE_SIZE - LINKED_LEAF_HEADER_SIZE - BLOOM_FILTER_SIZE
+        );
+
+        // Leaves without a next leaf pointer keep the shorter header
+        header.page_type = PageType::LeafTable;
+        assert_eq!(header.free_space(), PAGE_SIZE - LEAF_HEADER_SIZE - BLOOM_FILTER_SIZE);
     }
 }
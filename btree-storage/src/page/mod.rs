@@ -7,18 +7,26 @@
 //! - Cell content grows from the end toward the header
 //! - Free space is in the middle
 
+mod bloom;
 mod cell;
 mod header;
 mod slotted;
 
-pub use cell::{Cell, CellType};
-pub use header::PageHeader;
+pub use bloom::BloomFilter;
+pub use cell::{Cell, CellMeta, CellType, OverflowRef};
+pub use header::{
+    PageHeader, BLOOM_FILTER_SIZE, OVERFLOW_PAGE_CAPACITY, PAGE_CHECKSUM_SIZE, PAGE_FLAG_BLOOM,
+    PAGE_FLAG_CELL_META, PAGE_FLAG_CHECKSUM, PAGE_FLAG_INLINE_VALUES,
+};
 pub use slotted::SlottedPage;
 
 use crate::types::PAGE_SIZE;
 
 /// A raw page buffer
+///
+/// Aligned to the page size so it can be handed to direct I/O as is.
 #[derive(Clone)]
+#[repr(C, align(4096))]
 pub struct PageBuf {
     data: [u8; PAGE_SIZE],
 }
@@ -21,8 +21,13 @@
 //! Cell content grows from the end of the page toward the header.
 
 use crate::error::{Result, StorageError};
-use crate::page::{Cell, PageBuf, PageHeader};
-use crate::types::{PageId, PageType};
+use crate::page::header::{LEAF_HEADER_SIZE, OVERFLOW_HEADER_SIZE};
+use crate::page::{
+    BloomFilter, Cell, CellMeta, PageBuf, PageHeader, BLOOM_FILTER_SIZE, OVERFLOW_PAGE_CAPACITY,
+    PAGE_CHECKSUM_SIZE, PAGE_FLAG_BLOOM, PAGE_FLAG_CELL_META, PAGE_FLAG_CHECKSUM,
+    PAGE_FLAG_INLINE_VALUES,
+};
+use crate::types::{KeyOrder, PageId, PageType, PAGE_ID_SIZE, PAGE_SIZE};
 
 /// A slotted page providing cell-based storage
 pub struct SlottedPage {
@@ -49,6 +54,42 @@ impl SlottedPage {
         Self { data, header }
     }
 
+    /// Create an overflow page holding `payload` and pointing at `next`
+    ///
+    /// `next` is `PageId::HEADER` on the last page of a chain. Fails if the
+    /// payload is longer than `OVERFLOW_PAGE_CAPACITY`.
+    pub fn new_overflow(next: PageId, payload: &[u8]) -> Result<Self> {
+        if payload.len() > OVERFLOW_PAGE_CAPACITY {
+            return Err(StorageError::invalid_operation(format!(
+                "overflow payload of {} bytes exceeds the page capacity of {}",
+                payload.len(),
+                OVERFLOW_PAGE_CAPACITY
+            )));
+        }
+
+        let mut data = PageBuf::new();
+        let mut header = PageHeader::new_leaf();
+        header.page_type = PageType::Overflow;
+        header.write(&mut data);
+        data[LEAF_HEADER_SIZE..LEAF_HEADER_SIZE + PAGE_ID_SIZE].copy_from_slice(&next.to_be_bytes());
+        data[LEAF_HEADER_SIZE + PAGE_ID_SIZE..OVERFLOW_HEADER_SIZE]
+            .copy_from_slice(&(payload.len() as u16).to_be_bytes());
+        data[OVERFLOW_HEADER_SIZE..OVERFLOW_HEADER_SIZE + payload.len()].copy_from_slice(payload);
+        Ok(Self { data, header })
+    }
+
+    /// Create a free page pointing at the next page on the free list
+    ///
+    /// `next` is `PageId::HEADER` on the last page of the list.
+    pub fn new_free(next: PageId) -> Self {
+        let mut data = PageBuf::new();
+        let mut header = PageHeader::new_leaf();
+        header.page_type = PageType::Free;
+        header.write(&mut data);
+        data[LEAF_HEADER_SIZE..LEAF_HEADER_SIZE + PAGE_ID_SIZE].copy_from_slice(&next.to_be_bytes());
+        Self { data, header }
+    }
+
     /// Load a page from raw bytes
     pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
         let data = PageBuf::from_bytes(bytes);
@@ -57,6 +98,78 @@ impl SlottedPage {
         Ok(Self { data, header })
     }
 
+    /// Decode a page from untrusted bytes
+    ///
+    /// Unlike `from_bytes`, every header field, cell pointer and cell is
+    /// checked, so the returned page can be read without panicking. Never
+    /// panics; any inconsistency is reported as `StorageError::Corruption`.
+    pub fn try_decode(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != PAGE_SIZE {
+            return Err(StorageError::corruption(format!(
+                "page is {} bytes, expected {}",
+                bytes.len(),
+                PAGE_SIZE
+            )));
+        }
+        let header = PageHeader::read(bytes)
+            .ok_or_else(|| StorageError::corruption("invalid page header"))?;
+        if !header.page_type.is_leaf() && !header.page_type.is_interior() {
+            // Free and overflow pages carry no cells
+            if header.cell_count != 0 {
+                return Err(StorageError::corruption(format!(
+                    "{:?} page claims {} cells",
+                    header.page_type, header.cell_count
+                )));
+            }
+            let page = Self {
+                data: PageBuf::from_bytes(bytes),
+                header,
+            };
+            if page.page_type() == PageType::Overflow {
+                page.overflow_payload()?;
+            }
+            return Ok(page);
+        }
+
+        let content_start = header.cell_content_start as usize;
+        let pointers_end = header.cell_pointer_array_end();
+        if pointers_end > content_start || content_start > PAGE_SIZE {
+            return Err(StorageError::corruption(format!(
+                "{} cell pointers overlap the cell content at offset {}",
+                header.cell_count, content_start
+            )));
+        }
+
+        let page = Self {
+            data: PageBuf::from_bytes(bytes),
+            header,
+        };
+        let with_meta = page.extended_cells();
+        for i in 0..page.cell_count() {
+            let pointer = page.cell_pointer(i) as usize;
+            if pointer < content_start || pointer >= PAGE_SIZE {
+                return Err(StorageError::corruption(format!(
+                    "cell {} points outside the content area (offset {})",
+                    i, pointer
+                )));
+            }
+            let cell_bytes = &page.data[pointer..];
+            let decoded = if page.is_leaf() {
+                Cell::decode_leaf_ref(cell_bytes, with_meta).map(|_| ())
+            } else {
+                Cell::decode_interior_in(cell_bytes, with_meta).map(|_| ())
+            };
+            if decoded.is_none() {
+                return Err(StorageError::corruption(format!(
+                    "cell {} at offset {} does not decode",
+                    i, pointer
+                )));
+            }
+        }
+
+        Ok(page)
+    }
+
     /// Get the raw bytes of this page
     pub fn as_bytes(&self) -> &[u8] {
         self.data.as_bytes()
@@ -67,6 +180,311 @@ impl SlottedPage {
         &self.header
     }
 
+    /// Get the next page of this overflow page's chain
+    ///
+    /// Returns `PageId::HEADER` on the last page of a chain.
+    pub fn overflow_next(&self) -> Result<PageId> {
+        self.check_overflow()?;
+        let bytes = &self.data[LEAF_HEADER_SIZE..LEAF_HEADER_SIZE + PAGE_ID_SIZE];
+        Ok(PageId::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    /// Get the page after this one on the free list
+    pub fn free_next(&self) -> Result<PageId> {
+        if self.page_type() != PageType::Free {
+            return Err(StorageError::invalid_page(format!(
+                "expected a free page, found {:?}",
+                self.page_type()
+            )));
+        }
+        let bytes = &self.data[LEAF_HEADER_SIZE..LEAF_HEADER_SIZE + PAGE_ID_SIZE];
+        Ok(PageId::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    /// Get the value bytes stored on this overflow page
+    pub fn overflow_payload(&self) -> Result<&[u8]> {
+        self.check_overflow()?;
+        let len = self.overflow_len();
+        let start = self.header.size();
+        if start + len > PAGE_SIZE {
+            return Err(StorageError::corruption(format!(
+                "overflow page claims {} payload bytes",
+                len
+            )));
+        }
+        Ok(&self.data[start..start + len])
+    }
+
+    /// Get the payload length field of an overflow page
+    fn overflow_len(&self) -> usize {
+        let len_bytes = &self.data[LEAF_HEADER_SIZE + PAGE_ID_SIZE..OVERFLOW_HEADER_SIZE];
+        u16::from_be_bytes([len_bytes[0], len_bytes[1]]) as usize
+    }
+
+    /// Fail unless this is an overflow page
+    fn check_overflow(&self) -> Result<()> {
+        if self.page_type() != PageType::Overflow {
+            return Err(StorageError::invalid_page(format!(
+                "expected an overflow page, found {:?}",
+                self.page_type()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check whether this page carries a checksum
+    pub fn has_checksum(&self) -> bool {
+        self.header.has_checksum()
+    }
+
+    /// Reserve room for a checksum at the end of the header
+    ///
+    /// Moves the cell pointer array (or an overflow page's payload) up to
+    /// make room; cells stay where they are. Fails with `PageFull`, leaving
+    /// the page unchanged, if there is no room. Free pages are left as they
+    /// are.
+    pub fn enable_checksum(&mut self) -> Result<()> {
+        if self.has_checksum() {
+            return Ok(());
+        }
+        let start = self.header.size();
+        let (end, limit) = match self.page_type() {
+            PageType::Overflow => (start + self.overflow_len(), PAGE_SIZE),
+            PageType::Free => return Ok(()),
+            _ => (
+                self.header.cell_pointer_array_end(),
+                self.header.cell_content_start as usize,
+            ),
+        };
+        if end + PAGE_CHECKSUM_SIZE > limit {
+            return Err(StorageError::PageFull {
+                page_id: PageId::INVALID,
+                needed: PAGE_CHECKSUM_SIZE,
+                available: limit.saturating_sub(end),
+            });
+        }
+
+        self.data
+            .copy_within(start..end, start + PAGE_CHECKSUM_SIZE);
+        self.header.flags |= PAGE_FLAG_CHECKSUM;
+        self.sync_header();
+        self.update_checksum();
+        Ok(())
+    }
+
+    /// Compute the checksum of this page over everything but the checksum
+    /// itself
+    fn compute_checksum(&self, offset: usize) -> u32 {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&self.data[..offset]);
+        hasher.update(&self.data[offset + PAGE_CHECKSUM_SIZE..]);
+        hasher.finalize()
+    }
+
+    /// Store a fresh checksum of the page contents, if the page has one
+    pub fn update_checksum(&mut self) {
+        if let Some(offset) = self.header.checksum_offset() {
+            let checksum = self.compute_checksum(offset);
+            self.data[offset..offset + PAGE_CHECKSUM_SIZE].copy_from_slice(&checksum.to_be_bytes());
+        }
+    }
+
+    /// Check the page contents against the stored checksum
+    ///
+    /// Pages without a checksum always pass.
+    pub fn verify_checksum(&self) -> Result<()> {
+        let Some(offset) = self.header.checksum_offset() else {
+            return Ok(());
+        };
+        let stored = &self.data[offset..offset + PAGE_CHECKSUM_SIZE];
+        let stored = u32::from_be_bytes(stored.try_into().unwrap());
+        let computed = self.compute_checksum(offset);
+        if stored != computed {
+            return Err(StorageError::corruption(format!(
+                "page checksum {:08x} does not match contents ({:08x})",
+                stored, computed
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check whether leaf cells on this page carry metadata
+    pub fn has_cell_meta(&self) -> bool {
+        self.header.flags & PAGE_FLAG_CELL_META != 0
+    }
+
+    /// Switch this leaf page to the cell metadata format
+    ///
+    /// Existing cells are re-encoded with an empty metadata byte. Fails with
+    /// `PageFull`, leaving the page unchanged, if they no longer fit.
+    pub fn enable_cell_meta(&mut self) -> Result<()> {
+        if self.has_cell_meta() || !self.is_leaf() {
+            return Ok(());
+        }
+
+        let mut new_page = self.empty_like();
+        new_page.header.flags = self.header.flags | PAGE_FLAG_CELL_META;
+        new_page.sync_header();
+        for cell in self.get_all_cells()? {
+            new_page.push_cell(&cell)?;
+        }
+
+        *self = new_page;
+        Ok(())
+    }
+
+    /// Check whether this leaf page keeps a bloom filter of its keys
+    pub fn has_bloom(&self) -> bool {
+        self.is_leaf() && self.header.flags & PAGE_FLAG_BLOOM != 0
+    }
+
+    /// Get this page's bloom filter, if it keeps one
+    pub fn bloom_filter(&self) -> Option<BloomFilter> {
+        if !self.has_bloom() {
+            return None;
+        }
+        BloomFilter::from_bytes(&self.data[self.header.bloom_offset()..])
+    }
+
+    /// Check whether a key may be on this page
+    ///
+    /// Always true for pages without a bloom filter.
+    pub fn may_contain(&self, key: &[u8]) -> bool {
+        self.bloom_filter().is_none_or(|filter| filter.may_contain(key))
+    }
+
+    /// Add a bloom filter to this leaf page
+    ///
+    /// The filter is built from the existing keys. Fails with `PageFull`,
+    /// leaving the page unchanged, if the cells no longer fit.
+    pub fn enable_bloom(&mut self) -> Result<()> {
+        if self.has_bloom() || !self.is_leaf() {
+            return Ok(());
+        }
+
+        let mut new_page = self.empty_like();
+        new_page.header.flags = self.header.flags | PAGE_FLAG_BLOOM;
+        new_page.sync_header();
+        for cell in self.get_all_cells()? {
+            new_page.push_cell(&cell)?;
+        }
+
+        *self = new_page;
+        Ok(())
+    }
+
+    /// Check whether interior cells on this page carry inline values
+    pub fn has_inline_values(&self) -> bool {
+        self.is_interior() && self.header.flags & PAGE_FLAG_INLINE_VALUES != 0
+    }
+
+    /// Switch this interior page to the inline value format
+    ///
+    /// Existing cells are re-encoded with an empty value. Fails with
+    /// `PageFull`, leaving the page unchanged, if they no longer fit.
+    pub fn enable_inline_values(&mut self) -> Result<()> {
+        if self.has_inline_values() || !self.is_interior() {
+            return Ok(());
+        }
+
+        let mut new_page = SlottedPage::new_interior();
+        new_page.header.flags = self.header.flags | PAGE_FLAG_INLINE_VALUES;
+        new_page.set_right_child(self.right_child());
+        for cell in self.get_all_cells()? {
+            new_page.push_cell(&cell)?;
+        }
+
+        *self = new_page;
+        Ok(())
+    }
+
+    /// Get the inline value stored with the separator equal to `key`,
+    /// comparing keys bytewise
+    ///
+    /// Returns `None` if this is not an interior page with inline values,
+    /// `key` is not a separator here, or its separator has no value.
+    pub fn inline_value(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.inline_value_by(key, KeyOrder::Bytewise)
+    }
+
+    /// Get the inline value stored with the separator equal to `key`, with
+    /// cells sorted in `order`
+    pub fn inline_value_by(&self, key: &[u8], order: KeyOrder) -> Result<Option<Vec<u8>>> {
+        if !self.has_inline_values() {
+            return Ok(None);
+        }
+        match self.search_by(key, order)? {
+            Some(idx) => {
+                let cell = self.get_cell(idx)?;
+                Ok((!cell.value.is_empty()).then_some(cell.value))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Set the inline value of the interior cell at the given index
+    ///
+    /// An empty value clears it. Fails with `PageFull`, leaving the page
+    /// unchanged, if the new value does not fit.
+    pub fn set_inline_value(&mut self, index: usize, value: &[u8]) -> Result<()> {
+        if !self.is_interior() {
+            return Err(StorageError::invalid_operation(
+                "set_inline_value called on leaf page",
+            ));
+        }
+
+        let old = self.get_cell(index)?;
+        let mut cell = Cell::new_interior(old.key, old.left_child);
+        cell.value = value.to_vec();
+
+        // Work on a copy so a failed insert leaves the page as it was
+        let mut page = self.clone();
+        page.delete_cell(index)?;
+        if !page.can_fit(page.cell_size(&cell)) {
+            page.defragment()?;
+        }
+        page.insert_cell_at(index, &cell)?;
+
+        *self = page;
+        Ok(())
+    }
+
+    /// Get the number of bytes `cell` will take up when inserted here
+    ///
+    /// Includes the cost of switching to the metadata (or inline value)
+    /// format if the cell carries metadata (or a value) this page does not
+    /// store yet.
+    pub fn cell_size(&self, cell: &Cell) -> usize {
+        let needs_extended = if self.is_leaf() {
+            !cell.meta.is_empty()
+        } else {
+            !cell.value.is_empty()
+        };
+        if self.extended_cells() {
+            cell.encoded_size_in(true)
+        } else if needs_extended {
+            // Every existing cell grows by one byte
+            cell.encoded_size_in(true) + self.cell_count()
+        } else {
+            cell.encoded_size_in(false)
+        }
+    }
+
+    /// Get the number of bytes a cell already on this page occupies
+    fn stored_size(&self, cell: &Cell) -> usize {
+        cell.encoded_size_in(self.extended_cells())
+    }
+
+    /// Check whether cells use the extended format: metadata on leaves,
+    /// inline values on interior pages
+    fn extended_cells(&self) -> bool {
+        if self.is_leaf() {
+            self.has_cell_meta()
+        } else {
+            self.has_inline_values()
+        }
+    }
+
     /// Get the number of cells in this page
     pub fn cell_count(&self) -> usize {
         self.header.cell_count as usize
@@ -98,6 +516,23 @@ impl SlottedPage {
         self.sync_header();
     }
 
+    /// Get the next leaf in key order, if this leaf records it
+    ///
+    /// Returns `None` for leaves written before leaves were linked, and
+    /// `PageId::HEADER` on the last leaf.
+    pub fn next_leaf(&self) -> Option<PageId> {
+        (self.page_type() == PageType::LinkedLeafTable).then(|| self.right_child())
+    }
+
+    /// Point this leaf at the next leaf in key order
+    ///
+    /// Leaves that do not record a link are left unchanged.
+    pub fn set_next_leaf(&mut self, page_id: PageId) {
+        if self.page_type() == PageType::LinkedLeafTable {
+            self.set_right_child(page_id);
+        }
+    }
+
     /// Get the cell pointer at the given index
     fn cell_pointer(&self, index: usize) -> u16 {
         let offset = self.header.cell_pointer_offset() + index * 2;
@@ -121,14 +556,17 @@ impl SlottedPage {
         }
 
         let pointer = self.cell_pointer(index) as usize;
-        let cell_bytes = &self.data[pointer..];
+        let cell_bytes = self
+            .data
+            .get(pointer..)
+            .ok_or_else(|| StorageError::corruption("cell pointer past end of page"))?;
 
         let cell = if self.is_leaf() {
-            Cell::decode_leaf(cell_bytes)
+            Cell::decode_leaf_in(cell_bytes, self.has_cell_meta())
                 .ok_or_else(|| StorageError::corruption("failed to decode leaf cell"))?
                 .0
         } else {
-            Cell::decode_interior(cell_bytes)
+            Cell::decode_interior_in(cell_bytes, self.has_inline_values())
                 .ok_or_else(|| StorageError::corruption("failed to decode interior cell"))?
                 .0
         };
@@ -136,6 +574,41 @@ impl SlottedPage {
         Ok(cell)
     }
 
+    /// Borrow the key and value of the leaf cell at the given index
+    ///
+    /// For a value spilled to overflow pages only the inline part is
+    /// returned; see `get_leaf_ref`.
+    pub fn get_leaf_entry(&self, index: usize) -> Result<(&[u8], &[u8])> {
+        let (key, value, _) = self.get_leaf_ref(index)?;
+        Ok((key, value))
+    }
+
+    /// Borrow the key and value of the leaf cell at the given index along
+    /// with its metadata
+    pub fn get_leaf_ref(&self, index: usize) -> Result<(&[u8], &[u8], CellMeta)> {
+        if !self.is_leaf() {
+            return Err(StorageError::invalid_operation(
+                "get_leaf_entry called on interior page",
+            ));
+        }
+        if index >= self.cell_count() {
+            return Err(StorageError::invalid_operation(format!(
+                "cell index {} out of bounds (count: {})",
+                index,
+                self.cell_count()
+            )));
+        }
+
+        let pointer = self.cell_pointer(index) as usize;
+        let cell_bytes = self
+            .data
+            .get(pointer..)
+            .ok_or_else(|| StorageError::corruption("cell pointer past end of page"))?;
+        let (key, value, meta, _) = Cell::decode_leaf_ref(cell_bytes, self.has_cell_meta())
+            .ok_or_else(|| StorageError::corruption("failed to decode leaf cell"))?;
+        Ok((key, value, meta))
+    }
+
     /// Get all cells in this page (in sorted key order)
     pub fn get_all_cells(&self) -> Result<Vec<Cell>> {
         let mut cells = Vec::with_capacity(self.cell_count());
@@ -154,28 +627,83 @@ impl SlottedPage {
         content_start.saturating_sub(ptr_array_end).saturating_sub(2)
     }
 
+    /// Get the percentage of the page's cell area in use
+    ///
+    /// Counts cell pointers, cells and fragmented gaps against everything
+    /// after the header.
+    pub fn fill_percent(&self) -> f32 {
+        let usable = PAGE_SIZE - self.header.size();
+        let gap = (self.header.cell_content_start as usize)
+            .saturating_sub(self.header.cell_pointer_array_end());
+        (usable - gap.min(usable)) as f32 * 100.0 / usable as f32
+    }
+
     /// Check if a cell of the given size can fit
     pub fn can_fit(&self, cell_size: usize) -> bool {
         self.free_space() >= cell_size
     }
 
-    /// Insert a cell at the correct sorted position
+    /// Get the number of bytes in the cell content area held by no live
+    /// cell
+    ///
+    /// Computed from the live cells, since the header's `fragmented_bytes`
+    /// stops counting at 255.
+    pub fn dead_space(&self) -> Result<usize> {
+        let live: usize = self
+            .get_all_cells()?
+            .iter()
+            .map(|cell| self.stored_size(cell))
+            .sum();
+        let content = PAGE_SIZE - self.header.cell_content_start as usize;
+        Ok(content.saturating_sub(live))
+    }
+
+    /// Insert a cell at its sorted position, comparing keys bytewise
     ///
     /// Returns the index where the cell was inserted.
+    ///
+    /// A leaf cell with metadata switches the page to the metadata format,
+    /// and an interior cell with a value to the inline value format. If the
+    /// cell only fits once space left by deleted cells is reclaimed, the
+    /// page is defragmented first.
     pub fn insert_cell(&mut self, cell: &Cell) -> Result<usize> {
-        let encoded = cell.encode();
-        let cell_size = encoded.len();
+        self.insert_cell_by(cell, KeyOrder::Bytewise)
+    }
+
+    /// Insert a cell in sorted position, with cells sorted in `order`
+    pub fn insert_cell_by(&mut self, cell: &Cell, order: KeyOrder) -> Result<usize> {
+        let insert_pos = self.find_insert_position(&cell.key, order)?;
+        self.insert_cell_at(insert_pos, cell)?;
+        Ok(insert_pos)
+    }
+
+    /// Append a cell after the last one, for copying cells already in order
+    fn push_cell(&mut self, cell: &Cell) -> Result<()> {
+        self.insert_cell_at(self.cell_count(), cell)
+    }
 
-        if !self.can_fit(cell_size) {
+    /// Insert a cell at the given index
+    fn insert_cell_at(&mut self, insert_pos: usize, cell: &Cell) -> Result<()> {
+        let needed = self.cell_size(cell);
+        if !self.can_fit(needed) && self.free_space() + self.dead_space()? >= needed {
+            self.defragment()?;
+        }
+        if !self.can_fit(needed) {
             return Err(StorageError::PageFull {
                 page_id: PageId::INVALID,
-                needed: cell_size + 2,
+                needed: self.cell_size(cell) + 2,
                 available: self.free_space(),
             });
         }
+        if self.is_leaf() && !cell.meta.is_empty() {
+            self.enable_cell_meta()?;
+        }
+        if self.is_interior() && !cell.value.is_empty() {
+            self.enable_inline_values()?;
+        }
 
-        // Find insertion position using binary search
-        let insert_pos = self.find_insert_position(&cell.key)?;
+        let encoded = cell.encode_in(self.extended_cells());
+        let cell_size = encoded.len();
 
         // Allocate space for the cell content
         let new_content_start = self.header.cell_content_start as usize - cell_size;
@@ -196,11 +724,36 @@ impl SlottedPage {
         self.header.cell_content_start = new_content_start as u16;
         self.sync_header();
 
-        Ok(insert_pos)
+        if let Some(mut filter) = self.bloom_filter() {
+            filter.insert(&cell.key);
+            self.store_bloom(&filter);
+        }
+
+        Ok(())
+    }
+
+    /// Write a bloom filter into this page's filter region
+    fn store_bloom(&mut self, filter: &BloomFilter) {
+        let offset = self.header.bloom_offset();
+        self.data[offset..offset + BLOOM_FILTER_SIZE].copy_from_slice(filter.as_bytes());
+    }
+
+    /// Rebuild the bloom filter from the keys on the page, dropping keys
+    /// that were deleted
+    fn rebuild_bloom(&mut self) -> Result<()> {
+        if !self.has_bloom() {
+            return Ok(());
+        }
+        let mut filter = BloomFilter::new();
+        for i in 0..self.cell_count() {
+            filter.insert(self.get_leaf_ref(i)?.0);
+        }
+        self.store_bloom(&filter);
+        Ok(())
     }
 
     /// Find the position where a key should be inserted
-    fn find_insert_position(&self, key: &[u8]) -> Result<usize> {
+    fn find_insert_position(&self, key: &[u8], order: KeyOrder) -> Result<usize> {
         let cell_count = self.cell_count();
         if cell_count == 0 {
             return Ok(0);
@@ -214,7 +767,7 @@ impl SlottedPage {
             let mid = low + (high - low) / 2;
             let cell = self.get_cell(mid)?;
 
-            match key.cmp(&cell.key) {
+            match order.compare(key, &cell.key) {
                 std::cmp::Ordering::Less => high = mid,
                 std::cmp::Ordering::Greater => low = mid + 1,
                 std::cmp::Ordering::Equal => return Ok(mid), // Key exists
@@ -225,7 +778,14 @@ impl SlottedPage {
     }
 
     /// Search for a key and return its index, or None if not found
+    ///
+    /// Keys are compared bytewise.
     pub fn search(&self, key: &[u8]) -> Result<Option<usize>> {
+        self.search_by(key, KeyOrder::Bytewise)
+    }
+
+    /// Search for a key on a page whose cells are sorted in `order`
+    pub fn search_by(&self, key: &[u8], order: KeyOrder) -> Result<Option<usize>> {
         let cell_count = self.cell_count();
         if cell_count == 0 {
             return Ok(None);
@@ -238,7 +798,7 @@ impl SlottedPage {
             let mid = low + (high - low) / 2;
             let cell = self.get_cell(mid)?;
 
-            match key.cmp(&cell.key) {
+            match order.compare(key, &cell.key) {
                 std::cmp::Ordering::Less => high = mid,
                 std::cmp::Ordering::Greater => low = mid + 1,
                 std::cmp::Ordering::Equal => return Ok(Some(mid)),
@@ -248,7 +808,8 @@ impl SlottedPage {
         Ok(None)
     }
 
-    /// Find the child page for a given key (for interior pages)
+    /// Find the child page for a given key (for interior pages), comparing
+    /// keys bytewise
     ///
     /// Returns the page ID of the child that should contain the key.
     /// 
@@ -263,65 +824,134 @@ impl SlottedPage {
     /// - cell[1].left_child → keys >= 20 and < 30
     /// - cell[2].left_child → keys >= 30
     pub fn find_child(&self, key: &[u8]) -> Result<PageId> {
+        self.find_child_by(key, KeyOrder::Bytewise)
+    }
+
+    /// Find the child page for a key, with separators sorted in `order`
+    pub fn find_child_by(&self, key: &[u8], order: KeyOrder) -> Result<PageId> {
+        let child = match self.child_index_by(key, order)? {
+            0 => self.right_child(),
+            index => self.get_cell(index - 1)?.left_child,
+        };
+        self.child_pointer(child)
+    }
+
+    /// Find the position of the child for a key, in key order
+    ///
+    /// 0 is `right_child` and `i + 1` is the left child of cell `i`. The
+    /// result is the number of separators <= key, so the separator at the
+    /// returned index, if any, is the child's exclusive upper bound.
+    pub fn child_index_by(&self, key: &[u8], order: KeyOrder) -> Result<usize> {
         if !self.is_interior() {
             return Err(StorageError::invalid_operation(
                 "find_child called on leaf page",
             ));
         }
 
-        let cell_count = self.cell_count();
-        if cell_count == 0 {
-            // No separators, use right_child (which acts as the only child)
-            return Ok(self.right_child());
-        }
-
-        // Check if key is less than the first separator
-        let first_cell = self.get_cell(0)?;
-        if key < first_cell.key.as_slice() {
-            // Keys < first separator go to right_child
-            return Ok(self.right_child());
-        }
-
-        // Binary search for the largest separator <= key
+        // Binary search for the first separator > key
         let mut low = 0;
-        let mut high = cell_count;
-
+        let mut high = self.cell_count();
         while low < high {
             let mid = low + (high - low) / 2;
             let cell = self.get_cell(mid)?;
 
-            if key < cell.key.as_slice() {
+            if order.compare(key, &cell.key).is_lt() {
                 high = mid;
             } else {
                 low = mid + 1;
             }
         }
+        Ok(low)
+    }
 
-        // low is now the index AFTER the last separator <= key
-        // So the separator at low-1 is the largest one <= key
-        // Return its child pointer
-        Ok(self.get_cell(low - 1)?.left_child)
+    /// Check a child pointer read from this interior page
+    ///
+    /// Page 0 is the file header, so a zero pointer means the page is
+    /// corrupt.
+    fn child_pointer(&self, child: PageId) -> Result<PageId> {
+        if child == PageId::HEADER {
+            return Err(StorageError::corruption(format!(
+                "interior page with {} cell(s) has a null child pointer",
+                self.cell_count()
+            )));
+        }
+        Ok(child)
     }
 
     /// Update the value of an existing cell at the given index
     ///
-    /// This is only valid for leaf pages.
+    /// This is only valid for leaf pages. The same as `replace_value`.
     pub fn update_cell(&mut self, index: usize, new_value: &[u8]) -> Result<()> {
+        self.replace_value(index, new_value)
+    }
+
+    /// Replace the value of the leaf cell at `index`, keeping its key,
+    /// metadata and slot
+    ///
+    /// The new value is stored inline; any overflow chain of the old value
+    /// is left for the caller to free. A value that encodes no larger than
+    /// the old one is written over it in place. A larger one moves the cell
+    /// to free space, or is reinserted in the same slot with dead space
+    /// reclaimed if it needs more; if it still does not fit, the page is
+    /// left unchanged.
+    pub fn replace_value(&mut self, index: usize, new_value: &[u8]) -> Result<()> {
         if !self.is_leaf() {
             return Err(StorageError::invalid_operation(
-                "update_cell called on interior page",
+                "replace_value called on interior page",
             ));
         }
 
-        let cell = self.get_cell(index)?;
-        let new_cell = Cell::new_leaf(cell.key.clone(), new_value.to_vec());
+        let old = self.get_cell(index)?;
+        let old_size = self.stored_size(&old);
+        let meta = CellMeta {
+            overflow: None,
+            ..old.meta
+        };
+        let cell = Cell::new_leaf(old.key, new_value.to_vec()).with_meta(meta);
+        let encoded = cell.encode_in(self.extended_cells());
 
-        // For simplicity, we delete and re-insert
-        // A more efficient implementation would update in-place if the new cell fits
-        self.delete_cell(index)?;
+        let (pointer, freed) = if encoded.len() <= old_size {
+            (self.cell_pointer(index) as usize, old_size - encoded.len())
+        } else {
+            let gap = (self.header.cell_content_start as usize)
+                .saturating_sub(self.header.cell_pointer_array_end());
+            if gap < encoded.len() {
+                // Only fits, if at all, once the old cell's space is reclaimed
+                return self.replace_cell(index, &cell);
+            }
+            let start = self.header.cell_content_start as usize - encoded.len();
+            self.set_cell_pointer(index, start as u16);
+            self.header.cell_content_start = start as u16;
+            (start, old_size)
+        };
 
-        // Re-insert at the correct position (should be same position)
-        self.insert_cell(&new_cell)?;
+        self.data[pointer..pointer + encoded.len()].copy_from_slice(&encoded);
+        self.add_fragmented(freed);
+        self.sync_header();
+        Ok(())
+    }
+
+    /// Replace the leaf cell at the given index with a cell for the same key
+    pub fn replace_cell(&mut self, index: usize, new_cell: &Cell) -> Result<()> {
+        if !self.is_leaf() {
+            return Err(StorageError::invalid_operation(
+                "replace_cell called on interior page",
+            ));
+        }
+        if self.get_cell(index)?.key != new_cell.key {
+            return Err(StorageError::invalid_operation(
+                "replace_cell called with a different key",
+            ));
+        }
+
+        // Delete and re-insert; the insert reclaims the old cell's space
+        // if it has to. On failure the old cell goes back, which always
+        // fits in the space it just left.
+        let old = self.delete_cell(index)?;
+        if let Err(e) = self.insert_cell_at(index, new_cell) {
+            self.insert_cell_at(index, &old)?;
+            return Err(e);
+        }
 
         Ok(())
     }
@@ -348,26 +978,42 @@ impl SlottedPage {
         self.header.cell_count -= 1;
         // Note: We don't reclaim the cell content space immediately
         // A defragment operation would be needed to compact the page
-        self.header.fragmented_bytes += cell.encoded_size() as u8;
+        self.add_fragmented(self.stored_size(&cell));
         self.sync_header();
+        self.rebuild_bloom()?;
 
         Ok(cell)
     }
 
+    /// Count `freed` bytes toward the header's `fragmented_bytes`
+    ///
+    /// The field is one byte on disk, so it saturates at 255 and only says
+    /// whether there is dead space worth reclaiming; `dead_space` gives
+    /// the exact amount.
+    fn add_fragmented(&mut self, freed: usize) {
+        let freed = u8::try_from(freed).unwrap_or(u8::MAX);
+        self.header.fragmented_bytes = self.header.fragmented_bytes.saturating_add(freed);
+    }
+
     /// Split this page, returning a new page with the upper half of keys
     ///
     /// Returns (new_page, separator_key) where separator_key is the first key
     /// of the new page (for insertion into parent).
+    ///
+    /// Both pages end up with strictly fewer cells than before, so a page
+    /// with fewer than two cells cannot be split.
     pub fn split(&mut self) -> Result<(SlottedPage, Vec<u8>)> {
         let cell_count = self.cell_count();
+        if cell_count < 2 {
+            return Err(StorageError::invalid_operation(format!(
+                "cannot split a page with {} cell(s)",
+                cell_count
+            )));
+        }
         let mid = cell_count / 2;
 
         // Create new page of same type
-        let mut new_page = if self.is_leaf() {
-            SlottedPage::new_leaf()
-        } else {
-            SlottedPage::new_interior()
-        };
+        let mut new_page = self.empty_like();
 
         // Move upper half of cells to new page
         let cells_to_move: Vec<Cell> = (mid..cell_count)
@@ -403,12 +1049,12 @@ impl SlottedPage {
 
             // Insert remaining cells (after separator) into new page
             for cell in cells_to_move.iter().skip(1) {
-                new_page.insert_cell(cell)?;
+                new_page.push_cell(cell)?;
             }
         } else {
             // For leaf pages, copy all cells to new page
             for cell in &cells_to_move {
-                new_page.insert_cell(cell)?;
+                new_page.push_cell(cell)?;
             }
         }
 
@@ -423,22 +1069,181 @@ impl SlottedPage {
         Ok((new_page, separator_key))
     }
 
+    /// Split a full leaf page around a new cell, comparing keys bytewise
+    ///
+    /// Returns (left, right, separator_key) with `cell` placed on whichever
+    /// side its key belongs. `self` is left untouched so the caller can
+    /// commit both pages only once everything has succeeded.
+    ///
+    /// The split point starts at the middle cell and moves outward until
+    /// both halves fit, so each page gets at least one cell and strictly
+    /// fewer cells than the combined total. A fitting point always exists
+    /// when no cell is larger than half the usable page; otherwise this may
+    /// return `PageFull` (a large cell flanked by others can need three
+    /// pages).
+    pub fn split_insert(&self, cell: &Cell) -> Result<(SlottedPage, SlottedPage, Vec<u8>)> {
+        self.split_insert_by(cell, KeyOrder::Bytewise)
+    }
+
+    /// Split a full leaf page around a new cell, with cells sorted in `order`
+    pub fn split_insert_by(
+        &self,
+        cell: &Cell,
+        order: KeyOrder,
+    ) -> Result<(SlottedPage, SlottedPage, Vec<u8>)> {
+        self.split_insert_biased(cell, order, 0.5)
+    }
+
+    /// Split a full leaf page around a new cell, aiming to keep
+    /// `left_share` of the cells on the left
+    ///
+    /// The search for a fitting split point starts there instead of at the
+    /// middle. A share near 1 suits keys arriving in increasing order,
+    /// which would otherwise leave every left page half empty for good.
+    pub fn split_insert_biased(
+        &self,
+        cell: &Cell,
+        order: KeyOrder,
+        left_share: f64,
+    ) -> Result<(SlottedPage, SlottedPage, Vec<u8>)> {
+        if !self.is_leaf() {
+            return Err(StorageError::invalid_operation(
+                "split_insert called on interior page",
+            ));
+        }
+
+        let mut cells = self.get_all_cells()?;
+        let pos = match cells.binary_search_by(|c| order.compare(&c.key, &cell.key)) {
+            Ok(_) => {
+                return Err(StorageError::invalid_operation(
+                    "split_insert called with a key already on the page",
+                ))
+            }
+            Err(pos) => pos,
+        };
+        cells.insert(pos, cell.clone());
+
+        // Both halves use the metadata format if either input does
+        let mut template = self.empty_like();
+        if !cell.meta.is_empty() {
+            template.enable_cell_meta()?;
+        }
+        let with_meta = template.has_cell_meta();
+
+        // Each cell also costs a 2-byte pointer
+        let capacity = PAGE_SIZE - template.header.size();
+        let sizes: Vec<usize> = cells.iter().map(|c| c.encoded_size_in(with_meta) + 2).collect();
+        let total: usize = sizes.iter().sum();
+
+        let n = cells.len();
+        let split_at = split_points(n, left_share, n)
+            .find(|&i| {
+                let left: usize = sizes[..i].iter().sum();
+                left <= capacity && total - left <= capacity
+            })
+            .ok_or(StorageError::PageFull {
+                page_id: PageId::INVALID,
+                needed: cell.encoded_size_in(with_meta) + 2,
+                available: self.free_space(),
+            })?;
+
+        let mut left = template.clone();
+        let mut right = template;
+        for c in &cells[..split_at] {
+            left.push_cell(c)?;
+        }
+        for c in &cells[split_at..] {
+            right.push_cell(c)?;
+        }
+
+        Ok((left, right, cells[split_at].key.clone()))
+    }
+
+    /// Split a full interior page around a new cell, with cells sorted in
+    /// `order`
+    ///
+    /// Returns (left, right, separator_key). The cell at the split point
+    /// moves up to the parent: it is kept on neither page, and its child
+    /// becomes the right page's `right_child`. `self` is left untouched.
+    ///
+    /// The split point starts at `left_share` of all cells, the new one
+    /// included, and moves outward until both halves fit. With three or
+    /// more cells in all each page keeps at least one.
+    pub fn split_insert_interior_by(
+        &self,
+        cell: &Cell,
+        order: KeyOrder,
+        left_share: f64,
+    ) -> Result<(SlottedPage, SlottedPage, Vec<u8>)> {
+        if !self.is_interior() {
+            return Err(StorageError::invalid_operation(
+                "split_insert_interior called on leaf page",
+            ));
+        }
+
+        let mut cells = self.get_all_cells()?;
+        let pos = match cells.binary_search_by(|c| order.compare(&c.key, &cell.key)) {
+            Ok(_) => {
+                return Err(StorageError::invalid_operation(
+                    "split_insert_interior called with a key already on the page",
+                ))
+            }
+            Err(pos) => pos,
+        };
+        cells.insert(pos, cell.clone());
+
+        // Both halves use the inline value format if either input does
+        let mut template = self.empty_like();
+        if !cell.value.is_empty() {
+            template.enable_inline_values()?;
+        }
+        let with_values = template.has_inline_values();
+
+        // Each cell also costs a 2-byte pointer
+        let capacity = PAGE_SIZE - template.header.size();
+        let sizes: Vec<usize> = cells.iter().map(|c| c.encoded_size_in(with_values) + 2).collect();
+        let total: usize = sizes.iter().sum();
+
+        // The cell at the split point leaves, so keep one for the right
+        let n = cells.len();
+        let split_at = split_points(n, left_share, n.max(3) - 1)
+            .find(|&i| {
+                let left: usize = sizes[..i].iter().sum();
+                left <= capacity && total - left - sizes[i] <= capacity
+            })
+            .ok_or(StorageError::PageFull {
+                page_id: PageId::INVALID,
+                needed: cell.encoded_size_in(with_values) + 2,
+                available: self.free_space(),
+            })?;
+
+        let mut left = template.clone();
+        let mut right = template;
+        left.set_right_child(self.right_child());
+        right.set_right_child(cells[split_at].left_child);
+        for c in &cells[..split_at] {
+            left.push_cell(c)?;
+        }
+        for c in &cells[split_at + 1..] {
+            right.push_cell(c)?;
+        }
+
+        Ok((left, right, cells[split_at].key.clone()))
+    }
+
     /// Defragment the page to reclaim fragmented space
     pub fn defragment(&mut self) -> Result<()> {
         let cells = self.get_all_cells()?;
 
         // Reset page
-        let mut new_page = if self.is_leaf() {
-            SlottedPage::new_leaf()
-        } else {
-            let mut p = SlottedPage::new_interior();
-            p.set_right_child(self.right_child());
-            p
-        };
+        let mut new_page = self.empty_like();
+        if self.is_interior() {
+            new_page.set_right_child(self.right_child());
+        }
 
         // Re-insert all cells
         for cell in cells {
-            new_page.insert_cell(&cell)?;
+            new_page.push_cell(&cell)?;
         }
 
         // Copy new page data to self
@@ -448,12 +1253,40 @@ impl SlottedPage {
         Ok(())
     }
 
+    /// Create an empty page with the same type and flags as this one
+    ///
+    /// A leaf keeps its next leaf link. A bloom filter starts out empty and
+    /// is rebuilt as cells are inserted.
+    fn empty_like(&self) -> SlottedPage {
+        let mut page = if self.is_leaf() {
+            let mut page = SlottedPage::new_leaf();
+            page.header.page_type = self.header.page_type;
+            page.header.right_child = self.header.right_child;
+            page
+        } else {
+            SlottedPage::new_interior()
+        };
+        page.header.flags = self.header.flags;
+        page.sync_header();
+        page
+    }
+
     /// Sync the header to the raw page data
     fn sync_header(&mut self) {
         self.header.write(&mut self.data);
     }
 }
 
+/// Candidate split points in `1..end` for `n` cells, nearest to
+/// `left_share` of them first
+fn split_points(n: usize, left_share: f64, end: usize) -> impl Iterator<Item = usize> {
+    let target = (n as f64 * left_share) as usize;
+    (0..n)
+        .flat_map(move |d| [target.checked_sub(d), Some(target + d)])
+        .flatten()
+        .filter(move |&i| i >= 1 && i < end)
+}
+
 impl Clone for SlottedPage {
     fn clone(&self) -> Self {
         Self {
@@ -537,6 +1370,69 @@ mod tests {
         assert_eq!(page.get_cell(1).unwrap().key, b"c".to_vec());
     }
 
+    #[test]
+    fn test_updates_reuse_dead_space() -> Result<()> {
+        let mut page = SlottedPage::new_leaf();
+        for i in 0..20u8 {
+            page.insert_cell(&Cell::new_leaf(vec![b'a', i], vec![i; 100]))?;
+        }
+        page.insert_cell(&Cell::new_leaf(b"k".to_vec(), vec![0; 10]))?;
+
+        // Each update leaves the old cell behind as dead space; the page
+        // never has room for the largest value without reclaiming it
+        for round in 0..200usize {
+            let len = [10, 1500, 200, 1800, 50][round % 5];
+            page.update_cell(page.search(b"k")?.unwrap(), &vec![round as u8; len])?;
+            assert_eq!(page.get_leaf_entry(page.search(b"k")?.unwrap())?.1.len(), len);
+        }
+        assert_eq!(page.cell_count(), 21);
+
+        // An update that cannot fit fails and leaves the old value
+        let idx = page.search(b"k")?.unwrap();
+        let err = page.update_cell(idx, &vec![1; 3000]).unwrap_err();
+        assert!(matches!(err, StorageError::PageFull { .. }));
+        assert_eq!(page.get_leaf_entry(page.search(b"k")?.unwrap())?.1.len(), 50);
+        assert_eq!(page.cell_count(), 21);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_enable_checksum() -> Result<()> {
+        let mut page = SlottedPage::new_leaf();
+        page.enable_bloom()?;
+        for i in 0..10u8 {
+            page.insert_cell(&Cell::new_leaf(vec![b'k', i], vec![i; 20]))?;
+        }
+        let entries = |page: &SlottedPage| -> Vec<(Vec<u8>, Vec<u8>)> {
+            let cells = page.get_all_cells().unwrap();
+            cells.into_iter().map(|c| (c.key, c.value)).collect()
+        };
+        let before = entries(&page);
+        let bloom = page.bloom_filter().unwrap();
+
+        page.enable_checksum()?;
+        assert!(page.has_checksum());
+        page.verify_checksum()?;
+        assert_eq!(entries(&page), before);
+        assert_eq!(page.bloom_filter().unwrap().as_bytes(), bloom.as_bytes());
+
+        // Any change shows up until the checksum is restamped
+        page.insert_cell(&Cell::new_leaf(b"new".to_vec(), b"v".to_vec()))?;
+        assert!(matches!(page.verify_checksum(), Err(StorageError::Corruption(_))));
+        page.update_checksum();
+        page.verify_checksum()?;
+        SlottedPage::try_decode(page.as_bytes())?;
+
+        // Overflow payloads move past the checksum
+        let mut page = SlottedPage::new_overflow(PageId::new(3), &[7; OVERFLOW_PAGE_CAPACITY])?;
+        page.enable_checksum()?;
+        assert_eq!(page.overflow_payload()?, &[7; OVERFLOW_PAGE_CAPACITY][..]);
+        assert_eq!(page.overflow_next()?, PageId::new(3));
+
+        Ok(())
+    }
+
     #[test]
     fn test_split() {
         let mut page = SlottedPage::new_leaf();
@@ -591,6 +1487,21 @@ mod tests {
         assert_eq!(page.find_child(b"z").unwrap(), PageId::new(20));
     }
 
+    #[test]
+    fn test_find_child_rejects_null_pointer() {
+        // An empty interior page with a valid right_child routes everything there
+        let mut page = SlottedPage::new_interior();
+        page.set_right_child(PageId::new(7));
+        assert_eq!(page.find_child(b"any").unwrap(), PageId::new(7));
+
+        // A null right_child is corruption, not a pointer to the header
+        let page = SlottedPage::new_interior();
+        assert!(matches!(
+            page.find_child(b"any"),
+            Err(StorageError::Corruption(_))
+        ));
+    }
+
     #[test]
     fn test_from_bytes_roundtrip() {
         let mut page = SlottedPage::new_leaf();
@@ -605,4 +1516,248 @@ mod tests {
         assert_eq!(cell.key, b"test");
         assert_eq!(cell.value, b"data");
     }
+
+    #[test]
+    fn test_try_decode_never_panics() {
+        use rand::{Rng, SeedableRng};
+
+        let mut page = SlottedPage::new_leaf();
+        for i in 0..20u32 {
+            page.insert_cell(&Cell::new_leaf(i.to_be_bytes().to_vec(), vec![i as u8; 30]))
+                .unwrap();
+        }
+        let valid = page.as_bytes().to_vec();
+        assert_eq!(SlottedPage::try_decode(&valid).unwrap().cell_count(), 20);
+        assert!(SlottedPage::try_decode(&valid[..100]).is_err());
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        for round in 0..2000 {
+            let mut bytes = if round % 2 == 0 {
+                let mut bytes = valid.clone();
+                for _ in 0..rng.gen_range(1..8) {
+                    let at = rng.gen_range(0..PAGE_SIZE);
+                    bytes[at] = rng.gen();
+                }
+                bytes
+            } else {
+                (0..PAGE_SIZE).map(|_| rng.gen()).collect()
+            };
+            // Keep the type byte plausible so cell checks get exercised
+            if round % 3 == 0 {
+                bytes[0] = [0x0D, 0x02, 0x8D][round % 9 / 3];
+            }
+
+            if let Ok(page) = SlottedPage::try_decode(&bytes) {
+                // Everything reachable from a decoded page must be readable
+                if page.is_leaf() || page.is_interior() {
+                    page.get_all_cells().unwrap();
+                }
+                let _ = page.search(b"key");
+                let _ = page.free_space();
+            }
+        }
+    }
+
+    #[test]
+    fn test_split_needs_two_cells() {
+        let mut page = SlottedPage::new_leaf();
+        assert!(page.split().is_err());
+
+        page.insert_cell(&Cell::new_leaf(b"a".to_vec(), b"1".to_vec()))
+            .unwrap();
+        assert!(page.split().is_err());
+        assert_eq!(page.cell_count(), 1);
+    }
+
+    #[test]
+    fn test_split_insert_finds_fitting_point() {
+        // A plain midpoint split would put both large cells on one side
+        let big = vec![0u8; 2048];
+        let mut page = SlottedPage::new_leaf();
+        page.insert_cell(&Cell::new_leaf(b"a".to_vec(), b"1".to_vec()))
+            .unwrap();
+        page.insert_cell(&Cell::new_leaf(vec![b'b'; 1000], big.clone()))
+            .unwrap();
+
+        let cell = Cell::new_leaf(vec![b'c'; 1000], big);
+        assert!(!page.can_fit(cell.encoded_size()));
+
+        let (left, right, separator) = page.split_insert(&cell).unwrap();
+        assert_eq!(left.cell_count(), 2);
+        assert_eq!(right.cell_count(), 1);
+        assert_eq!(separator, vec![b'c'; 1000]);
+
+        // The original page is untouched
+        assert_eq!(page.cell_count(), 2);
+    }
+
+    #[test]
+    fn test_split_insert_rejects_oversized_cell() {
+        let mut page = SlottedPage::new_leaf();
+        page.insert_cell(&Cell::new_leaf(b"a".to_vec(), b"1".to_vec()))
+            .unwrap();
+
+        let cell = Cell::new_leaf(b"b".to_vec(), vec![0u8; PAGE_SIZE]);
+        assert!(matches!(
+            page.split_insert(&cell),
+            Err(StorageError::PageFull { .. })
+        ));
+    }
+
+    #[test]
+    fn test_cell_meta_upgrades_page() {
+        use crate::page::CellMeta;
+
+        let mut page = SlottedPage::new_leaf();
+        page.insert_cell(&Cell::new_leaf(b"a".to_vec(), b"1".to_vec()))
+            .unwrap();
+        assert!(!page.has_cell_meta());
+
+        let meta = CellMeta {
+            modified: Some(42),
+            overflow: None,
+            expires: None,
+        };
+        let cell = Cell::new_leaf(b"b".to_vec(), b"2".to_vec()).with_meta(meta);
+        assert_eq!(page.cell_size(&cell), cell.encoded_size_in(true) + 1);
+        page.insert_cell(&cell).unwrap();
+        assert!(page.has_cell_meta());
+
+        // The flag survives defragmenting and a round trip through bytes
+        page.delete_cell(0).unwrap();
+        page.defragment().unwrap();
+        let page = SlottedPage::from_bytes(page.as_bytes()).unwrap();
+        assert!(page.has_cell_meta());
+        assert_eq!(page.get_cell(0).unwrap().meta, meta);
+        assert_eq!(page.get_leaf_entry(0).unwrap(), (&b"b"[..], &b"2"[..]));
+    }
+
+    #[test]
+    fn test_bloom_filter_tracks_keys() {
+        let mut page = SlottedPage::new_leaf();
+        page.insert_cell(&Cell::new_leaf(b"a".to_vec(), b"1".to_vec()))
+            .unwrap();
+        assert!(page.bloom_filter().is_none());
+        assert!(page.may_contain(b"zzz"));
+
+        page.enable_bloom().unwrap();
+        page.insert_cell(&Cell::new_leaf(b"b".to_vec(), b"2".to_vec()))
+            .unwrap();
+        assert!(page.may_contain(b"a"));
+        assert!(page.may_contain(b"b"));
+        assert!(!page.may_contain(b"missing"));
+
+        // Deleting drops the key from the filter, and defragmenting keeps it
+        page.delete_cell(0).unwrap();
+        assert!(!page.may_contain(b"a"));
+        assert!(page.may_contain(b"b"));
+        page.defragment().unwrap();
+        let page = SlottedPage::from_bytes(page.as_bytes()).unwrap();
+        assert!(page.has_bloom());
+        assert!(!page.may_contain(b"a"));
+        assert_eq!(page.get_cell(0).unwrap().key, b"b");
+    }
+
+    #[test]
+    fn test_overflow_page() -> Result<()> {
+        let page = SlottedPage::new_overflow(PageId::new(7), b"spilled bytes")?;
+        assert_eq!(page.page_type(), PageType::Overflow);
+        assert_eq!(page.overflow_next()?, PageId::new(7));
+        assert_eq!(page.overflow_payload()?, b"spilled bytes");
+
+        let decoded = SlottedPage::try_decode(page.as_bytes())?;
+        assert_eq!(decoded.overflow_payload()?, b"spilled bytes");
+
+        assert!(SlottedPage::new_overflow(PageId::HEADER, &[0; OVERFLOW_PAGE_CAPACITY + 1]).is_err());
+        assert!(SlottedPage::new_leaf().overflow_payload().is_err());
+
+        let mut bytes = page.as_bytes().to_vec();
+        bytes[OVERFLOW_HEADER_SIZE - 2..OVERFLOW_HEADER_SIZE].copy_from_slice(&u16::MAX.to_be_bytes());
+        assert!(SlottedPage::try_decode(&bytes).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_insert_interior_moves_separator_up() {
+        let mut page = SlottedPage::new_interior();
+        page.set_right_child(PageId::new(1));
+        page.insert_cell(&Cell::new_interior(b"k2".to_vec(), PageId::new(2)))
+            .unwrap();
+        page.insert_cell(&Cell::new_interior(b"k4".to_vec(), PageId::new(4)))
+            .unwrap();
+
+        let cell = Cell::new_interior(b"k3".to_vec(), PageId::new(3));
+        let (left, right, separator) = page
+            .split_insert_interior_by(&cell, KeyOrder::Bytewise, 0.5)
+            .unwrap();
+        assert_eq!(separator, b"k3");
+        assert_eq!((left.cell_count(), right.cell_count()), (1, 1));
+
+        // Every key still routes to the child that covered it
+        assert_eq!(left.find_child(b"k1").unwrap(), PageId::new(1));
+        assert_eq!(left.find_child(b"k2").unwrap(), PageId::new(2));
+        assert_eq!(right.find_child(b"k3").unwrap(), PageId::new(3));
+        assert_eq!(right.find_child(b"k4").unwrap(), PageId::new(4));
+
+        assert_eq!(page.cell_count(), 2);
+        assert!(page.split_insert_interior_by(&cell, KeyOrder::Bytewise, 0.5).is_ok());
+        assert!(SlottedPage::new_leaf()
+            .split_insert_interior_by(&cell, KeyOrder::Bytewise, 0.5)
+            .is_err());
+    }
+
+    #[test]
+    fn test_replace_value_keeps_key_slot_and_meta() -> Result<()> {
+        let meta = CellMeta {
+            expires: Some(u64::MAX),
+            ..CellMeta::default()
+        };
+        let mut page = SlottedPage::new_leaf();
+        for key in [b"a", b"b", b"c"] {
+            page.insert_cell(&Cell::new_leaf(key.to_vec(), vec![b'x'; 100]).with_meta(meta))?;
+        }
+        let pointer = page.cell_pointer(1);
+
+        // A smaller value is written over the old one
+        page.replace_value(1, b"short")?;
+        let cell = page.get_cell(1)?;
+        assert_eq!((cell.key.as_slice(), cell.value.as_slice()), (&b"b"[..], &b"short"[..]));
+        assert_eq!(cell.meta.expires, Some(u64::MAX));
+        assert_eq!(page.cell_pointer(1), pointer);
+
+        // A larger one moves, keeping the slot and its neighbours
+        page.replace_value(1, &[b'y'; 500])?;
+        assert_ne!(page.cell_pointer(1), pointer);
+        let keys: Vec<Vec<u8>> = page.get_all_cells()?.into_iter().map(|c| c.key).collect();
+        assert_eq!(keys, vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]);
+        assert_eq!(page.get_cell(1)?.value, vec![b'y'; 500]);
+        assert_eq!(page.get_cell(1)?.meta.expires, Some(u64::MAX));
+        assert_eq!(page.get_cell(2)?.value, vec![b'x'; 100]);
+
+        let mut interior = SlottedPage::new_interior();
+        interior.insert_cell(&Cell::new_interior(b"k".to_vec(), PageId::new(2)))?;
+        assert!(interior.replace_value(0, b"v").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_fragmented_bytes_saturate() -> Result<()> {
+        let mut page = SlottedPage::new_leaf();
+        page.insert_cell(&Cell::new_leaf(b"a".to_vec(), vec![1; 300]))?;
+        page.insert_cell(&Cell::new_leaf(b"b".to_vec(), vec![2; 200]))?;
+        page.insert_cell(&Cell::new_leaf(b"c".to_vec(), b"3".to_vec()))?;
+
+        // A cell over 255 bytes would wrap a one-byte count
+        page.delete_cell(0)?;
+        assert_eq!(page.header().fragmented_bytes, u8::MAX);
+        page.delete_cell(0)?;
+        assert_eq!(page.header().fragmented_bytes, u8::MAX);
+        assert!(page.dead_space()? >= 500);
+
+        page.defragment()?;
+        assert_eq!(page.header().fragmented_bytes, 0);
+        assert_eq!(page.dead_space()?, 0);
+        Ok(())
+    }
 }
@@ -0,0 +1,51 @@
+//! Point-in-time reads.
+//!
+//! Every write bumps the tree version (see `BTree::version`). A `Snapshot`
+//! pins a version and reads the tree as it was at that version, without
+//! holding the tree lock, so a long read through a snapshot does not block
+//! writers. While a snapshot is live, writers save the image of each page
+//! before changing it and hold freed pages back from reuse; see
+//! `btree::snapshot`.
+
+use crate::btree::snapshot::FrozenTree;
+use crate::error::Result;
+use crate::Db;
+
+/// A read-only view of the database at a fixed version
+pub struct Snapshot<'a> {
+    db: &'a Db,
+    version: u64,
+    tree: FrozenTree,
+}
+
+impl<'a> Snapshot<'a> {
+    /// Create a snapshot of `db` at `version`, reading `tree`
+    pub(crate) fn new(db: &'a Db, version: u64, tree: FrozenTree) -> Self {
+        Self { db, version, tree }
+    }
+
+    /// Get the version this snapshot reads at
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Get a value by key as of this snapshot's version
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.tree.get(key)
+    }
+
+    /// Get all key-value pairs in `[start, end)` as of this snapshot's
+    /// version
+    pub fn range(&self, start: Option<&[u8]>, end: Option<&[u8]>) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        self.tree.scan(start, end)
+    }
+}
+
+impl Drop for Snapshot<'_> {
+    fn drop(&mut self) {
+        // End this snapshot before freeing what it held back
+        self.tree.close();
+        let btree = self.db.btree.write();
+        let _ = btree.release_snapshot_pages().and_then(|_| self.db.commit());
+    }
+}
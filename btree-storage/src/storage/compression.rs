@@ -0,0 +1,216 @@
+//! Page compression.
+//!
+//! Leaf pages can be stored compressed in their slot in the file. A
+//! compressed page starts with `COMPRESSED_PAGE`, a byte whose low nibble is
+//! not a page type, then the big-endian length of the compressed bytes. The
+//! rest of the slot is zero. Any other page is stored as it is, so
+//! compressed and plain pages can sit side by side in one file.
+//!
+//! The codec is a small LZ77 variant in the style of the LZ4 block format:
+//! each sequence is a token byte holding the literal count and match length,
+//! the literals, then a two-byte offset back into the output and the rest
+//! of the match length. The last sequence has literals only.
+
+use crate::error::{Result, StorageError};
+use crate::page::PageBuf;
+use crate::types::{PageId, PAGE_SIZE};
+
+/// First byte of a compressed page
+pub(crate) const COMPRESSED_PAGE: u8 = 0x0C;
+
+/// Marker byte and compressed length
+const COMPRESSED_HEADER_SIZE: usize = 3;
+
+/// Shortest match worth encoding
+const MIN_MATCH: usize = 4;
+
+/// Bits of the hash used to find earlier matches
+const HASH_BITS: u32 = 12;
+
+/// Compress a page for storage
+///
+/// Returns `None` if the compressed form would not be smaller.
+pub(crate) fn compress_page(data: &[u8]) -> Option<PageBuf> {
+    let compressed = compress(data);
+    let len = compressed.len();
+    if COMPRESSED_HEADER_SIZE + len >= PAGE_SIZE {
+        return None;
+    }
+
+    let mut buf = PageBuf::new();
+    buf[0] = COMPRESSED_PAGE;
+    buf[1..COMPRESSED_HEADER_SIZE].copy_from_slice(&(len as u16).to_be_bytes());
+    buf[COMPRESSED_HEADER_SIZE..COMPRESSED_HEADER_SIZE + len].copy_from_slice(&compressed);
+    Some(buf)
+}
+
+/// Restore a page read from storage, in place, if it was compressed
+pub(crate) fn decompress_page(page_id: PageId, buf: &mut PageBuf) -> Result<()> {
+    if buf[0] != COMPRESSED_PAGE {
+        return Ok(());
+    }
+
+    let len = u16::from_be_bytes([buf[1], buf[2]]) as usize;
+    let data = buf
+        .get(COMPRESSED_HEADER_SIZE..COMPRESSED_HEADER_SIZE + len)
+        .and_then(|compressed| decompress(compressed, PAGE_SIZE))
+        .ok_or_else(|| {
+            StorageError::corruption(format!("page {} is compressed but malformed", page_id))
+        })?;
+    buf.copy_from_slice(&data);
+    Ok(())
+}
+
+/// Compress bytes into a sequence stream
+fn compress(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len() / 2);
+    // Last position of each hashed 4-byte sequence, plus one; 0 is unset
+    let mut table = vec![0usize; 1 << HASH_BITS];
+    let mut anchor = 0;
+    let mut pos = 0;
+
+    while pos + MIN_MATCH <= input.len() {
+        let seq = u32::from_le_bytes(input[pos..pos + MIN_MATCH].try_into().unwrap());
+        let slot = (seq.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize;
+        let candidate = table[slot].checked_sub(1);
+        table[slot] = pos + 1;
+
+        let Some(candidate) = candidate.filter(|&c| {
+            pos - c <= u16::MAX as usize && input[c..c + MIN_MATCH] == input[pos..pos + MIN_MATCH]
+        }) else {
+            pos += 1;
+            continue;
+        };
+
+        let mut len = MIN_MATCH;
+        while pos + len < input.len() && input[candidate + len] == input[pos + len] {
+            len += 1;
+        }
+        write_sequence(&mut out, &input[anchor..pos], Some((pos - candidate, len)));
+        pos += len;
+        anchor = pos;
+    }
+
+    write_sequence(&mut out, &input[anchor..], None);
+    out
+}
+
+/// Append one sequence: literals, then an optional (offset, length) match
+fn write_sequence(out: &mut Vec<u8>, literals: &[u8], matched: Option<(usize, usize)>) {
+    let match_len = matched.map_or(0, |(_, len)| len - MIN_MATCH);
+    out.push(((literals.len().min(15) as u8) << 4) | match_len.min(15) as u8);
+    if literals.len() >= 15 {
+        write_length(out, literals.len() - 15);
+    }
+    out.extend_from_slice(literals);
+
+    if let Some((offset, _)) = matched {
+        out.extend_from_slice(&(offset as u16).to_le_bytes());
+        if match_len >= 15 {
+            write_length(out, match_len - 15);
+        }
+    }
+}
+
+/// Append the part of a length that does not fit in its token nibble
+fn write_length(out: &mut Vec<u8>, mut len: usize) {
+    while len >= 255 {
+        out.push(255);
+        len -= 255;
+    }
+    out.push(len as u8);
+}
+
+/// Decompress a sequence stream that must expand to exactly `size` bytes
+///
+/// Returns `None` if the stream is malformed.
+fn decompress(input: &[u8], size: usize) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(size);
+    let mut pos = 0;
+
+    while pos < input.len() {
+        let token = input[pos];
+        pos += 1;
+
+        let literal_len = read_length(input, &mut pos, (token >> 4) as usize)?;
+        let literals = input.get(pos..pos + literal_len)?;
+        if out.len() + literal_len > size {
+            return None;
+        }
+        out.extend_from_slice(literals);
+        pos += literal_len;
+        if pos == input.len() {
+            break;
+        }
+
+        let offset = input.get(pos..pos + 2)?;
+        let offset = u16::from_le_bytes([offset[0], offset[1]]) as usize;
+        pos += 2;
+        let match_len = read_length(input, &mut pos, (token & 0x0F) as usize)? + MIN_MATCH;
+        if offset == 0 || offset > out.len() || out.len() + match_len > size {
+            return None;
+        }
+        // Byte by byte, since a match may overlap the bytes it produces
+        let start = out.len() - offset;
+        for i in 0..match_len {
+            out.push(out[start + i]);
+        }
+    }
+
+    (out.len() == size).then_some(out)
+}
+
+/// Read a length whose first part is `nibble`, continued while bytes are 255
+fn read_length(input: &[u8], pos: &mut usize, nibble: usize) -> Option<usize> {
+    let mut len = nibble;
+    if nibble == 15 {
+        loop {
+            let byte = *input.get(*pos)?;
+            *pos += 1;
+            len += byte as usize;
+            if byte != 255 {
+                break;
+            }
+        }
+    }
+    Some(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_compress_round_trip() -> Result<()> {
+        let mut repetitive = PageBuf::new();
+        for (i, byte) in repetitive.iter_mut().enumerate() {
+            *byte = b"the quick brown fox "[i % 20];
+        }
+        let stored = compress_page(&repetitive).unwrap();
+        assert_eq!(stored[0], COMPRESSED_PAGE);
+        assert!(u16::from_be_bytes([stored[1], stored[2]]) < 200);
+        let mut restored = stored.clone();
+        decompress_page(PageId::new(1), &mut restored)?;
+        assert_eq!(restored.as_bytes(), repetitive.as_bytes());
+
+        // Random bytes do not compress and are left to be stored as they are
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let mut random = PageBuf::new();
+        rng.fill(&mut random[..]);
+        assert!(compress_page(&random).is_none());
+        let data = decompress(&compress(&random), PAGE_SIZE).unwrap();
+        assert_eq!(data, random.as_bytes());
+
+        // Damaged pages fail instead of panicking: a match before any
+        // output, then a length past the end of the page
+        let mut damaged = stored.clone();
+        damaged[COMPRESSED_HEADER_SIZE] = 0x0F;
+        assert!(decompress_page(PageId::new(1), &mut damaged).is_err());
+        let mut damaged = stored;
+        damaged[1..3].copy_from_slice(&(PAGE_SIZE as u16).to_be_bytes());
+        let err = decompress_page(PageId::new(1), &mut damaged).unwrap_err();
+        assert!(matches!(err, StorageError::Corruption(_)), "{}", err);
+        Ok(())
+    }
+}
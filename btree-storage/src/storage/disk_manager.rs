@@ -5,10 +5,14 @@
 //! so that the rest of the system can be tested with mock implementations.
 
 use crate::error::{Result, StorageError};
-use crate::page::PageBuf;
-use crate::storage::{FileHeader, FreeList};
-use crate::types::{PageId, PAGE_SIZE};
-use parking_lot::RwLock;
+use crate::page::{PageBuf, PageHeader, SlottedPage};
+use crate::storage::compression;
+use crate::storage::retry::IoCounters;
+use crate::storage::wal::Wal;
+use crate::storage::{FileHeader, FreeList, IoStats, RetryPolicy};
+use crate::types::{KeyOrder, PageId, PAGE_SIZE};
+use parking_lot::{Mutex, RwLock};
+use std::collections::{HashMap, HashSet};
 use std::fs::{File, OpenOptions};
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::Path;
@@ -29,14 +33,68 @@ pub trait DiskManager: Send + Sync {
     /// Deallocate a page (add to free list)
     fn deallocate_page(&self, page_id: PageId) -> Result<()>;
 
-    /// Sync all data to disk
+    /// Sync all data to disk, writing the file header first
     fn sync(&self) -> Result<()>;
 
+    /// Sync written pages to disk without writing the file header
+    fn sync_data(&self) -> Result<()>;
+
     /// Get the file header
     fn header(&self) -> FileHeader;
 
     /// Update the root page
     fn set_root_page(&self, page_id: PageId, height: u32) -> Result<()>;
+
+    /// Update the main tree's entry count
+    ///
+    /// The count is written along with the rest of the header, not on its
+    /// own.
+    fn set_entry_count(&self, count: u64) -> Result<()>;
+
+    /// Update the catalog page of the named trees
+    fn set_catalog_page(&self, _page_id: PageId) -> Result<()> {
+        Err(StorageError::invalid_operation(
+            "this disk manager cannot store named trees",
+        ))
+    }
+
+    /// Record the order the file's trees sort their keys in
+    fn set_key_order(&self, _order: KeyOrder) -> Result<()> {
+        Err(StorageError::invalid_operation(
+            "this disk manager cannot store a key order",
+        ))
+    }
+
+    /// Get counters for retried and failed I/O operations
+    fn io_stats(&self) -> IoStats {
+        IoStats::default()
+    }
+
+    /// Get the pages currently on the free list, next to be reused first
+    fn free_pages(&self) -> Vec<PageId> {
+        Vec::new()
+    }
+
+    /// Iterate over the allocated pages after the header, in file order
+    ///
+    /// Pages on the free list are skipped. The pages are listed when this
+    /// is called, so later allocations and frees are not seen.
+    fn allocated_pages(&self) -> Box<dyn Iterator<Item = PageId> + '_> {
+        let free: HashSet<PageId> = self.free_pages().into_iter().collect();
+        let page_count = self.header().page_count;
+        Box::new((1..page_count).map(PageId::new).filter(move |id| !free.contains(id)))
+    }
+
+    /// Replace the file header and free list with a known-good copy
+    ///
+    /// Data pages are not touched, though the pages on the free list may be
+    /// rewritten to match it, so none of them may be in use. Implementations
+    /// must check the metadata against the actual file before applying it.
+    fn restore_metadata(&self, _header: FileHeader, _free_pages: &[PageId]) -> Result<()> {
+        Err(StorageError::invalid_operation(
+            "this disk manager cannot restore metadata",
+        ))
+    }
 }
 
 /// File-based disk manager implementation
@@ -49,30 +107,80 @@ pub struct DiskManagerImpl {
     free_list: RwLock<FreeList>,
     /// Whether to sync on each write
     sync_on_write: bool,
+    /// How transient I/O errors are retried
+    retry_policy: RetryPolicy,
+    /// Retry and failure counters
+    io_counters: IoCounters,
+    /// Whether header changes wait for `sync` instead of being written at once
+    defer_header: bool,
+    /// Whether the file was opened for direct I/O
+    direct_io: bool,
+    /// Whether every page write is read back and checked
+    paranoid: bool,
+    /// Write-ahead log that page writes go to until the next checkpoint
+    wal: Option<Mutex<Wal>>,
+    /// Whether pages are given checksums and checked on read
+    checksums: bool,
+    /// Whether leaf pages are compressed when written
+    compression: bool,
+    /// Whether the file was opened read-only
+    read_only: bool,
 }
 
 impl DiskManagerImpl {
     /// Open or create a database file
     pub fn open(path: &Path, sync_on_write: bool) -> Result<Self> {
+        Self::open_file(path, sync_on_write, false, false)
+    }
+
+    /// Open an existing database file for reading only
+    ///
+    /// The file is opened without write permission, and every operation
+    /// that would change it fails with `InvalidOperation`.
+    pub fn open_read_only(path: &Path) -> Result<Self> {
+        Self::open_file(path, false, false, true)
+    }
+
+    /// Open or create a database file for direct I/O, bypassing the OS
+    /// page cache
+    ///
+    /// Only supported on Linux, and only on file systems that accept
+    /// `O_DIRECT`. Every transfer is a whole page from a page-aligned
+    /// `PageBuf`, which satisfies the usual device block alignment.
+    pub fn open_direct(path: &Path, sync_on_write: bool) -> Result<Self> {
+        Self::open_file(path, sync_on_write, true, false)
+    }
+
+    fn open_file(path: &Path, sync_on_write: bool, direct_io: bool, read_only: bool) -> Result<Self> {
         let exists = path.exists();
 
-        let file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .truncate(false)
-            .open(path)?;
+        let mut options = OpenOptions::new();
+        if read_only {
+            options.read(true);
+        } else {
+            options.read(true).write(true).create(true).truncate(false);
+        }
+        if direct_io {
+            Self::set_direct_flags(&mut options)?;
+        }
+        let file = options.open(path)?;
 
-        let header = if exists && file.metadata()?.len() >= PAGE_SIZE as u64 {
+        let has_header = exists && file.metadata()?.len() >= PAGE_SIZE as u64;
+        if read_only && !has_header {
+            return Err(StorageError::invalid_db(
+                "a read-only database must already exist",
+            ));
+        }
+        let header = if has_header {
             // Read existing header
             let mut file_ref = &file;
-            let mut buf = vec![0u8; PAGE_SIZE];
-            file_ref.read_exact(&mut buf)?;
+            let mut buf = PageBuf::new();
+            file_ref.read_exact(buf.as_bytes_mut())?;
             FileHeader::read(&buf)?
         } else {
             // Create new database
             let header = FileHeader::new();
-            let mut buf = vec![0u8; PAGE_SIZE];
+            let mut buf = PageBuf::new();
             header.write(&mut buf);
 
             let mut file_ref = &file;
@@ -83,29 +191,392 @@ impl DiskManagerImpl {
             header
         };
 
-        Ok(Self {
+        let dm = Self {
             file: RwLock::new(file),
             header: RwLock::new(header),
             free_list: RwLock::new(FreeList::new()),
             sync_on_write,
-        })
+            retry_policy: RetryPolicy::default(),
+            io_counters: IoCounters::default(),
+            defer_header: false,
+            direct_io,
+            paranoid: false,
+            wal: None,
+            checksums: false,
+            compression: false,
+            read_only,
+        };
+        dm.load_free_list()?;
+        Ok(dm)
+    }
+
+    /// Rebuild the free list by following the chain from the header
+    ///
+    /// A chain that does not check out (a page outside the file or not a
+    /// free page, or a length other than `free_page_count`) was left by an
+    /// older version that did not keep the chain on disk. Its pages are
+    /// left unused and the list starts empty.
+    fn load_free_list(&self) -> Result<()> {
+        let header = self.header();
+        let chain = self.read_free_chain(&header)?;
+
+        let mut free_list = self.free_list.write();
+        let mut header = self.header.write();
+        match chain {
+            Some(pages) => *free_list = FreeList::from_chain(pages),
+            None => {
+                *free_list = FreeList::new();
+                header.first_free_page = PageId::new(0);
+                header.free_page_count = 0;
+            }
+        }
+        Ok(())
+    }
+
+    /// Follow the free page chain from the header, or `None` if it is not
+    /// a valid chain of `free_page_count` pages
+    fn read_free_chain(&self, header: &FileHeader) -> Result<Option<Vec<PageId>>> {
+        let mut pages = Vec::new();
+        let mut next = header.first_free_page;
+        while next.value() != 0 {
+            if next.value() >= header.page_count || pages.len() >= header.free_page_count as usize {
+                return Ok(None);
+            }
+            let buf = match self.read_page(next) {
+                Ok(buf) => buf,
+                Err(StorageError::Corruption(_)) => return Ok(None),
+                Err(e) => return Err(e),
+            };
+            let Ok(following) = SlottedPage::from_bytes(&buf).and_then(|page| page.free_next())
+            else {
+                return Ok(None);
+            };
+            pages.push(next);
+            next = following;
+        }
+        Ok((pages.len() == header.free_page_count as usize).then_some(pages))
+    }
+
+    /// Write the free list to disk as a chain of free pages
+    fn write_free_chain(&self, pages: &[PageId]) -> Result<()> {
+        for (i, &page_id) in pages.iter().enumerate() {
+            let next = pages.get(i + 1).copied().unwrap_or(PageId::new(0));
+            self.write_page(page_id, SlottedPage::new_free(next).as_bytes())?;
+        }
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    fn set_direct_flags(options: &mut OpenOptions) -> Result<()> {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.custom_flags(libc::O_DIRECT);
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn set_direct_flags(_options: &mut OpenOptions) -> Result<()> {
+        Err(StorageError::invalid_operation(
+            "direct I/O is not supported on this platform",
+        ))
+    }
+
+    /// Check whether the file was opened for direct I/O
+    pub fn is_direct_io(&self) -> bool {
+        self.direct_io
+    }
+
+    /// Check whether the file was opened read-only
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Fail with `InvalidOperation` if the file was opened read-only
+    fn check_writable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(StorageError::invalid_operation("read-only"));
+        }
+        Ok(())
+    }
+
+    /// Set how transient I/O errors are retried
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Keep header changes (root, height, page counts) in memory until
+    /// `sync`, instead of writing the header on every change
+    ///
+    /// Lets callers write data pages before the metadata that refers to
+    /// them. Changes not yet synced are lost if the process exits.
+    pub fn with_deferred_header(mut self, deferred: bool) -> Self {
+        self.defer_header = deferred;
+        self
+    }
+
+    /// Read back and validate every page right after writing it
+    ///
+    /// A page that does not read back byte for byte, or does not decode as
+    /// a valid page, fails the write with `Corruption`. Doubles the I/O of
+    /// every write; meant for debugging the storage layer.
+    pub fn with_paranoid(mut self, enabled: bool) -> Self {
+        self.paranoid = enabled;
+        self
+    }
+
+    /// Send page writes to a write-ahead log at `log_path` instead of the
+    /// database file
+    ///
+    /// `sync_data` commits the pages logged so far together with the current
+    /// header; `sync` checkpoints them into the database file and empties
+    /// the log. If the log was not checkpointed when the database was last
+    /// closed, its committed pages are checkpointed first and anything
+    /// logged after the last commit is discarded.
+    pub fn with_wal(mut self, log_path: &Path) -> Result<Self> {
+        let (mut wal, committed) = Wal::open(log_path)?;
+        if let Some(header) = committed {
+            *self.header.write() = header;
+            self.checkpoint(&mut wal)?;
+            self.load_free_list()?;
+        }
+        self.wal = Some(Mutex::new(wal));
+        Ok(self)
+    }
+
+    /// Check whether page writes go to a write-ahead log
+    pub fn has_wal(&self) -> bool {
+        self.wal.is_some()
+    }
+
+    /// Copy every committed page from the log into the database file,
+    /// write the header and empty the log
+    fn checkpoint(&self, wal: &mut Wal) -> Result<()> {
+        let mut buf = PageBuf::new();
+        for (page_id, offset) in wal.pages() {
+            wal.read_at(offset, &mut buf)?;
+            let offset = page_id.file_offset(PAGE_SIZE);
+            self.with_file(|file| {
+                file.seek(SeekFrom::Start(offset))?;
+                file.write_all(&buf)
+            })?;
+        }
+        self.flush_header()?;
+        self.with_file(|file| file.sync_all())?;
+        wal.reset()
+    }
+
+    /// Give every written page a checksum and check it on every read
+    ///
+    /// Pages written without a checksum, e.g. by an older version, are read
+    /// unchecked and gain one when next written, if they have four bytes
+    /// to spare. A mismatch fails the read with `Corruption`.
+    pub fn with_checksums(mut self, enabled: bool) -> Self {
+        self.checksums = enabled;
+        self
+    }
+
+    /// Compress leaf pages as they are written
+    ///
+    /// A page that would not shrink is written as it is. Compressed pages
+    /// are recognized and expanded on read whether or not this is on, so
+    /// files can mix both kinds and the setting can change between opens.
+    pub fn with_compression(mut self, enabled: bool) -> Self {
+        self.compression = enabled;
+        self
+    }
+
+    /// Compress a leaf page for writing, if compression is on and helps
+    fn compress(&self, data: &[u8]) -> Option<PageBuf> {
+        let is_leaf = PageHeader::read(data).is_some_and(|h| h.page_type.is_leaf());
+        if !self.compression || !is_leaf {
+            return None;
+        }
+        compression::compress_page(data)
+    }
+
+    /// Stamp a page's checksum before it is written
+    ///
+    /// Pages that already carry a checksum are always restamped, so it
+    /// stays valid even when checksums are off. Returns `None` for data
+    /// that is written as it is.
+    fn seal(&self, data: &[u8]) -> Result<Option<SlottedPage>> {
+        let Some(header) = PageHeader::read(data) else {
+            return Ok(None);
+        };
+        if !header.has_checksum() && !self.checksums {
+            return Ok(None);
+        }
+
+        let mut page = SlottedPage::from_bytes(data)?;
+        match page.enable_checksum() {
+            Err(StorageError::PageFull { .. }) => return Ok(None),
+            result => result?,
+        }
+        if !page.has_checksum() {
+            return Ok(None);
+        }
+        page.update_checksum();
+        Ok(Some(page))
+    }
+
+    /// Check a page just read against its checksum, if checksums are on
+    fn check_checksum(&self, page_id: PageId, buf: &PageBuf) -> Result<()> {
+        if !self.checksums || !PageHeader::read(buf).is_some_and(|h| h.has_checksum()) {
+            return Ok(());
+        }
+        SlottedPage::from_bytes(buf)?
+            .verify_checksum()
+            .map_err(|e| match e {
+                StorageError::Corruption(msg) => {
+                    StorageError::corruption(format!("page {}: {}", page_id, msg))
+                }
+                e => e,
+            })
+    }
+
+    /// Check that a page just written reads back unchanged and decodes
+    fn verify_write(&self, page_id: PageId, data: &[u8]) -> Result<()> {
+        let read_back = self.read_page(page_id)?;
+        if read_back.as_bytes() != data {
+            return Err(StorageError::corruption(format!(
+                "page {} read back differently than it was written",
+                page_id
+            )));
+        }
+        SlottedPage::try_decode(&read_back).map_err(|e| {
+            StorageError::corruption(format!("page {} written invalid: {}", page_id, e))
+        })?;
+        Ok(())
+    }
+
+    /// Overwrite the file header of a database without opening it
+    ///
+    /// For repairing a file whose header is too damaged to open. The header
+    /// is checked against the file length first.
+    pub fn restore_header(path: &Path, header: &FileHeader) -> Result<()> {
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        check_metadata(header, &[], file.metadata()?.len())?;
+
+        // The free pages may have been reused since the header was saved
+        let mut header = *header;
+        header.first_free_page = PageId::new(0);
+        header.free_page_count = 0;
+
+        let mut buf = PageBuf::new();
+        header.write(&mut buf);
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&buf)?;
+        file.sync_all()?;
+        Ok(())
+    }
+
+    /// Rebuild the file header of a database from its data pages
+    ///
+    /// Best-effort repair for a file whose header no longer checks out. The
+    /// page count comes from the file length. Tree pages no interior page
+    /// points at are the candidate roots: a leaf of 8-byte entries naming
+    /// other candidates is taken as the catalog of named trees, and the
+    /// tallest remaining candidate, then the largest, as the main root.
+    /// Free pages are left out of the new header and stay unused. Returns
+    /// the header written.
+    pub fn recover_header(path: &Path, key_order: KeyOrder) -> Result<FileHeader> {
+        let mut file = File::open(path)?;
+        let page_count = (file.metadata()?.len() / PAGE_SIZE as u64) as u32;
+
+        let mut nodes = HashMap::new();
+        let mut buf = PageBuf::new();
+        for id in 1..page_count {
+            let page_id = PageId::new(id);
+            file.seek(SeekFrom::Start(u64::from(id) * PAGE_SIZE as u64))?;
+            file.read_exact(buf.as_bytes_mut())?;
+            if compression::decompress_page(page_id, &mut buf).is_err() {
+                continue;
+            }
+            let page = SlottedPage::try_decode(&buf).ok();
+            if let Some(node) = page.as_ref().and_then(RecoveredNode::from_page) {
+                nodes.insert(page_id, node);
+            }
+        }
+
+        let referenced: HashSet<PageId> = nodes
+            .values()
+            .flat_map(|node| match node {
+                RecoveredNode::Interior(children) => children.as_slice(),
+                RecoveredNode::Leaf { .. } => &[],
+            })
+            .copied()
+            .collect();
+        let mut candidates: Vec<PageId> =
+            nodes.keys().filter(|id| !referenced.contains(id)).copied().collect();
+        candidates.sort();
+
+        let catalog = candidates.iter().copied().find_map(|id| match &nodes[&id] {
+            RecoveredNode::Leaf { catalog_roots: Some(roots), .. }
+                if roots.iter().all(|r| *r != id && candidates.contains(r)) =>
+            {
+                Some((id, roots.clone()))
+            }
+            _ => None,
+        });
+        let (catalog_page, named_roots) = catalog.unwrap_or((PageId::new(0), Vec::new()));
+
+        let mut root = (PageId::new(0), 0, 0, 0);
+        for &id in &candidates {
+            if id == catalog_page || named_roots.contains(&id) {
+                continue;
+            }
+            let (height, pages, entries) = RecoveredNode::measure(&nodes, id, &mut HashSet::new());
+            if (height, pages) > (root.1, root.2) {
+                root = (id, height, pages, entries);
+            }
+        }
+
+        let header = FileHeader {
+            page_count,
+            root_page: root.0,
+            tree_height: root.1,
+            catalog_page,
+            entry_count: root.3,
+            key_order,
+            ..FileHeader::new()
+        };
+        Self::restore_header(path, &header)?;
+        Ok(header)
+    }
+
+    /// Run a file operation under the retry policy
+    ///
+    /// The operation must be repeatable from the start, e.g. seek then
+    /// read or write.
+    fn with_file<T>(&self, mut op: impl FnMut(&mut File) -> std::io::Result<T>) -> Result<T> {
+        let mut file = self.file.write();
+        self.io_counters.run(&self.retry_policy, || op(&mut file))
     }
 
     /// Flush the header to disk
     fn flush_header(&self) -> Result<()> {
         let header = self.header.read();
-        let mut buf = vec![0u8; PAGE_SIZE];
+        let mut buf = PageBuf::new();
         header.write(&mut buf);
 
-        let mut file = self.file.write();
-        file.seek(SeekFrom::Start(0))?;
-        file.write_all(&buf)?;
+        self.with_file(|file| {
+            file.seek(SeekFrom::Start(0))?;
+            file.write_all(&buf)?;
+            if self.sync_on_write {
+                file.sync_data()?;
+            }
+            Ok(())
+        })
+    }
 
-        if self.sync_on_write {
-            file.sync_data()?;
+    /// Persist a header change unless header writes are deferred
+    ///
+    /// With a write-ahead log the header is written at commit instead.
+    fn header_changed(&self) -> Result<()> {
+        if self.defer_header || self.wal.is_some() {
+            return Ok(());
         }
-
-        Ok(())
+        self.flush_header()
     }
 }
 
@@ -124,16 +595,26 @@ impl DiskManager for DiskManagerImpl {
         drop(header);
 
         let offset = page_id.file_offset(PAGE_SIZE);
-        let mut buf = vec![0u8; PAGE_SIZE];
+        let mut buf = PageBuf::new();
 
-        let mut file = self.file.write();
-        file.seek(SeekFrom::Start(offset))?;
-        file.read_exact(&mut buf)?;
+        let logged = match &self.wal {
+            Some(wal) => wal.lock().read_page(page_id, &mut buf)?,
+            None => false,
+        };
+        if !logged {
+            self.with_file(|file| {
+                file.seek(SeekFrom::Start(offset))?;
+                file.read_exact(buf.as_bytes_mut())
+            })?;
+        }
 
-        Ok(PageBuf::from_bytes(&buf))
+        compression::decompress_page(page_id, &mut buf)?;
+        self.check_checksum(page_id, &buf)?;
+        Ok(buf)
     }
 
     fn write_page(&self, page_id: PageId, data: &[u8]) -> Result<()> {
+        self.check_writable()?;
         if page_id.value() == 0 {
             return Err(StorageError::invalid_operation(
                 "cannot write header page directly",
@@ -150,22 +631,53 @@ impl DiskManager for DiskManagerImpl {
 
         let offset = page_id.file_offset(PAGE_SIZE);
 
-        let mut file = self.file.write();
-        file.seek(SeekFrom::Start(offset))?;
-        file.write_all(data)?;
+        let sealed = self.seal(data)?;
+        let data = sealed.as_ref().map_or(data, |page| page.as_bytes());
+        let compressed = self.compress(data);
+        let stored = compressed.as_ref().map_or(data, |buf| buf.as_bytes());
 
-        if self.sync_on_write {
-            file.sync_data()?;
+        // Direct I/O needs an aligned source buffer
+        let aligned;
+        let stored = if self.direct_io && !(stored.as_ptr() as usize).is_multiple_of(PAGE_SIZE) {
+            aligned = PageBuf::from_bytes(stored);
+            aligned.as_bytes()
+        } else {
+            stored
+        };
+
+        if let Some(wal) = &self.wal {
+            wal.lock().append_page(page_id, stored)?;
+        } else {
+            self.with_file(|file| {
+                file.seek(SeekFrom::Start(offset))?;
+                file.write_all(stored)?;
+                if self.sync_on_write {
+                    file.sync_data()?;
+                }
+                Ok(())
+            })?;
         }
 
+        if self.paranoid {
+            self.verify_write(page_id, data)?;
+        }
         Ok(())
     }
 
     fn allocate_page(&self) -> Result<PageId> {
+        self.check_writable()?;
+
         // First try the free list
         {
             let mut free_list = self.free_list.write();
             if let Some(page_id) = free_list.pop() {
+                {
+                    let mut header = self.header.write();
+                    header.first_free_page = free_list.head().unwrap_or(PageId::new(0));
+                    header.free_page_count = free_list.len() as u32;
+                }
+                drop(free_list);
+                self.header_changed()?;
                 return Ok(page_id);
             }
         }
@@ -178,27 +690,31 @@ impl DiskManager for DiskManagerImpl {
 
         // Extend the file
         let offset = page_id.file_offset(PAGE_SIZE);
-        let zeros = vec![0u8; PAGE_SIZE];
+        let zeros = PageBuf::new();
 
-        let mut file = self.file.write();
-        file.seek(SeekFrom::Start(offset))?;
-        file.write_all(&zeros)?;
+        self.with_file(|file| {
+            file.seek(SeekFrom::Start(offset))?;
+            file.write_all(&zeros)
+        })?;
 
         // Update header on disk
-        drop(file);
-        self.flush_header()?;
+        self.header_changed()?;
 
         Ok(page_id)
     }
 
     fn deallocate_page(&self, page_id: PageId) -> Result<()> {
+        self.check_writable()?;
         if page_id.value() == 0 {
             return Err(StorageError::invalid_operation(
                 "cannot deallocate header page",
             ));
         }
 
+        // Link the page into the chain on disk before the header points at it
         let mut free_list = self.free_list.write();
+        let next = free_list.head().unwrap_or(PageId::new(0));
+        self.write_page(page_id, SlottedPage::new_free(next).as_bytes())?;
         free_list.push(page_id);
 
         // Update header
@@ -208,16 +724,28 @@ impl DiskManager for DiskManagerImpl {
             header.first_free_page = page_id;
         }
 
-        self.flush_header()?;
+        self.header_changed()?;
 
         Ok(())
     }
 
     fn sync(&self) -> Result<()> {
+        self.check_writable()?;
+        if let Some(wal) = &self.wal {
+            let mut wal = wal.lock();
+            wal.commit(&self.header())?;
+            return self.checkpoint(&mut wal);
+        }
         self.flush_header()?;
-        let file = self.file.write();
-        file.sync_all()?;
-        Ok(())
+        self.with_file(|file| file.sync_all())
+    }
+
+    fn sync_data(&self) -> Result<()> {
+        self.check_writable()?;
+        if let Some(wal) = &self.wal {
+            return wal.lock().commit(&self.header());
+        }
+        self.with_file(|file| file.sync_data())
     }
 
     fn header(&self) -> FileHeader {
@@ -225,13 +753,192 @@ impl DiskManager for DiskManagerImpl {
     }
 
     fn set_root_page(&self, page_id: PageId, height: u32) -> Result<()> {
+        self.check_writable()?;
         {
             let mut header = self.header.write();
             header.root_page = page_id;
             header.tree_height = height;
         }
-        self.flush_header()
+        self.header_changed()
+    }
+
+    fn set_entry_count(&self, count: u64) -> Result<()> {
+        self.check_writable()?;
+        self.header.write().entry_count = count;
+        Ok(())
+    }
+
+    fn set_catalog_page(&self, page_id: PageId) -> Result<()> {
+        self.check_writable()?;
+        self.header.write().catalog_page = page_id;
+        self.header_changed()
+    }
+
+    fn set_key_order(&self, order: KeyOrder) -> Result<()> {
+        self.check_writable()?;
+        self.header.write().key_order = order;
+        self.header_changed()
+    }
+
+    fn io_stats(&self) -> IoStats {
+        self.io_counters.stats()
+    }
+
+    fn free_pages(&self) -> Vec<PageId> {
+        self.free_list.read().page_ids().collect()
+    }
+
+    fn allocated_pages(&self) -> Box<dyn Iterator<Item = PageId> + '_> {
+        // Read the free list and page count together, so a page moving
+        // between them is not missed or listed twice
+        let free_list = self.free_list.read();
+        let free: HashSet<PageId> = free_list.page_ids().collect();
+        let page_count = self.header.read().page_count;
+        drop(free_list);
+        Box::new((1..page_count).map(PageId::new).filter(move |id| !free.contains(id)))
+    }
+
+    fn restore_metadata(&self, header: FileHeader, free_pages: &[PageId]) -> Result<()> {
+        self.check_writable()?;
+        let file_len = self.with_file(|file| file.metadata().map(|m| m.len()))?;
+        check_metadata(&header, free_pages, file_len)?;
+
+        {
+            let mut free_list = self.free_list.write();
+            *free_list = FreeList::from_chain(free_pages.iter().copied());
+            let mut header = header;
+            header.first_free_page = free_list.head().unwrap_or(PageId::new(0));
+            header.free_page_count = free_list.len() as u32;
+            *self.header.write() = header;
+        }
+        self.write_free_chain(free_pages)?;
+        self.sync()
+    }
+}
+
+/// A tree page as `recover_header` sees it
+enum RecoveredNode {
+    /// Interior page with its children
+    Interior(Vec<PageId>),
+    /// Leaf page with its cell count, and the pages its entries name if it
+    /// could be the catalog
+    Leaf {
+        cells: u64,
+        catalog_roots: Option<Vec<PageId>>,
+    },
+}
+
+impl RecoveredNode {
+    /// Summarize a decoded page, or `None` if it is not a tree page
+    fn from_page(page: &SlottedPage) -> Option<Self> {
+        if page.is_interior() {
+            let mut children = vec![page.right_child()];
+            children.extend(page.get_all_cells().ok()?.iter().map(|c| c.left_child));
+            return Some(Self::Interior(children));
+        }
+        if !page.is_leaf() {
+            return None;
+        }
+        let catalog_roots = (page.cell_count() > 0)
+            .then(|| {
+                (0..page.cell_count())
+                    .map(|i| match page.get_leaf_entry(i) {
+                        Ok((_, value)) if value.len() == 8 => {
+                            Some(PageId::from_be_bytes(value[..4].try_into().unwrap()))
+                        }
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>()
+            })
+            .flatten()
+            .map(|roots| roots.into_iter().filter(|r| r.value() != 0).collect());
+        Some(Self::Leaf {
+            cells: page.cell_count() as u64,
+            catalog_roots,
+        })
+    }
+
+    /// Get the height, page count and entry count of the tree under `id`
+    fn measure(
+        nodes: &HashMap<PageId, Self>,
+        id: PageId,
+        seen: &mut HashSet<PageId>,
+    ) -> (u32, usize, u64) {
+        if !seen.insert(id) {
+            return (0, 0, 0);
+        }
+        match nodes.get(&id) {
+            None => (0, 0, 0),
+            Some(Self::Leaf { cells, .. }) => (1, 1, *cells),
+            Some(Self::Interior(children)) => {
+                let mut total = (0, 1, 0);
+                for (i, &child) in children.iter().enumerate() {
+                    let (height, pages, entries) = Self::measure(nodes, child, seen);
+                    if i == 0 {
+                        total.0 = height + 1;
+                    }
+                    total.1 += pages;
+                    total.2 += entries;
+                }
+                total
+            }
+        }
+    }
+}
+
+/// Check that metadata is consistent with itself and a file of `file_len`
+/// bytes
+fn check_metadata(header: &FileHeader, free_pages: &[PageId], file_len: u64) -> Result<()> {
+    let page_count = header.page_count;
+    if page_count == 0 {
+        return Err(StorageError::invalid_db("page count must include the header page"));
+    }
+    if u64::from(page_count) * PAGE_SIZE as u64 > file_len {
+        return Err(StorageError::invalid_db(format!(
+            "page count {} exceeds the {}-byte file",
+            page_count, file_len
+        )));
+    }
+    if header.root_page.value() >= page_count {
+        return Err(StorageError::invalid_db(format!(
+            "root page {} is past the page count {}",
+            header.root_page, page_count
+        )));
+    }
+    if header.catalog_page.value() >= page_count
+        || (header.catalog_page.value() != 0 && header.catalog_page == header.root_page)
+    {
+        return Err(StorageError::invalid_db(format!(
+            "catalog page {} is past the page count {} or is the root",
+            header.catalog_page, page_count
+        )));
+    }
+    if (header.root_page.value() == 0) != (header.tree_height == 0) {
+        return Err(StorageError::invalid_db(format!(
+            "root page {} does not match tree height {}",
+            header.root_page, header.tree_height
+        )));
     }
+
+    let mut seen = std::collections::HashSet::new();
+    for &page_id in free_pages {
+        if page_id.value() == 0 || page_id.value() >= page_count {
+            return Err(StorageError::invalid_db(format!(
+                "free page {} is outside the file",
+                page_id
+            )));
+        }
+        if page_id == header.root_page
+            || page_id == header.catalog_page
+            || !seen.insert(page_id)
+        {
+            return Err(StorageError::invalid_db(format!(
+                "free page {} is listed twice or in use as the root or catalog",
+                page_id
+            )));
+        }
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -270,6 +977,7 @@ mod tests {
 
         let read_data = dm.read_page(page_id)?;
         assert_eq!(&read_data[0..5], b"hello");
+        assert_eq!(dm.io_stats(), IoStats::default());
 
         Ok(())
     }
@@ -303,6 +1011,77 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_deferred_header() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+
+        {
+            let dm = DiskManagerImpl::open(&path, false)?.with_deferred_header(true);
+            let page_id = dm.allocate_page()?;
+            dm.write_page(page_id, &vec![0u8; PAGE_SIZE])?;
+            dm.set_root_page(page_id, 1)?;
+            dm.sync_data()?;
+        }
+        assert_eq!(DiskManagerImpl::open(&path, false)?.header().page_count, 1);
+
+        {
+            let dm = DiskManagerImpl::open(&path, false)?.with_deferred_header(true);
+            let page_id = dm.allocate_page()?;
+            dm.set_root_page(page_id, 1)?;
+            dm.sync()?;
+        }
+        let header = DiskManagerImpl::open(&path, false)?.header();
+        assert_eq!(header.page_count, 2);
+        assert_eq!(header.root_page, PageId::new(1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_direct_io() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+
+        let dm = match DiskManagerImpl::open_direct(&path, false) {
+            Ok(dm) => dm,
+            // Platform or file system without O_DIRECT
+            Err(_) => return Ok(()),
+        };
+        assert!(dm.is_direct_io());
+
+        let page_id = dm.allocate_page()?;
+        // Unaligned source buffer
+        let mut data = vec![0u8; PAGE_SIZE + 1];
+        data[1..6].copy_from_slice(b"hello");
+        dm.write_page(page_id, &data[1..])?;
+        dm.sync()?;
+
+        let read_data = dm.read_page(page_id)?;
+        assert_eq!(&read_data[0..5], b"hello");
+        assert!((read_data.as_ptr() as usize).is_multiple_of(PAGE_SIZE));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_paranoid_writes() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+
+        let dm = DiskManagerImpl::open(&path, false)?.with_paranoid(true);
+        let page_id = dm.allocate_page()?;
+        dm.write_page(page_id, SlottedPage::new_leaf().as_bytes())?;
+
+        // A leaf claiming more cells than fit on the page
+        let mut data = SlottedPage::new_leaf().as_bytes().to_vec();
+        data[3..5].copy_from_slice(&4000u16.to_be_bytes());
+        let err = dm.write_page(page_id, &data).unwrap_err();
+        assert!(matches!(err, StorageError::Corruption(_)));
+
+        Ok(())
+    }
+
     #[test]
     fn test_free_list() -> Result<()> {
         let dir = tempdir().unwrap();
@@ -327,4 +1106,58 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_allocated_pages_skips_free_ones() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let dm = DiskManagerImpl::open(&dir.path().join("test.db"), false)?;
+        assert_eq!(dm.allocated_pages().count(), 0);
+
+        let pages: Vec<PageId> = (0..5).map(|_| dm.allocate_page()).collect::<Result<_>>()?;
+        dm.deallocate_page(pages[2])?;
+
+        let live: Vec<PageId> = dm.allocated_pages().collect();
+        assert_eq!(live, vec![pages[0], pages[1], pages[3], pages[4]]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_free_list_survives_reopen() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+
+        {
+            let dm = DiskManagerImpl::open(&path, false)?;
+            for _ in 0..4 {
+                dm.allocate_page()?;
+            }
+            dm.deallocate_page(PageId::new(2))?;
+            dm.deallocate_page(PageId::new(4))?;
+            dm.sync()?;
+        }
+
+        let dm = DiskManagerImpl::open(&path, false)?;
+        let header = dm.header();
+        assert_eq!(header.free_page_count, 2);
+        assert_eq!(header.first_free_page, PageId::new(4));
+        assert_eq!(dm.free_pages(), vec![PageId::new(4), PageId::new(2)]);
+
+        assert_eq!(dm.allocate_page()?, PageId::new(4));
+        assert_eq!(dm.header().first_free_page, PageId::new(2));
+        assert_eq!(dm.allocate_page()?, PageId::new(2));
+        assert_eq!(dm.header().free_page_count, 0);
+        assert_eq!(dm.allocate_page()?, PageId::new(5));
+
+        // A header pointing at a page that is not free is not trusted
+        dm.write_page(PageId::new(1), SlottedPage::new_leaf().as_bytes())?;
+        dm.header.write().first_free_page = PageId::new(1);
+        dm.header.write().free_page_count = 1;
+        dm.sync()?;
+        drop(dm);
+        let dm = DiskManagerImpl::open(&path, false)?;
+        assert_eq!(dm.header().free_page_count, 0);
+        assert!(dm.free_pages().is_empty());
+
+        Ok(())
+    }
 }
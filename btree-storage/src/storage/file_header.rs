@@ -4,7 +4,7 @@
 //! about the database.
 
 use crate::error::{Result, StorageError};
-use crate::types::{PageId, PAGE_SIZE};
+use crate::types::{KeyOrder, PageId, PAGE_SIZE};
 
 /// Magic bytes to identify a valid database file
 pub const MAGIC: &[u8; 16] = b"BTreeStorageV01\0";
@@ -25,7 +25,15 @@ pub const FILE_HEADER_SIZE: usize = PAGE_SIZE;
 /// 32      4     Root page ID of the main B-tree
 /// 36      4     Tree height
 /// 40      4     Checksum of header (CRC32)
+/// 44      4     Catalog page ID of the named trees (0 if none)
+/// 48      8     Number of entries in the main B-tree
+/// 56      1     Key order of the trees (`KeyOrder` as a byte)
 /// ```
+///
+/// The checksum covers bytes 0..40, and the catalog page ID, entry count
+/// and key order too when they are set, so files written before those
+/// fields existed still verify. Such files read an entry count of 0 and
+/// bytewise key order.
 #[derive(Debug, Clone, Copy)]
 pub struct FileHeader {
     /// Page size in bytes
@@ -40,6 +48,12 @@ pub struct FileHeader {
     pub root_page: PageId,
     /// Height of the B-tree
     pub tree_height: u32,
+    /// Page mapping tree names to their roots (0 if no named trees)
+    pub catalog_page: PageId,
+    /// Number of entries in the main B-tree
+    pub entry_count: u64,
+    /// Order every tree in the file sorts its keys in
+    pub key_order: KeyOrder,
 }
 
 impl FileHeader {
@@ -52,12 +66,15 @@ impl FileHeader {
             free_page_count: 0,
             root_page: PageId::new(0), // No root yet
             tree_height: 0,
+            catalog_page: PageId::new(0),
+            entry_count: 0,
+            key_order: KeyOrder::Bytewise,
         }
     }
 
     /// Read a file header from bytes
     pub fn read(bytes: &[u8]) -> Result<Self> {
-        if bytes.len() < 44 {
+        if bytes.len() < 57 {
             return Err(StorageError::invalid_db("header too short"));
         }
 
@@ -72,21 +89,28 @@ impl FileHeader {
         let free_page_count = u32::from_be_bytes([bytes[28], bytes[29], bytes[30], bytes[31]]);
         let root_page = u32::from_be_bytes([bytes[32], bytes[33], bytes[34], bytes[35]]);
         let tree_height = u32::from_be_bytes([bytes[36], bytes[37], bytes[38], bytes[39]]);
+        let catalog_page = u32::from_be_bytes([bytes[44], bytes[45], bytes[46], bytes[47]]);
+        let entry_count = u64::from_be_bytes(bytes[48..56].try_into().unwrap());
+        let key_order = bytes[56];
 
         // Verify checksum
         let stored_checksum = u32::from_be_bytes([bytes[40], bytes[41], bytes[42], bytes[43]]);
-        let computed_checksum = crc32fast::hash(&bytes[0..40]);
+        let computed_checksum = Self::checksum(bytes);
         if stored_checksum != computed_checksum {
             return Err(StorageError::corruption("header checksum mismatch"));
         }
 
         if page_size != PAGE_SIZE as u32 {
-            return Err(StorageError::invalid_db(format!(
-                "unsupported page size: {} (expected {})",
-                page_size, PAGE_SIZE
-            )));
+            return Err(StorageError::IncompatiblePageSize {
+                found: page_size,
+                expected: PAGE_SIZE as u32,
+            });
         }
 
+        let key_order = KeyOrder::from_byte(key_order).ok_or_else(|| {
+            StorageError::invalid_db(format!("unknown key order: {}", key_order))
+        })?;
+
         Ok(Self {
             page_size,
             page_count,
@@ -94,9 +118,28 @@ impl FileHeader {
             free_page_count,
             root_page: PageId::new(root_page),
             tree_height,
+            catalog_page: PageId::new(catalog_page),
+            entry_count,
+            key_order,
         })
     }
 
+    /// Compute the header checksum over the fields it covers
+    fn checksum(bytes: &[u8]) -> u32 {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&bytes[0..40]);
+        if bytes[44..48] != [0; 4] {
+            hasher.update(&bytes[44..48]);
+        }
+        if bytes[48..56] != [0; 8] {
+            hasher.update(&bytes[48..56]);
+        }
+        if bytes[56] != 0 {
+            hasher.update(&bytes[56..57]);
+        }
+        hasher.finalize()
+    }
+
     /// Write this header to bytes
     pub fn write(&self, bytes: &mut [u8]) {
         // Clear the page first
@@ -112,9 +155,12 @@ impl FileHeader {
         bytes[28..32].copy_from_slice(&self.free_page_count.to_be_bytes());
         bytes[32..36].copy_from_slice(&self.root_page.value().to_be_bytes());
         bytes[36..40].copy_from_slice(&self.tree_height.to_be_bytes());
+        bytes[44..48].copy_from_slice(&self.catalog_page.value().to_be_bytes());
+        bytes[48..56].copy_from_slice(&self.entry_count.to_be_bytes());
+        bytes[56] = self.key_order as u8;
 
         // Checksum
-        let checksum = crc32fast::hash(&bytes[0..40]);
+        let checksum = Self::checksum(bytes);
         bytes[40..44].copy_from_slice(&checksum.to_be_bytes());
     }
 
@@ -145,6 +191,9 @@ mod tests {
             free_page_count: 5,
             root_page: PageId::new(1),
             tree_height: 3,
+            catalog_page: PageId::new(7),
+            entry_count: 1234,
+            key_order: KeyOrder::Reverse,
         };
 
         let mut bytes = vec![0u8; FILE_HEADER_SIZE];
@@ -157,6 +206,9 @@ mod tests {
         assert_eq!(restored.free_page_count, header.free_page_count);
         assert_eq!(restored.root_page, header.root_page);
         assert_eq!(restored.tree_height, header.tree_height);
+        assert_eq!(restored.catalog_page, header.catalog_page);
+        assert_eq!(restored.entry_count, header.entry_count);
+        assert_eq!(restored.key_order, header.key_order);
     }
 
     #[test]
@@ -1,8 +1,9 @@
 //! Free list management.
 //!
 //! Tracks deallocated pages that can be reused for new allocations.
-//! In v1, we use a simple in-memory list. A production implementation
-//! would persist free page information to disk.
+//! On disk the list is a chain of free pages, each pointing at the next,
+//! starting from the file header's `first_free_page`. The most recently
+//! freed page is at the head and is reused first.
 
 use crate::types::PageId;
 use std::collections::VecDeque;
@@ -10,7 +11,7 @@ use std::collections::VecDeque;
 /// Manages free pages for reuse
 #[derive(Debug, Default)]
 pub struct FreeList {
-    /// Queue of free page IDs
+    /// Free page IDs in chain order, head first
     pages: VecDeque<PageId>,
 }
 
@@ -22,9 +23,16 @@ impl FreeList {
         }
     }
 
-    /// Add a page to the free list
+    /// Create a free list from page IDs in chain order, head first
+    pub fn from_chain(pages: impl IntoIterator<Item = PageId>) -> Self {
+        Self {
+            pages: pages.into_iter().collect(),
+        }
+    }
+
+    /// Add a page at the head of the free list
     pub fn push(&mut self, page_id: PageId) {
-        self.pages.push_back(page_id);
+        self.pages.push_front(page_id);
     }
 
     /// Get a free page, if available
@@ -32,6 +40,11 @@ impl FreeList {
         self.pages.pop_front()
     }
 
+    /// Get the page at the head of the free list, if any
+    pub fn head(&self) -> Option<PageId> {
+        self.pages.front().copied()
+    }
+
     /// Get the number of free pages
     pub fn len(&self) -> usize {
         self.pages.len()
@@ -42,7 +55,7 @@ impl FreeList {
         self.pages.is_empty()
     }
 
-    /// Get all free page IDs (for persistence)
+    /// Get all free page IDs in chain order, head first
     pub fn page_ids(&self) -> impl Iterator<Item = PageId> + '_ {
         self.pages.iter().copied()
     }
@@ -61,9 +74,10 @@ mod tests {
         fl.push(PageId::new(5));
         fl.push(PageId::new(10));
         assert_eq!(fl.len(), 2);
+        assert_eq!(fl.head(), Some(PageId::new(10)));
 
-        assert_eq!(fl.pop(), Some(PageId::new(5)));
         assert_eq!(fl.pop(), Some(PageId::new(10)));
+        assert_eq!(fl.pop(), Some(PageId::new(5)));
         assert_eq!(fl.pop(), None);
     }
 }
@@ -0,0 +1,174 @@
+//! In-memory disk manager.
+//!
+//! Keeps every page in memory so the buffer pool and trees can be tested
+//! without touching the file system. Pages, the header and the free list
+//! behave as they do in `DiskManagerImpl`, but nothing outlives the value.
+
+use crate::error::{Result, StorageError};
+use crate::page::{PageBuf, SlottedPage};
+use crate::storage::{DiskManager, FileHeader, FreeList};
+use crate::types::{KeyOrder, PageId, PAGE_SIZE};
+use parking_lot::RwLock;
+
+/// Disk manager that keeps its pages in memory
+pub struct MemDiskManager {
+    /// Page contents, indexed by page ID; slot 0 stands in for the header
+    pages: RwLock<Vec<PageBuf>>,
+    /// The file header
+    header: RwLock<FileHeader>,
+    /// Free list for page reuse
+    free_list: RwLock<FreeList>,
+}
+
+impl MemDiskManager {
+    /// Create an empty in-memory database
+    pub fn new() -> Self {
+        Self {
+            pages: RwLock::new(vec![PageBuf::new()]),
+            header: RwLock::new(FileHeader::new()),
+            free_list: RwLock::new(FreeList::new()),
+        }
+    }
+}
+
+impl Default for MemDiskManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DiskManager for MemDiskManager {
+    fn read_page(&self, page_id: PageId) -> Result<PageBuf> {
+        if page_id.value() == 0 {
+            return Err(StorageError::invalid_operation(
+                "cannot read header page directly",
+            ));
+        }
+        self.pages
+            .read()
+            .get(page_id.value() as usize)
+            .cloned()
+            .ok_or(StorageError::PageNotFound(page_id))
+    }
+
+    fn write_page(&self, page_id: PageId, data: &[u8]) -> Result<()> {
+        if page_id.value() == 0 {
+            return Err(StorageError::invalid_operation(
+                "cannot write header page directly",
+            ));
+        }
+        if data.len() != PAGE_SIZE {
+            return Err(StorageError::invalid_operation(format!(
+                "page data must be {} bytes, got {}",
+                PAGE_SIZE,
+                data.len()
+            )));
+        }
+        let mut pages = self.pages.write();
+        let page = pages
+            .get_mut(page_id.value() as usize)
+            .ok_or(StorageError::PageNotFound(page_id))?;
+        page.copy_from_slice(data);
+        Ok(())
+    }
+
+    fn allocate_page(&self) -> Result<PageId> {
+        let mut free_list = self.free_list.write();
+        let mut header = self.header.write();
+        if let Some(page_id) = free_list.pop() {
+            header.first_free_page = free_list.head().unwrap_or(PageId::new(0));
+            header.free_page_count = free_list.len() as u32;
+            return Ok(page_id);
+        }
+
+        let page_id = header.allocate_page();
+        self.pages.write().push(PageBuf::new());
+        Ok(page_id)
+    }
+
+    fn deallocate_page(&self, page_id: PageId) -> Result<()> {
+        if page_id.value() == 0 {
+            return Err(StorageError::invalid_operation(
+                "cannot deallocate header page",
+            ));
+        }
+
+        let mut free_list = self.free_list.write();
+        let next = free_list.head().unwrap_or(PageId::new(0));
+        self.write_page(page_id, SlottedPage::new_free(next).as_bytes())?;
+        free_list.push(page_id);
+
+        let mut header = self.header.write();
+        header.free_page_count = free_list.len() as u32;
+        header.first_free_page = page_id;
+        Ok(())
+    }
+
+    fn sync(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn sync_data(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn header(&self) -> FileHeader {
+        *self.header.read()
+    }
+
+    fn set_root_page(&self, page_id: PageId, height: u32) -> Result<()> {
+        let mut header = self.header.write();
+        header.root_page = page_id;
+        header.tree_height = height;
+        Ok(())
+    }
+
+    fn set_entry_count(&self, count: u64) -> Result<()> {
+        self.header.write().entry_count = count;
+        Ok(())
+    }
+
+    fn set_catalog_page(&self, page_id: PageId) -> Result<()> {
+        self.header.write().catalog_page = page_id;
+        Ok(())
+    }
+
+    fn set_key_order(&self, order: KeyOrder) -> Result<()> {
+        self.header.write().key_order = order;
+        Ok(())
+    }
+
+    fn free_pages(&self) -> Vec<PageId> {
+        self.free_list.read().page_ids().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mem_allocate_and_reuse() -> Result<()> {
+        let dm = MemDiskManager::new();
+        let page1 = dm.allocate_page()?;
+        let page2 = dm.allocate_page()?;
+        assert_eq!(page1.value(), 1);
+        assert_eq!(page2.value(), 2);
+        assert_eq!(dm.header().page_count, 3);
+
+        let mut data = PageBuf::new();
+        data[0] = 0xAB;
+        dm.write_page(page2, &data)?;
+        assert_eq!(dm.read_page(page2)?[0], 0xAB);
+        assert!(dm.read_page(PageId::new(3)).is_err());
+        assert!(dm.read_page(PageId::new(0)).is_err());
+
+        dm.deallocate_page(page1)?;
+        assert_eq!(dm.free_pages(), vec![page1]);
+        assert_eq!(dm.header().free_page_count, 1);
+        assert_eq!(dm.allocate_page()?, page1);
+        assert_eq!(dm.header().first_free_page.value(), 0);
+        assert_eq!(dm.header().page_count, 3);
+        Ok(())
+    }
+}
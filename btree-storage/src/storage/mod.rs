@@ -1,12 +1,22 @@
 //! Storage layer: disk I/O and page management.
 //!
 //! This module provides abstractions for reading and writing pages to disk,
-//! managing the database file format, and tracking free pages.
+//! managing the database file format, tracking free pages, and logging
+//! page writes ahead of the database file.
 
+mod compression;
 mod disk_manager;
 mod file_header;
 mod freelist;
+#[cfg(any(test, feature = "mem"))]
+mod mem_disk_manager;
+mod retry;
+mod wal;
 
 pub use disk_manager::{DiskManager, DiskManagerImpl};
 pub use file_header::FileHeader;
 pub use freelist::FreeList;
+#[cfg(any(test, feature = "mem"))]
+pub use mem_disk_manager::MemDiskManager;
+pub use retry::{IoStats, RetryPolicy};
+pub use wal::wal_path;
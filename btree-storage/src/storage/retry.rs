@@ -0,0 +1,156 @@
+//! Retry policy for transient I/O errors.
+//!
+//! Interrupted (`EINTR`) and would-block (`EAGAIN`) errors are retried a
+//! bounded number of times before being reported. Any other error fails
+//! immediately.
+
+use crate::error::Result;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// How the disk manager retries transient I/O errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of retries per I/O operation
+    pub max_retries: u32,
+    /// Delay before each retry, multiplied by the attempt number
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            backoff: Duration::from_millis(1),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Create a policy that never retries
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            backoff: Duration::ZERO,
+        }
+    }
+
+    /// Set the maximum number of retries
+    pub fn max_retries(mut self, retries: u32) -> Self {
+        self.max_retries = retries;
+        self
+    }
+
+    /// Set the base delay between retries
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Check whether an error is worth retrying
+    pub fn is_retryable(err: &io::Error) -> bool {
+        matches!(
+            err.kind(),
+            io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock
+        )
+    }
+}
+
+/// Counters for I/O operations that needed retries or failed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IoStats {
+    /// Number of retries performed
+    pub retried: u64,
+    /// Number of I/O operations that failed after all retries
+    pub failed: u64,
+}
+
+/// Shared I/O counters, updated by `run`
+#[derive(Debug, Default)]
+pub(crate) struct IoCounters {
+    retried: AtomicU64,
+    failed: AtomicU64,
+}
+
+impl IoCounters {
+    /// Run an I/O operation under `policy`, counting retries and failures
+    pub(crate) fn run<T>(
+        &self,
+        policy: &RetryPolicy,
+        mut op: impl FnMut() -> io::Result<T>,
+    ) -> Result<T> {
+        let mut attempt = 0;
+        loop {
+            match op() {
+                Ok(value) => return Ok(value),
+                Err(e) if RetryPolicy::is_retryable(&e) && attempt < policy.max_retries => {
+                    attempt += 1;
+                    self.retried.fetch_add(1, Ordering::Relaxed);
+                    if !policy.backoff.is_zero() {
+                        std::thread::sleep(policy.backoff * attempt);
+                    }
+                }
+                Err(e) => {
+                    self.failed.fetch_add(1, Ordering::Relaxed);
+                    return Err(e.into());
+                }
+            }
+        }
+    }
+
+    /// Get a snapshot of the counters
+    pub(crate) fn stats(&self) -> IoStats {
+        IoStats {
+            retried: self.retried.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::StorageError;
+
+    fn flaky(failures: u32, kind: io::ErrorKind) -> impl FnMut() -> io::Result<u32> {
+        let mut calls = 0;
+        move || {
+            calls += 1;
+            if calls <= failures {
+                Err(io::Error::from(kind))
+            } else {
+                Ok(calls)
+            }
+        }
+    }
+
+    #[test]
+    fn test_transient_errors_are_retried() -> Result<()> {
+        let counters = IoCounters::default();
+        let policy = RetryPolicy::default().backoff(Duration::ZERO);
+
+        assert_eq!(counters.run(&policy, flaky(2, io::ErrorKind::Interrupted))?, 3);
+        assert_eq!(counters.run(&policy, flaky(1, io::ErrorKind::WouldBlock))?, 2);
+        assert_eq!(counters.stats(), IoStats { retried: 3, failed: 0 });
+
+        // Exhausting the budget reports the error
+        let result = counters.run(&policy, flaky(10, io::ErrorKind::Interrupted));
+        assert!(matches!(result, Err(StorageError::Io(_))));
+        assert_eq!(counters.stats(), IoStats { retried: 6, failed: 1 });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_persistent_errors_fail_immediately() {
+        let counters = IoCounters::default();
+        let result = counters.run(&RetryPolicy::default(), flaky(1, io::ErrorKind::PermissionDenied));
+        assert!(result.is_err());
+        assert_eq!(counters.stats(), IoStats { retried: 0, failed: 1 });
+
+        let result = counters.run(&RetryPolicy::none(), flaky(1, io::ErrorKind::Interrupted));
+        assert!(result.is_err());
+        assert_eq!(counters.stats(), IoStats { retried: 0, failed: 2 });
+    }
+}
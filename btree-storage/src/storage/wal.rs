@@ -0,0 +1,295 @@
+//! Write-ahead log.
+//!
+//! In WAL mode the disk manager appends page writes to the log instead of
+//! the database file, and commits by appending a copy of the file header.
+//! Pages reach the database file only at a checkpoint, which copies the
+//! latest image of every logged page across, writes the header, and
+//! truncates the log back to a single checkpoint record.
+//!
+//! A log holding anything past that record was not shut down cleanly. On
+//! open, the pages up to the last commit are recovered; a torn or
+//! uncommitted tail is discarded.
+//!
+//! Record layout:
+//! ```text
+//! Offset  Size  Description
+//! 0       1     Kind (1 = page, 2 = commit, 3 = checkpoint)
+//! 1       4     Page ID for page records, 0 otherwise
+//! 5       4     Payload length
+//! 9       n     Page image, or the file header for a commit
+//! 9+n     4     CRC32 of the kind, page ID, length and payload
+//! ```
+
+use crate::error::Result;
+use crate::page::PageBuf;
+use crate::storage::FileHeader;
+use crate::types::{PageId, PAGE_SIZE};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Record holding a page image
+const RECORD_PAGE: u8 = 1;
+
+/// Record holding the file header as of a commit
+const RECORD_COMMIT: u8 = 2;
+
+/// Record marking a log with nothing left to replay
+const RECORD_CHECKPOINT: u8 = 3;
+
+/// Size of the kind, page ID and length fields before the payload
+const RECORD_HEADER_SIZE: usize = 9;
+
+/// Bytes of the file header page kept in a commit record; covers every
+/// header field
+const COMMIT_HEADER_LEN: usize = 64;
+
+/// Get the path of the write-ahead log for a database file
+pub fn wal_path(db_path: &Path) -> PathBuf {
+    let mut path = db_path.as_os_str().to_owned();
+    path.push("-wal");
+    PathBuf::from(path)
+}
+
+/// An open write-ahead log
+pub(crate) struct Wal {
+    /// The log file
+    file: File,
+    /// Offset of the latest logged image of each page
+    index: HashMap<PageId, u64>,
+    /// Offset the next record is written at
+    end: u64,
+}
+
+/// A record read back from the log
+struct Record {
+    kind: u8,
+    page_id: PageId,
+    payload: Vec<u8>,
+}
+
+impl Wal {
+    /// Open or create the log at `path`
+    ///
+    /// Returns the header of the last commit if the log holds committed
+    /// pages that were never checkpointed; the caller must checkpoint them
+    /// before using the database file. Otherwise the log is reset.
+    pub(crate) fn open(path: &Path) -> Result<(Self, Option<FileHeader>)> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        let mut index = HashMap::new();
+        let mut pending = Vec::new();
+        let mut header = None;
+        let mut offset = 0u64;
+        let mut end = 0u64;
+
+        let mut reader = BufReader::new(&file);
+        while let Some(record) = Self::read_record(&mut reader)? {
+            let payload_offset = offset + RECORD_HEADER_SIZE as u64;
+            offset = payload_offset + record.payload.len() as u64 + 4;
+            match record.kind {
+                RECORD_PAGE if record.payload.len() == PAGE_SIZE => {
+                    pending.push((record.page_id, payload_offset));
+                }
+                RECORD_COMMIT => match FileHeader::read(&record.payload) {
+                    Ok(committed) => {
+                        index.extend(pending.drain(..));
+                        header = Some(committed);
+                        end = offset;
+                    }
+                    Err(_) => break,
+                },
+                RECORD_CHECKPOINT => {
+                    pending.clear();
+                    end = offset;
+                }
+                _ => break,
+            }
+        }
+
+        let mut wal = Self { file, index, end };
+        if header.is_none() {
+            wal.reset()?;
+        }
+        Ok((wal, header))
+    }
+
+    /// Read the next record, or `None` at the end of the log or at the
+    /// first torn or damaged record
+    fn read_record(reader: &mut impl Read) -> Result<Option<Record>> {
+        let mut head = [0u8; RECORD_HEADER_SIZE];
+        if !read_full(reader, &mut head)? {
+            return Ok(None);
+        }
+        let len = u32::from_be_bytes(head[5..9].try_into().unwrap()) as usize;
+        if len > PAGE_SIZE {
+            return Ok(None);
+        }
+
+        let mut payload = vec![0u8; len];
+        let mut crc = [0u8; 4];
+        if !read_full(reader, &mut payload)? || !read_full(reader, &mut crc)? {
+            return Ok(None);
+        }
+
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&head);
+        hasher.update(&payload);
+        if hasher.finalize() != u32::from_be_bytes(crc) {
+            return Ok(None);
+        }
+
+        Ok(Some(Record {
+            kind: head[0],
+            page_id: PageId::new(u32::from_be_bytes(head[1..5].try_into().unwrap())),
+            payload,
+        }))
+    }
+
+    /// Append a record, returning the offset of its payload
+    fn append(&mut self, kind: u8, page_id: PageId, payload: &[u8]) -> Result<u64> {
+        let mut record = Vec::with_capacity(RECORD_HEADER_SIZE + payload.len() + 4);
+        record.push(kind);
+        record.extend_from_slice(&page_id.value().to_be_bytes());
+        record.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        record.extend_from_slice(payload);
+        let crc = crc32fast::hash(&record);
+        record.extend_from_slice(&crc.to_be_bytes());
+
+        self.file.seek(SeekFrom::Start(self.end))?;
+        self.file.write_all(&record)?;
+        let payload_offset = self.end + RECORD_HEADER_SIZE as u64;
+        self.end += record.len() as u64;
+        Ok(payload_offset)
+    }
+
+    /// Log a page image
+    ///
+    /// The image is not recoverable until the next `commit`.
+    pub(crate) fn append_page(&mut self, page_id: PageId, data: &[u8]) -> Result<()> {
+        let offset = self.append(RECORD_PAGE, page_id, data)?;
+        self.index.insert(page_id, offset);
+        Ok(())
+    }
+
+    /// Read the latest logged image of a page into `buf`
+    ///
+    /// Returns false if the page has not been logged since the last
+    /// checkpoint.
+    pub(crate) fn read_page(&mut self, page_id: PageId, buf: &mut PageBuf) -> Result<bool> {
+        let Some(&offset) = self.index.get(&page_id) else {
+            return Ok(false);
+        };
+        self.read_at(offset, buf)?;
+        Ok(true)
+    }
+
+    /// Commit every page logged so far along with `header`, and sync the
+    /// log
+    pub(crate) fn commit(&mut self, header: &FileHeader) -> Result<()> {
+        let mut buf = PageBuf::new();
+        header.write(&mut buf);
+        self.append(RECORD_COMMIT, PageId::HEADER, &buf[..COMMIT_HEADER_LEN])?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    /// Get every logged page with the offset of its latest image
+    pub(crate) fn pages(&self) -> Vec<(PageId, u64)> {
+        let mut pages: Vec<_> = self.index.iter().map(|(&id, &offset)| (id, offset)).collect();
+        pages.sort_unstable();
+        pages
+    }
+
+    /// Read the page image at `offset` into `buf`
+    pub(crate) fn read_at(&mut self, offset: u64, buf: &mut PageBuf) -> Result<()> {
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(buf.as_bytes_mut())?;
+        Ok(())
+    }
+
+    /// Empty the log and mark it clean
+    ///
+    /// Only call once every logged page is synced to the database file.
+    pub(crate) fn reset(&mut self) -> Result<()> {
+        self.file.set_len(0)?;
+        self.index.clear();
+        self.end = 0;
+        self.append(RECORD_CHECKPOINT, PageId::HEADER, &[])?;
+        self.file.sync_all()?;
+        Ok(())
+    }
+}
+
+/// Fill `buf` from `reader`, returning false if it ends first
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => return Ok(false),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_wal_recovers_committed_pages() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db-wal");
+        let page = |byte: u8| PageBuf::from_bytes(&[byte; PAGE_SIZE]);
+
+        {
+            let (mut wal, header) = Wal::open(&path)?;
+            assert!(header.is_none());
+            wal.append_page(PageId::new(1), &page(1))?;
+            wal.append_page(PageId::new(2), &page(2))?;
+            wal.append_page(PageId::new(1), &page(3))?;
+            let mut header = FileHeader::new();
+            header.page_count = 3;
+            wal.commit(&header)?;
+
+            // Uncommitted, then a torn record
+            wal.append_page(PageId::new(2), &page(4))?;
+            let mut buf = PageBuf::new();
+            assert!(wal.read_page(PageId::new(2), &mut buf)?);
+            assert_eq!(buf[0], 4);
+            wal.file.write_all(&[RECORD_PAGE, 0, 0])?;
+        }
+
+        let (mut wal, header) = Wal::open(&path)?;
+        assert_eq!(header.unwrap().page_count, 3);
+        let mut buf = PageBuf::new();
+        let recovered: Vec<u8> = wal
+            .pages()
+            .into_iter()
+            .map(|(_, offset)| {
+                wal.read_at(offset, &mut buf).unwrap();
+                buf[0]
+            })
+            .collect();
+        assert_eq!(recovered, vec![3, 2]);
+
+        // A reset log is clean on the next open
+        wal.reset()?;
+        drop(wal);
+        let (wal, header) = Wal::open(&path)?;
+        assert!(header.is_none());
+        assert!(wal.pages().is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,69 @@
+//! Named trees.
+//!
+//! A database file can hold any number of named trees next to the main
+//! tree. Their roots are recorded in a catalog page referenced from the
+//! file header (see `Db::create_tree`), so each is an independent key
+//! space with its own root, splits and merges.
+//!
+//! Writes to any tree are serialized on the main tree's write lock, which
+//! keeps a commit to the write-ahead log from catching another tree's
+//! write half done.
+
+use crate::btree::{BTree, PutResult};
+use crate::error::Result;
+use crate::Db;
+use parking_lot::RwLock;
+use std::sync::Arc;
+
+/// A handle to a named tree in a database
+pub struct TreeHandle<'a> {
+    db: &'a Db,
+    name: String,
+    tree: Arc<RwLock<BTree>>,
+}
+
+impl<'a> TreeHandle<'a> {
+    /// Create a handle to an open named tree
+    pub(crate) fn new(db: &'a Db, name: &str, tree: Arc<RwLock<BTree>>) -> Self {
+        Self {
+            db,
+            name: name.to_string(),
+            tree,
+        }
+    }
+
+    /// Get the name of this tree
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get a value by key
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.tree.read().get(key)
+    }
+
+    /// Insert or update a key-value pair
+    ///
+    /// Returns whether the key was new.
+    pub fn put(&self, key: &[u8], value: &[u8]) -> Result<PutResult> {
+        let _writer = self.db.write_lock()?;
+        let result = self.tree.write().put(key, value)?;
+        self.db.commit()?;
+        Ok(result)
+    }
+
+    /// Delete a key-value pair
+    ///
+    /// Returns `true` if the key existed and was deleted.
+    pub fn delete(&self, key: &[u8]) -> Result<bool> {
+        let _writer = self.db.write_lock()?;
+        let deleted = self.tree.write().delete(key)?;
+        self.db.commit()?;
+        Ok(deleted)
+    }
+
+    /// Get all key-value pairs in `[start, end)` in sorted order
+    pub fn range(&self, start: Option<&[u8]>, end: Option<&[u8]>) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        self.tree.read().scan(start, end)
+    }
+}
@@ -0,0 +1,92 @@
+//! All-or-nothing groups of writes.
+//!
+//! A `Txn` buffers puts and deletes in memory; the tree is not touched
+//! until the closure passed to `Db::transaction` returns `Ok`. The buffered
+//! writes are then applied under one write lock, so readers see either none
+//! or all of them. If applying one fails part way, the writes already
+//! applied are undone from the entries saved before each one, metadata
+//! included. If undoing fails too, commit reports `Corruption`, since the
+//! tree is left holding part of the transaction.
+
+use crate::error::{Result, StorageError};
+use crate::Db;
+use std::collections::BTreeMap;
+
+/// A set of writes applied together by `Db::transaction`
+pub struct Txn<'a> {
+    db: &'a Db,
+    /// Pending writes by key; `None` deletes
+    writes: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+}
+
+impl<'a> Txn<'a> {
+    /// Create an empty transaction against `db`
+    pub(crate) fn new(db: &'a Db) -> Self {
+        Self {
+            db,
+            writes: BTreeMap::new(),
+        }
+    }
+
+    /// Insert or update a key-value pair when the transaction commits
+    ///
+    /// Sizes are checked now, so an entry that could never be written fails
+    /// here rather than at commit.
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.db.btree.read().validate_entry(key, value)?;
+        self.writes.insert(key.to_vec(), Some(value.to_vec()));
+        Ok(())
+    }
+
+    /// Delete a key when the transaction commits
+    pub fn delete(&mut self, key: &[u8]) {
+        self.writes.insert(key.to_vec(), None);
+    }
+
+    /// Get a value by key, seeing this transaction's own pending writes
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        match self.writes.get(key) {
+            Some(value) => Ok(value.clone()),
+            None => self.db.get(key),
+        }
+    }
+
+    /// Apply the pending writes under one write lock
+    pub(crate) fn commit(self) -> Result<()> {
+        if self.writes.is_empty() {
+            return Ok(());
+        }
+
+        let mut btree = self.db.write_lock()?;
+        let mut undo = Vec::with_capacity(self.writes.len());
+        let result = self.writes.iter().try_for_each(|(key, value)| {
+            undo.push((key, btree.get_with_meta(key)?));
+            match value {
+                Some(value) => btree.put(key, value).map(drop),
+                None => btree.delete(key).map(drop),
+            }
+        });
+
+        if let Err(e) = result {
+            // Undo every write, even past a failed one, to restore as much
+            // of the tree as possible
+            let mut undo_err = None;
+            for (key, old) in undo.into_iter().rev() {
+                let undone = match old {
+                    Some((value, meta)) => btree.put_entry(key, &value, meta).map(drop),
+                    None => btree.delete(key).map(drop),
+                };
+                undo_err = undo_err.or(undone.err());
+            }
+            self.db.commit()?;
+            return Err(match undo_err {
+                None => e,
+                Some(undo_err) => StorageError::corruption(format!(
+                    "transaction partly applied; undoing after {} failed: {}",
+                    e, undo_err
+                )),
+            });
+        }
+        self.db.commit()
+    }
+}
@@ -0,0 +1,92 @@
+//! Order-preserving integer key encoding.
+//!
+//! Keys compare bytewise, so integers must be stored as fixed-width
+//! big-endian bytes to sort numerically. Signed integers additionally flip
+//! the sign bit so negative values sort before positive ones.
+
+/// Encode a u64 as an 8-byte key that sorts numerically
+pub fn encode_u64_key(value: u64) -> [u8; 8] {
+    value.to_be_bytes()
+}
+
+/// Decode a key written by `encode_u64_key`
+///
+/// Returns `None` unless `bytes` is exactly 8 bytes long.
+pub fn decode_u64_key(bytes: &[u8]) -> Option<u64> {
+    Some(u64::from_be_bytes(bytes.try_into().ok()?))
+}
+
+/// Encode an i64 as an 8-byte key that sorts numerically
+///
+/// Flipping the sign bit maps `i64::MIN..=i64::MAX` onto `0..=u64::MAX` in
+/// order.
+pub fn encode_i64_key(value: i64) -> [u8; 8] {
+    encode_u64_key((value as u64) ^ (1 << 63))
+}
+
+/// Decode a key written by `encode_i64_key`
+///
+/// Returns `None` unless `bytes` is exactly 8 bytes long.
+pub fn decode_i64_key(bytes: &[u8]) -> Option<i64> {
+    Some((decode_u64_key(bytes)? ^ (1 << 63)) as i64)
+}
+
+/// Encode a u32 as a 4-byte key that sorts numerically
+pub fn encode_u32_key(value: u32) -> [u8; 4] {
+    value.to_be_bytes()
+}
+
+/// Decode a key written by `encode_u32_key`
+///
+/// Returns `None` unless `bytes` is exactly 4 bytes long.
+pub fn decode_u32_key(bytes: &[u8]) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.try_into().ok()?))
+}
+
+/// Encode an i32 as a 4-byte key that sorts numerically
+pub fn encode_i32_key(value: i32) -> [u8; 4] {
+    encode_u32_key((value as u32) ^ (1 << 31))
+}
+
+/// Decode a key written by `encode_i32_key`
+///
+/// Returns `None` unless `bytes` is exactly 4 bytes long.
+pub fn decode_i32_key(bytes: &[u8]) -> Option<i32> {
+    Some((decode_u32_key(bytes)? ^ (1 << 31)) as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_int_keys_sort_numerically() {
+        let signed = [i64::MIN, -1_000_000, -256, -1, 0, 1, 255, 256, i64::MAX];
+        for pair in signed.windows(2) {
+            assert!(encode_i64_key(pair[0]) < encode_i64_key(pair[1]));
+        }
+        for &value in &signed {
+            assert_eq!(decode_i64_key(&encode_i64_key(value)), Some(value));
+        }
+
+        let unsigned = [0u64, 1, 255, 256, 65536, u64::MAX];
+        for pair in unsigned.windows(2) {
+            assert!(encode_u64_key(pair[0]) < encode_u64_key(pair[1]));
+        }
+        for &value in &unsigned {
+            assert_eq!(decode_u64_key(&encode_u64_key(value)), Some(value));
+        }
+
+        let small = [i32::MIN, -1, 0, 1, i32::MAX];
+        for pair in small.windows(2) {
+            assert!(encode_i32_key(pair[0]) < encode_i32_key(pair[1]));
+        }
+        for &value in &small {
+            assert_eq!(decode_i32_key(&encode_i32_key(value)), Some(value));
+        }
+        assert_eq!(decode_u32_key(&encode_u32_key(7)), Some(7));
+
+        assert_eq!(decode_u64_key(b"short"), None);
+        assert_eq!(decode_i32_key(b"toolong"), None);
+    }
+}
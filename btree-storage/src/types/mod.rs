@@ -1,12 +1,22 @@
 //! Common types used throughout the storage engine.
 
+mod int_key;
 mod page_id;
 mod varint;
 
-pub use page_id::PageId;
-pub use varint::{decode_varint, encode_varint, varint_size};
+pub use int_key::{
+    decode_i32_key, decode_i64_key, decode_u32_key, decode_u64_key, encode_i32_key,
+    encode_i64_key, encode_u32_key, encode_u64_key,
+};
+pub use page_id::{PageId, PAGE_ID_SIZE};
+pub use varint::{
+    decode_varint, decode_varint_signed, encode_varint, encode_varint_signed, varint_size,
+    zigzag_decode, zigzag_encode,
+};
 
+use crate::error::{Result, StorageError};
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 
 /// Page size in bytes (4KB)
 pub const PAGE_SIZE: usize = 4096;
@@ -16,9 +26,14 @@ pub const PAGE_SIZE: usize = 4096;
 /// we allow keys up to 1/4 of page size
 pub const MAX_KEY_SIZE: usize = PAGE_SIZE / 4;
 
-/// Maximum value size for inline storage
-/// Larger values would need overflow pages (not implemented in v1)
-pub const MAX_VALUE_SIZE: usize = PAGE_SIZE / 2;
+/// Maximum value size (1MB)
+pub const MAX_VALUE_SIZE: usize = 1024 * 1024;
+
+/// Maximum number of value bytes stored in a leaf cell
+///
+/// Longer values keep this many bytes in the cell and spill the rest into a
+/// chain of overflow pages.
+pub const MAX_INLINE_VALUE_SIZE: usize = PAGE_SIZE / 4;
 
 /// Minimum number of keys per node (B-tree order property)
 /// A node must have at least MIN_KEYS keys (except root)
@@ -30,6 +45,9 @@ pub const DEFAULT_MAX_LEAF_KEYS: usize = 4;
 /// Default maximum keys per interior node (for visualization-friendly defaults)
 pub const DEFAULT_MAX_INTERIOR_KEYS: usize = 3;
 
+/// Default share of cells kept on the left when appending splits a page
+pub const DEFAULT_APPEND_SPLIT_RATIO: f64 = 0.9;
+
 /// BTree configuration for customizable node limits
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -38,6 +56,50 @@ pub struct BTreeConfig {
     pub max_leaf_keys: usize,
     /// Maximum keys per interior node
     pub max_interior_keys: usize,
+    /// Fixed prefix length for composite keys (`None` for plain keys)
+    ///
+    /// Composite keys sort by the prefix first and the suffix second. With a
+    /// fixed-length prefix this is the same as bytewise order, so the tree
+    /// needs no special comparator; the setting enforces the layout.
+    #[serde(default)]
+    pub composite_prefix_len: Option<usize>,
+    /// Record a last-modified timestamp in each leaf cell on write
+    #[serde(default)]
+    pub track_modified: bool,
+    /// Keep a bloom filter on each leaf so lookups can skip absent keys
+    #[serde(default)]
+    pub bloom_filters: bool,
+    /// Accept empty values on write
+    #[serde(default = "default_true")]
+    pub allow_empty_values: bool,
+    /// Largest value copied into interior separators (0 disables it)
+    ///
+    /// Experimental: a value of at most this many bytes is stored with its
+    /// key when the key becomes a separator, so lookups for it stop at the
+    /// interior node.
+    #[serde(default)]
+    pub inline_interior_max: usize,
+    /// Share of cells the left page keeps when a key past the end of the
+    /// tree splits a page
+    ///
+    /// Ascending inserts only ever add to the rightmost pages, so keeping
+    /// the left page nearly full packs them densely. 0.5 splits evenly.
+    #[serde(default = "default_append_split_ratio")]
+    pub append_split_ratio: f64,
+    /// Order in which keys are sorted
+    ///
+    /// Fixed when the database is created; reopening with another order is
+    /// rejected.
+    #[serde(default)]
+    pub key_order: KeyOrder,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_append_split_ratio() -> f64 {
+    DEFAULT_APPEND_SPLIT_RATIO
 }
 
 impl Default for BTreeConfig {
@@ -45,6 +107,13 @@ impl Default for BTreeConfig {
         Self {
             max_leaf_keys: DEFAULT_MAX_LEAF_KEYS,
             max_interior_keys: DEFAULT_MAX_INTERIOR_KEYS,
+            composite_prefix_len: None,
+            track_modified: false,
+            bloom_filters: false,
+            allow_empty_values: true,
+            inline_interior_max: 0,
+            append_split_ratio: DEFAULT_APPEND_SPLIT_RATIO,
+            key_order: KeyOrder::Bytewise,
         }
     }
 }
@@ -55,6 +124,13 @@ impl BTreeConfig {
         Self {
             max_leaf_keys: max_leaf_keys.max(MIN_KEYS),
             max_interior_keys: max_interior_keys.max(MIN_KEYS),
+            composite_prefix_len: None,
+            track_modified: false,
+            bloom_filters: false,
+            allow_empty_values: true,
+            inline_interior_max: 0,
+            append_split_ratio: DEFAULT_APPEND_SPLIT_RATIO,
+            key_order: KeyOrder::Bytewise,
         }
     }
 
@@ -64,10 +140,196 @@ impl BTreeConfig {
             // Use a large number to effectively disable key-count based splits
             max_leaf_keys: 1000,
             max_interior_keys: 1000,
+            composite_prefix_len: None,
+            track_modified: false,
+            bloom_filters: false,
+            allow_empty_values: true,
+            inline_interior_max: 0,
+            append_split_ratio: DEFAULT_APPEND_SPLIT_RATIO,
+            key_order: KeyOrder::Bytewise,
+        }
+    }
+
+    /// Record a last-modified timestamp with every write
+    pub fn track_modified(mut self, enabled: bool) -> Self {
+        self.track_modified = enabled;
+        self
+    }
+
+    /// Keep a per-leaf bloom filter for negative lookups
+    pub fn bloom_filters(mut self, enabled: bool) -> Self {
+        self.bloom_filters = enabled;
+        self
+    }
+
+    /// Accept or reject writes with an empty value
+    pub fn allow_empty_values(mut self, allowed: bool) -> Self {
+        self.allow_empty_values = allowed;
+        self
+    }
+
+    /// Copy values of at most `max_len` bytes into interior separators
+    pub fn inline_interior_values(mut self, max_len: usize) -> Self {
+        self.inline_interior_max = max_len;
+        self
+    }
+
+    /// Keep `ratio` of the cells on the left when a key past the end of
+    /// the tree splits a page; 0.5 splits evenly
+    pub fn append_split_ratio(mut self, ratio: f64) -> Self {
+        self.append_split_ratio = ratio.clamp(0.5, 1.0);
+        self
+    }
+
+    /// Sort keys in the given order
+    pub fn key_order(mut self, order: KeyOrder) -> Self {
+        self.key_order = order;
+        self
+    }
+
+    /// Use composite keys with a fixed-length prefix
+    pub fn composite_prefix(mut self, len: usize) -> Self {
+        self.composite_prefix_len = Some(len);
+        self
+    }
+
+    /// Fewest separators a non-root interior page keeps after a delete
+    ///
+    /// Half the maximum, so an underfull interior page and a sibling at the
+    /// minimum always fit on one page together with their separator.
+    pub fn min_interior_keys(&self) -> usize {
+        (self.max_interior_keys / 2).max(1)
+    }
+
+    /// Compare two keys in the order the tree stores them
+    ///
+    /// Under bytewise order composite keys sort by prefix first, because
+    /// the prefix has a fixed length.
+    pub fn compare_keys(&self, a: &[u8], b: &[u8]) -> Ordering {
+        self.key_order.compare(a, b)
+    }
+
+    /// Check that a key matches the configured key layout
+    pub fn validate_key(&self, key: &[u8]) -> Result<()> {
+        match self.composite_prefix_len {
+            Some(len) if key.len() < len => Err(StorageError::invalid_operation(format!(
+                "key of {} bytes is shorter than the {}-byte composite prefix",
+                key.len(),
+                len
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Build a composite key from a prefix and suffix
+    pub fn composite_key(&self, prefix: &[u8], suffix: &[u8]) -> Result<Vec<u8>> {
+        self.validate_prefix(prefix)?;
+        let mut key = Vec::with_capacity(prefix.len() + suffix.len());
+        key.extend_from_slice(prefix);
+        key.extend_from_slice(suffix);
+        Ok(key)
+    }
+
+    /// Split a composite key into its prefix and suffix
+    ///
+    /// Returns `None` if composite keys are not configured or the key is
+    /// shorter than the prefix.
+    pub fn split_key<'a>(&self, key: &'a [u8]) -> Option<(&'a [u8], &'a [u8])> {
+        let len = self.composite_prefix_len?;
+        (key.len() >= len).then(|| key.split_at(len))
+    }
+
+    /// Check that a prefix has exactly the configured composite prefix length
+    pub fn validate_prefix(&self, prefix: &[u8]) -> Result<()> {
+        match self.composite_prefix_len {
+            Some(len) if prefix.len() == len => Ok(()),
+            Some(len) => Err(StorageError::invalid_operation(format!(
+                "composite prefix must be {} bytes, got {}",
+                len,
+                prefix.len()
+            ))),
+            None => Err(StorageError::invalid_operation(
+                "composite keys are not configured",
+            )),
         }
     }
 }
 
+/// Order in which a tree sorts its keys
+///
+/// Every order is total: keys that are equal under the order's own rule
+/// (`"A"` and `"a"` case-insensitively) fall back to bytewise order, so
+/// distinct keys never compare equal.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum KeyOrder {
+    /// Lexicographic byte order
+    #[default]
+    Bytewise = 0,
+    /// Descending byte order
+    Reverse = 1,
+    /// ASCII case-insensitive order
+    CaseInsensitive = 2,
+    /// Keys as big-endian unsigned integers of any width
+    ///
+    /// Leading zero bytes are ignored, so `[0, 5]` sorts just before `[5]`
+    /// and both sort before `[1, 0]`.
+    Numeric = 3,
+}
+
+impl KeyOrder {
+    /// Compare two keys in this order
+    pub fn compare(self, a: &[u8], b: &[u8]) -> Ordering {
+        match self {
+            Self::Bytewise => a.cmp(b),
+            Self::Reverse => b.cmp(a),
+            Self::CaseInsensitive => a
+                .iter()
+                .map(u8::to_ascii_lowercase)
+                .cmp(b.iter().map(u8::to_ascii_lowercase))
+                .then_with(|| a.cmp(b)),
+            Self::Numeric => {
+                fn trim(k: &[u8]) -> &[u8] {
+                    &k[k.iter().take_while(|&&b| b == 0).count()..]
+                }
+                let (ta, tb) = (trim(a), trim(b));
+                ta.len().cmp(&tb.len()).then_with(|| ta.cmp(tb)).then_with(|| a.cmp(b))
+            }
+        }
+    }
+
+    /// Check whether this order matches the order of the raw bytes
+    pub fn is_bytewise(self) -> bool {
+        self == Self::Bytewise
+    }
+
+    /// Convert from byte value
+    pub fn from_byte(b: u8) -> Option<Self> {
+        match b {
+            0 => Some(Self::Bytewise),
+            1 => Some(Self::Reverse),
+            2 => Some(Self::CaseInsensitive),
+            3 => Some(Self::Numeric),
+            _ => None,
+        }
+    }
+}
+
+/// Get the smallest key greater than every key starting with `prefix`
+///
+/// Returns `None` if no such key exists (empty or all-0xFF prefix).
+pub(crate) fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return Some(end);
+        }
+    }
+    None
+}
+
 /// Page types
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -80,6 +342,8 @@ pub enum PageType {
     InteriorIndex = 0x05,
     /// Leaf node of table B-tree (keys + values)
     LeafTable = 0x0D,
+    /// Leaf node of table B-tree that also points at the next leaf
+    LinkedLeafTable = 0x0E,
     /// Leaf node of index B-tree
     LeafIndex = 0x0A,
     /// Overflow page for large payloads
@@ -89,7 +353,7 @@ pub enum PageType {
 impl PageType {
     /// Check if this is a leaf page type
     pub fn is_leaf(self) -> bool {
-        matches!(self, Self::LeafTable | Self::LeafIndex)
+        matches!(self, Self::LeafTable | Self::LinkedLeafTable | Self::LeafIndex)
     }
 
     /// Check if this is an interior page type
@@ -104,6 +368,7 @@ impl PageType {
             0x02 => Some(Self::InteriorTable),
             0x05 => Some(Self::InteriorIndex),
             0x0D => Some(Self::LeafTable),
+            0x0E => Some(Self::LinkedLeafTable),
             0x0A => Some(Self::LeafIndex),
             0x0F => Some(Self::Overflow),
             _ => None,
@@ -119,6 +384,7 @@ mod tests {
     fn test_page_type_conversions() {
         assert!(PageType::LeafTable.is_leaf());
         assert!(PageType::LeafIndex.is_leaf());
+        assert!(PageType::LinkedLeafTable.is_leaf());
         assert!(!PageType::InteriorTable.is_leaf());
 
         assert!(PageType::InteriorTable.is_interior());
@@ -127,4 +393,44 @@ mod tests {
         assert_eq!(PageType::from_byte(0x0D), Some(PageType::LeafTable));
         assert_eq!(PageType::from_byte(0xFF), None);
     }
+
+    #[test]
+    fn test_composite_keys() {
+        let config = BTreeConfig::default().composite_prefix(4);
+
+        let key = config.composite_key(b"p001", b"ts42").unwrap();
+        assert_eq!(config.split_key(&key), Some((&b"p001"[..], &b"ts42"[..])));
+        assert!(config.composite_key(b"p1", b"ts").is_err());
+        assert!(config.validate_key(b"abc").is_err());
+        assert!(config.validate_key(b"abcd").is_ok());
+
+        assert!(BTreeConfig::default().validate_key(b"a").is_ok());
+        assert!(BTreeConfig::default().split_key(b"abcd").is_none());
+    }
+
+    #[test]
+    fn test_prefix_end() {
+        assert_eq!(prefix_end(b"ab"), Some(b"ac".to_vec()));
+        assert_eq!(prefix_end(b"a\xff"), Some(b"b".to_vec()));
+        assert_eq!(prefix_end(b"\xff\xff"), None);
+        assert_eq!(prefix_end(b""), None);
+    }
+
+    #[test]
+    fn test_key_orders() {
+        use std::cmp::Ordering::*;
+
+        assert_eq!(KeyOrder::Bytewise.compare(b"a", b"b"), Less);
+        assert_eq!(KeyOrder::Reverse.compare(b"a", b"b"), Greater);
+        assert_eq!(KeyOrder::CaseInsensitive.compare(b"B", b"a"), Greater);
+        assert_eq!(KeyOrder::CaseInsensitive.compare(b"A", b"a"), Less);
+        assert_eq!(KeyOrder::CaseInsensitive.compare(b"ab", b"AbC"), Less);
+        assert_eq!(KeyOrder::CaseInsensitive.compare(b"abc", b"AB"), Greater);
+        assert_eq!(KeyOrder::CaseInsensitive.compare(b"Key", b"Key"), Equal);
+        assert_eq!(KeyOrder::Numeric.compare(&[9], &[1, 0]), Less);
+        assert_eq!(KeyOrder::Numeric.compare(&[5], &[0, 5]), Greater);
+        assert_eq!(KeyOrder::Numeric.compare(&[0, 6], &[5]), Greater);
+        assert_eq!(KeyOrder::from_byte(KeyOrder::Numeric as u8), Some(KeyOrder::Numeric));
+        assert_eq!(KeyOrder::from_byte(9), None);
+    }
 }
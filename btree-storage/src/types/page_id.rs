@@ -2,6 +2,9 @@
 
 use std::fmt;
 
+/// Size of an encoded page ID in bytes
+pub const PAGE_ID_SIZE: usize = std::mem::size_of::<u32>();
+
 /// Unique identifier for a page in the database file.
 ///
 /// Page IDs are 0-indexed. Page 0 is reserved for the file header.
@@ -31,6 +34,16 @@ impl PageId {
         self.0 != u32::MAX
     }
 
+    /// Encode as big-endian bytes
+    pub const fn to_be_bytes(self) -> [u8; PAGE_ID_SIZE] {
+        self.0.to_be_bytes()
+    }
+
+    /// Decode from big-endian bytes
+    pub const fn from_be_bytes(bytes: [u8; PAGE_ID_SIZE]) -> Self {
+        Self(u32::from_be_bytes(bytes))
+    }
+
     /// Calculate the byte offset of this page in the file
     pub const fn file_offset(self, page_size: usize) -> u64 {
         self.0 as u64 * page_size as u64
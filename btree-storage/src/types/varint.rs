@@ -66,6 +66,30 @@ pub fn decode_varint(bytes: &[u8]) -> Option<(u64, usize)> {
     None
 }
 
+/// Map a signed integer to an unsigned one so that small magnitudes stay
+/// small: 0, -1, 1, -2, ... become 0, 1, 2, 3, ...
+pub fn zigzag_encode(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// Reverse `zigzag_encode`
+pub fn zigzag_decode(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+/// Encode an i64 as a zigzag variable-length integer.
+pub fn encode_varint_signed(value: i64) -> Vec<u8> {
+    encode_varint(zigzag_encode(value))
+}
+
+/// Decode a zigzag variable-length integer from a byte slice.
+///
+/// Returns the decoded value and the number of bytes consumed.
+/// Returns `None` if the encoding is invalid or incomplete.
+pub fn decode_varint_signed(bytes: &[u8]) -> Option<(i64, usize)> {
+    decode_varint(bytes).map(|(value, size)| (zigzag_decode(value), size))
+}
+
 /// Calculate the number of bytes needed to encode a value.
 pub fn varint_size(value: u64) -> usize {
     if value <= 127 {
@@ -127,6 +151,25 @@ mod tests {
         assert_eq!(encode_varint(128).len(), 2);
     }
 
+    #[test]
+    fn test_varint_signed_roundtrip() {
+        assert_eq!(zigzag_encode(0), 0);
+        assert_eq!(zigzag_encode(-1), 1);
+        assert_eq!(zigzag_encode(1), 2);
+        assert_eq!(zigzag_encode(i64::MAX), u64::MAX - 1);
+        assert_eq!(zigzag_encode(i64::MIN), u64::MAX);
+
+        for value in [0i64, 1, -1, 63, -64, 64, -65, i64::MAX, i64::MIN] {
+            assert_eq!(zigzag_decode(zigzag_encode(value)), value);
+            let encoded = encode_varint_signed(value);
+            let (decoded, size) = decode_varint_signed(&encoded).unwrap();
+            assert_eq!(decoded, value, "Failed for value {}", value);
+            assert_eq!(size, encoded.len());
+        }
+        assert_eq!(encode_varint_signed(-1).len(), 1);
+        assert!(decode_varint_signed(&[]).is_none());
+    }
+
     #[test]
     fn test_decode_empty() {
         assert!(decode_varint(&[]).is_none());
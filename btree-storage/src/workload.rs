@@ -0,0 +1,516 @@
+//! Deterministic workload generation for benchmarking.
+//!
+//! A [`Workload`] produces a reproducible stream of operations from a seed,
+//! so different configurations can be compared against exactly the same
+//! inputs. Keys are drawn from a sequential, uniform, or zipfian
+//! distribution over a fixed key space, and key/value sizes from a
+//! configurable size distribution.
+//!
+//! ```rust,ignore
+//! use btree_storage::workload::{KeyDistribution, Workload, WorkloadConfig};
+//!
+//! let workload = Workload::new(
+//!     WorkloadConfig::new(42)
+//!         .operation_count(100_000)
+//!         .key_distribution(KeyDistribution::Zipfian { theta: 0.99 })
+//!         .read_ratio(0.9),
+//! )?;
+//! let report = workload.run(&db)?;
+//! println!("{:.0} ops/sec, p99 = {:?}", report.ops_per_sec, report.p99);
+//! ```
+
+use crate::error::{Result, StorageError};
+use crate::Db;
+use std::time::{Duration, Instant};
+
+/// How keys are chosen from the key space
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyDistribution {
+    /// Keys 0, 1, 2, ... in order, wrapping at the key space size
+    Sequential,
+    /// Every key equally likely
+    Uniform,
+    /// Skewed toward low key indices; `theta` in (0, 1) controls the skew
+    Zipfian { theta: f64 },
+}
+
+/// How key or value sizes are chosen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeDistribution {
+    /// Always the same size
+    Fixed(usize),
+    /// Uniformly distributed in `min..=max`
+    Uniform { min: usize, max: usize },
+}
+
+impl SizeDistribution {
+    fn sample(&self, rng: &mut SplitMix64) -> usize {
+        match *self {
+            Self::Fixed(size) => size,
+            Self::Uniform { min, max } => {
+                let (lo, hi) = (min.min(max), min.max(max));
+                lo + rng.below((hi - lo + 1) as u64) as usize
+            }
+        }
+    }
+}
+
+/// Workload configuration
+#[derive(Debug, Clone)]
+pub struct WorkloadConfig {
+    /// Seed for the operation stream; equal seeds give equal streams
+    pub seed: u64,
+    /// Number of operations to generate
+    pub operation_count: usize,
+    /// Number of distinct keys; must be at least 1
+    pub key_space: u64,
+    /// Fraction of operations that are reads (0.0 = all writes)
+    pub read_ratio: f64,
+    /// Key selection distribution
+    pub key_distribution: KeyDistribution,
+    /// Key size distribution (sizes are stable per key)
+    pub key_size: SizeDistribution,
+    /// Value size distribution
+    pub value_size: SizeDistribution,
+}
+
+impl WorkloadConfig {
+    /// Create a configuration with the given seed and default settings
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            operation_count: 10_000,
+            key_space: 10_000,
+            read_ratio: 0.0,
+            key_distribution: KeyDistribution::Uniform,
+            key_size: SizeDistribution::Fixed(16),
+            value_size: SizeDistribution::Fixed(100),
+        }
+    }
+
+    /// Set the number of operations
+    pub fn operation_count(mut self, count: usize) -> Self {
+        self.operation_count = count;
+        self
+    }
+
+    /// Set the number of distinct keys
+    pub fn key_space(mut self, key_space: u64) -> Self {
+        self.key_space = key_space.max(1);
+        self
+    }
+
+    /// Set the fraction of reads, clamped to `0.0..=1.0`
+    pub fn read_ratio(mut self, ratio: f64) -> Self {
+        self.read_ratio = ratio.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set the key distribution
+    pub fn key_distribution(mut self, distribution: KeyDistribution) -> Self {
+        self.key_distribution = distribution;
+        self
+    }
+
+    /// Set the key size distribution
+    pub fn key_size(mut self, size: SizeDistribution) -> Self {
+        self.key_size = size;
+        self
+    }
+
+    /// Set the value size distribution
+    pub fn value_size(mut self, size: SizeDistribution) -> Self {
+        self.value_size = size;
+        self
+    }
+}
+
+/// A single generated operation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
+    /// Insert or update a key
+    Put { key: Vec<u8>, value: Vec<u8> },
+    /// Look up a key
+    Get { key: Vec<u8> },
+}
+
+/// Results of running a workload against a database
+#[derive(Debug, Clone)]
+pub struct WorkloadReport {
+    /// Number of operations executed
+    pub operations: usize,
+    /// Number of reads executed
+    pub reads: usize,
+    /// Number of writes executed
+    pub writes: usize,
+    /// Wall-clock time for the whole run
+    pub elapsed: Duration,
+    /// Throughput over the whole run
+    pub ops_per_sec: f64,
+    /// Median operation latency
+    pub p50: Duration,
+    /// 95th percentile operation latency
+    pub p95: Duration,
+    /// 99th percentile operation latency
+    pub p99: Duration,
+    /// Slowest operation
+    pub max: Duration,
+}
+
+/// A reproducible workload
+#[derive(Debug, Clone)]
+pub struct Workload {
+    config: WorkloadConfig,
+}
+
+impl Workload {
+    /// Create a workload from a configuration
+    ///
+    /// Fails if the key space is empty, since no key could be chosen.
+    pub fn new(config: WorkloadConfig) -> Result<Self> {
+        if config.key_space == 0 {
+            return Err(StorageError::invalid_operation(
+                "workload key space must be at least 1",
+            ));
+        }
+        Ok(Self { config })
+    }
+
+    /// Get the configuration
+    pub fn config(&self) -> &WorkloadConfig {
+        &self.config
+    }
+
+    /// Generate the operation stream
+    pub fn operations(&self) -> Operations<'_> {
+        let chooser = match self.config.key_distribution {
+            KeyDistribution::Sequential => KeyChooser::Sequential { next: 0 },
+            KeyDistribution::Uniform => KeyChooser::Uniform,
+            KeyDistribution::Zipfian { theta } => {
+                KeyChooser::Zipfian(Zipfian::new(self.config.key_space, theta))
+            }
+        };
+
+        Operations {
+            config: &self.config,
+            rng: SplitMix64::new(self.config.seed),
+            chooser,
+            remaining: self.config.operation_count,
+        }
+    }
+
+    /// Drive a database with this workload, measuring each operation
+    pub fn run(&self, db: &Db) -> Result<WorkloadReport> {
+        let mut latencies = Vec::with_capacity(self.config.operation_count);
+        let (mut reads, mut writes) = (0, 0);
+
+        let start = Instant::now();
+        for op in self.operations() {
+            let op_start = Instant::now();
+            match op {
+                Operation::Put { key, value } => {
+                    db.put(&key, &value)?;
+                    writes += 1;
+                }
+                Operation::Get { key } => {
+                    db.get(&key)?;
+                    reads += 1;
+                }
+            }
+            latencies.push(op_start.elapsed());
+        }
+        let elapsed = start.elapsed();
+
+        latencies.sort_unstable();
+        let operations = latencies.len();
+        let ops_per_sec = if elapsed.as_secs_f64() > 0.0 {
+            operations as f64 / elapsed.as_secs_f64()
+        } else {
+            0.0
+        };
+
+        Ok(WorkloadReport {
+            operations,
+            reads,
+            writes,
+            elapsed,
+            ops_per_sec,
+            p50: percentile(&latencies, 0.50),
+            p95: percentile(&latencies, 0.95),
+            p99: percentile(&latencies, 0.99),
+            max: latencies.last().copied().unwrap_or_default(),
+        })
+    }
+}
+
+/// Iterator over a workload's operations
+pub struct Operations<'a> {
+    config: &'a WorkloadConfig,
+    rng: SplitMix64,
+    chooser: KeyChooser,
+    remaining: usize,
+}
+
+impl Iterator for Operations<'_> {
+    type Item = Operation;
+
+    fn next(&mut self) -> Option<Operation> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+
+        let index = self.chooser.next_index(&mut self.rng, self.config.key_space);
+        let key = make_key(index, self.config.seed, &self.config.key_size);
+
+        if self.rng.next_f64() < self.config.read_ratio {
+            Some(Operation::Get { key })
+        } else {
+            let len = self.config.value_size.sample(&mut self.rng);
+            let value = (0..len).map(|i| b'a' + ((index as usize + i) % 26) as u8).collect();
+            Some(Operation::Put { key, value })
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+/// Build the key for a key index
+///
+/// The size is derived from the index alone so a key is identical every
+/// time it is chosen. Keys are zero-padded decimal so they sort by index.
+fn make_key(index: u64, seed: u64, size: &SizeDistribution) -> Vec<u8> {
+    let mut rng = SplitMix64::new(seed ^ index.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    let len = size.sample(&mut rng);
+
+    let mut key = format!("key{:016}", index).into_bytes();
+    key.resize(len.max(key.len()), b'_');
+    key
+}
+
+/// Nearest-rank percentile of sorted samples
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = ((p * sorted.len() as f64).ceil() as usize).clamp(1, sorted.len());
+    sorted[rank - 1]
+}
+
+enum KeyChooser {
+    Sequential { next: u64 },
+    Uniform,
+    Zipfian(Zipfian),
+}
+
+impl KeyChooser {
+    fn next_index(&mut self, rng: &mut SplitMix64, key_space: u64) -> u64 {
+        match self {
+            Self::Sequential { next } => {
+                let index = *next % key_space;
+                *next += 1;
+                index
+            }
+            Self::Uniform => rng.below(key_space),
+            Self::Zipfian(zipfian) => zipfian.sample(rng),
+        }
+    }
+}
+
+/// Zipfian generator (Gray et al., "Quickly Generating Billion-Record
+/// Synthetic Databases"), as used by YCSB
+struct Zipfian {
+    items: u64,
+    theta: f64,
+    alpha: f64,
+    zetan: f64,
+    eta: f64,
+}
+
+impl Zipfian {
+    fn new(items: u64, theta: f64) -> Self {
+        let theta = theta.clamp(0.01, 0.99);
+        let zeta2 = zeta(2, theta);
+        let zetan = zeta(items, theta);
+        let alpha = 1.0 / (1.0 - theta);
+        let eta = (1.0 - (2.0 / items as f64).powf(1.0 - theta)) / (1.0 - zeta2 / zetan);
+
+        Self {
+            items,
+            theta,
+            alpha,
+            zetan,
+            eta,
+        }
+    }
+
+    fn sample(&self, rng: &mut SplitMix64) -> u64 {
+        let u = rng.next_f64();
+        let uz = u * self.zetan;
+
+        if uz < 1.0 {
+            return 0;
+        }
+        if uz < 1.0 + 0.5f64.powf(self.theta) {
+            return 1.min(self.items - 1);
+        }
+
+        let index = (self.items as f64 * (self.eta * u - self.eta + 1.0).powf(self.alpha)) as u64;
+        index.min(self.items - 1)
+    }
+}
+
+fn zeta(n: u64, theta: f64) -> f64 {
+    (1..=n).map(|i| 1.0 / (i as f64).powf(theta)).sum()
+}
+
+/// SplitMix64 PRNG
+///
+/// Implemented here rather than taken from a crate so a seed produces the
+/// same stream regardless of dependency versions.
+struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform float in `[0, 1)`
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform integer in `0..bound`
+    fn below(&mut self, bound: u64) -> u64 {
+        if bound == 0 {
+            return 0;
+        }
+        ((self.next_u64() as u128 * bound as u128) >> 64) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+    use std::collections::HashMap;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_same_seed_same_operations() -> Result<()> {
+        let config = WorkloadConfig::new(7)
+            .operation_count(500)
+            .read_ratio(0.5)
+            .value_size(SizeDistribution::Uniform { min: 10, max: 50 });
+
+        let a: Vec<Operation> = Workload::new(config.clone())?.operations().collect();
+        let b: Vec<Operation> = Workload::new(config)?.operations().collect();
+        assert_eq!(a, b);
+
+        let c: Vec<Operation> = Workload::new(WorkloadConfig::new(8).operation_count(500).read_ratio(0.5))?
+            .operations()
+            .collect();
+        assert_ne!(a, c);
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_sizes_are_stable_per_key() -> Result<()> {
+        let workload = Workload::new(
+            WorkloadConfig::new(1)
+                .operation_count(2_000)
+                .key_space(50)
+                .key_size(SizeDistribution::Uniform { min: 20, max: 40 }),
+        )?;
+
+        let mut seen: HashMap<Vec<u8>, usize> = HashMap::new();
+        for op in workload.operations() {
+            let key = match op {
+                Operation::Put { key, .. } | Operation::Get { key } => key,
+            };
+            assert!((20..=40).contains(&key.len()));
+            let index = key[..19].to_vec();
+            let len = *seen.entry(index).or_insert(key.len());
+            assert_eq!(len, key.len());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_zipfian_is_skewed() -> Result<()> {
+        let key_space = 1_000;
+        let count_hot = |distribution| -> Result<usize> {
+            Ok(Workload::new(
+                WorkloadConfig::new(3)
+                    .operation_count(10_000)
+                    .key_space(key_space)
+                    .key_distribution(distribution),
+            )?
+            .operations()
+            .filter(|op| matches!(op, Operation::Put { key, .. } if key.starts_with(b"key0000000000000000")))
+            .count())
+        };
+
+        let zipfian_hits = count_hot(KeyDistribution::Zipfian { theta: 0.99 })?;
+        let uniform_hits = count_hot(KeyDistribution::Uniform)?;
+        assert!(zipfian_hits > uniform_hits * 10);
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_reports_counts() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let db = Db::open(Config::new(dir.path().join("test.db")))?;
+
+        let workload = Workload::new(
+            WorkloadConfig::new(11)
+                .operation_count(300)
+                .key_space(100)
+                .key_distribution(KeyDistribution::Sequential)
+                .read_ratio(0.25),
+        )?;
+        let report = workload.run(&db)?;
+
+        assert_eq!(report.operations, 300);
+        assert_eq!(report.reads + report.writes, 300);
+        assert!(report.p50 <= report.p99);
+        assert!(report.p99 <= report.max);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_key_space_is_rejected() -> Result<()> {
+        let mut config = WorkloadConfig::new(5);
+        config.key_space = 0;
+        for distribution in [
+            KeyDistribution::Sequential,
+            KeyDistribution::Uniform,
+            KeyDistribution::Zipfian { theta: 0.99 },
+        ] {
+            let config = config.clone().key_distribution(distribution);
+            assert!(matches!(
+                Workload::new(config),
+                Err(StorageError::InvalidOperation(_))
+            ));
+        }
+
+        // The builder keeps at least one key
+        let workload = Workload::new(WorkloadConfig::new(5).key_space(0).operation_count(10))?;
+        assert_eq!(workload.config().key_space, 1);
+        assert_eq!(workload.operations().count(), 10);
+        Ok(())
+    }
+}
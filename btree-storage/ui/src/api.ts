@@ -6,6 +6,9 @@ export interface TreeNode {
     keys: string[];
     values: string[];
     children: TreeNode[];
+    freeBytes?: number;
+    fillPercent?: number;
+    cellCount?: number;
 }
 
 export interface BTreeConfig {
@@ -113,7 +113,13 @@ export function BTreeVisualizer({ tree }: Props) {
                         width={pos.width}
                         height={NODE_HEIGHT}
                         rx={6}
-                    />
+                    >
+                        {pos.node.fillPercent !== undefined && (
+                            <title>
+                                {`${pos.node.fillPercent.toFixed(1)}% full, ${pos.node.freeBytes ?? 0} bytes free, ${pos.node.cellCount ?? 0} cells`}
+                            </title>
+                        )}
+                    </rect>
 
                     {/* Page ID label */}
                     <text className="node-label" x={-pos.width / 2 + 6} y={-NODE_HEIGHT / 2 + 12}>